@@ -0,0 +1,78 @@
+#![deny(warnings)]
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+mod utilities;
+use stm32h5xx_hal::{
+    lpuart::{Config, Event, WakeupEvent},
+    pac::{self, interrupt, NVIC},
+    prelude::*,
+    pwr,
+    rcc::rec::LpUart1ClkSel,
+};
+
+use embedded_io::{Read, Write};
+use log::info;
+
+#[interrupt]
+fn LPUART1() {
+    // Only used to leave Stop mode. Mask the interrupt so that it doesn't
+    // fire again before the main loop has cleared the wakeup flag.
+    NVIC::mask(interrupt::LPUART1);
+}
+
+#[entry]
+fn main() -> ! {
+    utilities::logger::init();
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Constrain and Freeze power
+    info!("Setup PWR...                  ");
+    let pwr = dp.PWR.constrain();
+    let pwrcfg = pwr.freeze();
+
+    // Constrain and Freeze clock. The LSE keeps running in Stop mode.
+    info!("Setup RCC...                  ");
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc
+        .sys_ck(192.MHz())
+        .lse_ck(32_768.Hz())
+        .freeze(pwrcfg, &dp.SBS);
+
+    let gpioa = dp.GPIOA.split(ccdr.peripheral.GPIOA);
+    let tx = gpioa.pa9.into_alternate();
+    let rx = gpioa.pa10.into_alternate();
+
+    info!("");
+    info!("stm32h5xx-hal example - LPUART wakeup from Stop");
+    info!("");
+
+    let rec = ccdr.peripheral.LPUART1.kernel_clk_mux(LpUart1ClkSel::Lse);
+    let config = Config::new(9600.Hz()).wakeup_from_stop(WakeupEvent::StartBit);
+    let mut lpuart = dp.LPUART.lpuart((tx, rx), config, rec, &ccdr.clocks);
+    lpuart.listen(Event::Wakeup);
+
+    lpuart
+        .write_all(b"Send a character to wake up\r\n")
+        .unwrap();
+    lpuart.flush().unwrap();
+
+    let mut buf = [0u8; 16];
+    loop {
+        lpuart.clear_wakeup();
+        unsafe { NVIC::unmask(interrupt::LPUART1) };
+
+        pwr::stop(&mut cp.SCB);
+
+        // Echo what was received, then go back to sleep once the line is
+        // quiet again
+        match lpuart.read(&mut buf) {
+            Ok(n) => lpuart.write_all(&buf[..n]).unwrap(),
+            Err(e) => info!("Receive error: {e:?}"),
+        }
+        lpuart.flush().unwrap();
+        while lpuart.is_busy() {}
+    }
+}
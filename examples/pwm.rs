@@ -0,0 +1,63 @@
+#![deny(warnings)]
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+mod utilities;
+use embedded_hal::{delay::DelayNs, pwm::SetDutyCycle};
+use stm32h5xx_hal::{delay::Delay, pac, prelude::*, pwm::Polarity};
+
+use log::info;
+
+#[entry]
+fn main() -> ! {
+    utilities::logger::init();
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Constrain and Freeze power
+    info!("Setup PWR...                  ");
+    let pwr = dp.PWR.constrain();
+    let pwrcfg = pwr.freeze();
+
+    // Constrain and Freeze clock
+    info!("Setup RCC...                  ");
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc.sys_ck(192.MHz()).freeze(pwrcfg, &dp.SBS);
+
+    // Acquire the GPIOA peripheral. This also enables the clock for
+    // GPIOA in the RCC register.
+    let gpioa = dp.GPIOA.split(ccdr.peripheral.GPIOA);
+
+    info!("");
+    info!("stm32h5xx-hal example - PWM");
+    info!("");
+
+    // Initialise TIM3 for 10 kHz PWM on PA6 (CH1) and PA7 (CH2)
+    let (pwm, channels) =
+        dp.TIM3.pwm(10.kHz(), ccdr.peripheral.TIM3, &ccdr.clocks);
+    info!(
+        "PWM frequency: {}, max duty: {}",
+        pwm.frequency(),
+        pwm.max_duty()
+    );
+
+    let mut ch1 = channels.ch1.pwm(gpioa.pa6.into_alternate());
+    let mut ch2 = channels.ch2.pwm(gpioa.pa7.into_alternate());
+
+    // CH2 is the inverse of CH1
+    ch2.set_polarity(Polarity::ActiveLow);
+    ch1.enable();
+    ch2.enable();
+
+    let mut delay = Delay::new(cp.SYST, &ccdr.clocks);
+
+    // Sweep the duty cycle
+    loop {
+        for percent in (0..=100).step_by(10) {
+            ch1.set_duty_cycle_percent(percent).unwrap();
+            ch2.set_duty_cycle_percent(percent).unwrap();
+            delay.delay_ms(200);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+#![deny(warnings)]
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+mod utilities;
+use embedded_io::{Read, Write};
+use stm32h5xx_hal::{pac, prelude::*};
+
+use log::info;
+
+#[entry]
+fn main() -> ! {
+    utilities::logger::init();
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Constrain and Freeze power
+    info!("Setup PWR...                  ");
+    let pwr = dp.PWR.constrain();
+    let pwrcfg = pwr.freeze();
+
+    // Constrain and Freeze clock
+    info!("Setup RCC...                  ");
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc.sys_ck(192.MHz()).freeze(pwrcfg, &dp.SBS);
+
+    // Acquire the GPIOA peripheral. This also enables the clock for
+    // GPIOA in the RCC register.
+    let gpioa = dp.GPIOA.split(ccdr.peripheral.GPIOA);
+
+    let tx = gpioa.pa9.into_alternate();
+    let rx = gpioa.pa10.into_alternate();
+
+    info!("");
+    info!("stm32h5xx-hal example - Serial");
+    info!("");
+
+    // Initialise the USART peripheral.
+    let mut serial = dp.USART1.serial(
+        (tx, rx),
+        115_200.Hz(),
+        ccdr.peripheral.USART1,
+        &ccdr.clocks,
+    );
+
+    serial.write_all(b"Hello, world!\r\n").unwrap();
+
+    // Echo what is received on the serial port
+    let mut buf = [0u8; 16];
+    loop {
+        match serial.read(&mut buf) {
+            Ok(n) => serial.write_all(&buf[..n]).unwrap(),
+            Err(e) => info!("Receive error: {e:?}"),
+        }
+    }
+}
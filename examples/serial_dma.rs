@@ -0,0 +1,59 @@
+#![deny(warnings)]
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+mod utilities;
+use stm32h5xx_hal::{pac, prelude::*, serial::Config};
+
+use log::info;
+
+#[entry]
+fn main() -> ! {
+    utilities::logger::init();
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Constrain and Freeze power
+    info!("Setup PWR...                  ");
+    let pwr = dp.PWR.constrain();
+    let pwrcfg = pwr.freeze();
+
+    // Constrain and Freeze clock
+    info!("Setup RCC...                  ");
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc.sys_ck(192.MHz()).freeze(pwrcfg, &dp.SBS);
+
+    let gpioa = dp.GPIOA.split(ccdr.peripheral.GPIOA);
+    let tx = gpioa.pa9.into_alternate();
+    let rx = gpioa.pa10.into_alternate();
+
+    let channels = dp.GPDMA1.channels(ccdr.peripheral.GPDMA1);
+
+    info!("");
+    info!("stm32h5xx-hal example - Serial DMA");
+    info!("");
+
+    // End a reception once the line has been idle for 2 characters
+    let config = Config::new(115_200.Hz()).receiver_timeout(20);
+    let serial = dp.USART1.serial(
+        (tx, rx),
+        config,
+        ccdr.peripheral.USART1,
+        &ccdr.clocks,
+    );
+
+    let (tx, rx) = serial.split();
+    let mut tx = tx.with_dma(channels.0);
+    let mut rx = rx.with_dma(channels.1);
+
+    tx.write_dma(b"Hello, world!\r\n").unwrap();
+
+    // Echo each packet received on the serial port
+    let mut buf = [0u8; 64];
+    loop {
+        match rx.read_dma(&mut buf) {
+            Ok(n) => tx.write_dma(&buf[..n]).unwrap(),
+            Err(e) => info!("Receive error: {e:?}"),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+#![deny(warnings)]
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+mod utilities;
+use embedded_hal::delay::DelayNs;
+use stm32h5xx_hal::{
+    delay::Delay, pac, prelude::*, serial::SpiConfig, spi, time::MilliSeconds,
+};
+
+use log::info;
+
+const TEST_STR: &[u8] = b"TEST USART SPI";
+
+#[entry]
+fn main() -> ! {
+    utilities::logger::init();
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Constrain and Freeze power
+    info!("Setup PWR...                  ");
+    let pwr = dp.PWR.constrain();
+    let pwrcfg = pwr.freeze();
+
+    // Constrain and Freeze clock
+    info!("Setup RCC...                  ");
+    let rcc = dp.RCC.constrain();
+    let ccdr = rcc.sys_ck(192.MHz()).freeze(pwrcfg, &dp.SBS);
+
+    let gpioa = dp.GPIOA.split(ccdr.peripheral.GPIOA);
+    let ck = gpioa.pa8.into_alternate();
+    let tx = gpioa.pa9.into_alternate();
+    let rx = gpioa.pa10.into_alternate();
+
+    info!("");
+    info!("stm32h5xx-hal example - USART SPI");
+    info!("");
+
+    // USART1 as an SPI master: CK is SCK, RX is MISO and TX is MOSI
+    let mut spi = dp.USART1.usart_spi(
+        (ck, rx, tx),
+        SpiConfig::new(spi::MODE_0),
+        1.MHz(),
+        ccdr.peripheral.USART1,
+        &ccdr.clocks,
+    );
+
+    spi.write(&[0x11u8, 0x22, 0x33]).unwrap();
+
+    info!("Transfer starting");
+    let mut delay = Delay::new(cp.SYST, &ccdr.clocks);
+    let duration = MilliSeconds::secs(1).to_millis();
+    // Loop MOSI back to MISO to receive what is sent
+    let read = &mut [0u8; TEST_STR.len()];
+    loop {
+        spi.transfer(read, TEST_STR).unwrap();
+        info!("Received {:?}", read);
+        delay.delay_ms(duration);
+    }
+}
@@ -0,0 +1,481 @@
+//! Analog to digital converters (ADC)
+//!
+//! ADC1 is on all devices, and ADC2 on STM32H52x/53x/56x/573. Both are
+//! clocked from the ADC/DAC kernel clock, which is shared with the DACs and
+//! selected with `kernel_adcdac_clk_mux` before the peripheral is enabled.
+//! The clock is divided by the smallest prescaler that keeps it within the
+//! maximum ADC clock frequency.
+//!
+//! The ADC is powered up and calibrated, for both single-ended and
+//! differential conversions, when it is constrained. Conversions are then
+//! done on a pin in analog mode, whose type selects the ADC channel:
+//!
+//! ```
+//! use stm32h5xx_hal::adc::{AdcExt, SampleTime};
+//!
+//! let mut adc = dp.ADC1.adc(ccdr.peripheral.ADC, &ccdr.clocks);
+//! let mut pa0 = gpioa.pa0.into_analog();
+//!
+//! adc.set_sample_time(&pa0, SampleTime::Cycles47_5);
+//! let raw: u16 = adc.read(&mut pa0);
+//! let millivolts = adc.read_millivolts(&mut pa0);
+//! ```
+//!
+//! The millivolts are relative to the analog supply (VDDA), which is taken
+//! to be 3.3 V unless it is set or measured with the internal reference
+//! voltage:
+//!
+//! ```
+//! let mut vrefint = adc.enable_vrefint();
+//! let vdda = adc.measure_vdda(&mut vrefint);
+//! ```
+//!
+//! The ADCs share their peripheral's reset and enable control, so on
+//! devices with both, they are constrained together with `adc12`.
+//!
+//! For sampling several channels at a steady rate, a regular [`Sequence`]
+//! of up to 16 channels is converted continuously or on an external
+//! trigger, with a GPDMA channel filling a ring buffer. Each half of the
+//! buffer is read while the other is filled:
+//!
+//! ```
+//! static mut SAMPLES: [u16; 8 * 64] = [0; 8 * 64];
+//!
+//! let sequence = Sequence::new().rank(&pa0).rank(&pa1).rank(&pa6);
+//! let mode = SequenceMode::Triggered(ExternalTrigger::Tim6Trgo, TriggerEdge::Rising);
+//! let buffer = unsafe { &mut *addr_of_mut!(SAMPLES) };
+//! let mut adc_dma = adc.with_dma(channels.0, &sequence, mode, buffer);
+//! adc_dma.start()?;
+//!
+//! // In the GPDMA1_CH0 interrupt handler, or polled
+//! adc_dma.read_half(|half, samples| process(half, samples));
+//! ```
+
+use crate::gpdma::periph::RxAddr;
+use crate::rcc::rec::{self, AdcDacClkSel, AdcDacClkSelGetter};
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::adc1;
+use crate::time::Hertz;
+
+mod adc_def;
+mod dma;
+
+pub use adc_def::{Temperature, Vrefint};
+pub use dma::{
+    AdcDma, Error, ExternalTrigger, Sequence, SequenceMode, TriggerEdge,
+};
+
+/// The maximum frequency of the ADC clock
+const MAX_ADC_CLOCK: u32 = 75_000_000;
+
+/// The address of the factory measurement of the internal reference voltage
+/// with a 12-bit resolution, at VDDA = 3.3 V
+const VREFINT_CAL: *const u16 = 0x08FF_F810 as *const u16;
+
+/// The analog supply voltage of the factory measurements, in millivolts
+const VREFINT_CAL_VDDA: u32 = 3300;
+
+/// The resolution of the conversions
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Resolution {
+    Twelve,
+    Ten,
+    Eight,
+    Six,
+}
+
+impl Resolution {
+    /// The resolution selection (RES) value
+    fn bits(self) -> u8 {
+        match self {
+            Resolution::Twelve => 0b00,
+            Resolution::Ten => 0b01,
+            Resolution::Eight => 0b10,
+            Resolution::Six => 0b11,
+        }
+    }
+
+    /// The largest conversion result
+    pub fn max_sample(self) -> u16 {
+        match self {
+            Resolution::Twelve => 0xFFF,
+            Resolution::Ten => 0x3FF,
+            Resolution::Eight => 0xFF,
+            Resolution::Six => 0x3F,
+        }
+    }
+}
+
+/// The number of ADC clock cycles for which a channel is sampled. Longer
+/// sample times are needed for sources with a higher impedance.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SampleTime {
+    Cycles2_5,
+    Cycles6_5,
+    Cycles12_5,
+    Cycles24_5,
+    Cycles47_5,
+    Cycles92_5,
+    Cycles247_5,
+    Cycles640_5,
+}
+
+impl SampleTime {
+    /// The sample time selection (SMPx) value
+    fn bits(self) -> u32 {
+        match self {
+            SampleTime::Cycles2_5 => 0b000,
+            SampleTime::Cycles6_5 => 0b001,
+            SampleTime::Cycles12_5 => 0b010,
+            SampleTime::Cycles24_5 => 0b011,
+            SampleTime::Cycles47_5 => 0b100,
+            SampleTime::Cycles92_5 => 0b101,
+            SampleTime::Cycles247_5 => 0b110,
+            SampleTime::Cycles640_5 => 0b111,
+        }
+    }
+}
+
+// Implemented by all ADC instances
+pub trait Instance: crate::Sealed + RxAddr<u16> + Sized {
+    /// GPDMA hardware request line of the ADC
+    const DMA_REQUEST: u8;
+
+    #[doc(hidden)]
+    fn ptr() -> *const adc1::RegisterBlock;
+}
+
+/// An input of ADC `ADC`, which is either a pin in analog mode or an
+/// internal channel
+pub trait Channel<ADC> {
+    /// The channel number
+    const CHANNEL: u8;
+}
+
+/// An analog to digital converter
+#[derive(Debug)]
+pub struct Adc<ADC> {
+    adc: ADC,
+    clock: Hertz,
+    resolution: Resolution,
+    vdda: u32,
+}
+
+pub trait AdcExt<ADC: Instance>: Sized {
+    /// Power up and calibrate the ADC
+    fn adc(self, rec: rec::Adc, clocks: &CoreClocks) -> Adc<ADC>;
+}
+
+impl<ADC: Instance> AdcExt<ADC> for ADC {
+    fn adc(self, rec: rec::Adc, clocks: &CoreClocks) -> Adc<ADC> {
+        let clock = init(rec, clocks);
+        Adc::new(self, clock, clocks)
+    }
+}
+
+/// Power up and calibrate ADC1 and ADC2, which share their peripheral's
+/// reset and enable control
+#[cfg(feature = "rm0481")]
+pub fn adc12(
+    adc1: crate::stm32::ADC1,
+    adc2: crate::stm32::ADC2,
+    rec: rec::Adc,
+    clocks: &CoreClocks,
+) -> (Adc<crate::stm32::ADC1>, Adc<crate::stm32::ADC2>) {
+    let clock = init(rec, clocks);
+    (Adc::new(adc1, clock, clocks), Adc::new(adc2, clock, clocks))
+}
+
+/// The frequency of the ADC/DAC kernel clock
+fn kernel_clock(rec: &rec::Adc, clocks: &CoreClocks) -> Hertz {
+    match rec.get_kernel_clk_mux().expect("No source clock selected") {
+        AdcDacClkSel::Hclk => Some(clocks.hclk()),
+        AdcDacClkSel::Sys => Some(clocks.sys_ck()),
+        AdcDacClkSel::Pll2R => clocks.pll2().r_ck(),
+        AdcDacClkSel::Hse => clocks.hse_ck(),
+        AdcDacClkSel::HsiKer => clocks.hsi_ck(),
+        AdcDacClkSel::CsiKer => clocks.csi_ck(),
+    }
+    .expect("Source clock not enabled")
+}
+
+/// The common prescaler (PRESC) value and division for the smallest division
+/// of the kernel clock `ker_ck` that does not exceed `max`
+fn prescaler(ker_ck: u32, max: u32) -> (u8, u32) {
+    const DIVS: [u32; 12] = [1, 2, 4, 6, 8, 10, 12, 16, 32, 64, 128, 256];
+    DIVS.iter()
+        .enumerate()
+        .find(|(_, &div)| ker_ck / div <= max)
+        .map(|(presc, &div)| (presc as u8, div))
+        .expect("The ADC kernel clock is too fast")
+}
+
+/// Reset and enable the ADCs, and set the common prescaler of their clock.
+/// Returns the ADC clock frequency.
+fn init(rec: rec::Adc, clocks: &CoreClocks) -> Hertz {
+    let ker_ck = kernel_clock(&rec, clocks);
+    let _ = rec.enable().reset();
+
+    let (presc, div) = prescaler(ker_ck.raw(), MAX_ADC_CLOCK);
+    // The ADC clock is the kernel clock (CKMODE = 0), divided by PRESC
+    // NOTE(unsafe) The prescaler value is valid, and the ADCs are disabled
+    // after the reset
+    #[cfg(feature = "rm0492")]
+    unsafe { &*crate::stm32::ADC1::ptr() }
+        .ccr()
+        .write(|w| unsafe { w.ckmode().bits(0).presc().bits(presc) });
+    #[cfg(feature = "rm0481")]
+    unsafe { &*crate::stm32::ADCC::ptr() }
+        .ccr()
+        .write(|w| unsafe { w.ckmode().bits(0).presc().bits(presc) });
+
+    Hertz::from_raw(ker_ck.raw() / div)
+}
+
+/// The analog supply voltage in millivolts, from a 12-bit measurement `raw`
+/// of the internal reference voltage and its factory measurement `cal`
+fn vdda_millivolts(cal: u16, raw: u16) -> u32 {
+    VREFINT_CAL_VDDA * cal as u32 / (raw as u32).max(1)
+}
+
+/// The voltage in millivolts of a conversion result `sample` at
+/// `resolution`, with an analog supply of `vdda` millivolts
+fn millivolts(sample: u16, resolution: Resolution, vdda: u32) -> u16 {
+    (sample as u32 * vdda / resolution.max_sample() as u32) as u16
+}
+
+impl<ADC: Instance> Adc<ADC> {
+    fn new(adc: ADC, clock: Hertz, clocks: &CoreClocks) -> Self {
+        let mut adc = Adc {
+            adc,
+            clock,
+            resolution: Resolution::Twelve,
+            vdda: VREFINT_CAL_VDDA,
+        };
+        adc.power_up(clocks);
+        adc.calibrate();
+        adc
+    }
+
+    #[inline(always)]
+    fn regs(&self) -> &adc1::RegisterBlock {
+        // NOTE(unsafe) The ADC is owned by this driver
+        unsafe { &*ADC::ptr() }
+    }
+
+    /// Leave deep power-down and enable the voltage regulator
+    fn power_up(&mut self, clocks: &CoreClocks) {
+        let adc = self.regs();
+        adc.cr().modify(|_, w| w.deeppwd().clear_bit());
+        adc.cr().modify(|_, w| w.advregen().set_bit());
+        // The regulator takes up to 10 µs to start up
+        cortex_m::asm::delay(clocks.sys_ck().raw().div_ceil(100_000));
+    }
+
+    /// Enable the ADC, and wait until it is ready
+    fn enable(&mut self) {
+        let adc = self.regs();
+        adc.isr().write(|w| w.adrdy().clear());
+        // ADEN cannot be set for a few ADC clock cycles after calibration, so
+        // it is set until it sticks
+        while adc.isr().read().adrdy().bit_is_clear() {
+            if adc.cr().read().aden().bit_is_clear() {
+                adc.cr().modify(|_, w| w.aden().set_bit());
+            }
+        }
+        adc.isr().write(|w| w.adrdy().clear());
+    }
+
+    /// Stop any conversion and disable the ADC
+    fn disable(&mut self) {
+        let adc = self.regs();
+        if adc.cr().read().aden().bit_is_clear() {
+            return;
+        }
+        if adc.cr().read().adstart().bit_is_set() {
+            adc.cr().modify(|_, w| w.adstp().set_bit());
+            while adc.cr().read().adstp().bit_is_set() {}
+        }
+        adc.cr().modify(|_, w| w.addis().set_bit());
+        while adc.cr().read().aden().bit_is_set() {}
+    }
+
+    /// Run the self-calibration for single-ended and then differential
+    /// conversions. This is done when the ADC is constrained, and can be
+    /// repeated if the supply voltage or temperature has changed.
+    pub fn calibrate(&mut self) {
+        self.disable();
+        let adc = self.regs();
+        for differential in [false, true] {
+            adc.cr().modify(|_, w| w.adcaldif().bit(differential));
+            adc.cr().modify(|_, w| w.adcal().set_bit());
+            while adc.cr().read().adcal().bit_is_set() {}
+        }
+        adc.cr().modify(|_, w| w.adcaldif().clear_bit());
+        self.enable();
+    }
+
+    /// The frequency of the ADC clock
+    pub fn clock(&self) -> Hertz {
+        self.clock
+    }
+
+    /// Set the resolution of the following conversions
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.regs()
+            .cfgr()
+            .modify(|_, w| w.res().set(resolution.bits()));
+    }
+
+    /// The resolution of the conversions
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Set the sample time of `channel`, which is kept for all of its
+    /// conversions
+    pub fn set_sample_time<CH>(
+        &mut self,
+        _channel: &CH,
+        sample_time: SampleTime,
+    ) where
+        CH: Channel<ADC>,
+    {
+        let adc = self.regs();
+        let shift = (CH::CHANNEL % 10) * 3;
+        // NOTE(unsafe) Only the sample time of the channel is changed, to a
+        // valid value
+        let smp = |bits: u32| {
+            (bits & !(0b111 << shift)) | (sample_time.bits() << shift)
+        };
+        if CH::CHANNEL < 10 {
+            adc.smpr1().modify(|r, w| unsafe { w.bits(smp(r.bits())) });
+        } else {
+            adc.smpr2().modify(|r, w| unsafe { w.bits(smp(r.bits())) });
+        }
+    }
+
+    /// Convert `channel` as a differential input, with the next channel as
+    /// its negative input, or as a single-ended input. The ADC is disabled
+    /// while the mode is changed.
+    pub fn set_differential<CH>(&mut self, _channel: &CH, differential: bool)
+    where
+        CH: Channel<ADC>,
+    {
+        self.disable();
+        self.regs()
+            .difsel()
+            .modify(|_, w| w.difsel(CH::CHANNEL).bit(differential));
+        self.enable();
+    }
+
+    /// Convert `channel`, and return the raw result, blocking until the
+    /// conversion is done
+    pub fn read<CH>(&mut self, _channel: &mut CH) -> u16
+    where
+        CH: Channel<ADC>,
+    {
+        let adc = self.regs();
+        // A sequence of a single conversion of the channel
+        // NOTE(unsafe) The channel number is valid
+        adc.sqr1()
+            .write(|w| unsafe { w.l().set(0).sq1().bits(CH::CHANNEL) });
+        adc.isr().write(|w| w.eoc().clear());
+        adc.cr().modify(|_, w| w.adstart().set_bit());
+        while adc.isr().read().eoc().bit_is_clear() {}
+        // Reading the result clears EOC
+        adc.dr().read().bits() as u16
+    }
+
+    /// Convert `channel`, and return its voltage in millivolts, blocking
+    /// until the conversion is done
+    pub fn read_millivolts<CH>(&mut self, channel: &mut CH) -> u16
+    where
+        CH: Channel<ADC>,
+    {
+        let sample = self.read(channel);
+        self.to_millivolts(sample)
+    }
+
+    /// The voltage in millivolts of a raw conversion result, at the current
+    /// resolution
+    pub fn to_millivolts(&self, sample: u16) -> u16 {
+        millivolts(sample, self.resolution, self.vdda)
+    }
+
+    /// Set the analog supply voltage, which is the reference of the
+    /// conversions, in millivolts
+    pub fn set_vdda(&mut self, millivolts: u32) {
+        self.vdda = millivolts;
+    }
+
+    /// The analog supply voltage in millivolts
+    pub fn vdda(&self) -> u32 {
+        self.vdda
+    }
+
+    /// Releases the ADC peripheral. The peripheral's reset and enable
+    /// control is shared by the ADCs, so the ADC is only disabled.
+    pub fn free(mut self) -> ADC {
+        self.disable();
+        self.regs().cr().modify(|_, w| w.advregen().clear_bit());
+        self.adc
+    }
+}
+
+impl Adc<crate::stm32::ADC1> {
+    /// Enable the internal reference voltage channel
+    pub fn enable_vrefint(&mut self) -> Vrefint {
+        adc_def::set_internal_channels(|w| w.vrefen().set_bit());
+        Vrefint::new()
+    }
+
+    /// Enable the temperature sensor channel
+    pub fn enable_temperature(&mut self) -> Temperature {
+        adc_def::set_internal_channels(|w| w.tsen().set_bit());
+        Temperature::new()
+    }
+
+    /// Measure the analog supply voltage with the internal reference voltage,
+    /// and use it for the conversions to millivolts. Returns the voltage in
+    /// millivolts.
+    pub fn measure_vdda(&mut self, vrefint: &mut Vrefint) -> u32 {
+        // The factory measurement is at a 12-bit resolution, with the
+        // sample time needed by the internal reference
+        let resolution = self.resolution;
+        self.set_resolution(Resolution::Twelve);
+        self.set_sample_time(vrefint, SampleTime::Cycles640_5);
+        let raw = self.read(vrefint);
+        self.set_resolution(resolution);
+
+        // NOTE(unsafe) The factory measurement is in read-only memory
+        let cal = unsafe { VREFINT_CAL.read_volatile() };
+        self.vdda = vdda_millivolts(cal, raw);
+        self.vdda
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prescaler() {
+        assert_eq!(prescaler(64_000_000, MAX_ADC_CLOCK), (0, 1));
+        assert_eq!(prescaler(250_000_000, MAX_ADC_CLOCK), (2, 4));
+        assert_eq!(prescaler(150_000_000, MAX_ADC_CLOCK), (1, 2));
+        assert_eq!(prescaler(400_000_000, MAX_ADC_CLOCK), (3, 6));
+    }
+
+    #[test]
+    fn test_millivolts() {
+        assert_eq!(millivolts(0xFFF, Resolution::Twelve, 3300), 3300);
+        assert_eq!(millivolts(0x800, Resolution::Twelve, 3300), 1650);
+        assert_eq!(millivolts(0x3F, Resolution::Six, 3000), 3000);
+        assert_eq!(vdda_millivolts(1500, 1500), 3300);
+        assert_eq!(vdda_millivolts(1500, 1650), 3000);
+    }
+}
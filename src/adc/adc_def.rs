@@ -0,0 +1,151 @@
+use super::{Channel, Instance};
+use crate::gpdma::periph::RxAddr;
+use crate::gpio::{self, Analog};
+#[cfg(feature = "rm0481")]
+use crate::stm32::ADC2;
+use crate::stm32::{adc1, ADC1};
+
+macro_rules! pins {
+    ($($ADCX:ty: [$($( #[ $pmeta:meta ] )* $PIN:ty => $ch:literal),*])+) => {
+        $(
+            $(
+                $( #[ $pmeta ] )*
+                impl Channel<$ADCX> for $PIN {
+                    const CHANNEL: u8 = $ch;
+                }
+            )*
+        )+
+    }
+}
+
+// The data register is read as a half-word, which holds the result of any
+// resolution
+macro_rules! instance {
+    ($ADCX:ident, $request:literal) => {
+        impl Instance for $ADCX {
+            const DMA_REQUEST: u8 = $request;
+
+            fn ptr() -> *const adc1::RegisterBlock {
+                <$ADCX>::ptr() as *const _
+            }
+        }
+
+        impl RxAddr<u16> for $ADCX {
+            unsafe fn rx_addr() -> *const u16 {
+                (*<$ADCX>::ptr()).dr().as_ptr() as *const u16
+            }
+        }
+
+        impl crate::Sealed for $ADCX {}
+    };
+}
+
+instance!(ADC1, 0);
+#[cfg(feature = "rm0481")]
+instance!(ADC2, 1);
+
+/// The internal reference voltage channel of ADC1
+#[derive(Debug)]
+pub struct Vrefint {
+    _private: (),
+}
+
+/// The temperature sensor channel of ADC1
+#[derive(Debug)]
+pub struct Temperature {
+    _private: (),
+}
+
+impl Vrefint {
+    pub(super) fn new() -> Self {
+        Vrefint { _private: () }
+    }
+}
+
+impl Temperature {
+    pub(super) fn new() -> Self {
+        Temperature { _private: () }
+    }
+}
+
+impl Channel<ADC1> for Temperature {
+    const CHANNEL: u8 = 16;
+}
+
+impl Channel<ADC1> for Vrefint {
+    const CHANNEL: u8 = 17;
+}
+
+/// Modify the enables of the internal channels in the common control
+/// register
+#[cfg(feature = "rm0492")]
+pub(super) fn set_internal_channels(
+    f: impl FnOnce(&mut adc1::ccr::W) -> &mut adc1::ccr::W,
+) {
+    // NOTE(unsafe) Only the enables of the internal channels are changed
+    unsafe { &*ADC1::ptr() }.ccr().modify(|_, w| f(w));
+}
+
+/// Modify the enables of the internal channels in the common control
+/// register
+#[cfg(feature = "rm0481")]
+pub(super) fn set_internal_channels(
+    f: impl FnOnce(
+        &mut crate::stm32::adcc::ccr::W,
+    ) -> &mut crate::stm32::adcc::ccr::W,
+) {
+    // NOTE(unsafe) Only the enables of the internal channels are changed
+    unsafe { &*crate::stm32::ADCC::ptr() }
+        .ccr()
+        .modify(|_, w| f(w));
+}
+
+// The same pins are on both ADCs, which is all of them on STM32H503
+pins! {
+    ADC1: [
+        gpio::PA0<Analog> => 0,
+        gpio::PA1<Analog> => 1,
+        gpio::PA6<Analog> => 3,
+        gpio::PC4<Analog> => 4,
+        gpio::PB1<Analog> => 5,
+        gpio::PA7<Analog> => 7,
+        gpio::PC5<Analog> => 8,
+        gpio::PB0<Analog> => 9,
+        gpio::PC0<Analog> => 10,
+        gpio::PC1<Analog> => 11,
+        gpio::PC2<Analog> => 12,
+        gpio::PC3<Analog> => 13,
+        gpio::PA2<Analog> => 14,
+        gpio::PA3<Analog> => 15,
+        gpio::PA4<Analog> => 18,
+        gpio::PA5<Analog> => 19
+    ]
+}
+
+#[cfg(feature = "rm0481")]
+pins! {
+    ADC1: [
+        gpio::PF11<Analog> => 2,
+        gpio::PF12<Analog> => 6
+    ]
+    ADC2: [
+        gpio::PA0<Analog> => 0,
+        gpio::PA1<Analog> => 1,
+        gpio::PF13<Analog> => 2,
+        gpio::PA6<Analog> => 3,
+        gpio::PC4<Analog> => 4,
+        gpio::PB1<Analog> => 5,
+        gpio::PF14<Analog> => 6,
+        gpio::PA7<Analog> => 7,
+        gpio::PC5<Analog> => 8,
+        gpio::PB0<Analog> => 9,
+        gpio::PC0<Analog> => 10,
+        gpio::PC1<Analog> => 11,
+        gpio::PC2<Analog> => 12,
+        gpio::PC3<Analog> => 13,
+        gpio::PA2<Analog> => 14,
+        gpio::PA3<Analog> => 15,
+        gpio::PA4<Analog> => 18,
+        gpio::PA5<Analog> => 19
+    ]
+}
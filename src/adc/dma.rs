@@ -0,0 +1,330 @@
+//! Regular sequences converted into a ring buffer by the GPDMA
+//!
+//! A [`Sequence`] of up to 16 channels is converted either continuously or
+//! on each external trigger, with the GPDMA transferring each result into a
+//! circular buffer. The application reads each half of the buffer while the
+//! GPDMA fills the other.
+
+use core::marker::PhantomData;
+
+use embedded_hal_nb::nb;
+
+use super::{Adc, Channel, Instance};
+use crate::gpdma::{
+    self,
+    circular::{CircularRx, Half},
+    config::PeripheralToMemory,
+    DmaChannel, DmaConfig,
+};
+
+/// Errors of conversions into a ring buffer
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Results were lost, either by the ADC because the GPDMA did not read
+    /// them in time, or in the buffer because a half was not read before it
+    /// was filled again
+    Overrun,
+    /// A GPDMA error
+    Dma(gpdma::Error),
+}
+
+impl From<gpdma::Error> for Error {
+    fn from(error: gpdma::Error) -> Self {
+        match error {
+            gpdma::Error::Overrun => Error::Overrun,
+            error => Error::Dma(error),
+        }
+    }
+}
+
+/// The external events that can trigger the conversion of a sequence
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExternalTrigger {
+    Tim1Cc1,
+    Tim1Cc2,
+    Tim1Cc3,
+    Tim2Cc2,
+    Tim3Trgo,
+    Exti11,
+    Tim1Trgo,
+    Tim1Trgo2,
+    Tim2Trgo,
+    Tim6Trgo,
+    Tim15Trgo,
+    Tim3Cc4,
+}
+
+impl ExternalTrigger {
+    /// The external trigger selection (EXTSEL) value
+    fn bits(self) -> u8 {
+        match self {
+            ExternalTrigger::Tim1Cc1 => 0,
+            ExternalTrigger::Tim1Cc2 => 1,
+            ExternalTrigger::Tim1Cc3 => 2,
+            ExternalTrigger::Tim2Cc2 => 3,
+            ExternalTrigger::Tim3Trgo => 4,
+            ExternalTrigger::Exti11 => 6,
+            ExternalTrigger::Tim1Trgo => 9,
+            ExternalTrigger::Tim1Trgo2 => 10,
+            ExternalTrigger::Tim2Trgo => 11,
+            ExternalTrigger::Tim6Trgo => 13,
+            ExternalTrigger::Tim15Trgo => 14,
+            ExternalTrigger::Tim3Cc4 => 15,
+        }
+    }
+}
+
+/// The edges of the external trigger that start a conversion
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerEdge {
+    Rising,
+    Falling,
+    Both,
+}
+
+impl TriggerEdge {
+    /// The external trigger enable (EXTEN) value
+    fn bits(self) -> u8 {
+        match self {
+            TriggerEdge::Rising => 0b01,
+            TriggerEdge::Falling => 0b10,
+            TriggerEdge::Both => 0b11,
+        }
+    }
+}
+
+/// How the conversions of a sequence are started
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SequenceMode {
+    /// Convert the sequence repeatedly, starting again as soon as it ends
+    Continuous,
+    /// Convert the sequence once on each edge of an external trigger, such
+    /// as the update event of a timer, which sets the sample rate
+    Triggered(ExternalTrigger, TriggerEdge),
+}
+
+/// A regular sequence of up to 16 channels of ADC `ADC`, which are
+/// converted in turn. A channel can be converted more than once.
+#[derive(Debug, Clone)]
+pub struct Sequence<ADC> {
+    _adc: PhantomData<ADC>,
+    ranks: [u8; 16],
+    len: usize,
+}
+
+impl<ADC> Default for Sequence<ADC> {
+    fn default() -> Self {
+        Sequence::new()
+    }
+}
+
+impl<ADC> Sequence<ADC> {
+    /// An empty sequence
+    pub fn new() -> Self {
+        Sequence {
+            _adc: PhantomData,
+            ranks: [0; 16],
+            len: 0,
+        }
+    }
+
+    /// Add `channel` at the end of the sequence. This will panic if the
+    /// sequence already has 16 ranks.
+    pub fn rank<CH: Channel<ADC>>(mut self, _channel: &CH) -> Self {
+        assert!(self.len < 16, "A sequence has at most 16 ranks");
+        self.ranks[self.len] = CH::CHANNEL;
+        self.len += 1;
+        self
+    }
+
+    /// The number of ranks
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the sequence has no ranks
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The values of SQR1 to SQR4 for a sequence of the channels `ranks`. SQR1
+/// holds the length and the first 4 ranks, and each of the others 5 ranks.
+fn sequence_registers(ranks: &[u8]) -> [u32; 4] {
+    let mut sqr = [0; 4];
+    sqr[0] = ranks.len() as u32 - 1;
+    for (i, &channel) in ranks.iter().enumerate() {
+        let rank = i + 1;
+        sqr[rank / 5] |= (channel as u32) << ((rank % 5) * 6);
+    }
+    sqr
+}
+
+/// An ADC converting a sequence into a ring buffer with a GPDMA channel
+pub struct AdcDma<ADC, CH: DmaChannel> {
+    adc: Adc<ADC>,
+    rx: CircularRx<ADC, u16, CH>,
+}
+
+impl<ADC: Instance> Adc<ADC> {
+    /// Convert `sequence` repeatedly into `buffer` with a GPDMA channel. The
+    /// conversions are started by [`AdcDma::start`].
+    ///
+    /// Each half of the buffer must hold whole sequences, so that each result
+    /// stays at the same position within a half. This will panic if it does
+    /// not, or if the sequence is empty.
+    pub fn with_dma<CH: DmaChannel>(
+        self,
+        channel: CH,
+        sequence: &Sequence<ADC>,
+        mode: SequenceMode,
+        buffer: &'static mut [u16],
+    ) -> AdcDma<ADC, CH> {
+        assert!(!sequence.is_empty(), "The sequence has no ranks");
+        assert!(
+            buffer.len().is_multiple_of(2 * sequence.len()),
+            "Each half of the buffer must hold whole sequences"
+        );
+
+        let adc = self.regs();
+        let [sqr1, sqr2, sqr3, sqr4] =
+            sequence_registers(&sequence.ranks[..sequence.len]);
+        // NOTE(unsafe) The channel numbers are valid
+        adc.sqr1().write(|w| unsafe { w.bits(sqr1) });
+        adc.sqr2().write(|w| unsafe { w.bits(sqr2) });
+        adc.sqr3().write(|w| unsafe { w.bits(sqr3) });
+        adc.sqr4().write(|w| unsafe { w.bits(sqr4) });
+
+        // The ADC raises a DMA request for each result, and keeps doing so
+        // until it is stopped
+        let (cont, exten, extsel) = match mode {
+            SequenceMode::Continuous => (true, 0, 0),
+            SequenceMode::Triggered(trigger, edge) => {
+                (false, edge.bits(), trigger.bits())
+            }
+        };
+        // NOTE(unsafe) The trigger selection is valid
+        adc.cfgr().modify(|_, w| unsafe {
+            w.dmaen()
+                .enabled()
+                .dmacfg()
+                .circular()
+                .cont()
+                .bit(cont)
+                .exten()
+                .set(exten)
+                .extsel()
+                .bits(extsel)
+        });
+
+        let config: DmaConfig<PeripheralToMemory, u16, u16> =
+            DmaConfig::new().with_request(ADC::DMA_REQUEST);
+        AdcDma {
+            adc: self,
+            rx: CircularRx::new(channel, config, buffer),
+        }
+    }
+
+    /// Stop any ongoing conversion
+    fn stop_conversions(&mut self) {
+        let adc = self.regs();
+        if adc.cr().read().adstart().bit_is_set() {
+            adc.cr().modify(|_, w| w.adstp().set_bit());
+            while adc.cr().read().adstp().bit_is_set() {}
+        }
+    }
+}
+
+impl<ADC: Instance, CH: DmaChannel> AdcDma<ADC, CH> {
+    /// Start converting the sequence from the beginning of the buffer. In
+    /// triggered mode, this arms the trigger.
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.stop();
+        self.rx.start()?;
+        let adc = self.adc.regs();
+        adc.isr()
+            .write(|w| w.ovr().clear().eoc().clear().eos().clear());
+        adc.cr().modify(|_, w| w.adstart().set_bit());
+        Ok(())
+    }
+
+    /// Stop the conversions and the GPDMA transfer
+    pub fn stop(&mut self) {
+        self.adc.stop_conversions();
+        self.rx.stop();
+    }
+
+    /// Enable the GPDMA interrupts raised as each half of the buffer is
+    /// filled, from whose handler [`read_half`](Self::read_half) is called
+    pub fn enable_interrupts(&mut self) {
+        self.rx.enable_interrupts();
+    }
+
+    /// Disable the GPDMA interrupts
+    pub fn disable_interrupts(&mut self) {
+        self.rx.disable_interrupts();
+    }
+
+    /// Call `f` with the half of the buffer that was last filled, if it has
+    /// not already been read, and return its result. The results are in the
+    /// order of the sequence, repeated to fill the half.
+    ///
+    /// [`Error::Overrun`] is returned if results were lost, in which case the
+    /// conversions must be restarted.
+    pub fn read_half<R>(
+        &mut self,
+        f: impl FnOnce(Half, &[u16]) -> R,
+    ) -> nb::Result<R, Error> {
+        if self.adc.regs().isr().read().ovr().bit_is_set() {
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+        self.rx.read_half(f).map_err(|e| e.map(Error::from))
+    }
+
+    /// The ADC, to convert values to millivolts
+    pub fn adc(&self) -> &Adc<ADC> {
+        &self.adc
+    }
+
+    /// Stop the conversions, and release the ADC, the GPDMA channel and the
+    /// buffer. The channel is returned reset and unlinked from the buffer.
+    pub fn free(mut self) -> (Adc<ADC>, CH, &'static mut [u16]) {
+        self.stop();
+        self.adc.regs().cfgr().modify(|_, w| {
+            w.dmaen()
+                .disabled()
+                .dmacfg()
+                .one_shot()
+                .cont()
+                .clear_bit()
+                .exten()
+                .set(0)
+        });
+        let (channel, buffer) = self.rx.free();
+        (self.adc, channel, buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_registers() {
+        assert_eq!(sequence_registers(&[5]), [5 << 6, 0, 0, 0]);
+        let ranks: [u8; 16] = core::array::from_fn(|i| i as u8 + 1);
+        assert_eq!(
+            sequence_registers(&ranks),
+            [
+                15 | 1 << 6 | 2 << 12 | 3 << 18 | 4 << 24,
+                5 | 6 << 6 | 7 << 12 | 8 << 18 | 9 << 24,
+                10 | 11 << 6 | 12 << 12 | 13 << 18 | 14 << 24,
+                15 | 16 << 6,
+            ]
+        );
+    }
+}
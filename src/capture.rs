@@ -0,0 +1,617 @@
+//! Input capture
+//!
+//! This module provides input capture on the capture/compare channels of
+//! the general-purpose and advanced-control timers, which records the
+//! value of the counter when an edge is detected on the channel input, and
+//! PWM-input mode, which measures the period and pulse width of a PWM
+//! signal in hardware.
+//!
+//! # Input capture
+//!
+//! A timer is configured for input capture from the device peripheral, a
+//! counter frequency (or a [`Config`]) and the peripheral's reset and
+//! enable control. The counter frequency sets the resolution of the
+//! captures. This returns a [`Capture`] that controls the counter, and the
+//! timer's [`Channels`]. Each channel is turned into a [`CaptureChannel`]
+//! by binding a pin to it:
+//!
+//! ```
+//! use stm32h5xx_hal::capture::{Edge, InputConfig, Prescaler};
+//!
+//! let (capture, channels) = dp.TIM2.capture(1.MHz(), ccdr.peripheral.TIM2, &ccdr.clocks);
+//!
+//! let mut tacho = channels.ch1.capture(
+//!     pa0.into_alternate(),
+//!     InputConfig::new(Edge::Rising).filter(4).prescaler(Prescaler::Div2),
+//! );
+//!
+//! let first = block!(tacho.read())?;
+//! let second = block!(tacho.read())?;
+//! let period = capture.duration(capture.elapsed(first, second));
+//! ```
+//!
+//! If an edge is captured before the previous capture was read, the
+//! previous capture is lost and [`Error::Overcapture`] is returned.
+//!
+//! # PWM input
+//!
+//! In PWM-input mode, one pin is captured by channels 1 and 2: one channel
+//! captures the period on the active edge, which also resets the counter,
+//! and the other captures the pulse width on the opposite edge. Only pins
+//! on channel 1 or 2 can be used, of the timers with a slave mode
+//! controller to reset the counter ([`SlaveInstance`]).
+//!
+//! [`SlaveInstance`]: crate::timer::SlaveInstance
+//!
+//! ```
+//! let mut input = dp.TIM3.pwm_input(pa6.into_alternate(), 1.MHz(), ccdr.peripheral.TIM3, &ccdr.clocks);
+//!
+//! let measurement = block!(input.read())?;
+//! info!("period: {}, duty: {}", measurement.period, measurement.duty);
+//! ```
+//!
+//! Periods longer than the counter overflow period, which depends on the
+//! counter frequency and whether the timer is 16 or 32-bit, cannot be
+//! measured.
+
+use core::marker::PhantomData;
+
+use embedded_hal_nb::nb;
+
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::tim2;
+use crate::time::{Hertz, NanoSeconds};
+use crate::timer::sync::{master_methods, slave_methods};
+use crate::timer::{
+    clear_pending, counter_prescaler, dma_methods, is_pending, set_interrupt,
+    Channel, Channels, DmaInstance, Event, Instance, MasterInstance, PinCh,
+    SlaveInstance,
+};
+
+/// Input capture error
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// An edge was captured before the previous capture was read, which
+    /// was lost
+    Overcapture,
+}
+
+/// The input edge that is captured
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Number of edges that need to be detected for each capture
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Prescaler {
+    Div1,
+    Div2,
+    Div4,
+    Div8,
+}
+
+/// Timer configuration for input capture
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    frequency: Hertz,
+}
+
+impl Config {
+    /// Count at `frequency`, which sets the resolution of the captures
+    pub fn new(frequency: Hertz) -> Self {
+        Config { frequency }
+    }
+}
+
+impl From<Hertz> for Config {
+    fn from(frequency: Hertz) -> Self {
+        Config::new(frequency)
+    }
+}
+
+/// Configuration of a capture channel input
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InputConfig {
+    edge: Edge,
+    filter: u8,
+    prescaler: Prescaler,
+}
+
+impl InputConfig {
+    /// Capture on each `edge`, without filtering
+    pub fn new(edge: Edge) -> Self {
+        InputConfig {
+            edge,
+            filter: 0,
+            prescaler: Prescaler::Div1,
+        }
+    }
+
+    /// Set the digital filter of the input (ICxF), from 0 (no filter) to
+    /// 15. Higher values need the input to be stable for more samples, at
+    /// a lower sampling frequency, before an edge is detected.
+    pub fn filter(mut self, filter: u8) -> Self {
+        assert!(filter <= 15, "The input filter is at most 15");
+        self.filter = filter;
+        self
+    }
+
+    /// Only capture every 2, 4 or 8 edges
+    pub fn prescaler(mut self, prescaler: Prescaler) -> Self {
+        self.prescaler = prescaler;
+        self
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig::new(Edge::Rising)
+    }
+}
+
+impl From<Edge> for InputConfig {
+    fn from(edge: Edge) -> Self {
+        InputConfig::new(edge)
+    }
+}
+
+/// A timer configured for input capture, which controls the counter shared
+/// by all of its channels
+#[derive(Debug)]
+pub struct Capture<TIM> {
+    tim: TIM,
+    frequency: Hertz,
+}
+
+/// A capture/compare channel of a timer configured for input capture
+#[derive(Debug)]
+pub struct CaptureChannel<TIM, const C: u8> {
+    _tim: PhantomData<TIM>,
+}
+
+pub trait CaptureExt<TIM: Instance>: Sized {
+    fn capture<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (Capture<TIM>, Channels<TIM>)
+    where
+        CONFIG: Into<Config>;
+}
+
+impl<TIM: Instance> CaptureExt<TIM> for TIM {
+    fn capture<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (Capture<TIM>, Channels<TIM>)
+    where
+        CONFIG: Into<Config>,
+    {
+        (Capture::new(self, config, rec, clocks), Channels::new())
+    }
+}
+
+#[inline(always)]
+fn regs<TIM: Instance>() -> &'static tim2::RegisterBlock {
+    // NOTE(unsafe) The registers used here are at the same offsets in all
+    // timers
+    unsafe { &*TIM::ptr() }
+}
+
+/// Convert `ticks` of a counter running at `frequency` to nanoseconds,
+/// saturating if it does not fit
+fn ticks_to_nanos(ticks: u32, frequency: Hertz) -> NanoSeconds {
+    let nanos = ticks as u64 * 1_000_000_000 / frequency.raw() as u64;
+    NanoSeconds::from_ticks(nanos.min(u32::MAX as u64) as u32)
+}
+
+/// Reset and enable the timer, and set its counter running at `frequency`
+/// up to its maximum value. Returns the actual counter frequency.
+fn init_counter<TIM: Instance>(
+    rec: TIM::Rec,
+    clocks: &CoreClocks,
+    frequency: Hertz,
+) -> Hertz {
+    let _ = rec.enable().reset();
+
+    let clock = TIM::clock(clocks);
+    let psc = counter_prescaler(clock, frequency);
+
+    let tim = regs::<TIM>();
+    tim.psc().write(|w| w.psc().set((psc - 1) as u16));
+    tim.arr().write(|w| w.arr().set(TIM::MAX_ARR));
+    // Only counter overflows generate an update event
+    tim.cr1().write(|w| w.urs().set_bit());
+    tim.egr().write(|w| w.ug().set_bit());
+
+    Hertz::from_raw(clock.raw() / psc)
+}
+
+/// Configure channel `c` as an input from TI`ti`, which is either its own
+/// input or that of the other channel in the same pair
+fn set_input<TIM: Instance>(c: u8, ti: u8, config: &InputConfig) {
+    let tim = regs::<TIM>();
+    let n = c - 1;
+    let direct = c == ti;
+    let psc = match config.prescaler {
+        Prescaler::Div1 => 0,
+        Prescaler::Div2 => 1,
+        Prescaler::Div4 => 2,
+        Prescaler::Div8 => 3,
+    };
+
+    // The channel selection can only be changed while the channel is
+    // disabled
+    tim.ccer().modify(|_, w| w.cce(n).clear_bit());
+    match c {
+        1 => tim.ccmr1_input().modify(|_, w| {
+            let w = if direct {
+                w.cc1s().ti1()
+            } else {
+                w.cc1s().ti2()
+            };
+            w.icf(0).set(config.filter).icpsc(0).set(psc)
+        }),
+        2 => tim.ccmr1_input().modify(|_, w| {
+            let w = if direct {
+                w.cc2s().ti2()
+            } else {
+                w.cc2s().ti1()
+            };
+            w.icf(1).set(config.filter).icpsc(1).set(psc)
+        }),
+        3 => tim.ccmr2_input().modify(|_, w| {
+            let w = if direct {
+                w.cc3s().ti3()
+            } else {
+                w.cc3s().ti4()
+            };
+            w.icf(0).set(config.filter).icpsc(0).set(psc)
+        }),
+        _ => tim.ccmr2_input().modify(|_, w| {
+            let w = if direct {
+                w.cc4s().ti4()
+            } else {
+                w.cc4s().ti3()
+            };
+            w.icf(1).set(config.filter).icpsc(1).set(psc)
+        }),
+    };
+
+    // CCxNP:CCxP selects the edge
+    let (ccnp, ccp) = match config.edge {
+        Edge::Rising => (false, false),
+        Edge::Falling => (false, true),
+        Edge::Both => (true, true),
+    };
+    tim.ccer()
+        .modify(|_, w| w.ccp(n).bit(ccp).ccnp(n).bit(ccnp).cce(n).set_bit());
+}
+
+/// Read the capture of channel `c`, if there is one
+fn read_capture<TIM: Instance>(c: u8) -> nb::Result<u32, Error> {
+    let tim = regs::<TIM>();
+    let n = c - 1;
+    let sr = tim.sr().read();
+
+    if sr.ccof(n).bit_is_set() {
+        // Discard the capture that the lost one was overwritten by, so
+        // that the next one is consistent
+        let _ = tim.ccr(n as usize).read();
+        // NOTE(unsafe) The flags are cleared by writing zero, so writing
+        // ones to the other bits leaves them unchanged
+        let mask = 1 << (9 + n);
+        tim.sr().write(|w| unsafe { w.bits(!mask) });
+        Err(nb::Error::Other(Error::Overcapture))
+    } else if sr.ccif(n).bit_is_set() {
+        // Reading the capture clears the flag
+        Ok(tim.ccr(n as usize).read().ccr().bits())
+    } else {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+impl<TIM: Instance> Capture<TIM> {
+    fn new(
+        tim: TIM,
+        config: impl Into<Config>,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let config: Config = config.into();
+        let frequency = init_counter::<TIM>(rec, clocks, config.frequency);
+        clear_pending::<TIM>(Event::Update);
+        regs::<TIM>().cr1().modify(|_, w| w.cen().enabled());
+
+        Capture { tim, frequency }
+    }
+
+    /// The counter frequency, in which captures are measured
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// The current value of the counter
+    pub fn counter(&self) -> u32 {
+        regs::<TIM>().cnt().read().cnt().bits()
+    }
+
+    /// The number of counter ticks from capture `earlier` to capture
+    /// `later`, assuming the counter has wrapped at most once in between
+    pub fn elapsed(&self, earlier: u32, later: u32) -> u32 {
+        later.wrapping_sub(earlier) & TIM::MAX_ARR
+    }
+
+    /// Convert a number of counter ticks to a duration
+    pub fn duration(&self, ticks: u32) -> NanoSeconds {
+        ticks_to_nanos(ticks, self.frequency)
+    }
+
+    /// Stop the counter
+    pub fn pause(&mut self) {
+        regs::<TIM>().cr1().modify(|_, w| w.cen().disabled());
+    }
+
+    /// Restart the counter after it was paused
+    pub fn resume(&mut self) {
+        regs::<TIM>().cr1().modify(|_, w| w.cen().enabled());
+    }
+
+    /// Start listening for `event`. [`Event::CaptureCompare1`] to
+    /// [`Event::CaptureCompare4`] are generated on each capture.
+    pub fn listen(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, true);
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, false);
+    }
+
+    /// Whether `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        is_pending::<TIM>(event)
+    }
+
+    /// Clear the pending flag of `event`
+    pub fn clear_pending(&mut self, event: Event) {
+        clear_pending::<TIM>(event);
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> TIM {
+        let _ = TIM::rec().reset().disable();
+        self.tim
+    }
+}
+
+impl<TIM: Instance + MasterInstance> Capture<TIM> {
+    master_methods!();
+}
+
+impl<TIM: Instance> Capture<TIM> {
+    slave_methods!();
+}
+
+impl<TIM: DmaInstance> Capture<TIM> {
+    dma_methods!(capture);
+}
+
+impl<TIM: Instance, const C: u8> Channel<TIM, C> {
+    /// Use this channel to capture the counter on edges of `pin`
+    pub fn capture<PIN>(
+        self,
+        _pin: PIN,
+        config: impl Into<InputConfig>,
+    ) -> CaptureChannel<TIM, C>
+    where
+        PIN: PinCh<TIM, C>,
+    {
+        assert!(C <= TIM::CHANNELS, "The timer does not have this channel");
+        set_input::<TIM>(C, C, &config.into());
+        CaptureChannel { _tim: PhantomData }
+    }
+}
+
+impl<TIM: Instance, const C: u8> CaptureChannel<TIM, C> {
+    /// Read the last capture, returning `WouldBlock` if there has been no
+    /// new capture since the last read
+    pub fn read(&mut self) -> nb::Result<u32, Error> {
+        read_capture::<TIM>(C)
+    }
+
+    /// Change the input configuration
+    pub fn set_config(&mut self, config: impl Into<InputConfig>) {
+        set_input::<TIM>(C, C, &config.into());
+    }
+
+    /// Stop capturing, and return the channel
+    pub fn release(self) -> Channel<TIM, C> {
+        regs::<TIM>().ccer().modify(|_, w| w.cce(C - 1).clear_bit());
+        Channel::new()
+    }
+}
+
+/// A period and pulse width measured in PWM-input mode
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement {
+    /// The time between two rising edges
+    pub period: NanoSeconds,
+    /// The time between a rising edge and the next falling edge
+    pub duty: NanoSeconds,
+}
+
+/// A timer configured in PWM-input mode, measuring the signal on one pin
+/// with channels 1 and 2
+#[derive(Debug)]
+pub struct PwmInput<TIM> {
+    tim: TIM,
+    frequency: Hertz,
+    // The channel (0 or 1) that captures the period
+    period_ch: u8,
+}
+
+pub trait PwmInputExt<TIM: SlaveInstance>: Sized {
+    /// Measure the PWM signal on `pin`, which is on channel 1 or 2
+    fn pwm_input<PIN, CONFIG, const C: u8>(
+        self,
+        pin: PIN,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> PwmInput<TIM>
+    where
+        PIN: PinCh<TIM, C>,
+        CONFIG: Into<Config>;
+}
+
+impl<TIM: SlaveInstance> PwmInputExt<TIM> for TIM {
+    fn pwm_input<PIN, CONFIG, const C: u8>(
+        self,
+        _pin: PIN,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> PwmInput<TIM>
+    where
+        PIN: PinCh<TIM, C>,
+        CONFIG: Into<Config>,
+    {
+        PwmInput::new(self, C, config, rec, clocks)
+    }
+}
+
+impl<TIM: SlaveInstance> PwmInput<TIM> {
+    fn new(
+        tim: TIM,
+        c: u8,
+        config: impl Into<Config>,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        assert!(c <= 2, "PWM input is only available on channels 1 and 2");
+        let config: Config = config.into();
+        let frequency = init_counter::<TIM>(rec, clocks, config.frequency);
+
+        // The channel of the pin captures the period on rising edges, and
+        // the other channel the pulse width on falling edges
+        let other = 3 - c;
+        set_input::<TIM>(c, c, &InputConfig::new(Edge::Rising));
+        set_input::<TIM>(other, c, &InputConfig::new(Edge::Falling));
+
+        // Rising edges of the filtered input (TI1FP1 or TI2FP2) reset the
+        // counter
+        let ts = if c == 1 { 0b101 } else { 0b110 };
+        regs::<TIM>()
+            .smcr()
+            .modify(|_, w| w.ts().set(ts).ts2().set(0).sms().set(0b100));
+
+        clear_pending::<TIM>(Event::Update);
+        regs::<TIM>().cr1().modify(|_, w| w.cen().enabled());
+
+        PwmInput {
+            tim,
+            frequency,
+            period_ch: c - 1,
+        }
+    }
+
+    /// The counter frequency, which sets the resolution of the measurements
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Set the digital filter of the input, from 0 (no filter) to 15
+    pub fn set_filter(&mut self, filter: u8) {
+        assert!(filter <= 15, "The input filter is at most 15");
+        let c = self.period_ch + 1;
+        set_input::<TIM>(c, c, &InputConfig::new(Edge::Rising).filter(filter));
+        set_input::<TIM>(
+            3 - c,
+            c,
+            &InputConfig::new(Edge::Falling).filter(filter),
+        );
+    }
+
+    /// Read the last measured period and pulse width, returning
+    /// `WouldBlock` if no period has completed since the last read
+    pub fn read(&mut self) -> nb::Result<Measurement, Error> {
+        let pulse_ch = 1 - self.period_ch;
+        // The pulse width of a period is captured before the period ends,
+        // so it is read first to get a matching pair
+        let duty = regs::<TIM>().ccr(pulse_ch as usize).read().ccr().bits();
+        let period = read_capture::<TIM>(self.period_ch + 1)?;
+
+        Ok(Measurement {
+            period: ticks_to_nanos(period, self.frequency),
+            duty: ticks_to_nanos(duty.min(period), self.frequency),
+        })
+    }
+
+    /// Start listening for `event`. The event of the channel of the pin,
+    /// [`Event::CaptureCompare1`] or [`Event::CaptureCompare2`], is
+    /// generated at the end of each period, and [`Event::Update`] when no
+    /// period has completed before the counter overflowed.
+    pub fn listen(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, true);
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, false);
+    }
+
+    /// Whether `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        is_pending::<TIM>(event)
+    }
+
+    /// Clear the pending flag of `event`
+    pub fn clear_pending(&mut self, event: Event) {
+        clear_pending::<TIM>(event);
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> TIM {
+        let _ = TIM::rec().reset().disable();
+        self.tim
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticks_to_nanos() {
+        let mhz = Hertz::from_raw(1_000_000);
+        assert_eq!(ticks_to_nanos(0, mhz), NanoSeconds::from_ticks(0));
+        assert_eq!(
+            ticks_to_nanos(1500, mhz),
+            NanoSeconds::from_ticks(1_500_000)
+        );
+        // 3 ticks at 250 MHz is 12 ns
+        assert_eq!(
+            ticks_to_nanos(3, Hertz::from_raw(250_000_000)),
+            NanoSeconds::from_ticks(12)
+        );
+        // Longer than u32::MAX nanoseconds saturates
+        assert_eq!(
+            ticks_to_nanos(u32::MAX, mhz),
+            NanoSeconds::from_ticks(u32::MAX)
+        );
+    }
+}
@@ -62,6 +62,11 @@
 //! These work similarly to the peripheral to memory transfers, but the peripheral driving the
 //! request must be identified via the typing of the TransferType implementation.
 //!
+//! ## Circular transfers
+//!
+//! A peripheral can also fill a ring buffer indefinitely, with the GPDMA linking each block back
+//! to the start of the buffer. See the [`circular`] module.
+//!
 //! ## Data transforms
 //!
 //! The GPDMA provides a data transformation pipeline which facilitates transforms for transfers
@@ -89,6 +94,7 @@ use core::{
 use embedded_dma::{ReadBuffer, Word as DmaWord, WriteBuffer};
 
 mod ch;
+pub mod circular;
 pub mod config;
 #[cfg(feature = "gpdma-futures")]
 mod future;
@@ -136,6 +142,9 @@ pub enum Error {
 
     /// Resume was called on a channel that was not previously suspended
     NotSuspendedError,
+
+    /// The GPDMA filled a half of the buffer of a circular transfer again before it was read
+    Overrun,
 }
 
 pub trait GpdmaExt<DMA: Instance> {
@@ -503,6 +512,22 @@ where
         self.channel.is_running()
     }
 
+    /// Number of bytes remaining to be read from the source. For transfers that are ended early
+    /// by the peripheral (eg. on a USART idle line) this can be used to determine how much data was
+    /// transferred.
+    pub fn remaining_transfer_size_bytes(&self) -> usize {
+        self.channel.remaining_transfer_size_bytes()
+    }
+
+    /// Suspend a transfer once all data read from the source has been written to the destination.
+    /// This is used to stop a transfer early without losing any data held in the channel FIFO.
+    pub fn suspend_after_fifo_drained(&mut self) {
+        while self.channel.is_running() && !self.channel.is_fifo_empty() {}
+        if self.channel.is_running() {
+            self.suspend();
+        }
+    }
+
     /// Blocks waiting for a transfer to complete. Returns an error if one occurred during the
     /// transfer.
     pub fn wait_for_transfer_complete(&mut self) -> Result<(), Error> {
@@ -1,9 +1,14 @@
-use core::{marker::PhantomData, ops::Deref};
+use core::{
+    marker::PhantomData,
+    ops::Deref,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use crate::stm32::gpdma1::{
     self,
     ch::{CR, DAR, FCR, LBAR, SAR, SR, TR1, TR2},
 };
+use crate::stm32::GPDMA1;
 use crate::Sealed;
 
 use super::{
@@ -16,7 +21,6 @@ use super::{
 };
 
 pub(super) trait ChannelRegs: Sealed {
-    #[allow(unused)] // TODO: this will be used for linked-list transfers
     fn lbar(&self) -> &LBAR;
     fn fcr(&self) -> &FCR;
     fn sr(&self) -> &SR;
@@ -26,6 +30,12 @@ pub(super) trait ChannelRegs: Sealed {
     fn sar(&self) -> &SAR;
     fn dar(&self) -> &DAR;
     fn set_block_size(&self, size: u16);
+    fn block_size(&self) -> u16;
+    /// Link to the item at the low 16 bits of `addr`, which reloads the destination address and
+    /// the link itself. Returns the value of the link register.
+    fn set_destination_link(&self, addr: u16) -> u32;
+    /// Clear the link to the next item, so that the channel stops at the end of the block
+    fn clear_link(&self);
 }
 
 impl Sealed for gpdma1::CH {}
@@ -59,6 +69,18 @@ impl ChannelRegs for gpdma1::CH {
     fn set_block_size(&self, size: u16) {
         self.br1().modify(|_, w| w.bndt().set(size));
     }
+    fn block_size(&self) -> u16 {
+        self.br1().read().bndt().bits()
+    }
+    fn set_destination_link(&self, addr: u16) -> u32 {
+        self.llr()
+            .write(|w| w.la().set(addr >> 2).uda().set_bit().ull().set_bit());
+        self.llr().read().bits()
+    }
+    fn clear_link(&self) {
+        self.llr().reset();
+        self.lbar().reset();
+    }
 }
 
 impl ChannelRegs for gpdma1::CH2D {
@@ -89,6 +111,18 @@ impl ChannelRegs for gpdma1::CH2D {
     fn set_block_size(&self, size: u16) {
         self.br1().modify(|_, w| w.bndt().set(size));
     }
+    fn block_size(&self) -> u16 {
+        self.br1().read().bndt().bits()
+    }
+    fn set_destination_link(&self, addr: u16) -> u32 {
+        self.llr()
+            .write(|w| w.la().set(addr >> 2).uda().set_bit().ull().set_bit());
+        self.llr().read().bits()
+    }
+    fn clear_link(&self) {
+        self.llr().reset();
+        self.lbar().reset();
+    }
 }
 
 /// DmaChannelRef provides access to individual channels of the GPDMA instance via Deref.
@@ -141,6 +175,29 @@ where
 
 impl<DMA, CH, const N: usize> Sealed for DmaChannelRef<DMA, CH, N> {}
 
+/// Linked-list item of a circular transfer, which reloads the destination address and links back
+/// to itself at the end of each block. The block size is restored by the channel itself.
+#[repr(C)]
+struct CircularLink {
+    dar: AtomicU32,
+    llr: AtomicU32,
+}
+
+impl CircularLink {
+    const fn new() -> Self {
+        Self {
+            dar: AtomicU32::new(0),
+            llr: AtomicU32::new(0),
+        }
+    }
+}
+
+// One linked-list item for each channel of each GPDMA peripheral. The items must be in memory
+// that the GPDMA can access, and are only written by the owner of the channel before the
+// channel is enabled.
+static CIRCULAR_LINKS: [[CircularLink; 8]; 2] =
+    [const { [const { CircularLink::new() }; 8] }; 2];
+
 /// Non-error transfer event, including transfer complete and half-transfer events. Half-transfer
 /// events can be used for double-buffering/linked buffer transfers.
 pub enum TransferEvent {
@@ -241,8 +298,6 @@ where
         });
     }
 
-    // TODO: Remove clippy allow when FIFO use is implemented
-    #[allow(unused)]
     #[inline(always)]
     fn fifo_level(&self) -> u8 {
         self.sr().read().fifol().bits()
@@ -434,6 +489,23 @@ where
             }
         });
     }
+
+    #[inline(always)]
+    fn circular_link(&self) -> &'static CircularLink {
+        let dma = if DMA::ptr() == GPDMA1::ptr() { 0 } else { 1 };
+        &CIRCULAR_LINKS[dma][N]
+    }
+
+    #[inline(always)]
+    fn set_half_transfer_interrupt(&self, enable: bool) {
+        self.cr().modify(|_, w| {
+            if enable {
+                w.htie().enabled()
+            } else {
+                w.htie().disabled()
+            }
+        });
+    }
 }
 
 /// The Channel trait is a private trait that abstracts over control of the linear and 2D channels.
@@ -466,7 +538,8 @@ pub(super) trait Channel {
 
     fn is_running(&self) -> bool;
 
-    /// Reset the channel registers so it can be reused.
+    /// Reset the channel registers so it can be reused. This also clears the link to a
+    /// linked-list item, which the channel reset does not.
     fn reset_channel(&mut self);
 
     /// Suspend the transfer and blocks until it has been suspended. Reports any that occur while
@@ -528,6 +601,12 @@ pub(super) trait Channel {
     /// must be aligned with the source data width.
     fn set_transfer_size_bytes(&mut self, size: usize);
 
+    /// Get the number of bytes remaining to be read from the source in the current block
+    fn remaining_transfer_size_bytes(&self) -> usize;
+
+    /// Checks whether the channel FIFO has been drained to the destination
+    fn is_fifo_empty(&self) -> bool;
+
     /// Enable transfer interrupts for the channel. This enables the transfer complete,
     /// half-transfer complete, data transfer error and user setting error interrupts. This is
     /// useful for starting a transfer that will be monitored by an interrupt handler.
@@ -536,6 +615,20 @@ pub(super) trait Channel {
     /// Disable transfer interrupts for the channel. It is expected that this will be called from
     /// an interrupt handler after a transfer is completed.
     fn disable_transfer_interrupts(&mut self);
+
+    /// Clear the flag of a transfer event
+    fn clear_transfer_event(&mut self, event: TransferEvent);
+
+    /// Make the transfer circular, by linking each block back to the destination address that is
+    /// currently set. Must be called after the destination and transfer size are set.
+    fn set_circular(&mut self);
+
+    /// Enable the half transfer complete interrupt in addition to the transfer interrupts, for
+    /// circular transfers.
+    fn enable_circular_interrupts(&mut self);
+
+    /// Disable the interrupts enabled by `enable_circular_interrupts`.
+    fn disable_circular_interrupts(&mut self);
 }
 
 impl<DMA, CH, const N: usize> Channel for DmaChannelRef<DMA, CH, N>
@@ -592,6 +685,7 @@ where
 
     fn reset_channel(&mut self) {
         self.reset();
+        self.clear_link();
         self.clear_all_event_flags();
     }
 
@@ -726,6 +820,14 @@ where
         self.set_block_size(size as u16);
     }
 
+    fn remaining_transfer_size_bytes(&self) -> usize {
+        self.block_size() as usize
+    }
+
+    fn is_fifo_empty(&self) -> bool {
+        self.fifo_level() == 0
+    }
+
     #[inline(always)]
     fn enable_transfer_interrupts(&mut self) {
         self.cr().modify(|_, w| {
@@ -739,6 +841,34 @@ where
             w.tcie().disabled().dteie().disabled().useie().disabled()
         });
     }
+
+    fn clear_transfer_event(&mut self, event: TransferEvent) {
+        self.clear_transfer_event_flag(event);
+    }
+
+    fn set_circular(&mut self) {
+        let link = self.circular_link();
+        let addr = link as *const CircularLink as u32;
+
+        // The link reloads the destination address and itself, while the block size is restored
+        // to its programmed value at the end of each block
+        self.lbar().write(|w| w.lba().set((addr >> 16) as u16));
+        let llr = self.set_destination_link(addr as u16);
+        link.dar.store(self.dar().read().bits(), Ordering::Relaxed);
+        link.llr.store(llr, Ordering::Relaxed);
+    }
+
+    fn enable_circular_interrupts(&mut self) {
+        self.enable_transfer_interrupts();
+        self.cr().modify(|_, w| w.uleie().enabled());
+        self.set_half_transfer_interrupt(true);
+    }
+
+    fn disable_circular_interrupts(&mut self) {
+        self.disable_transfer_interrupts();
+        self.cr().modify(|_, w| w.uleie().disabled());
+        self.set_half_transfer_interrupt(false);
+    }
 }
 
 #[cfg(feature = "gpdma-futures")]
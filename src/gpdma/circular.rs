@@ -0,0 +1,201 @@
+//! Circular peripheral-to-memory transfers into a ring buffer
+//!
+//! A [`CircularRx`] transfers data from a peripheral into a buffer indefinitely, starting over
+//! from the beginning of the buffer once it is full. The half transfer and transfer complete
+//! events mark the first and second halves of the buffer as filled, so that the application can
+//! process one half while the GPDMA fills the other:
+//!
+//! ```
+//! static mut BUFFER: [u16; 256] = [0; 256];
+//!
+//! let config = DmaConfig::new().with_request(request);
+//! let mut rx = CircularRx::<ADC1, u16, _>::new(channels.0, config, unsafe { &mut *addr_of_mut!(BUFFER) });
+//! rx.start()?;
+//!
+//! loop {
+//!     match rx.read_half(|half, samples| process(half, samples)) {
+//!         Ok(_) | Err(nb::Error::WouldBlock) => {}
+//!         Err(nb::Error::Other(error)) => handle(error),
+//!     }
+//! }
+//! ```
+//!
+//! [`CircularRx::read_half`] can also be called from the interrupt handler of the channel, with
+//! the interrupts enabled by [`CircularRx::enable_interrupts`]. This is not possible with the
+//! `gpdma-futures` feature, which defines the interrupt handlers of the channels.
+
+use core::{
+    marker::PhantomData,
+    sync::atomic::{fence, Ordering},
+};
+
+use embedded_hal_nb::nb;
+
+use super::{
+    ch::TransferEvent, config::PeripheralToMemory, periph::RxAddr, DmaChannel,
+    DmaConfig, Error, Word,
+};
+
+/// One of the halves of the buffer of a circular transfer
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Half {
+    First,
+    Second,
+}
+
+/// A circular transfer from a peripheral register into a ring buffer
+pub struct CircularRx<PERIPH, W: 'static, CH> {
+    _periph: PhantomData<PERIPH>,
+    channel: CH,
+    config: DmaConfig<PeripheralToMemory, W, W>,
+    buffer: &'static mut [W],
+}
+
+impl<PERIPH, W, CH> CircularRx<PERIPH, W, CH>
+where
+    PERIPH: RxAddr<W>,
+    W: Word,
+    CH: DmaChannel,
+{
+    /// Create a circular transfer from the peripheral into `buffer`, which must have an even
+    /// length and at most `u16::MAX` bytes. The transfer is started by [`start`](Self::start).
+    pub fn new(
+        channel: CH,
+        config: DmaConfig<PeripheralToMemory, W, W>,
+        buffer: &'static mut [W],
+    ) -> Self {
+        let size = core::mem::size_of_val(buffer);
+        assert!(
+            !buffer.is_empty() && buffer.len().is_multiple_of(2),
+            "The buffer must have an even length"
+        );
+        assert!(size <= u16::MAX as usize, "Max block size is {}", u16::MAX);
+
+        Self {
+            _periph: PhantomData,
+            channel,
+            config,
+            buffer,
+        }
+    }
+
+    /// Start the transfer from the beginning of the buffer, and block waiting for it to start.
+    /// Returns an error if one occurred starting the transfer.
+    pub fn start(&mut self) -> Result<(), Error> {
+        let channel = &mut self.channel;
+        channel.reset_channel();
+        // NOTE(unsafe) The register address is only used as the source of this transfer
+        channel.set_source(unsafe { PERIPH::rx_addr() });
+        channel.set_destination(self.buffer.as_mut_ptr());
+        channel.set_transfer_size_bytes(core::mem::size_of_val(self.buffer));
+        channel.apply_config(self.config);
+        channel.configure_hardware_request(self.config);
+        channel.configure_peripheral_flow_control(self.config);
+        channel.set_circular();
+
+        // Preserve the instruction and bus ordering of preceding buffer access
+        // to the subsequent access by the DMA peripheral due to enabling it.
+        fence(Ordering::SeqCst);
+
+        channel.enable();
+        channel.wait_for_transfer_started()
+    }
+
+    /// Stop the transfer, and wait for it to be suspended before resetting the channel. The reset
+    /// also unlinks the channel from the buffer, so that it no longer reloads its address.
+    pub fn stop(&mut self) {
+        self.channel.abort();
+        self.channel.disable_circular_interrupts();
+
+        // Preserve the instruction and bus sequence of the preceding operation and
+        // the subsequent buffer access.
+        fence(Ordering::SeqCst);
+    }
+
+    /// Enable the half transfer and transfer complete interrupts, which are raised as each half
+    /// of the buffer is filled, as well as the error interrupts.
+    pub fn enable_interrupts(&mut self) {
+        self.channel.enable_circular_interrupts();
+    }
+
+    /// Disable the interrupts of the transfer
+    pub fn disable_interrupts(&mut self) {
+        self.channel.disable_circular_interrupts();
+    }
+
+    /// Call `f` with the half of the buffer that was last filled, if it has not already been
+    /// read, and return its result. The GPDMA fills the other half meanwhile.
+    ///
+    /// Returns [`Error::Overrun`] if both halves were filled since the last read, or if the GPDMA
+    /// started filling the half again before `f` returned, in which case the data that `f` read
+    /// may have been overwritten.
+    pub fn read_half<R>(
+        &mut self,
+        f: impl FnOnce(Half, &[W]) -> R,
+    ) -> nb::Result<R, Error> {
+        let first = self.channel.check_half_transfer_complete()?;
+        let second = self.channel.check_transfer_complete()?;
+        let half = match (first, second) {
+            (false, false) => return Err(nb::Error::WouldBlock),
+            (true, true) => {
+                self.channel
+                    .clear_transfer_event(TransferEvent::HalfTransferComplete);
+                self.channel
+                    .clear_transfer_event(TransferEvent::TransferComplete);
+                return Err(nb::Error::Other(Error::Overrun));
+            }
+            (true, false) => Half::First,
+            (false, true) => Half::Second,
+        };
+        let (event, next) = match half {
+            Half::First => (
+                TransferEvent::HalfTransferComplete,
+                TransferEvent::TransferComplete,
+            ),
+            Half::Second => (
+                TransferEvent::TransferComplete,
+                TransferEvent::HalfTransferComplete,
+            ),
+        };
+        self.channel.clear_transfer_event(event);
+
+        // Preserve the instruction and bus sequence of the preceding operation and
+        // the subsequent buffer access.
+        fence(Ordering::SeqCst);
+
+        let (first_half, second_half) =
+            self.buffer.split_at(self.buffer.len() / 2);
+        let result = f(
+            half,
+            match half {
+                Half::First => first_half,
+                Half::Second => second_half,
+            },
+        );
+
+        // The GPDMA finishing the other half means that it has moved on to this one. Its event
+        // is left pending, for the next read.
+        fence(Ordering::SeqCst);
+        let overrun = match next {
+            TransferEvent::TransferComplete => {
+                self.channel.check_transfer_complete()?
+            }
+            TransferEvent::HalfTransferComplete => {
+                self.channel.check_half_transfer_complete()?
+            }
+        };
+        if overrun {
+            Err(nb::Error::Other(Error::Overrun))
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Stop the transfer, and release the channel and the buffer. The channel is returned reset
+    /// and unlinked from the buffer, so a later transfer on it doesn't write to the buffer.
+    pub fn free(mut self) -> (CH, &'static mut [W]) {
+        self.stop();
+        (self.channel, self.buffer)
+    }
+}
@@ -74,7 +74,7 @@ pub trait RxAddr<W: Word> {
     unsafe fn rx_addr() -> *const W;
 }
 
-trait TxBuffer<W: Word> {
+pub(crate) trait TxBuffer<W: Word> {
     /// Returns a `PeriphTxBuffer` that provides a write buffer for the peripheral's transmit data
     /// register. This is used to initiate memory-to-peripheral DMA transfers. Implemented
     /// automatically for any implementer of `TxAddr`.
@@ -76,6 +76,33 @@ pub mod delay;
 #[cfg(feature = "device-selected")]
 pub mod spi;
 
+#[cfg(feature = "device-selected")]
+pub mod serial;
+
+#[cfg(feature = "device-selected")]
+pub mod lpuart;
+
+#[cfg(feature = "device-selected")]
+pub mod lptim;
+
+#[cfg(feature = "device-selected")]
+pub mod adc;
+
+#[cfg(feature = "device-selected")]
+pub mod timer;
+
+#[cfg(feature = "device-selected")]
+pub mod pwm;
+
+#[cfg(feature = "device-selected")]
+pub mod capture;
+
+#[cfg(feature = "device-selected")]
+pub mod qei;
+
+#[cfg(feature = "device-selected")]
+pub mod one_pulse;
+
 #[cfg(feature = "device-selected")]
 pub mod dwt;
 
@@ -0,0 +1,618 @@
+//! Low-power timers (LPTIM)
+//!
+//! The LPTIMs are 16-bit timers with their own kernel clock. When clocked
+//! from the LSE (or LSI), they keep counting while the system is in Stop
+//! mode, and their interrupts wake the system. LPTIM1 and LPTIM2 are on all
+//! devices, and LPTIM3 to LPTIM6 on STM32H56x/573.
+//!
+//! Each timer can be used as:
+//!
+//! - an [`LpTimer`], which raises its auto-reload match flag after a single
+//!   timeout or periodically
+//! - an [`LpPwm`], which generates PWM (or set-once) waveforms on its two
+//!   channels
+//! - an [`LpCapture`], which captures the counter on edges of its two
+//!   channel inputs
+//! - an [`LpEncoder`], which counts the edges of a quadrature encoder on its
+//!   IN1 and IN2 inputs
+//! - an [`LpPulseCounter`], which counts the pulses on its IN1 input, and
+//!   wakes the system after a number of them
+//!
+//! With the `time-driver-lptim1` feature, LPTIM1 is the time driver of
+//! `embassy-time`, and `stop_until_alarm` lets an executor idle in Stop mode
+//! until its next alarm.
+//!
+//! LPTIM4 only has one output channel, and only the IN1 input, so it can
+//! not be used for input capture or as an encoder.
+//!
+//! # Usage
+//!
+//! The kernel clock of the timer is selected through its peripheral's reset
+//! and enable control. To keep running in Stop mode, the LSE must be enabled
+//! when the RCC is frozen and selected as the kernel clock:
+//!
+//! ```
+//! use stm32h5xx_hal::lptim::{Event, LpTimExt};
+//! use stm32h5xx_hal::rcc::rec::LpTim1ClkSel;
+//!
+//! let ccdr = rcc.lse_ck(32_768.Hz()).freeze(pwrcfg, &dp.SBS);
+//! let rec = ccdr.peripheral.LPTIM1.kernel_clk_mux(LpTim1ClkSel::LseKer);
+//!
+//! let mut timer = dp.LPTIM1.lptim_timer(rec, &ccdr.clocks);
+//! timer.start(5_000_000.micros());
+//! ```
+//!
+//! PWM outputs and input captures are set up from the timer's [`Channels`],
+//! in the same way as those of the general-purpose timers:
+//!
+//! ```
+//! let (pwm, channels) = dp.LPTIM2.lptim_pwm(1.kHz(), rec, &ccdr.clocks);
+//! let mut led = channels.ch1.pwm(pa4.into_alternate());
+//! led.set_duty(pwm.max_duty() / 4);
+//! led.enable();
+//! ```
+//!
+//! ## Wakeup from Stop mode
+//!
+//! The interrupts of the LPTIMs are connected to direct EXTI lines, which
+//! are unmasked at reset, so an enabled LPTIM interrupt wakes the system
+//! from Stop mode once it is unmasked in the NVIC:
+//!
+//! ```
+//! timer.listen(Event::AutoReloadMatch);
+//! unsafe { NVIC::unmask(interrupt::LPTIM1) };
+//!
+//! pwr::stop(&mut cp.SCB);
+//! timer.clear_pending(Event::AutoReloadMatch);
+//! ```
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use embedded_hal_nb::nb;
+
+use crate::capture::Edge;
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::lptim1;
+use crate::time::{Hertz, MicroSeconds};
+
+mod capture;
+mod encoder;
+mod lptim_def;
+mod pulse_counter;
+mod pwm;
+#[cfg(feature = "time-driver-lptim1")]
+mod time_driver;
+
+pub use capture::{InputConfig, LpCapture, LpCaptureChannel};
+pub use encoder::LpEncoder;
+pub use pulse_counter::LpPulseCounter;
+pub use pwm::{LpPwm, LpPwmChannel, Waveform};
+#[cfg(feature = "time-driver-lptim1")]
+pub use time_driver::{init_time_driver, stop_until_alarm};
+
+/// Interrupt events
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// Compare match or capture on channel 1 (CC1IF)
+    CaptureCompare1,
+    /// Compare match or capture on channel 2 (CC2IF)
+    CaptureCompare2,
+    /// The counter reached the auto-reload value (ARRM)
+    AutoReloadMatch,
+    /// The counter was updated at the end of a period (UE)
+    Update,
+    /// The encoder counting direction changed to up (UP)
+    DirectionUp,
+    /// The encoder counting direction changed to down (DOWN)
+    DirectionDown,
+}
+
+impl Event {
+    /// The bits of this event in DIER, ISR and ICR, which are the same in
+    /// all three
+    fn mask(self) -> u32 {
+        match self {
+            Event::CaptureCompare1 => 1 << 0,
+            Event::AutoReloadMatch => 1 << 1,
+            Event::DirectionUp => 1 << 5,
+            Event::DirectionDown => 1 << 6,
+            Event::Update => 1 << 7,
+            Event::CaptureCompare2 => 1 << 9,
+        }
+    }
+}
+
+/// The number of consecutive equal samples of the kernel clock needed to
+/// validate a level change on an input
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Filter {
+    /// Any level change is valid
+    None,
+    Clocks2,
+    Clocks4,
+    Clocks8,
+}
+
+impl Filter {
+    /// The filter bits, which have the same meaning in all filter fields
+    fn bits(self) -> u8 {
+        match self {
+            Filter::None => 0b00,
+            Filter::Clocks2 => 0b01,
+            Filter::Clocks4 => 0b10,
+            Filter::Clocks8 => 0b11,
+        }
+    }
+}
+
+// Implemented by all low-power timer instances
+pub trait Instance: crate::Sealed + Sized {
+    type Rec: ResetEnable;
+
+    /// The number of capture/compare channels
+    const CHANNELS: u8;
+
+    /// True for the timers with input capture and encoder mode
+    const HAS_INPUTS: bool;
+
+    #[doc(hidden)]
+    fn ptr() -> *const lptim1::RegisterBlock;
+
+    #[doc(hidden)]
+    fn clock(clocks: &CoreClocks) -> Hertz;
+
+    #[doc(hidden)]
+    fn rec() -> Self::Rec;
+}
+
+/// A pin that can be used for channel `C` (1 or 2) of the timer
+pub trait PinCh<LPTIM, const C: u8> {}
+
+/// A pin that can be used for input 1 of the timer, which is the external
+/// clock input and encoder input A
+pub trait PinIn1<LPTIM> {}
+
+/// A pin that can be used for input 2 of the timer, which is encoder input
+/// B
+pub trait PinIn2<LPTIM> {}
+
+/// A channel of a low-power timer, which is bound to a pin to use it
+#[derive(Debug)]
+pub struct Channel<LPTIM, const C: u8> {
+    _lptim: PhantomData<LPTIM>,
+}
+
+impl<LPTIM, const C: u8> Channel<LPTIM, C> {
+    fn new() -> Self {
+        Channel {
+            _lptim: PhantomData,
+        }
+    }
+}
+
+/// The channels of a low-power timer
+#[derive(Debug)]
+pub struct Channels<LPTIM> {
+    pub ch1: Channel<LPTIM, 1>,
+    pub ch2: Channel<LPTIM, 2>,
+}
+
+impl<LPTIM> Channels<LPTIM> {
+    fn new() -> Self {
+        Channels {
+            ch1: Channel::new(),
+            ch2: Channel::new(),
+        }
+    }
+}
+
+/// A low-power timer used to count timeouts
+#[derive(Debug)]
+pub struct LpTimer<LPTIM> {
+    lptim: LPTIM,
+    clock: Hertz,
+}
+
+pub trait LpTimExt<LPTIM: Instance>: Sized {
+    /// Use the timer to count timeouts
+    fn lptim_timer(
+        self,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> LpTimer<LPTIM>;
+
+    /// Use the timer as a PWM output at `frequency`
+    fn lptim_pwm(
+        self,
+        frequency: Hertz,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (LpPwm<LPTIM>, Channels<LPTIM>);
+
+    /// Use the timer for input capture, with the counter running at
+    /// `frequency`
+    fn lptim_capture(
+        self,
+        frequency: Hertz,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (LpCapture<LPTIM>, Channels<LPTIM>);
+
+    /// Use the timer to count the edges of a quadrature encoder on `pins`
+    fn lptim_encoder<IN1, IN2>(
+        self,
+        pins: (IN1, IN2),
+        filter: Filter,
+        rec: LPTIM::Rec,
+    ) -> LpEncoder<LPTIM>
+    where
+        IN1: PinIn1<LPTIM>,
+        IN2: PinIn2<LPTIM>;
+
+    /// Use the timer to count the `edge`s of the pulses on `pin`, filtered
+    /// by `filter`
+    fn lptim_pulse_counter<IN1>(
+        self,
+        pin: IN1,
+        edge: Edge,
+        filter: Filter,
+        rec: LPTIM::Rec,
+    ) -> LpPulseCounter<LPTIM>
+    where
+        IN1: PinIn1<LPTIM>;
+}
+
+impl<LPTIM: Instance> LpTimExt<LPTIM> for LPTIM {
+    fn lptim_timer(
+        self,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> LpTimer<LPTIM> {
+        LpTimer::new(self, rec, clocks)
+    }
+
+    fn lptim_pwm(
+        self,
+        frequency: Hertz,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (LpPwm<LPTIM>, Channels<LPTIM>) {
+        (LpPwm::new(self, frequency, rec, clocks), Channels::new())
+    }
+
+    fn lptim_capture(
+        self,
+        frequency: Hertz,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (LpCapture<LPTIM>, Channels<LPTIM>) {
+        (
+            LpCapture::new(self, frequency, rec, clocks),
+            Channels::new(),
+        )
+    }
+
+    fn lptim_encoder<IN1, IN2>(
+        self,
+        _pins: (IN1, IN2),
+        filter: Filter,
+        rec: LPTIM::Rec,
+    ) -> LpEncoder<LPTIM>
+    where
+        IN1: PinIn1<LPTIM>,
+        IN2: PinIn2<LPTIM>,
+    {
+        LpEncoder::new(self, filter, rec)
+    }
+
+    fn lptim_pulse_counter<IN1>(
+        self,
+        _pin: IN1,
+        edge: Edge,
+        filter: Filter,
+        rec: LPTIM::Rec,
+    ) -> LpPulseCounter<LPTIM>
+    where
+        IN1: PinIn1<LPTIM>,
+    {
+        LpPulseCounter::new(self, edge, filter, rec)
+    }
+}
+
+#[inline(always)]
+fn regs<LPTIM: Instance>() -> &'static lptim1::RegisterBlock {
+    // NOTE(unsafe) All low-power timers have the same registers
+    unsafe { &*LPTIM::ptr() }
+}
+
+/// Calculate the prescaler (as a power of two) and auto-reload values for a
+/// period of `ticks` cycles of the kernel clock, with an auto-reload value of
+/// at most `max_arr`. This will panic if the period is out of range.
+fn presc_arr(ticks: u64, max_arr: u16) -> (u8, u16) {
+    let presc = (0..=7)
+        .find(|presc| ticks >> presc <= max_arr as u64 + 1)
+        .expect("The period is too long for the timer");
+    let arr = ticks >> presc;
+    assert!(arr >= 2, "The period is too short for the timer");
+    (presc, (arr - 1) as u16)
+}
+
+/// The prescaler (as a power of two) for a counter running at `frequency`
+/// from the kernel clock `clock`, which is the largest division that does not
+/// make the counter slower than `frequency`
+fn counter_presc(clock: Hertz, frequency: Hertz) -> u8 {
+    let div = (clock.raw() / frequency.raw()).max(1);
+    (div.ilog2() as u8).min(7)
+}
+
+/// Reset and enable the timer, leaving the counter disabled with `cfgr` as
+/// its configuration
+fn init<LPTIM: Instance>(
+    rec: LPTIM::Rec,
+    cfgr: impl FnOnce(&mut lptim1::cfgr::W) -> &mut lptim1::cfgr::W,
+) {
+    let _ = rec.enable().reset();
+    regs::<LPTIM>().cfgr().write(|w| cfgr(w));
+}
+
+/// Enable or disable the counter. The configuration registers can only be
+/// written while it is disabled, and the others while it is enabled.
+fn set_enabled<LPTIM: Instance>(enable: bool) {
+    regs::<LPTIM>().cr().modify(|_, w| w.enable().bit(enable));
+}
+
+/// Disable the timer to change its configuration with `f`, and enable it
+/// again. The counter is stopped and reset.
+fn reconfigure<LPTIM: Instance>(f: impl FnOnce(&lptim1::RegisterBlock)) {
+    set_enabled::<LPTIM>(false);
+    f(regs::<LPTIM>());
+    set_enabled::<LPTIM>(true);
+}
+
+/// Start counting continuously
+fn start_continuous<LPTIM: Instance>() {
+    regs::<LPTIM>().cr().modify(|_, w| w.cntstrt().set_bit());
+}
+
+/// The bit of ARROK, CMP1OK, CMP2OK and DIEROK in ISR and ICR
+const ARROK: u32 = 1 << 4;
+const CMP1OK: u32 = 1 << 3;
+const CMP2OK: u32 = 1 << 19;
+const DIEROK: u32 = 1 << 24;
+
+/// The value of ISR. The interrupt registers have the same layout whether
+/// the channels are inputs or outputs, but are named differently in the PACs
+/// of the STM32H523/533.
+fn isr<LPTIM: Instance>() -> u32 {
+    let lptim = regs::<LPTIM>();
+    #[cfg(feature = "h523_h533")]
+    {
+        lptim.isr().read().bits()
+    }
+    #[cfg(not(feature = "h523_h533"))]
+    {
+        lptim.isr_output().read().bits()
+    }
+}
+
+/// Clear the flags of ISR that are set in `flags`
+fn clear_flags<LPTIM: Instance>(flags: u32) {
+    let lptim = regs::<LPTIM>();
+    // NOTE(unsafe) Writing ones clears the flags, and zeros have no effect
+    #[cfg(feature = "h523_h533")]
+    lptim.icr().write(|w| unsafe { w.bits(flags) });
+    #[cfg(not(feature = "h523_h533"))]
+    lptim.icr_output().write(|w| unsafe { w.bits(flags) });
+}
+
+/// Wait for a write to a register of the kernel clock domain to complete,
+/// as signalled by `flag`, and clear the flag
+fn wait_write<LPTIM: Instance>(flag: u32) {
+    while isr::<LPTIM>() & flag == 0 {}
+    clear_flags::<LPTIM>(flag);
+}
+
+/// Set the auto-reload value. The counter must be enabled.
+fn set_arr<LPTIM: Instance>(arr: u16) {
+    // NOTE(unsafe) All values are valid
+    regs::<LPTIM>()
+        .arr()
+        .write(|w| unsafe { w.arr().bits(arr) });
+    wait_write::<LPTIM>(ARROK);
+}
+
+/// The auto-reload value
+fn arr<LPTIM: Instance>() -> u16 {
+    regs::<LPTIM>().arr().read().arr().bits()
+}
+
+/// Set the compare value of channel `c`. The counter must be enabled.
+fn set_ccr<LPTIM: Instance>(c: u8, ccr: u16) {
+    let lptim = regs::<LPTIM>();
+    // NOTE(unsafe) All values are valid
+    if c == 1 {
+        lptim.ccr1().write(|w| unsafe { w.ccr1().bits(ccr) });
+        wait_write::<LPTIM>(CMP1OK);
+    } else {
+        lptim.ccr2().write(|w| unsafe { w.ccr2().bits(ccr) });
+        wait_write::<LPTIM>(CMP2OK);
+    }
+}
+
+/// The compare or capture value of channel `c`
+fn ccr<LPTIM: Instance>(c: u8) -> u16 {
+    let lptim = regs::<LPTIM>();
+    if c == 1 {
+        lptim.ccr1().read().ccr1().bits()
+    } else {
+        lptim.ccr2().read().ccr2().bits()
+    }
+}
+
+/// The value of the counter. As the counter runs from the kernel clock, it is
+/// read until two consecutive reads agree.
+fn counter<LPTIM: Instance>() -> u16 {
+    let lptim = regs::<LPTIM>();
+    let mut cnt = lptim.cnt().read().cnt().bits();
+    loop {
+        let next = lptim.cnt().read().cnt().bits();
+        if next == cnt {
+            return cnt;
+        }
+        cnt = next;
+    }
+}
+
+/// Enable or disable the interrupt of `event`. The counter must be enabled.
+fn set_interrupt<LPTIM: Instance>(event: Event, enable: bool) {
+    let lptim = regs::<LPTIM>();
+    #[cfg(feature = "h523_h533")]
+    let dier = lptim.dier();
+    #[cfg(not(feature = "h523_h533"))]
+    let dier = lptim.dier_output();
+    // NOTE(unsafe) Only the bit of the event is changed
+    dier.modify(|r, w| unsafe {
+        if enable {
+            w.bits(r.bits() | event.mask())
+        } else {
+            w.bits(r.bits() & !event.mask())
+        }
+    });
+    wait_write::<LPTIM>(DIEROK);
+}
+
+/// Whether the flag of `event` is set
+fn is_pending<LPTIM: Instance>(event: Event) -> bool {
+    isr::<LPTIM>() & event.mask() != 0
+}
+
+/// Clear the flag of `event`
+fn clear_pending<LPTIM: Instance>(event: Event) {
+    clear_flags::<LPTIM>(event.mask());
+}
+
+// Event methods of the low-power timer drivers
+macro_rules! event_methods {
+    () => {
+        /// Start listening for an interrupt event
+        pub fn listen(&mut self, event: Event) {
+            set_interrupt::<LPTIM>(event, true);
+        }
+
+        /// Stop listening for an interrupt event
+        pub fn unlisten(&mut self, event: Event) {
+            set_interrupt::<LPTIM>(event, false);
+        }
+
+        /// Whether the flag of an event is set
+        pub fn is_pending(&self, event: Event) -> bool {
+            is_pending::<LPTIM>(event)
+        }
+
+        /// Clear the flag of an event
+        pub fn clear_pending(&mut self, event: Event) {
+            clear_pending::<LPTIM>(event);
+        }
+
+        /// Releases the timer peripheral
+        pub fn free(self) -> LPTIM {
+            use $crate::rcc::ResetEnable;
+
+            let _ = LPTIM::rec().reset().disable();
+            self.lptim
+        }
+    };
+}
+use event_methods;
+
+impl<LPTIM: Instance> LpTimer<LPTIM> {
+    fn new(lptim: LPTIM, rec: LPTIM::Rec, clocks: &CoreClocks) -> Self {
+        init::<LPTIM>(rec, |w| w);
+        set_enabled::<LPTIM>(true);
+        LpTimer {
+            lptim,
+            clock: LPTIM::clock(clocks),
+        }
+    }
+
+    /// Set the prescaler and auto-reload value for a period of `period`,
+    /// leaving the counter stopped
+    fn load(&mut self, period: MicroSeconds) {
+        let ticks = self.clock.raw() as u64 * period.ticks() as u64 / 1_000_000;
+        let (presc, arr) = presc_arr(ticks, u16::MAX);
+
+        reconfigure::<LPTIM>(|lptim| {
+            // NOTE(unsafe) The prescaler is at most 7
+            lptim.cfgr().modify(|_, w| unsafe { w.presc().bits(presc) });
+        });
+        set_arr::<LPTIM>(arr);
+        clear_pending::<LPTIM>(Event::AutoReloadMatch);
+    }
+
+    /// Start a single timeout of `timeout`, after which the auto-reload match
+    /// flag is set and the counter stops. This will panic if the timeout is
+    /// out of the range of the timer.
+    pub fn start(&mut self, timeout: MicroSeconds) {
+        self.load(timeout);
+        regs::<LPTIM>().cr().modify(|_, w| w.sngstrt().set_bit());
+    }
+
+    /// Start counting periods of `period`, at the end of each of which the
+    /// auto-reload match flag is set. This will panic if the period is out
+    /// of the range of the timer.
+    pub fn start_periodic(&mut self, period: MicroSeconds) {
+        self.load(period);
+        start_continuous::<LPTIM>();
+    }
+
+    /// Whether the timeout or period has elapsed, and clear the auto-reload
+    /// match flag if it has
+    pub fn wait(&mut self) -> nb::Result<(), Infallible> {
+        if is_pending::<LPTIM>(Event::AutoReloadMatch) {
+            clear_pending::<LPTIM>(Event::AutoReloadMatch);
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Stop the counter
+    pub fn cancel(&mut self) {
+        // The counter is only stopped by disabling the timer, which keeps the
+        // interrupt enables
+        reconfigure::<LPTIM>(|_| {});
+    }
+
+    /// The current value of the counter
+    pub fn counter(&self) -> u16 {
+        counter::<LPTIM>()
+    }
+
+    event_methods!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presc_arr() {
+        assert_eq!(presc_arr(2, u16::MAX), (0, 1));
+        assert_eq!(presc_arr(0x1_0000, u16::MAX), (0, 0xFFFF));
+        assert_eq!(presc_arr(0x1_0001, u16::MAX), (1, 0x7FFF));
+        // A 5 second timeout from the LSE
+        assert_eq!(presc_arr(5 * 32_768, u16::MAX), (2, 40959));
+        assert_eq!(presc_arr(0x1_0000, 0xFFFE), (1, 0x7FFF));
+    }
+
+    #[test]
+    fn test_counter_presc() {
+        let lse = Hertz::from_raw(32_768);
+        assert_eq!(counter_presc(lse, Hertz::from_raw(32_768)), 0);
+        assert_eq!(counter_presc(lse, Hertz::from_raw(10_000)), 1);
+        assert_eq!(counter_presc(lse, Hertz::from_raw(1)), 7);
+    }
+}
@@ -0,0 +1,233 @@
+//! Input capture on the channels of the low-power timers
+//!
+//! The counter of an [`LpCapture`] runs freely over its 16-bit range, and
+//! each channel captures it on edges of its input. If an edge is captured
+//! before the previous capture was read, the previous capture is lost and
+//! [`Error::Overcapture`] is returned.
+
+use core::marker::PhantomData;
+
+use embedded_hal_nb::nb;
+
+use super::{
+    clear_flags, clear_pending, counter, counter_presc, event_methods, init,
+    is_pending, isr, reconfigure, regs, set_arr, set_enabled, set_interrupt,
+    start_continuous, Channel, Event, Filter, Instance, PinCh,
+};
+use crate::capture::{Edge, Error, Prescaler};
+use crate::rcc::CoreClocks;
+use crate::time::Hertz;
+
+/// The bits of CC1OF and CC2OF in ISR and ICR
+const CC1OF: u32 = 1 << 12;
+const CC2OF: u32 = 1 << 13;
+
+/// Configuration of a capture channel input
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InputConfig {
+    edge: Edge,
+    filter: Filter,
+    prescaler: Prescaler,
+}
+
+impl InputConfig {
+    /// Capture on each `edge`, without filtering
+    pub fn new(edge: Edge) -> Self {
+        InputConfig {
+            edge,
+            filter: Filter::None,
+            prescaler: Prescaler::Div1,
+        }
+    }
+
+    /// Set the digital filter of the input, which is sampled at the kernel
+    /// clock
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Only capture every 2, 4 or 8 edges
+    pub fn prescaler(mut self, prescaler: Prescaler) -> Self {
+        self.prescaler = prescaler;
+        self
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig::new(Edge::Rising)
+    }
+}
+
+impl From<Edge> for InputConfig {
+    fn from(edge: Edge) -> Self {
+        InputConfig::new(edge)
+    }
+}
+
+/// A low-power timer configured for input capture, which controls the
+/// counter shared by its channels
+#[derive(Debug)]
+pub struct LpCapture<LPTIM> {
+    lptim: LPTIM,
+    frequency: Hertz,
+}
+
+/// A channel of a low-power timer configured for input capture
+#[derive(Debug)]
+pub struct LpCaptureChannel<LPTIM, const C: u8> {
+    _lptim: PhantomData<LPTIM>,
+}
+
+/// Configure channel `c` as an input. The counter is restarted.
+fn set_input<LPTIM: Instance>(c: u8, config: &InputConfig) {
+    let psc = match config.prescaler {
+        Prescaler::Div1 => 0,
+        Prescaler::Div2 => 1,
+        Prescaler::Div4 => 2,
+        Prescaler::Div8 => 3,
+    };
+    let ccp = match config.edge {
+        Edge::Rising => 0b00,
+        Edge::Falling => 0b01,
+        Edge::Both => 0b11,
+    };
+    let filter = config.filter.bits();
+
+    // NOTE(unsafe) All the values are valid for inputs
+    reconfigure::<LPTIM>(|lptim| {
+        if c == 1 {
+            lptim.ccmr1().modify(|_, w| unsafe {
+                w.ic1psc()
+                    .bits(psc)
+                    .ic1f()
+                    .bits(filter)
+                    .cc1p()
+                    .bits(ccp)
+                    .cc1e()
+                    .set_bit()
+            });
+        } else {
+            lptim.ccmr1().modify(|_, w| unsafe {
+                w.ic2psc()
+                    .bits(psc)
+                    .ic2f()
+                    .bits(filter)
+                    .cc2p()
+                    .bits(ccp)
+                    .cc2e()
+                    .set_bit()
+            });
+        }
+    });
+    start_continuous::<LPTIM>();
+}
+
+/// Read the capture of channel `c`, if there is one
+fn read_capture<LPTIM: Instance>(c: u8) -> nb::Result<u16, Error> {
+    let (event, overcapture) = if c == 1 {
+        (Event::CaptureCompare1, CC1OF)
+    } else {
+        (Event::CaptureCompare2, CC2OF)
+    };
+    let isr = isr::<LPTIM>();
+
+    if isr & overcapture != 0 {
+        // Discard the capture that the lost one was overwritten by, so that
+        // the next one is consistent
+        clear_flags::<LPTIM>(overcapture | event.mask());
+        Err(nb::Error::Other(Error::Overcapture))
+    } else if isr & event.mask() != 0 {
+        let capture = super::ccr::<LPTIM>(c);
+        clear_pending::<LPTIM>(event);
+        Ok(capture)
+    } else {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+impl<LPTIM: Instance> LpCapture<LPTIM> {
+    pub(super) fn new(
+        lptim: LPTIM,
+        frequency: Hertz,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        assert!(LPTIM::HAS_INPUTS, "The timer does not have inputs");
+
+        let clock = LPTIM::clock(clocks);
+        let presc = counter_presc(clock, frequency);
+        // NOTE(unsafe) The prescaler is at most 7
+        init::<LPTIM>(rec, |w| unsafe { w.presc().bits(presc) });
+        // Both channels are inputs
+        regs::<LPTIM>()
+            .ccmr1()
+            .write(|w| w.cc1sel().set_bit().cc2sel().set_bit());
+        set_enabled::<LPTIM>(true);
+        set_arr::<LPTIM>(u16::MAX);
+        start_continuous::<LPTIM>();
+
+        LpCapture {
+            lptim,
+            frequency: Hertz::from_raw(clock.raw() >> presc),
+        }
+    }
+
+    /// The frequency of the counter, which sets the resolution of the
+    /// captures
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// The current value of the counter
+    pub fn counter(&self) -> u16 {
+        counter::<LPTIM>()
+    }
+
+    event_methods!();
+}
+
+impl<LPTIM: Instance, const C: u8> Channel<LPTIM, C> {
+    /// Use this channel to capture the counter on edges of `pin`. The
+    /// counter is restarted.
+    pub fn capture<PIN>(
+        self,
+        _pin: PIN,
+        config: impl Into<InputConfig>,
+    ) -> LpCaptureChannel<LPTIM, C>
+    where
+        PIN: PinCh<LPTIM, C>,
+    {
+        assert!(C <= LPTIM::CHANNELS, "The timer does not have this channel");
+        set_input::<LPTIM>(C, &config.into());
+        LpCaptureChannel {
+            _lptim: PhantomData,
+        }
+    }
+}
+
+impl<LPTIM: Instance, const C: u8> LpCaptureChannel<LPTIM, C> {
+    /// Read the last capture, returning `WouldBlock` if there has been no
+    /// new capture since the last read
+    pub fn read(&mut self) -> nb::Result<u16, Error> {
+        read_capture::<LPTIM>(C)
+    }
+
+    /// Change the input configuration. The counter is restarted.
+    pub fn set_config(&mut self, config: impl Into<InputConfig>) {
+        set_input::<LPTIM>(C, &config.into());
+    }
+
+    /// Stop capturing, and return the channel
+    pub fn release(self) -> Channel<LPTIM, C> {
+        let lptim = regs::<LPTIM>();
+        if C == 1 {
+            lptim.ccmr1().modify(|_, w| w.cc1e().clear_bit());
+        } else {
+            lptim.ccmr1().modify(|_, w| w.cc2e().clear_bit());
+        }
+        Channel::new()
+    }
+}
@@ -0,0 +1,53 @@
+//! Quadrature encoder mode of the low-power timers
+//!
+//! The counter of an [`LpEncoder`] counts both edges of both inputs, so
+//! four times per encoder cycle, up or down depending on the phase between
+//! the inputs. It is clocked by the encoder signals, and sampled by the
+//! kernel clock, which must be at least four times faster than the inputs.
+
+use super::{
+    clear_pending, counter, event_methods, init, is_pending, set_arr,
+    set_enabled, set_interrupt, start_continuous, Event, Filter, Instance,
+};
+
+/// A low-power timer counting the edges of a quadrature encoder
+#[derive(Debug)]
+pub struct LpEncoder<LPTIM> {
+    lptim: LPTIM,
+}
+
+impl<LPTIM: Instance> LpEncoder<LPTIM> {
+    pub(super) fn new(lptim: LPTIM, filter: Filter, rec: LPTIM::Rec) -> Self {
+        assert!(LPTIM::HAS_INPUTS, "The timer does not have inputs");
+
+        // CKPOL selects counting on both edges in encoder mode, which needs
+        // the prescaler to be 1 and the internal clock
+        // NOTE(unsafe) The values are valid
+        init::<LPTIM>(rec, |w| unsafe {
+            w.enc()
+                .set_bit()
+                .ckpol()
+                .bits(0b10)
+                .ckflt()
+                .bits(filter.bits())
+        });
+        set_enabled::<LPTIM>(true);
+        set_arr::<LPTIM>(u16::MAX);
+        start_continuous::<LPTIM>();
+
+        LpEncoder { lptim }
+    }
+
+    /// The current count, which wraps around between 0 and the maximum
+    /// count
+    pub fn count(&self) -> u16 {
+        counter::<LPTIM>()
+    }
+
+    /// Set the maximum count, after which the count wraps around to 0
+    pub fn set_max_count(&mut self, max: u16) {
+        set_arr::<LPTIM>(max);
+    }
+
+    event_methods!();
+}
@@ -0,0 +1,275 @@
+use core::marker::PhantomData;
+
+use super::{Instance, PinCh, PinIn1, PinIn2};
+use crate::gpio::{self, Alternate};
+use crate::rcc::{rec, CoreClocks};
+use crate::stm32::rcc::ccipr2::LPTIMSEL;
+use crate::stm32::{lptim1, LPTIM1, LPTIM2, RCC};
+use crate::time::Hertz;
+
+macro_rules! pins {
+    ($($LPTIMX:ty:
+       CH1: [$($( #[ $pmeta1:meta ] )* $CH1:ty),*]
+       CH2: [$($( #[ $pmeta2:meta ] )* $CH2:ty),*]
+       IN1: [$($( #[ $pmeta3:meta ] )* $IN1:ty),*]
+       IN2: [$($( #[ $pmeta4:meta ] )* $IN2:ty),*]
+    )+) => {
+        $(
+            $(
+                $( #[ $pmeta1 ] )*
+                impl PinCh<$LPTIMX, 1> for $CH1 {}
+            )*
+            $(
+                $( #[ $pmeta2 ] )*
+                impl PinCh<$LPTIMX, 2> for $CH2 {}
+            )*
+            $(
+                $( #[ $pmeta3 ] )*
+                impl PinIn1<$LPTIMX> for $IN1 {}
+            )*
+            $(
+                $( #[ $pmeta4 ] )*
+                impl PinIn2<$LPTIMX> for $IN2 {}
+            )*
+        )+
+    }
+}
+
+// Implemented by all low-power timer instances. The kernel clock is selected
+// in CCIPR2, where the bus clock is pclk1 for LPTIM2 and pclk3 for the
+// others.
+macro_rules! instance {
+    ($LPTIMX:ident: $LpTim:ident, $channels:literal, $inputs:literal, $sel:ident, $pclk:ident) => {
+        impl Instance for $LPTIMX {
+            type Rec = rec::$LpTim;
+
+            const CHANNELS: u8 = $channels;
+            const HAS_INPUTS: bool = $inputs;
+
+            fn ptr() -> *const lptim1::RegisterBlock {
+                <$LPTIMX>::ptr() as *const _
+            }
+
+            fn clock(clocks: &CoreClocks) -> Hertz {
+                // NOTE(unsafe) Atomic read with no side effects
+                let ccipr2 = unsafe { (*RCC::ptr()).ccipr2().read() };
+                let ck_sel =
+                    ccipr2.$sel().variant().expect("No source clock selected");
+                match ck_sel {
+                    LPTIMSEL::Pclk => Some(clocks.$pclk()),
+                    LPTIMSEL::Pll2P => clocks.pll2().p_ck(),
+                    #[cfg(feature = "rm0481")]
+                    LPTIMSEL::Pll3R => clocks.pll3().r_ck(),
+                    LPTIMSEL::LseKer => clocks.lse_ck(),
+                    LPTIMSEL::LsiKer => clocks.lsi_ck(),
+                    LPTIMSEL::PerCk => clocks.per_ck(),
+                }
+                .expect("Source clock not enabled")
+            }
+
+            fn rec() -> Self::Rec {
+                rec::$LpTim {
+                    _marker: PhantomData,
+                }
+            }
+        }
+
+        impl crate::Sealed for $LPTIMX {}
+    };
+}
+
+instance! { LPTIM1: LpTim1, 2, true, lptim1sel, pclk3 }
+instance! { LPTIM2: Lptim2, 2, true, lptim2sel, pclk1 }
+
+#[cfg(feature = "rm0492")]
+mod rm0492 {
+    use super::*;
+
+    pins! {
+        LPTIM1:
+            CH1: [
+                gpio::PA5<Alternate<4>>,
+                gpio::PA13<Alternate<2>>,
+                gpio::PB2<Alternate<5>>,
+                gpio::PB3<Alternate<2>>,
+                gpio::PB15<Alternate<2>>,
+                gpio::PC12<Alternate<2>>
+            ]
+            CH2: [
+                gpio::PB2<Alternate<2>>,
+                gpio::PB4<Alternate<4>>,
+                gpio::PB8<Alternate<2>>,
+                gpio::PC0<Alternate<2>>
+            ]
+            IN1: [
+                gpio::PA1<Alternate<5>>,
+                gpio::PB0<Alternate<4>>,
+                gpio::PB5<Alternate<14>>
+            ]
+            IN2: [
+                gpio::PA0<Alternate<5>>,
+                gpio::PA2<Alternate<5>>,
+                gpio::PA15<Alternate<2>>,
+                gpio::PB1<Alternate<5>>
+            ]
+        LPTIM2:
+            CH1: [
+                gpio::PA4<Alternate<3>>,
+                gpio::PA14<Alternate<3>>,
+                gpio::PA15<Alternate<3>>,
+                gpio::PB8<Alternate<14>>,
+                gpio::PB13<Alternate<3>>,
+                gpio::PC5<Alternate<14>>
+            ]
+            CH2: [
+                gpio::PA5<Alternate<3>>,
+                gpio::PA11<Alternate<14>>,
+                gpio::PB10<Alternate<14>>,
+                gpio::PC12<Alternate<3>>
+            ]
+            IN1: [
+                gpio::PA8<Alternate<3>>,
+                gpio::PB10<Alternate<3>>,
+                gpio::PC1<Alternate<14>>
+            ]
+            IN2: [
+                gpio::PA10<Alternate<4>>,
+                gpio::PB15<Alternate<3>>,
+                gpio::PC3<Alternate<14>>
+            ]
+    }
+}
+
+#[cfg(feature = "rm0481")]
+mod rm0481 {
+    use super::*;
+
+    pins! {
+        LPTIM1:
+            CH1: [
+                gpio::PB2<Alternate<5>>,
+                gpio::PD13<Alternate<1>>,
+                gpio::PG13<Alternate<1>>
+            ]
+            CH2: [
+                gpio::PB4<Alternate<4>>,
+                gpio::PG14<Alternate<4>>
+            ]
+            IN1: [
+                gpio::PA1<Alternate<5>>,
+                gpio::PD12<Alternate<1>>,
+                gpio::PG12<Alternate<1>>
+            ]
+            IN2: [
+                gpio::PA2<Alternate<5>>,
+                gpio::PE2<Alternate<1>>,
+                gpio::PG11<Alternate<1>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PE1<Alternate<1>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH2<Alternate<1>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH13<Alternate<1>>
+            ]
+        LPTIM2:
+            CH1: [
+                gpio::PA4<Alternate<3>>,
+                gpio::PB13<Alternate<3>>,
+                gpio::PD13<Alternate<3>>
+            ]
+            CH2: [
+                gpio::PD10<Alternate<3>>,
+                gpio::PE0<Alternate<3>>,
+                #[cfg(feature = "h523_h533")]
+                gpio::PC12<Alternate<3>>
+            ]
+            IN1: [
+                gpio::PB10<Alternate<3>>,
+                gpio::PD12<Alternate<3>>
+            ]
+            IN2: [
+                gpio::PA10<Alternate<4>>,
+                gpio::PD11<Alternate<3>>
+            ]
+    }
+}
+
+// LPTIM3 to LPTIM6 are only on the STM32H56x/573. LPTIM4 has a single output
+// (its channel 1), and only the input that clocks the counter.
+#[cfg(feature = "h56x_h573")]
+mod h56x_h573 {
+    use super::*;
+    use crate::stm32::{LPTIM3, LPTIM4, LPTIM5, LPTIM6};
+
+    instance! { LPTIM3: LpTim3, 2, true, lptim3sel, pclk3 }
+    instance! { LPTIM4: LpTim4, 1, false, lptim4sel, pclk3 }
+    instance! { LPTIM5: LpTim5, 2, true, lptim5sel, pclk3 }
+    instance! { LPTIM6: LpTim6, 2, true, lptim6sel, pclk3 }
+
+    pins! {
+        LPTIM3:
+            CH1: [
+                gpio::PB0<Alternate<14>>,
+                gpio::PB10<Alternate<2>>,
+                gpio::PC3<Alternate<3>>,
+                gpio::PF5<Alternate<2>>
+            ]
+            CH2: [
+                gpio::PB1<Alternate<14>>,
+                gpio::PF2<Alternate<2>>
+            ]
+            IN1: [
+                gpio::PB13<Alternate<2>>,
+                gpio::PC11<Alternate<2>>,
+                gpio::PF3<Alternate<2>>,
+                gpio::PF5<Alternate<14>>
+            ]
+            IN2: [
+                gpio::PA15<Alternate<2>>,
+                gpio::PF2<Alternate<3>>
+            ]
+        LPTIM4:
+            CH1: [
+                gpio::PD7<Alternate<14>>
+            ]
+            CH2: []
+            IN1: [
+                gpio::PD13<Alternate<14>>,
+                gpio::PG0<Alternate<14>>
+            ]
+            IN2: []
+        LPTIM5:
+            CH1: [
+                gpio::PF0<Alternate<13>>,
+                gpio::PF6<Alternate<13>>,
+                gpio::PG12<Alternate<14>>
+            ]
+            CH2: [
+                gpio::PF1<Alternate<13>>,
+                gpio::PF7<Alternate<13>>,
+                gpio::PG13<Alternate<13>>
+            ]
+            IN1: [
+                gpio::PF2<Alternate<13>>,
+                gpio::PF8<Alternate<13>>,
+                gpio::PG14<Alternate<13>>
+            ]
+            IN2: [
+                gpio::PF3<Alternate<13>>,
+                gpio::PF9<Alternate<13>>
+            ]
+        LPTIM6:
+            CH1: [
+                gpio::PF11<Alternate<14>>
+            ]
+            CH2: [
+                gpio::PF12<Alternate<14>>
+            ]
+            IN1: [
+                gpio::PF13<Alternate<14>>
+            ]
+            IN2: [
+                gpio::PF14<Alternate<14>>
+            ]
+    }
+}
@@ -0,0 +1,172 @@
+//! External pulse counting with the low-power timers
+//!
+//! The counter of an [`LpPulseCounter`] counts the edges of its IN1 input,
+//! such as the pulses of the reed switch of a water or gas meter. The input
+//! is sampled by the kernel clock through a glitch filter, so with the LSE
+//! or LSI as the kernel clock, pulses are counted while the system is in
+//! Stop mode.
+//!
+//! The 16-bit counter is extended by tallying its overflows, and a compare
+//! match wakes the system after a number of pulses. Both need the timer's
+//! interrupt to be unmasked, with [`LpPulseCounter::on_interrupt`] called
+//! from its handler:
+//!
+//! ```
+//! let mut meter = dp.LPTIM1.lptim_pulse_counter(
+//!     pa1.into_alternate(),
+//!     Edge::Falling,
+//!     Filter::Clocks8,
+//!     rec,
+//! );
+//! unsafe { NVIC::unmask(interrupt::LPTIM1) };
+//! if !meter.wake_after(1000) {
+//!     // 1000 pulses were counted already
+//! }
+//!
+//! // In the LPTIM1 interrupt handler
+//! if meter.on_interrupt() {
+//!     // 1000 pulses were counted
+//! }
+//! ```
+
+use super::{
+    clear_pending, counter, init, is_pending, regs, set_arr, set_ccr,
+    set_enabled, set_interrupt, start_continuous, Event, Filter, Instance,
+};
+use crate::capture::Edge;
+
+/// A low-power timer counting the pulses on its IN1 input
+#[derive(Debug)]
+pub struct LpPulseCounter<LPTIM> {
+    lptim: LPTIM,
+    overflows: u32,
+    target: Option<u64>,
+}
+
+impl<LPTIM: Instance> LpPulseCounter<LPTIM> {
+    pub(super) fn new(
+        lptim: LPTIM,
+        edge: Edge,
+        filter: Filter,
+        rec: LPTIM::Rec,
+    ) -> Self {
+        let ckpol = match edge {
+            Edge::Rising => 0b00,
+            Edge::Falling => 0b01,
+            Edge::Both => 0b10,
+        };
+        // The counter is clocked by the kernel clock, and counts the valid
+        // edges of IN1 after the filter
+        // NOTE(unsafe) The values are valid
+        init::<LPTIM>(rec, |w| unsafe {
+            w.countmode()
+                .set_bit()
+                .ckpol()
+                .bits(ckpol)
+                .ckflt()
+                .bits(filter.bits())
+        });
+        set_enabled::<LPTIM>(true);
+        set_arr::<LPTIM>(u16::MAX);
+        // Each overflow of the counter is an update event
+        set_interrupt::<LPTIM>(Event::Update, true);
+        start_continuous::<LPTIM>();
+
+        LpPulseCounter {
+            lptim,
+            overflows: 0,
+            target: None,
+        }
+    }
+
+    /// The number of pulses counted
+    pub fn count(&self) -> u64 {
+        let cnt = counter::<LPTIM>();
+        if is_pending::<LPTIM>(Event::Update) {
+            // The counter has overflowed since the last interrupt, and may
+            // have done so after it was read
+            let cnt = counter::<LPTIM>();
+            ((self.overflows as u64 + 1) << 16) + cnt as u64
+        } else {
+            ((self.overflows as u64) << 16) + cnt as u64
+        }
+    }
+
+    /// The number of overflows of the 16-bit counter
+    pub fn overflows(&self) -> u32 {
+        self.overflows
+    }
+
+    /// Reset the count to zero
+    pub fn reset_count(&mut self) {
+        let lptim = regs::<LPTIM>();
+        lptim.cr().modify(|_, w| w.countrst().set_bit());
+        // The reset is synchronised to the kernel clock, and the bit is
+        // cleared once it is done
+        while lptim.cr().read().countrst().bit_is_set() {}
+        clear_pending::<LPTIM>(Event::Update);
+        self.overflows = 0;
+    }
+
+    /// Raise the channel 1 compare match interrupt after `pulses` more
+    /// pulses, which is reported by [`on_interrupt`](Self::on_interrupt).
+    /// This replaces any previous wakeup.
+    ///
+    /// Returns false if the pulses were already counted while the compare
+    /// value was being set, in which case no interrupt is raised.
+    pub fn wake_after(&mut self, pulses: u32) -> bool {
+        assert!(pulses > 0, "The number of pulses must be at least one");
+        let target = self.count() + pulses as u64;
+        self.target = Some(target);
+
+        set_ccr::<LPTIM>(1, target as u16);
+        clear_pending::<LPTIM>(Event::CaptureCompare1);
+        // Setting the compare value is synchronised to the kernel clock, so
+        // the target may have been passed before it took effect
+        if self.count() >= target {
+            self.cancel_wakeup();
+            return false;
+        }
+        set_interrupt::<LPTIM>(Event::CaptureCompare1, true);
+        true
+    }
+
+    /// Cancel the wakeup set by [`wake_after`](Self::wake_after)
+    pub fn cancel_wakeup(&mut self) {
+        self.target = None;
+        set_interrupt::<LPTIM>(Event::CaptureCompare1, false);
+        clear_pending::<LPTIM>(Event::CaptureCompare1);
+    }
+
+    /// Handle the interrupt of the timer, which must be called from its
+    /// interrupt handler. Returns true when the number of pulses set by
+    /// [`wake_after`](Self::wake_after) has been reached.
+    pub fn on_interrupt(&mut self) -> bool {
+        if is_pending::<LPTIM>(Event::Update) {
+            clear_pending::<LPTIM>(Event::Update);
+            self.overflows = self.overflows.wrapping_add(1);
+        }
+
+        if !is_pending::<LPTIM>(Event::CaptureCompare1) {
+            return false;
+        }
+        clear_pending::<LPTIM>(Event::CaptureCompare1);
+        // The compare value matches once per overflow, so only the match
+        // in the overflow of the target counts
+        match self.target {
+            Some(target) if self.count() >= target => {
+                self.cancel_wakeup();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> LPTIM {
+        use crate::rcc::ResetEnable;
+
+        let _ = LPTIM::rec().reset().disable();
+        self.lptim
+    }
+}
@@ -0,0 +1,219 @@
+//! PWM and set-once outputs of the low-power timers
+//!
+//! The counter of an [`LpPwm`] counts up to its auto-reload value, and each
+//! channel output is active while the counter is below the compare value
+//! of the channel. In set-once mode, each output only changes once, at the
+//! first compare match after the counter is started, which generates a
+//! single edge after a delay when the counter is started for a single
+//! period.
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+use super::{
+    arr, ccr, clear_pending, event_methods, init, is_pending, presc_arr,
+    reconfigure, regs, set_arr, set_ccr, set_enabled, set_interrupt,
+    start_continuous, Channel, Event, Instance, PinCh,
+};
+use crate::pwm::Polarity;
+use crate::rcc::CoreClocks;
+use crate::time::Hertz;
+
+/// The waveform generated on the channel outputs
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Waveform {
+    /// A PWM waveform, in each period
+    Pwm,
+    /// A single change of level at the first compare match, which is kept
+    /// until the counter is restarted
+    SetOnce,
+}
+
+/// A low-power timer configured for PWM, which controls the frequency
+/// shared by its channels
+#[derive(Debug)]
+pub struct LpPwm<LPTIM> {
+    lptim: LPTIM,
+    clock: Hertz,
+    continuous: bool,
+}
+
+/// A channel of a low-power timer configured as a PWM output
+#[derive(Debug)]
+pub struct LpPwmChannel<LPTIM, const C: u8> {
+    _lptim: PhantomData<LPTIM>,
+}
+
+impl<LPTIM: Instance> LpPwm<LPTIM> {
+    pub(super) fn new(
+        lptim: LPTIM,
+        frequency: Hertz,
+        rec: LPTIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        init::<LPTIM>(rec, |w| w);
+        set_enabled::<LPTIM>(true);
+
+        let mut pwm = LpPwm {
+            lptim,
+            clock: LPTIM::clock(clocks),
+            continuous: true,
+        };
+        pwm.set_frequency(frequency);
+        pwm
+    }
+
+    /// Set the PWM frequency. The duty cycle of each channel is not
+    /// rescaled, so it should be set again afterwards.
+    pub fn set_frequency(&mut self, frequency: Hertz) {
+        let ticks = self.clock.raw() as u64 / frequency.raw() as u64;
+        // The compare value must be able to exceed the auto-reload value
+        // for a 100% duty cycle
+        let (presc, arr) = presc_arr(ticks, u16::MAX - 1);
+
+        reconfigure::<LPTIM>(|lptim| {
+            // NOTE(unsafe) The prescaler is at most 7
+            lptim.cfgr().modify(|_, w| unsafe { w.presc().bits(presc) });
+        });
+        set_arr::<LPTIM>(arr);
+        if self.continuous {
+            start_continuous::<LPTIM>();
+        }
+    }
+
+    /// The current PWM frequency
+    pub fn frequency(&self) -> Hertz {
+        let presc = regs::<LPTIM>().cfgr().read().presc().bits();
+        let period = (arr::<LPTIM>() as u32 + 1) << presc;
+        Hertz::from_raw(self.clock.raw() / period)
+    }
+
+    /// The maximum duty cycle value of the channels, which corresponds to
+    /// a 100% duty cycle
+    pub fn max_duty(&self) -> u16 {
+        arr::<LPTIM>() + 1
+    }
+
+    /// Select the waveform of the outputs. The counter is stopped, and is
+    /// started again by [`resume`](Self::resume) or
+    /// [`start_single`](Self::start_single).
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.continuous = false;
+        reconfigure::<LPTIM>(|lptim| {
+            lptim
+                .cfgr()
+                .modify(|_, w| w.wave().bit(waveform == Waveform::SetOnce));
+        });
+    }
+
+    /// Count a single period and stop. If the counter is counting
+    /// continuously, it stops at the end of the current period.
+    pub fn start_single(&mut self) {
+        self.continuous = false;
+        regs::<LPTIM>().cr().modify(|_, w| w.sngstrt().set_bit());
+    }
+
+    /// Stop and reset the counter
+    pub fn pause(&mut self) {
+        self.continuous = false;
+        reconfigure::<LPTIM>(|_| {});
+    }
+
+    /// Count continuously after the counter was paused or stopped
+    pub fn resume(&mut self) {
+        self.continuous = true;
+        start_continuous::<LPTIM>();
+    }
+
+    event_methods!();
+}
+
+impl<LPTIM: Instance, const C: u8> Channel<LPTIM, C> {
+    /// Use this channel as a PWM output on `pin`. The output is disabled
+    /// and its duty cycle is zero until it is set.
+    pub fn pwm<PIN>(self, _pin: PIN) -> LpPwmChannel<LPTIM, C>
+    where
+        PIN: PinCh<LPTIM, C>,
+    {
+        assert!(C <= LPTIM::CHANNELS, "The timer does not have this channel");
+        let mut channel = LpPwmChannel {
+            _lptim: PhantomData,
+        };
+        channel.set_duty(0);
+        channel
+    }
+}
+
+impl<LPTIM: Instance, const C: u8> LpPwmChannel<LPTIM, C> {
+    /// Enable the channel output
+    pub fn enable(&mut self) {
+        let lptim = regs::<LPTIM>();
+        if C == 1 {
+            lptim.ccmr1().modify(|_, w| w.cc1e().set_bit());
+        } else {
+            lptim.ccmr1().modify(|_, w| w.cc2e().set_bit());
+        }
+    }
+
+    /// Disable the channel output
+    pub fn disable(&mut self) {
+        let lptim = regs::<LPTIM>();
+        if C == 1 {
+            lptim.ccmr1().modify(|_, w| w.cc1e().clear_bit());
+        } else {
+            lptim.ccmr1().modify(|_, w| w.cc2e().clear_bit());
+        }
+    }
+
+    /// Set the polarity of the channel output
+    pub fn set_polarity(&mut self, polarity: Polarity) {
+        let ccp = (polarity == Polarity::ActiveLow) as u8;
+        let lptim = regs::<LPTIM>();
+        // NOTE(unsafe) Only the low bit of the polarity is used for outputs
+        if C == 1 {
+            lptim.ccmr1().modify(|_, w| unsafe { w.cc1p().bits(ccp) });
+        } else {
+            lptim.ccmr1().modify(|_, w| unsafe { w.cc2p().bits(ccp) });
+        }
+    }
+
+    /// The maximum duty cycle value, which corresponds to a 100% duty cycle
+    pub fn max_duty(&self) -> u16 {
+        arr::<LPTIM>() + 1
+    }
+
+    /// The current duty cycle value
+    pub fn duty(&self) -> u16 {
+        ccr::<LPTIM>(C)
+    }
+
+    /// Set the duty cycle value, from 0 to [`max_duty`](Self::max_duty)
+    pub fn set_duty(&mut self, duty: u16) {
+        set_ccr::<LPTIM>(C, duty);
+    }
+
+    /// Returns the channel to the timer, so that it can be used for
+    /// something else. The output is disabled.
+    pub fn release(mut self) -> Channel<LPTIM, C> {
+        self.disable();
+        Channel::new()
+    }
+}
+
+impl<LPTIM: Instance, const C: u8> ErrorType for LpPwmChannel<LPTIM, C> {
+    type Error = Infallible;
+}
+
+impl<LPTIM: Instance, const C: u8> SetDutyCycle for LpPwmChannel<LPTIM, C> {
+    fn max_duty_cycle(&self) -> u16 {
+        self.max_duty()
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        self.set_duty(duty);
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Time driver for `embassy-time` on LPTIM1
+//!
+//! With the `time-driver-lptim1` feature, LPTIM1 is the time driver of
+//! `embassy-time`. Its kernel clock is normally the LSE, with the
+//! `tick-hz-32_768` feature of `embassy-time`, so that time keeps running in
+//! Stop mode. The kernel clock must be the tick rate multiplied by a power
+//! of two up to 128.
+//!
+//! The 16-bit counter is extended to 64 bits by counting half periods, as
+//! for the monotonic timers on TIM2 and TIM5. Channel 1 compares against the
+//! next alarm.
+//!
+//! As the counter keeps running in Stop mode, the idle loop of an executor
+//! can enter Stop mode with [`stop_until_alarm`] instead of waiting for
+//! the next SysTick. It must be called with interrupts disabled, once the
+//! executor has found no more work, so that an interrupt that wakes a task
+//! in between still wakes the system:
+//!
+//! ```
+//! let rec = ccdr.peripheral.LPTIM1.kernel_clk_mux(LpTim1ClkSel::LseKer);
+//! lptim::init_time_driver(dp.LPTIM1, rec, &ccdr.clocks);
+//!
+//! loop {
+//!     unsafe { executor.poll() };
+//!     cortex_m::interrupt::free(|_| {
+//!         if !WORK_PENDING.swap(false, Ordering::Relaxed) {
+//!             lptim::stop_until_alarm(&mut cp.SCB);
+//!         }
+//!     });
+//! }
+//! ```
+//!
+//! The interrupt is handled once the critical section ends, after the
+//! clocks configured by `freeze` have been restored.
+
+#[cfg(any(feature = "time-driver-tim2", feature = "time-driver-tim5"))]
+compile_error!("Only one timer can be the time driver");
+
+use cortex_m::peripheral::{NVIC, SCB};
+use embassy_time_driver::TICK_HZ;
+
+use super::{
+    clear_pending, counter, init, is_pending, set_arr, set_ccr, set_enabled,
+    set_interrupt, start_continuous, Event, Instance,
+};
+use crate::interrupt;
+use crate::rcc::{rec, CoreClocks};
+use crate::stm32::rcc::ccipr2::LPTIMSEL;
+use crate::stm32::{Interrupt, LPTIM1, RCC};
+use crate::timer::half_period::{HalfPeriodTimer, TimeDriver};
+
+/// The value of CCR2 halfway through the period of the counter
+const HALF_PERIOD: u16 = 1 << 15;
+
+impl HalfPeriodTimer for LPTIM1 {
+    const BITS: u32 = 16;
+
+    fn counter() -> u32 {
+        counter::<LPTIM1>() as u32
+    }
+
+    fn set_compare_value(ccr: u32) {
+        set_ccr::<LPTIM1>(1, ccr as u16);
+    }
+
+    fn take_half_periods() -> u32 {
+        let mut count = 0;
+        for event in [Event::Update, Event::CaptureCompare2] {
+            if is_pending::<LPTIM1>(event) {
+                clear_pending::<LPTIM1>(event);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    fn take_compare() -> bool {
+        let pending = is_pending::<LPTIM1>(Event::CaptureCompare1);
+        clear_pending::<LPTIM1>(Event::CaptureCompare1);
+        pending
+    }
+
+    /// Writes to DIER take a few cycles of the kernel clock, so the compare
+    /// interrupt stays enabled without an alarm, and only wakes the queue
+    /// once per period
+    fn listen_compare(_enable: bool) {}
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: TimeDriver<LPTIM1> = TimeDriver::new());
+
+/// Start the time driver and unmask the interrupt of LPTIM1. This will panic
+/// if the kernel clock of LPTIM1 is not the tick rate of `embassy-time`
+/// multiplied by a power of two up to 128.
+pub fn init_time_driver(_lptim: LPTIM1, rec: rec::LpTim1, clocks: &CoreClocks) {
+    let clock = LPTIM1::clock(clocks).raw() as u64;
+    let div = clock / TICK_HZ;
+    assert!(
+        div * TICK_HZ == clock && div.is_power_of_two() && div <= 128,
+        "The LPTIM1 clock is not a power of two multiple of the tick rate"
+    );
+    let presc = div.ilog2() as u8;
+
+    // NOTE(unsafe) The prescaler is at most 7
+    init::<LPTIM1>(rec, |w| unsafe { w.presc().bits(presc) });
+    set_enabled::<LPTIM1>(true);
+    set_arr::<LPTIM1>(u16::MAX);
+    set_ccr::<LPTIM1>(2, HALF_PERIOD);
+    for event in [
+        Event::Update,
+        Event::CaptureCompare2,
+        Event::CaptureCompare1,
+    ] {
+        set_interrupt::<LPTIM1>(event, true);
+    }
+    start_continuous::<LPTIM1>();
+
+    // NOTE(unsafe) The interrupt handler is defined here, and only uses the
+    // timer, which is owned by the time driver
+    unsafe { NVIC::unmask(Interrupt::LPTIM1) };
+}
+
+/// Enter Stop mode until the next alarm of the time driver, or any other
+/// interrupt, and restore the clocks configured by `freeze` on wakeup. The
+/// time driver wakes the system at least twice per period of its counter to
+/// count half periods.
+///
+/// This must be called with interrupts disabled, and will panic in debug
+/// builds if the kernel clock of LPTIM1 does not run in Stop mode.
+pub fn stop_until_alarm(scb: &mut SCB) {
+    // NOTE(unsafe) Atomic read with no side effects
+    let ck_sel = unsafe { (*RCC::ptr()).ccipr2().read() }
+        .lptim1sel()
+        .variant();
+    debug_assert!(
+        matches!(ck_sel, Some(LPTIMSEL::LseKer | LPTIMSEL::LsiKer)),
+        "The LPTIM1 clock is stopped in Stop mode"
+    );
+    crate::pwr::stop(scb);
+}
+
+#[interrupt]
+fn LPTIM1() {
+    DRIVER.on_interrupt();
+}
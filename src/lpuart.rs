@@ -0,0 +1,500 @@
+//! Low-power UART (LPUART)
+//!
+//! This module provides a blocking driver for the LPUART1 peripheral. Unlike
+//! the USARTs, the LPUART can keep receiving while the system is in Stop
+//! mode when it is clocked from the LSE (or HSI/CSI), and can wake the system
+//! when a start bit, a complete character or a matching address is
+//! received. This allows a battery-powered node to sleep until a host sends
+//! it a command.
+//!
+//! [`LpUart`] implements the [embedded-io][embedded_io] `Read` and `Write`
+//! traits as well as the [embedded-hal-nb][embedded_hal_nb] serial traits,
+//! and can be split into separate [`Tx`] and [`Rx`] halves. The frame format
+//! types and the pin traits are shared with the [`serial`](crate::serial)
+//! module.
+//!
+//! # Usage
+//!
+//! The LPUART is initialised in the same way as a USART. To run at 9600
+//! baud from a 32.768 kHz LSE crystal, the LSE must be enabled when the RCC
+//! is frozen and selected as the LPUART kernel clock:
+//!
+//! ```
+//! use stm32h5xx_hal::lpuart::{Config, WakeupEvent};
+//! use stm32h5xx_hal::rcc::rec::LpUart1ClkSel;
+//!
+//! let ccdr = rcc.lse_ck(32_768.Hz()).freeze(pwrcfg, &dp.SBS);
+//! let rec = ccdr.peripheral.LPUART1.kernel_clk_mux(LpUart1ClkSel::Lse);
+//!
+//! let config = Config::new(9600.Hz()).wakeup_from_stop(WakeupEvent::StartBit);
+//! let mut lpuart = dp.LPUART.lpuart((tx, rx), config, rec, &ccdr.clocks);
+//! ```
+//!
+//! ## Wakeup from Stop mode
+//!
+//! When a wakeup event is configured, the LPUART sets its wakeup flag when
+//! the event is detected in Stop mode. To leave Stop mode, the wakeup
+//! interrupt must be enabled and the LPUART1 interrupt unmasked in the NVIC.
+//! [`pwr::stop`](crate::pwr::stop) restores the system clocks before
+//! returning:
+//!
+//! ```
+//! lpuart.listen(Event::Wakeup);
+//! unsafe { NVIC::unmask(interrupt::LPUART1) };
+//!
+//! pwr::stop(&mut cp.SCB);
+//! lpuart.clear_wakeup();
+//!
+//! let mut buf = [0u8; 16];
+//! let n = lpuart.read(&mut buf)?;
+//! ```
+//!
+//! # Examples
+//!
+//! - [Waking from Stop mode on LPUART reception](https://github.com/stm32-rs/stm32h5xx-hal/blob/master/examples/lpuart_stop.rs)
+//!
+//! [embedded_io]: https://docs.rs/embedded-io/0.6.1/embedded_io/
+//! [embedded_hal_nb]: https://docs.rs/embedded-hal-nb/1.0.0/embedded_hal_nb/serial/index.html
+
+use core::marker::PhantomData;
+
+use embedded_hal_nb::nb;
+
+use crate::rcc::{rec, CoreClocks, ResetEnable};
+use crate::serial::{
+    AddressLength, BitOrder, Parity, Pins, StopBits, WordLength,
+};
+use crate::stm32::rcc::ccipr1::USARTSEL;
+use crate::stm32::{lpuart, LPUART, RCC};
+use crate::time::Hertz;
+use lpuart::presc::PRESCALER;
+
+mod config;
+mod hal;
+
+pub use crate::serial::Error;
+pub use config::{Config, WakeupEvent};
+
+/// Interrupt events
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A character was received (RXNE/RXFNE)
+    RxNotEmpty,
+    /// The transmit register can accept a new character (TXE/TXFNF)
+    TxNotFull,
+    /// Transmission of the last character is complete (TC)
+    TransmissionComplete,
+    /// An idle line was detected (IDLE)
+    Idle,
+    /// The configured wakeup event was detected in Stop mode (WUF)
+    Wakeup,
+}
+
+/// Low-power UART
+#[derive(Debug)]
+pub struct LpUart {
+    lpuart: LPUART,
+    tx: Tx,
+    rx: Rx,
+}
+
+/// Transmitter half of the LPUART
+#[derive(Debug)]
+pub struct Tx {
+    _lpuart: PhantomData<LPUART>,
+}
+
+/// Receiver half of the LPUART
+#[derive(Debug)]
+pub struct Rx {
+    _lpuart: PhantomData<LPUART>,
+    mask: u16,
+}
+
+pub trait LpUartExt: Sized {
+    fn lpuart<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: rec::LpUart1,
+        clocks: &CoreClocks,
+    ) -> LpUart
+    where
+        PINS: Pins<LPUART>,
+        CONFIG: Into<Config>;
+
+    fn lpuart_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: rec::LpUart1,
+        clocks: &CoreClocks,
+    ) -> LpUart
+    where
+        CONFIG: Into<Config>;
+}
+
+impl LpUartExt for LPUART {
+    fn lpuart<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: rec::LpUart1,
+        clocks: &CoreClocks,
+    ) -> LpUart
+    where
+        PINS: Pins<LPUART>,
+        CONFIG: Into<Config>,
+    {
+        LpUart::new(self, config, rec, clocks)
+    }
+
+    fn lpuart_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: rec::LpUart1,
+        clocks: &CoreClocks,
+    ) -> LpUart
+    where
+        CONFIG: Into<Config>,
+    {
+        LpUart::new(self, config, rec, clocks)
+    }
+}
+
+/// Baud rate generator settings
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct BaudRate {
+    presc: PRESCALER,
+    brr: u32,
+}
+
+const PRESCALERS: [(PRESCALER, u32); 12] = [
+    (PRESCALER::Div1, 1),
+    (PRESCALER::Div2, 2),
+    (PRESCALER::Div4, 4),
+    (PRESCALER::Div6, 6),
+    (PRESCALER::Div8, 8),
+    (PRESCALER::Div10, 10),
+    (PRESCALER::Div12, 12),
+    (PRESCALER::Div16, 16),
+    (PRESCALER::Div32, 32),
+    (PRESCALER::Div64, 64),
+    (PRESCALER::Div128, 128),
+    (PRESCALER::Div256, 256),
+];
+
+/// Calculate the prescaler and BRR value to generate `baud` from
+/// `lpuart_ker_ck`. The smallest prescaler is chosen for the best
+/// resolution. BRR must be at least 0x300 and fit in 20 bits, so the baud
+/// rate must be between 1/4096 and 1/3 of the prescaled kernel clock.
+fn calc_baud_rate(lpuart_ker_ck: u32, baud: u32) -> Option<BaudRate> {
+    if baud == 0 {
+        return None;
+    }
+
+    for (presc, div) in PRESCALERS {
+        let ck = (lpuart_ker_ck / div) as u64;
+        let brr = (256 * ck + baud as u64 / 2) / baud as u64;
+        if brr < 0x300 {
+            return None;
+        }
+        if brr < (1 << 20) {
+            return Some(BaudRate {
+                presc,
+                brr: brr as u32,
+            });
+        }
+    }
+    None
+}
+
+fn clock(clocks: &CoreClocks) -> Hertz {
+    let ccipr3 = unsafe { (*RCC::ptr()).ccipr3().read() };
+    let ck_sel = ccipr3
+        .lpuart1sel()
+        .variant()
+        .expect("No source clock selected");
+    match ck_sel {
+        USARTSEL::Pclk => Some(clocks.pclk3()),
+        USARTSEL::Pll2Q => clocks.pll2().q_ck(),
+        #[cfg(feature = "rm0481")]
+        USARTSEL::Pll3Q => clocks.pll3().q_ck(),
+        USARTSEL::HsiKer => clocks.hsi_ck(),
+        USARTSEL::CsiKer => clocks.csi_ck(),
+        USARTSEL::Lse => clocks.lse_ck(),
+    }
+    .expect("Source clock not enabled")
+}
+
+impl LpUart {
+    fn new(
+        lpuart: LPUART,
+        config: impl Into<Config>,
+        rec: rec::LpUart1,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let config: Config = config.into();
+        let _ = rec.enable().reset();
+
+        let lpuart = LpUart {
+            lpuart,
+            tx: Tx {
+                _lpuart: PhantomData,
+            },
+            rx: Rx {
+                _lpuart: PhantomData,
+                mask: (1 << config.word_length.bits()) - 1,
+            },
+        };
+        lpuart.init(config, clock(clocks))
+    }
+
+    fn init(self, config: Config, clock: Hertz) -> Self {
+        assert!(
+            !(config.word_length == WordLength::DataBits9
+                && config.parity != Parity::ParityNone),
+            "9 data bits cannot be used together with a parity bit"
+        );
+
+        let baud = calc_baud_rate(clock.raw(), config.baudrate.raw())
+            .expect("Baud rate cannot be generated from the kernel clock");
+
+        // The configuration registers can only be written while the LPUART
+        // is disabled
+        self.lpuart.cr1().reset();
+        self.lpuart.cr2().reset();
+        self.lpuart.cr3().reset();
+
+        self.lpuart
+            .presc()
+            .write(|w| w.prescaler().variant(baud.presc));
+        self.lpuart
+            .brr()
+            .write(|w| unsafe { w.brr().bits(baud.brr) });
+
+        let (address, addm7) = match config.wakeup {
+            Some(WakeupEvent::AddressMatch { address, length }) => {
+                let addm7 = length == AddressLength::Bits7;
+                let bits = if addm7 {
+                    config.word_length.bits() - 1
+                } else {
+                    4
+                };
+                assert!(
+                    (address as u16) < (1 << bits),
+                    "Node address must fit in {bits} bits"
+                );
+                (address, addm7)
+            }
+            _ => (0, false),
+        };
+
+        self.lpuart.cr2().write(|w| {
+            unsafe { w.add().bits(address) };
+            w.addm7().bit(addm7);
+            match config.stop_bits {
+                StopBits::Stop1 => w.stop().stop1(),
+                StopBits::Stop2 => w.stop().stop2(),
+                // Rejected by `Config::stop_bits`
+                StopBits::Stop0p5 | StopBits::Stop1p5 => {
+                    unreachable!("LPUART only supports 1 or 2 stop bits")
+                }
+            };
+            w.msbfirst()
+                .bit(config.bit_order == BitOrder::MsbFirst)
+                .swap()
+                .bit(config.swap_tx_rx)
+        });
+
+        if let Some(event) = config.wakeup {
+            self.lpuart.cr3().write(|w| match event {
+                WakeupEvent::StartBit => w.wus().start(),
+                WakeupEvent::RxNotEmpty => w.wus().rxne(),
+                WakeupEvent::AddressMatch { .. } => w.wus().address(),
+            });
+        }
+
+        let (m1, m0) = match config.frame_bits() {
+            7 => (true, false),
+            8 => (false, false),
+            _ => (false, true),
+        };
+
+        self.lpuart.cr1().write(|w| {
+            w.m1()
+                .bit(m1)
+                .m0()
+                .bit(m0)
+                .pce()
+                .bit(config.parity != Parity::ParityNone)
+                .ps()
+                .bit(config.parity == Parity::ParityOdd)
+                .fifoen()
+                .bit(config.fifo)
+                .uesm()
+                .bit(config.wakeup.is_some())
+                .te()
+                .enabled()
+                .re()
+                .enabled()
+                .ue()
+                .enabled()
+        });
+
+        self
+    }
+
+    /// Start listening for an interrupt event
+    pub fn listen(&mut self, event: Event) {
+        match event {
+            Event::RxNotEmpty => {
+                self.lpuart.cr1().modify(|_, w| w.rxneie().enabled())
+            }
+            Event::TxNotFull => {
+                self.lpuart.cr1().modify(|_, w| w.txeie().enabled())
+            }
+            Event::TransmissionComplete => {
+                self.lpuart.cr1().modify(|_, w| w.tcie().enabled())
+            }
+            Event::Idle => {
+                self.lpuart.cr1().modify(|_, w| w.idleie().enabled())
+            }
+            Event::Wakeup => {
+                self.lpuart.cr3().modify(|_, w| w.wufie().enabled())
+            }
+        };
+    }
+
+    /// Stop listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        match event {
+            Event::RxNotEmpty => {
+                self.lpuart.cr1().modify(|_, w| w.rxneie().disabled())
+            }
+            Event::TxNotFull => {
+                self.lpuart.cr1().modify(|_, w| w.txeie().disabled())
+            }
+            Event::TransmissionComplete => {
+                self.lpuart.cr1().modify(|_, w| w.tcie().disabled())
+            }
+            Event::Idle => {
+                self.lpuart.cr1().modify(|_, w| w.idleie().disabled())
+            }
+            Event::Wakeup => {
+                self.lpuart.cr3().modify(|_, w| w.wufie().disabled())
+            }
+        };
+        interrupt_clear_clock_sync_delay!(self.lpuart.cr3());
+    }
+
+    /// Returns true if the line idle status is set
+    pub fn is_idle(&self) -> bool {
+        self.lpuart.isr().read().idle().bit_is_set()
+    }
+
+    /// Clear the line idle status flag
+    pub fn clear_idle(&mut self) {
+        self.lpuart.icr().write(|w| w.idlecf().clear());
+        interrupt_clear_clock_sync_delay!(self.lpuart.isr());
+    }
+
+    /// Returns true if the wakeup event was detected
+    pub fn is_wakeup(&self) -> bool {
+        self.lpuart.isr().read().wuf().bit_is_set()
+    }
+
+    /// Clear the wakeup flag. This must be done before entering Stop mode
+    /// again.
+    pub fn clear_wakeup(&mut self) {
+        self.lpuart.icr().write(|w| w.wucf().clear());
+        interrupt_clear_clock_sync_delay!(self.lpuart.isr());
+    }
+
+    /// Returns true while a character is being received. Stop mode should
+    /// not be entered until reception is complete.
+    pub fn is_busy(&self) -> bool {
+        self.lpuart.isr().read().busy().bit_is_set()
+    }
+
+    /// Returns true if there is a received character waiting to be read
+    pub fn is_rx_not_empty(&self) -> bool {
+        self.rx.is_rx_not_empty()
+    }
+
+    /// Returns true if the transmitter can accept another character
+    pub fn is_tx_not_full(&self) -> bool {
+        self.tx.is_tx_not_full()
+    }
+
+    /// Returns true if transmission of all queued characters is complete
+    pub fn is_tx_complete(&self) -> bool {
+        self.tx.is_tx_complete()
+    }
+
+    /// Split the LPUART into transmitter and receiver halves
+    pub fn split(self) -> (Tx, Rx) {
+        (self.tx, self.rx)
+    }
+
+    /// Disable the peripheral and return it
+    pub fn free(self) -> LPUART {
+        self.lpuart.cr1().reset();
+        self.lpuart
+    }
+
+    pub fn inner(&self) -> &LPUART {
+        &self.lpuart
+    }
+
+    pub fn inner_mut(&mut self) -> &mut LPUART {
+        &mut self.lpuart
+    }
+}
+
+impl Tx {
+    fn lpuart(&self) -> &lpuart::RegisterBlock {
+        // NOTE(unsafe) Tx only ever accesses the transmit related bits
+        unsafe { &*LPUART::ptr() }
+    }
+
+    crate::serial::tx_methods!(lpuart);
+}
+
+impl Rx {
+    fn lpuart(&self) -> &lpuart::RegisterBlock {
+        // NOTE(unsafe) Rx only ever accesses the receive related bits
+        unsafe { &*LPUART::ptr() }
+    }
+
+    crate::serial::rx_methods!(lpuart);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_baud_rate() {
+        // 32.768 kHz LSE, 9600 baud
+        assert_eq!(
+            calc_baud_rate(32_768, 9600),
+            Some(BaudRate {
+                presc: PRESCALER::Div1,
+                brr: 874
+            })
+        );
+
+        // 250 MHz PCLK, 9600 baud needs a prescaler to fit BRR
+        assert_eq!(
+            calc_baud_rate(250_000_000, 9600),
+            Some(BaudRate {
+                presc: PRESCALER::Div8,
+                brr: 833_333
+            })
+        );
+
+        // LSE cannot generate more than a third of its frequency
+        assert_eq!(calc_baud_rate(32_768, 19_200), None);
+    }
+}
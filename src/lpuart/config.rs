@@ -0,0 +1,145 @@
+use crate::serial::{AddressLength, BitOrder, Parity, StopBits, WordLength};
+use crate::time::Hertz;
+
+/// The event that wakes the system from Stop mode
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeupEvent {
+    /// Wake up on detection of a start bit. The character that follows is
+    /// received once the clocks have been restored.
+    StartBit,
+    /// Wake up once a complete character has been received
+    RxNotEmpty,
+    /// Wake up when an address character matching the node address is
+    /// received. Address characters are those with the most significant data
+    /// bit set.
+    AddressMatch {
+        /// The address of this node
+        address: u8,
+        /// Number of address bits to compare
+        length: AddressLength,
+    },
+}
+
+/// A structure for specifying the LPUART configuration.
+///
+/// This structure uses builder semantics to generate the configuration.
+///
+/// `Example`
+/// ```
+/// use stm32h5xx_hal::lpuart::{Config, WakeupEvent};
+///
+/// let config = Config::new(9600.Hz())
+///     .wakeup_from_stop(WakeupEvent::StartBit);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub(super) baudrate: Hertz,
+    pub(super) word_length: WordLength,
+    pub(super) parity: Parity,
+    pub(super) stop_bits: StopBits,
+    pub(super) bit_order: BitOrder,
+    pub(super) swap_tx_rx: bool,
+    pub(super) fifo: bool,
+    pub(super) wakeup: Option<WakeupEvent>,
+}
+
+impl Config {
+    /// Create a default configuration for the LPUART interface: 8 data bits,
+    /// no parity, 1 stop bit and no wakeup from Stop mode.
+    ///
+    /// Arguments:
+    /// * `baudrate` - The baud rate to configure.
+    pub fn new(baudrate: Hertz) -> Self {
+        Config {
+            baudrate,
+            word_length: WordLength::DataBits8,
+            parity: Parity::ParityNone,
+            stop_bits: StopBits::Stop1,
+            bit_order: BitOrder::LsbFirst,
+            swap_tx_rx: false,
+            fifo: false,
+            wakeup: None,
+        }
+    }
+
+    /// Set the baud rate
+    #[must_use]
+    pub fn baudrate(mut self, baudrate: Hertz) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+
+    /// Set the number of data bits in each character
+    #[must_use]
+    pub fn word_length(mut self, word_length: WordLength) -> Self {
+        self.word_length = word_length;
+        self
+    }
+
+    /// Set the parity mode
+    #[must_use]
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Set the number of stop bits. Only 1 or 2 stop bits are supported by
+    /// the LPUART.
+    #[must_use]
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        assert!(
+            matches!(stop_bits, StopBits::Stop1 | StopBits::Stop2),
+            "LPUART only supports 1 or 2 stop bits"
+        );
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Set the order in which data bits are transmitted
+    #[must_use]
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    /// Swap the functions of the TX and RX pins
+    #[must_use]
+    pub fn swap_tx_rx(mut self) -> Self {
+        self.swap_tx_rx = true;
+        self
+    }
+
+    /// Enable or disable the 8-character transmit and receive FIFOs
+    #[must_use]
+    pub fn fifo(mut self, enable: bool) -> Self {
+        self.fifo = enable;
+        self
+    }
+
+    /// Allow the LPUART to wake the system from Stop mode on the given
+    /// event. The kernel clock must be one that is available in Stop mode,
+    /// such as the LSE.
+    #[must_use]
+    pub fn wakeup_from_stop(mut self, event: WakeupEvent) -> Self {
+        self.wakeup = Some(event);
+        self
+    }
+
+    /// Number of bits in a character frame as configured in the M bits: the
+    /// data bits plus the parity bit, if any.
+    pub(super) fn frame_bits(&self) -> u8 {
+        let parity_bit = match self.parity {
+            Parity::ParityNone => 0,
+            _ => 1,
+        };
+        self.word_length.bits() + parity_bit
+    }
+}
+
+impl From<Hertz> for Config {
+    fn from(baudrate: Hertz) -> Self {
+        Config::new(baudrate)
+    }
+}
@@ -0,0 +1,156 @@
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial;
+
+use super::{Error, LpUart, Rx, Tx};
+
+macro_rules! error_type {
+    ($($Type:ident),+) => {
+        $(
+            impl serial::ErrorType for $Type {
+                type Error = Error;
+            }
+
+            impl embedded_io::ErrorType for $Type {
+                type Error = Error;
+            }
+        )+
+    };
+}
+
+error_type!(LpUart, Tx, Rx);
+
+impl serial::Write<u8> for Tx {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.write_word(word as u16)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.is_tx_complete() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl serial::Write<u16> for Tx {
+    fn write(&mut self, word: u16) -> nb::Result<(), Self::Error> {
+        self.write_word(word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        serial::Write::<u8>::flush(self)
+    }
+}
+
+impl serial::Read<u8> for Rx {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.read_word().map(|word| word as u8)
+    }
+}
+
+impl serial::Read<u16> for Rx {
+    fn read(&mut self) -> nb::Result<u16, Self::Error> {
+        self.read_word()
+    }
+}
+
+impl embedded_io::Write for Tx {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        nb::block!(self.write_word(buf[0] as u16))?;
+        let mut count = 1;
+        for &byte in &buf[1..] {
+            match self.write_word(byte as u16) {
+                Ok(()) => count += 1,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(err)) => return Err(err),
+            }
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.bflush()
+    }
+}
+
+impl embedded_io::WriteReady for Tx {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_tx_not_full())
+    }
+}
+
+impl embedded_io::Read for Rx {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.bread(buf)
+    }
+}
+
+impl embedded_io::ReadReady for Rx {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_rx_not_empty())
+    }
+}
+
+impl serial::Write<u8> for LpUart {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        serial::Write::write(&mut self.tx, word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        serial::Write::<u8>::flush(&mut self.tx)
+    }
+}
+
+impl serial::Write<u16> for LpUart {
+    fn write(&mut self, word: u16) -> nb::Result<(), Self::Error> {
+        self.tx.write_word(word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        serial::Write::<u8>::flush(&mut self.tx)
+    }
+}
+
+impl serial::Read<u8> for LpUart {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        serial::Read::read(&mut self.rx)
+    }
+}
+
+impl serial::Read<u16> for LpUart {
+    fn read(&mut self) -> nb::Result<u16, Self::Error> {
+        self.rx.read_word()
+    }
+}
+
+impl embedded_io::Write for LpUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(&mut self.tx, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.tx.bflush()
+    }
+}
+
+impl embedded_io::WriteReady for LpUart {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_tx_not_full())
+    }
+}
+
+impl embedded_io::Read for LpUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.rx.bread(buf)
+    }
+}
+
+impl embedded_io::ReadReady for LpUart {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_rx_not_empty())
+    }
+}
@@ -0,0 +1,457 @@
+//! One-pulse mode
+//!
+//! In one-pulse mode, the counter of a general-purpose or advanced-control
+//! timer is started by a trigger, and generates a single pulse on its
+//! output channels a programmable delay after the trigger. The counter then
+//! stops until the next trigger, so the pulse timing is handled entirely in
+//! hardware.
+//!
+//! # Usage
+//!
+//! A timer is configured for one-pulse mode from the device peripheral, a
+//! counter frequency (or a [`Config`]) and the peripheral's reset and
+//! enable control. The counter frequency sets the resolution of the delay
+//! and pulse width. This returns a [`OnePulse`] that controls the counter
+//! and the trigger, and the timer's [`Channels`]. On the timers with a
+//! slave mode controller ([`SlaveInstance`]), the trigger input can be a
+//! channel input, the external trigger input (ETR), or the trigger output
+//! of another timer. The other timers are only triggered by software:
+//!
+//! ```
+//! use stm32h5xx_hal::capture::Edge;
+//!
+//! let (mut opm, channels) = dp.TIM3.one_pulse(10.MHz(), ccdr.peripheral.TIM3, &ccdr.clocks);
+//!
+//! // Channel 1 is used as the trigger input, so it cannot be an output
+//! opm.trigger_on_ti1(channels.ch1, pa6.into_alternate(), Edge::Rising);
+//! opm.set_pulse(2.micros(), 500.nanos());
+//!
+//! let mut laser = channels.ch2.one_pulse(pa7.into_alternate());
+//! laser.enable();
+//! ```
+//!
+//! The delay is measured from the trigger edge, after it was synchronised
+//! to the timer clock, so it has a latency of a few cycles of the timer
+//! clock. Channels can have different delays, set with
+//! [`OnePulseChannel::set_delay`], but their pulses all end at the same
+//! time.
+//!
+//! # Retriggerable one-pulse mode
+//!
+//! With [`Config::retriggerable`], a trigger during a pulse restarts the
+//! counter, so that the pulse is extended. The pulse then ends when no
+//! trigger has been detected for the whole pulse width. This can be used
+//! as a watchdog for a periodic signal, and the delay can be zero so that
+//! the pulse starts as soon as the trigger is detected.
+
+use core::marker::PhantomData;
+
+use crate::capture::Edge;
+use crate::pwm::Polarity;
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::tim2;
+use crate::time::{Hertz, NanoSeconds};
+use crate::timer::sync::master_methods;
+use crate::timer::{
+    clear_pending, counter_prescaler, internal_trigger_selection, is_pending,
+    select_trigger, set_interrupt, set_main_output, Channel, Channels, Event,
+    Instance, InternalTrigger, MasterInstance, PinCh, PinEtr, SlaveInstance,
+};
+
+/// One-pulse mode configuration
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    frequency: Hertz,
+    retriggerable: bool,
+}
+
+impl Config {
+    /// Count at `frequency`, which sets the resolution of the delay and
+    /// pulse width
+    pub fn new(frequency: Hertz) -> Self {
+        Config {
+            frequency,
+            retriggerable: false,
+        }
+    }
+
+    /// Restart the counter on triggers during a pulse, which extends the
+    /// pulse
+    pub fn retriggerable(mut self, retriggerable: bool) -> Self {
+        self.retriggerable = retriggerable;
+        self
+    }
+}
+
+impl From<Hertz> for Config {
+    fn from(frequency: Hertz) -> Self {
+        Config::new(frequency)
+    }
+}
+
+/// A timer configured in one-pulse mode, which controls the counter and
+/// the trigger input
+#[derive(Debug)]
+pub struct OnePulse<TIM> {
+    tim: TIM,
+    frequency: Hertz,
+    retriggerable: bool,
+}
+
+/// A capture/compare channel of a timer in one-pulse mode, which outputs
+/// the pulse
+#[derive(Debug)]
+pub struct OnePulseChannel<TIM, const C: u8> {
+    _tim: PhantomData<TIM>,
+}
+
+pub trait OnePulseExt<TIM: Instance>: Sized {
+    fn one_pulse<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (OnePulse<TIM>, Channels<TIM>)
+    where
+        CONFIG: Into<Config>;
+}
+
+impl<TIM: Instance> OnePulseExt<TIM> for TIM {
+    fn one_pulse<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (OnePulse<TIM>, Channels<TIM>)
+    where
+        CONFIG: Into<Config>,
+    {
+        (OnePulse::new(self, config, rec, clocks), Channels::new())
+    }
+}
+
+#[inline(always)]
+fn regs<TIM: Instance>() -> &'static tim2::RegisterBlock {
+    // NOTE(unsafe) The registers used here are at the same offsets in all
+    // timers
+    unsafe { &*TIM::ptr() }
+}
+
+/// The number of ticks at `frequency` in `duration`, rounded to the nearest
+/// tick
+fn nanos_to_ticks(duration: NanoSeconds, frequency: Hertz) -> u64 {
+    (duration.ticks() as u64 * frequency.raw() as u64 + 500_000_000)
+        / 1_000_000_000
+}
+
+/// Calculate the compare and auto-reload values for a pulse of `width`
+/// ticks, `delay` ticks after the trigger. The counter stops at 0 after the
+/// pulse, so the compare value is at least `min_delay` to keep the output
+/// inactive, and the auto-reload value is at least 1.
+fn pulse_ccr_arr(
+    delay: u64,
+    width: u64,
+    min_delay: u64,
+    max_arr: u32,
+) -> (u32, u32) {
+    let ccr = delay.max(min_delay);
+    let arr = ccr + width.max(1) - 1;
+    assert!(
+        (1..=max_arr as u64).contains(&arr),
+        "The pulse cannot be generated at the counter frequency"
+    );
+    (ccr as u32, arr as u32)
+}
+
+/// Put channel `c` in the output mode that generates the pulse, with the
+/// output disabled
+fn set_output_mode<TIM: Instance>(c: u8, retriggerable: bool) {
+    let tim = regs::<TIM>();
+    let n = (c - 1) % 2;
+    // Retriggerable OPM mode 2 is OCxM = 1001, which is the extended
+    // variant of active on match
+    if c <= 2 {
+        tim.ccmr1_output().modify(|_, w| {
+            let w = w.ccs(n).output();
+            if retriggerable {
+                w.ocm(n).active_on_match().ocm_3(n).extended()
+            } else {
+                w.ocm(n).pwm_mode2().ocm_3(n).normal()
+            }
+        });
+    } else {
+        tim.ccmr2_output().modify(|_, w| {
+            let w = w.ccs(n).output();
+            if retriggerable {
+                w.ocm(n).active_on_match().ocm_3(n).extended()
+            } else {
+                w.ocm(n).pwm_mode2().ocm_3(n).normal()
+            }
+        });
+    }
+}
+
+impl<TIM: Instance> OnePulse<TIM> {
+    fn new(
+        tim: TIM,
+        config: impl Into<Config>,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let config: Config = config.into();
+        let _ = rec.enable().reset();
+
+        let clock = TIM::clock(clocks);
+        let psc = counter_prescaler(clock, config.frequency);
+
+        let tim_regs = regs::<TIM>();
+        tim_regs.psc().write(|w| w.psc().set((psc - 1) as u16));
+        tim_regs.arr().write(|w| w.arr().set(1));
+        // The counter stops at the end of each pulse, and only counter
+        // overflows generate an update event
+        tim_regs
+            .cr1()
+            .write(|w| w.opm().set_bit().urs().set_bit().arpe().set_bit());
+        tim_regs.egr().write(|w| w.ug().set_bit());
+
+        for c in 1..=TIM::CHANNELS {
+            set_output_mode::<TIM>(c, config.retriggerable);
+            tim_regs.ccr(c as usize - 1).write(|w| w.ccr().set(1));
+        }
+
+        // Channels of timers with a break and dead-time register only
+        // output while the main output is enabled
+        if TIM::HAS_BDTR {
+            set_main_output::<TIM>(true);
+        }
+
+        OnePulse {
+            tim,
+            frequency: Hertz::from_raw(clock.raw() / psc),
+            retriggerable: config.retriggerable,
+        }
+    }
+
+    /// Start a pulse now, as if a trigger was detected
+    pub fn trigger(&mut self) {
+        regs::<TIM>().cr1().modify(|_, w| w.cen().enabled());
+    }
+
+    /// Whether a pulse is in progress
+    pub fn is_running(&self) -> bool {
+        regs::<TIM>().cr1().read().cen().is_enabled()
+    }
+
+    /// The counter frequency, which sets the resolution of the delay and
+    /// pulse width
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Set the delay from the trigger to the start of the pulse, and the
+    /// width of the pulse, for all channels. This takes effect from the
+    /// next pulse.
+    ///
+    /// Outside of retriggerable mode, the delay is at least one counter
+    /// tick. This will panic if the pulse does not fit in the range of the
+    /// counter.
+    pub fn set_pulse(&mut self, delay: NanoSeconds, width: NanoSeconds) {
+        let (ccr, arr) = pulse_ccr_arr(
+            nanos_to_ticks(delay, self.frequency),
+            nanos_to_ticks(width, self.frequency),
+            if self.retriggerable { 0 } else { 1 },
+            TIM::MAX_ARR,
+        );
+        let tim = regs::<TIM>();
+        tim.arr().write(|w| w.arr().set(arr));
+        for c in 0..TIM::CHANNELS as usize {
+            tim.ccr(c).write(|w| w.ccr().set(ccr));
+        }
+    }
+
+    /// Start listening for `event`. [`Event::Update`] is generated at the
+    /// end of each pulse.
+    pub fn listen(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, true);
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, false);
+    }
+
+    /// Whether `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        is_pending::<TIM>(event)
+    }
+
+    /// Clear the pending flag of `event`
+    pub fn clear_pending(&mut self, event: Event) {
+        clear_pending::<TIM>(event);
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> TIM {
+        let _ = TIM::rec().reset().disable();
+        self.tim
+    }
+}
+
+// The trigger input is selected by the slave mode controller, so the timers
+// without one can only be triggered by software
+impl<TIM: SlaveInstance> OnePulse<TIM> {
+    /// Start the counter on the triggers selected by `ts`
+    fn set_trigger(&mut self, ts: u8) {
+        let tim = regs::<TIM>();
+        // Disable the slave mode while the trigger is changed
+        tim.smcr().modify(|_, w| w.sms().set(0).sms_3().clear_bit());
+        select_trigger::<TIM>(ts);
+        tim.smcr().modify(|_, w| {
+            if self.retriggerable {
+                // Combined reset and trigger mode
+                w.sms().set(0b000).sms_3().set_bit()
+            } else {
+                // Trigger mode
+                w.sms().set(0b110)
+            }
+        });
+    }
+
+    /// Start a pulse on `edge` of `pin`, through channel 1. `Edge::Both` is
+    /// supported.
+    pub fn trigger_on_ti1<PIN>(
+        &mut self,
+        _ch: Channel<TIM, 1>,
+        _pin: PIN,
+        edge: Edge,
+    ) where
+        PIN: PinCh<TIM, 1>,
+    {
+        let tim = regs::<TIM>();
+        tim.ccmr1_input().modify(|_, w| w.cc1s().ti1());
+        tim.ccer()
+            .modify(|_, w| w.ccp(0).bit(edge == Edge::Falling));
+        // Both edges are selected with TI1F_ED, and single edges with
+        // TI1FP1
+        self.set_trigger(if edge == Edge::Both { 0b00100 } else { 0b00101 });
+    }
+
+    /// Start a pulse on `edge` of `pin`, through channel 2. `Edge::Both` is
+    /// not supported.
+    pub fn trigger_on_ti2<PIN>(
+        &mut self,
+        _ch: Channel<TIM, 2>,
+        _pin: PIN,
+        edge: Edge,
+    ) where
+        PIN: PinCh<TIM, 2>,
+    {
+        assert!(edge != Edge::Both, "TI2 can only trigger on one edge");
+        let tim = regs::<TIM>();
+        tim.ccmr1_input().modify(|_, w| w.cc2s().ti2());
+        tim.ccer()
+            .modify(|_, w| w.ccp(1).bit(edge == Edge::Falling));
+        self.set_trigger(0b00110);
+    }
+
+    /// Start a pulse on `edge` of the external trigger input `pin`.
+    /// `Edge::Both` is not supported.
+    pub fn trigger_on_etr<PIN>(&mut self, _pin: PIN, edge: Edge)
+    where
+        PIN: PinEtr<TIM>,
+    {
+        assert!(edge != Edge::Both, "ETR can only trigger on one edge");
+        regs::<TIM>()
+            .smcr()
+            .modify(|_, w| w.etp().bit(edge == Edge::Falling));
+        self.set_trigger(0b00111);
+    }
+
+    /// Start a pulse on the trigger output of timer `M`
+    pub fn trigger_on_internal<M>(&mut self)
+    where
+        M: InternalTrigger<TIM>,
+    {
+        self.set_trigger(internal_trigger_selection(M::ITR));
+    }
+}
+
+impl<TIM: Instance + MasterInstance> OnePulse<TIM> {
+    master_methods!();
+}
+
+impl<TIM: Instance, const C: u8> Channel<TIM, C> {
+    /// Use this channel to output the pulse on `pin`. The output is
+    /// disabled until it is enabled.
+    pub fn one_pulse<PIN>(self, _pin: PIN) -> OnePulseChannel<TIM, C>
+    where
+        PIN: PinCh<TIM, C>,
+    {
+        assert!(C <= TIM::CHANNELS, "The timer does not have this channel");
+        OnePulseChannel { _tim: PhantomData }
+    }
+}
+
+impl<TIM: Instance, const C: u8> OnePulseChannel<TIM, C> {
+    /// Enable the channel output
+    pub fn enable(&mut self) {
+        regs::<TIM>().ccer().modify(|_, w| w.cce(C - 1).set_bit());
+    }
+
+    /// Disable the channel output
+    pub fn disable(&mut self) {
+        regs::<TIM>().ccer().modify(|_, w| w.cce(C - 1).clear_bit());
+    }
+
+    /// Set the polarity of the pulse
+    pub fn set_polarity(&mut self, polarity: Polarity) {
+        regs::<TIM>()
+            .ccer()
+            .modify(|_, w| w.ccp(C - 1).bit(polarity == Polarity::ActiveLow));
+    }
+
+    /// Set the delay from the trigger to the start of the pulse on this
+    /// channel, in counter ticks. The pulse ends at the same time as on
+    /// the other channels, so it must start before then.
+    pub fn set_delay(&mut self, ticks: u32) {
+        let tim = regs::<TIM>();
+        assert!(
+            ticks <= tim.arr().read().arr().bits(),
+            "The pulse starts after it ends"
+        );
+        tim.ccr(C as usize - 1).write(|w| w.ccr().set(ticks));
+    }
+
+    /// Stop using this channel, and return it
+    pub fn release(mut self) -> Channel<TIM, C> {
+        self.disable();
+        Channel::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nanos_to_ticks() {
+        let mhz = Hertz::from_raw(10_000_000);
+        assert_eq!(nanos_to_ticks(NanoSeconds::from_ticks(0), mhz), 0);
+        assert_eq!(nanos_to_ticks(NanoSeconds::from_ticks(149), mhz), 1);
+        assert_eq!(nanos_to_ticks(NanoSeconds::from_ticks(150), mhz), 2);
+        assert_eq!(nanos_to_ticks(NanoSeconds::from_ticks(2_000), mhz), 20);
+    }
+
+    #[test]
+    fn test_pulse_ccr_arr() {
+        // tDELAY = CCR, tPULSE = ARR - CCR + 1
+        assert_eq!(pulse_ccr_arr(20, 5, 1, 0xFFFF), (20, 24));
+        // Outside of retriggerable mode, the delay is at least one tick
+        assert_eq!(pulse_ccr_arr(0, 5, 1, 0xFFFF), (1, 5));
+        assert_eq!(pulse_ccr_arr(0, 5, 0, 0xFFFF), (0, 4));
+        // The pulse is at least one tick
+        assert_eq!(pulse_ccr_arr(3, 0, 1, 0xFFFF), (3, 3));
+        assert_eq!(pulse_ccr_arr(0xFFFE, 2, 1, 0xFFFF), (0xFFFE, 0xFFFF));
+    }
+}
@@ -1,14 +1,25 @@
 //! Prelude
 
+pub use crate::adc::AdcExt as _stm32h5xx_hal_adc_AdcExt;
+pub use crate::capture::CaptureExt as _stm32h5xx_hal_capture_CaptureExt;
+pub use crate::capture::PwmInputExt as _stm32h5xx_hal_capture_PwmInputExt;
 pub use crate::delay::DelayExt as _stm32h5xx_hal_delay_DelayExt;
 pub use crate::dwt::DwtExt as _stm32h5xx_hal_delay_DwtExt;
 pub use crate::gpdma::GpdmaExt as _stm32h5xx_hal_gpdma_GpdmaExt;
 pub use crate::gpio::GpioExt as _stm32h5xx_hal_gpio_GpioExt;
 pub use crate::i2c::I2cExt as _stm32h5xx_hal_i2c_I2cExt;
 pub use crate::icache::ICacheExt as _stm32h5xx_hal_icache_ICacheExt;
+pub use crate::lptim::LpTimExt as _stm32h5xx_hal_lptim_LpTimExt;
+pub use crate::lpuart::LpUartExt as _stm32h5xx_hal_lpuart_LpUartExt;
+pub use crate::one_pulse::OnePulseExt as _stm32h5xx_hal_one_pulse_OnePulseExt;
+pub use crate::pwm::PwmExt as _stm32h5xx_hal_pwm_PwmExt;
 pub use crate::pwr::PwrExt as _stm32h5xx_hal_pwr_PwrExt;
+pub use crate::qei::QeiExt as _stm32h5xx_hal_qei_QeiExt;
 pub use crate::rcc::RccExt as _stm32h5xx_hal_rcc_RccExt;
+pub use crate::serial::SerialExt as _stm32h5xx_hal_serial_SerialExt;
 pub use crate::spi::SpiExt as _stm32h5xx_hal_spi_SpiExt;
+pub use crate::timer::HallExt as _stm32h5xx_hal_timer_HallExt;
+pub use crate::timer::TimerExt as _stm32h5xx_hal_timer_TimerExt;
 pub use crate::usb::UsbExt as _stm32h5xx_hal_usb_UsbExt;
 
 pub use crate::time::U32Ext as _;
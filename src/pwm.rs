@@ -0,0 +1,655 @@
+//! Pulse Width Modulation (PWM)
+//!
+//! This module provides PWM outputs on the capture/compare channels of the
+//! general-purpose and advanced-control timers. The timer's counter sets the PWM frequency, which
+//! is shared by all of its channels, while the duty cycle and polarity of
+//! each channel are controlled independently.
+//!
+//! # Usage
+//!
+//! A timer is configured for PWM from the device peripheral, a frequency
+//! (or a [`Config`]) and the peripheral's reset and enable control. This
+//! returns a [`Pwm`] that controls the counter, and the timer's
+//! [`Channels`]. Each channel is turned into a [`PwmChannel`] by binding a
+//! pin to it:
+//!
+//! ```
+//! use embedded_hal::pwm::SetDutyCycle;
+//!
+//! let dp = ...;           // Device peripherals
+//! let (pa6, pa7) = ...;   // GPIO pins
+//!
+//! let (mut pwm, channels) = dp.TIM3.pwm(10.kHz(), ccdr.peripheral.TIM3, &ccdr.clocks);
+//!
+//! let mut ch1 = channels.ch1.pwm(pa6.into_alternate());
+//! let mut ch2 = channels.ch2.pwm(pa7.into_alternate());
+//!
+//! ch1.set_duty_cycle_percent(25)?;
+//! ch1.enable();
+//!
+//! ch2.set_polarity(Polarity::ActiveLow);
+//! ch2.set_duty_cycle_fraction(1, 3)?;
+//! ch2.enable();
+//! ```
+//!
+//! [`PwmChannel`] implements the embedded-hal [`SetDutyCycle`][set_duty]
+//! trait. As its duty cycle is a 16-bit value, timers with a longer period
+//! are scaled to fit. The full resolution of the timer is available through
+//! [`PwmChannel::set_duty`] and [`PwmChannel::max_duty`].
+//!
+//! ## Configuration
+//!
+//! By default the timer counts up (edge-aligned PWM), and the period and
+//! duty cycle registers are preloaded so that changes only take effect at
+//! the start of the next period. Both can be changed through [`Config`]:
+//!
+//! ```
+//! use stm32h5xx_hal::pwm::{Alignment, Config};
+//!
+//! let config = Config::new(20.kHz())
+//!     .alignment(Alignment::Center1)
+//!     .preload(false);
+//! let (mut pwm, channels) = dp.TIM2.pwm(config, ccdr.peripheral.TIM2, &ccdr.clocks);
+//! ```
+//!
+//! Center-aligned counting is only available on timers that can count down
+//! (TIM1 to TIM5 and TIM8). At the same frequency it halves the resolution of the
+//! duty cycle.
+//!
+//! ## Complementary outputs, dead time and break
+//!
+//! The timers with a break and dead-time register (TIM1, TIM8 and TIM15 to
+//! TIM17) can drive a complementary output for some channels, with a dead
+//! time inserted between the two outputs switching, as needed for the high
+//! and low side switches of a half bridge. Their break inputs disable the
+//! main output in hardware, putting the outputs in their idle state without
+//! any CPU involvement:
+//!
+//! ```
+//! use stm32h5xx_hal::gpio::PinState;
+//! use stm32h5xx_hal::pwm::{BreakInput, Config, LockLevel, OffState, Polarity};
+//!
+//! let config = Config::new(20.kHz())
+//!     .dead_time(500.nanos())
+//!     .off_state_idle(OffState::Idle)
+//!     .off_state_run(OffState::Idle);
+//! let (pwm, channels) = dp.TIM1.pwm(config, ccdr.peripheral.TIM1, &ccdr.clocks);
+//! let mut pwm = pwm.with_break(bkin, BreakInput::new(Polarity::ActiveLow).filter(4));
+//!
+//! let mut ch1 = channels.ch1.pwm(ch1_pin).with_complementary(ch1n_pin);
+//! ch1.set_idle_state(PinState::Low);
+//! ch1.set_complementary_idle_state(PinState::Low);
+//! pwm.lock(LockLevel::Level1);
+//!
+//! ch1.set_duty_cycle_percent(50)?;
+//! ch1.enable();
+//!
+//! // After a break, the outputs stay off until enabled again
+//! if pwm.is_break_detected() {
+//!     pwm.clear_break();
+//!     pwm.enable_outputs();
+//! }
+//! ```
+//!
+//! ## Six-step commutation
+//!
+//! TIM1 and TIM8 can drive a three-phase bridge for a brushless motor with
+//! six-step commutation. The next [`Step`] is preloaded, and takes effect
+//! on a commutation event, which can be triggered by the trigger output of
+//! a timer configured as a [`HallSensor`](crate::timer::HallSensor):
+//!
+//! ```
+//! use stm32h5xx_hal::pwm::PhaseState::{LowSideOn, Off, Pwm};
+//! use stm32h5xx_hal::timer::{Event, HallConfig};
+//!
+//! let hall = dp.TIM3.hall_sensor(hall_pins, HallConfig::new(1.MHz()), ccdr.peripheral.TIM3, &ccdr.clocks);
+//!
+//! let (pwm, channels) = dp.TIM1.pwm(config, ccdr.peripheral.TIM1, &ccdr.clocks);
+//! let ch1 = channels.ch1.pwm(ch1_pin).with_complementary(ch1n_pin);
+//! let ch2 = channels.ch2.pwm(ch2_pin).with_complementary(ch2n_pin);
+//! let ch3 = channels.ch3.pwm(ch3_pin).with_complementary(ch3n_pin);
+//!
+//! let mut motor = pwm.six_step(ch1, ch2, ch3);
+//! motor.set_duty(motor.max_duty() / 4);
+//! motor.preload([Pwm, LowSideOn, Off]);
+//! motor.commutate();
+//! motor.commutate_on_trigger(&hall);
+//! motor.listen(Event::Commutation);
+//!
+//! // In the commutation interrupt, preload the step for the next Hall state
+//! motor.clear_pending(Event::Commutation);
+//! motor.preload([Pwm, Off, LowSideOn]);
+//! ```
+//!
+//! ## DMA waveforms
+//!
+//! The timers with GPDMA requests can play a waveform from memory, with the
+//! DMA loading the duty cycle of each PWM period on the update event. This
+//! generates protocols that encode bits as pulse widths, such as WS2812
+//! LEDs at 800 kHz, where a 0 bit is a short pulse and a 1 bit a long one:
+//!
+//! ```
+//! let channels = dp.GPDMA1.channels(ccdr.peripheral.GPDMA1);
+//! let (pwm, tim_channels) = dp.TIM3.pwm(800.kHz(), ccdr.peripheral.TIM3, &ccdr.clocks);
+//!
+//! let mut leds = tim_channels.ch1.pwm(pa6.into_alternate()).with_dma(channels.0);
+//! leds.channel().enable();
+//!
+//! // Each value is the duty cycle of one bit, and the trailing zeros keep
+//! // the line low for the reset time
+//! let (zero, one) = (pwm.max_duty() / 3, pwm.max_duty() * 2 / 3);
+//! let mut bits = [0u16; 24 + 40];
+//! // ... fill in the bits from the colours
+//! leds.play(&bits)?;
+//! ```
+//!
+//! [`Pwm::with_dma`] updates several consecutive channels at once on each
+//! update event, through the timer's DMA burst register, which drives up
+//! to four DShot ESCs from one timer.
+//!
+//! ## Clocks
+//!
+//! The timers are clocked from `timx_ker_ck` (TIM2 to TIM7 and TIM12 to
+//! TIM14) or `timy_ker_ck` (TIM1, TIM8 and TIM15 to TIM17). The smallest prescaler that
+//! allows the requested frequency is used, which gives the finest duty
+//! cycle resolution. This will panic if the frequency cannot be generated
+//! from the timer clock.
+//!
+//! [set_duty]: https://docs.rs/embedded-hal/1.0.0/embedded_hal/pwm/trait.SetDutyCycle.html
+
+use core::convert::Infallible;
+use core::marker::PhantomData;
+
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::tim2;
+use crate::time::{Hertz, NanoSeconds};
+use crate::timer::sync::{master_methods, slave_methods};
+use crate::timer::{
+    calculate_psc_arr, dma_methods, set_main_output, Channel, Channels,
+    DmaInstance, Instance, MasterInstance, PinCh,
+};
+
+mod advanced;
+mod commutation;
+mod dma;
+
+pub use advanced::{
+    BreakInput, Complementary, LockLevel, OffState, Outputs, Single,
+};
+pub use commutation::{PhaseState, SixStep, Step};
+pub use dma::{PwmBurst, PwmDma};
+
+/// Counter alignment
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Alignment {
+    /// The counter counts up, and the outputs change state at the compare
+    /// value and at the end of each period
+    Edge,
+    /// The counter counts up and down, and the outputs change state
+    /// symmetrically around the middle of the period. Compare flags are set
+    /// only when counting down.
+    Center1,
+    /// Center-aligned, with compare flags set only when counting up
+    Center2,
+    /// Center-aligned, with compare flags set when counting both up and
+    /// down
+    Center3,
+}
+
+/// Output polarity
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Polarity {
+    /// The output is high during the active part of the period
+    ActiveHigh,
+    /// The output is low during the active part of the period
+    ActiveLow,
+}
+
+/// PWM configuration
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    frequency: Hertz,
+    alignment: Alignment,
+    preload: bool,
+    dead_time: Option<NanoSeconds>,
+    off_state_idle: OffState,
+    off_state_run: OffState,
+    automatic_output_enable: bool,
+}
+
+impl Config {
+    /// Edge-aligned PWM at `frequency`, with preloaded period and duty cycle
+    pub fn new(frequency: Hertz) -> Self {
+        Config {
+            frequency,
+            alignment: Alignment::Edge,
+            preload: true,
+            dead_time: None,
+            off_state_idle: OffState::Disabled,
+            off_state_run: OffState::Disabled,
+            automatic_output_enable: false,
+        }
+    }
+
+    /// Set the counter alignment
+    pub fn alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Enable or disable preloading of the period and duty cycle registers.
+    /// When preloaded, new values only take effect at the start of the next
+    /// period, which avoids glitches on the outputs.
+    pub fn preload(mut self, preload: bool) -> Self {
+        self.preload = preload;
+        self
+    }
+
+    /// Insert a dead time between the deactivation of a channel's output and
+    /// the activation of its complementary output, and vice versa. The dead
+    /// time is rounded up to the resolution of the timer.
+    ///
+    /// Only for timers with a break and dead-time register.
+    pub fn dead_time(mut self, dead_time: NanoSeconds) -> Self {
+        self.dead_time = Some(dead_time);
+        self
+    }
+
+    /// The state of the outputs while the main output is disabled, either by
+    /// software or by a break (OSSI). With [`OffState::Idle`], the outputs
+    /// are driven to the idle levels set for each channel with
+    /// [`PwmChannel::set_idle_state`].
+    ///
+    /// Only for timers with a break and dead-time register.
+    pub fn off_state_idle(mut self, state: OffState) -> Self {
+        self.off_state_idle = state;
+        self
+    }
+
+    /// The state of disabled outputs while the main output is enabled
+    /// (OSSR). With [`OffState::Idle`], disabled outputs are driven to their
+    /// inactive level.
+    ///
+    /// Only for timers with a break and dead-time register.
+    pub fn off_state_run(mut self, state: OffState) -> Self {
+        self.off_state_run = state;
+        self
+    }
+
+    /// Enable the main output again automatically at the next update event
+    /// once a break has cleared. By default, it must be enabled again with
+    /// [`Pwm::enable_outputs`].
+    ///
+    /// Only for timers with a break and dead-time register.
+    pub fn automatic_output_enable(mut self, enable: bool) -> Self {
+        self.automatic_output_enable = enable;
+        self
+    }
+}
+
+impl From<Hertz> for Config {
+    fn from(frequency: Hertz) -> Self {
+        Config::new(frequency)
+    }
+}
+
+/// A timer configured for PWM, which controls the frequency shared by all
+/// of its channels
+#[derive(Debug)]
+pub struct Pwm<TIM> {
+    tim: TIM,
+    clock: Hertz,
+}
+
+/// A capture/compare channel of a timer configured as a PWM output. `OUT`
+/// is [`Single`] or, when the channel also drives its complementary output,
+/// [`Complementary`].
+#[derive(Debug)]
+pub struct PwmChannel<TIM, const C: u8, OUT = Single> {
+    _tim: PhantomData<TIM>,
+    _out: PhantomData<OUT>,
+}
+
+pub trait PwmExt<TIM: Instance>: Sized {
+    fn pwm<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (Pwm<TIM>, Channels<TIM>)
+    where
+        CONFIG: Into<Config>;
+}
+
+impl<TIM: Instance> PwmExt<TIM> for TIM {
+    fn pwm<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> (Pwm<TIM>, Channels<TIM>)
+    where
+        CONFIG: Into<Config>,
+    {
+        (Pwm::new(self, config, rec, clocks), Channels::new())
+    }
+}
+
+#[inline(always)]
+fn regs<TIM: Instance>() -> &'static tim2::RegisterBlock {
+    // NOTE(unsafe) The registers used here are at the same offsets in all
+    // timers
+    unsafe { &*TIM::ptr() }
+}
+
+/// Calculate the prescaler and auto-reload values for a PWM period of
+/// `ticks` cycles of the timer clock. The auto-reload value is kept below
+/// `max_arr` so that the compare register can always hold a value for a
+/// 100% duty cycle.
+fn pwm_psc_arr(ticks: u64, max_arr: u32, alignment: Alignment) -> (u16, u32) {
+    match alignment {
+        Alignment::Edge => calculate_psc_arr(ticks, max_arr - 1),
+        // The counter counts up to ARR and back down, so one period is
+        // twice ARR
+        _ => {
+            let (psc, arr) = calculate_psc_arr(ticks / 2, max_arr - 1);
+            (psc, arr + 1)
+        }
+    }
+}
+
+/// Scale a 16-bit duty cycle to a channel's full duty cycle range
+fn scale_duty(duty: u16, max_duty: u32) -> u32 {
+    if max_duty <= u16::MAX as u32 {
+        duty as u32
+    } else {
+        (duty as u64 * max_duty as u64 / u16::MAX as u64) as u32
+    }
+}
+
+impl<TIM: Instance> Pwm<TIM> {
+    fn new(
+        tim: TIM,
+        config: impl Into<Config>,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let config: Config = config.into();
+        let _ = rec.enable().reset();
+
+        let tim_regs = regs::<TIM>();
+        if config.alignment != Alignment::Edge {
+            assert!(
+                TIM::UP_DOWN,
+                "Center-aligned counting is not supported by this timer"
+            );
+            tim_regs.cr1().write(|w| match config.alignment {
+                Alignment::Center1 => w.cms().center_aligned1(),
+                Alignment::Center2 => w.cms().center_aligned2(),
+                _ => w.cms().center_aligned3(),
+            });
+        }
+        tim_regs.cr1().modify(|_, w| w.arpe().bit(config.preload));
+
+        let mut pwm = Pwm {
+            tim,
+            clock: TIM::clock(clocks),
+        };
+        pwm.set_frequency(config.frequency);
+
+        // Load the prescaler and auto-reload values now, rather than at the
+        // end of the first period
+        tim_regs.egr().write(|w| w.ug().set_bit());
+
+        // Channels of timers with a break and dead-time register only
+        // output while the main output is enabled
+        if TIM::HAS_BDTR {
+            advanced::init_bdtr::<TIM>(&config, pwm.clock);
+            set_main_output::<TIM>(true);
+        } else {
+            assert!(
+                config.dead_time.is_none()
+                    && config.off_state_idle == OffState::Disabled
+                    && config.off_state_run == OffState::Disabled
+                    && !config.automatic_output_enable,
+                "The timer does not have a break and dead-time register"
+            );
+        }
+
+        for c in 1..=TIM::CHANNELS {
+            set_output_mode::<TIM>(c, config.preload);
+        }
+
+        tim_regs.cr1().modify(|_, w| w.cen().enabled());
+
+        pwm
+    }
+
+    /// Set the PWM frequency. The duty cycle of each channel is not
+    /// rescaled, so it should be set again afterwards.
+    pub fn set_frequency(&mut self, frequency: Hertz) {
+        let tim = regs::<TIM>();
+        let alignment = if tim.cr1().read().cms().is_edge_aligned() {
+            Alignment::Edge
+        } else {
+            Alignment::Center1
+        };
+        let ticks = self.clock.raw() as u64 / frequency.raw() as u64;
+        let (psc, arr) = pwm_psc_arr(ticks, TIM::MAX_ARR, alignment);
+
+        tim.psc().write(|w| w.psc().set(psc));
+        tim.arr().write(|w| w.arr().set(arr));
+    }
+
+    /// The current PWM frequency
+    pub fn frequency(&self) -> Hertz {
+        let tim = regs::<TIM>();
+        let psc = tim.psc().read().psc().bits() as u32;
+        let period = max_duty::<TIM>() as u64 * (psc as u64 + 1);
+        let period = if tim.cr1().read().cms().is_edge_aligned() {
+            period
+        } else {
+            2 * period
+        };
+        Hertz::from_raw((self.clock.raw() as u64 / period) as u32)
+    }
+
+    /// The maximum duty cycle value of the channels, which corresponds to
+    /// a 100% duty cycle
+    pub fn max_duty(&self) -> u32 {
+        max_duty::<TIM>()
+    }
+
+    /// Stop the counter. The outputs stay at their current level.
+    pub fn pause(&mut self) {
+        regs::<TIM>().cr1().modify(|_, w| w.cen().disabled());
+    }
+
+    /// Restart the counter after it was paused
+    pub fn resume(&mut self) {
+        regs::<TIM>().cr1().modify(|_, w| w.cen().enabled());
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> TIM {
+        let _ = TIM::rec().reset().disable();
+        self.tim
+    }
+}
+
+impl<TIM: Instance + MasterInstance> Pwm<TIM> {
+    master_methods!();
+}
+
+impl<TIM: Instance> Pwm<TIM> {
+    slave_methods!();
+}
+
+impl<TIM: DmaInstance> Pwm<TIM> {
+    dma_methods!();
+}
+
+/// The maximum duty cycle: ARR + 1 when edge-aligned, as the output is
+/// active while the counter is below the compare value, and ARR when
+/// center-aligned
+fn max_duty<TIM: Instance>() -> u32 {
+    let tim = regs::<TIM>();
+    let arr = tim.arr().read().arr().bits();
+    if tim.cr1().read().cms().is_edge_aligned() {
+        arr + 1
+    } else {
+        arr
+    }
+}
+
+/// Put channel `c` in PWM mode 1, with the compare register optionally
+/// preloaded. The channel output stays disabled.
+fn set_output_mode<TIM: Instance>(c: u8, preload: bool) {
+    let tim = regs::<TIM>();
+    let n = (c - 1) % 2;
+    if c <= 2 {
+        tim.ccmr1_output().modify(|_, w| {
+            w.ccs(n)
+                .output()
+                .ocm(n)
+                .pwm_mode1()
+                .ocm_3(n)
+                .normal()
+                .ocpe(n)
+                .bit(preload)
+        });
+    } else {
+        tim.ccmr2_output().modify(|_, w| {
+            w.ccs(n)
+                .output()
+                .ocm(n)
+                .pwm_mode1()
+                .ocm_3(n)
+                .normal()
+                .ocpe(n)
+                .bit(preload)
+        });
+    }
+}
+
+impl<TIM: Instance, const C: u8> Channel<TIM, C> {
+    /// Use this channel as a PWM output on `pin`. The output is disabled
+    /// and its duty cycle is zero until it is set.
+    pub fn pwm<PIN>(self, _pin: PIN) -> PwmChannel<TIM, C>
+    where
+        PIN: PinCh<TIM, C>,
+    {
+        self.pwm_unchecked()
+    }
+
+    /// Use this channel as a PWM output without an assigned pin, for
+    /// example to generate an internal trigger from its compare value
+    pub fn pwm_unchecked(self) -> PwmChannel<TIM, C> {
+        assert!(C <= TIM::CHANNELS, "The timer does not have this channel");
+        let mut channel = PwmChannel {
+            _tim: PhantomData,
+            _out: PhantomData,
+        };
+        channel.set_duty(0);
+        channel
+    }
+}
+
+impl<TIM: Instance, const C: u8, OUT: advanced::Outputs>
+    PwmChannel<TIM, C, OUT>
+{
+    /// Enable the channel output, and its complementary output if it has
+    /// one
+    pub fn enable(&mut self) {
+        advanced::set_outputs::<TIM, C, OUT>(true);
+    }
+
+    /// Disable the channel output, and its complementary output if it has
+    /// one
+    pub fn disable(&mut self) {
+        advanced::set_outputs::<TIM, C, OUT>(false);
+    }
+
+    /// Set the polarity of the channel output
+    pub fn set_polarity(&mut self, polarity: Polarity) {
+        regs::<TIM>()
+            .ccer()
+            .modify(|_, w| w.ccp(C - 1).bit(polarity == Polarity::ActiveLow));
+    }
+
+    /// The maximum duty cycle value, which corresponds to a 100% duty cycle
+    pub fn max_duty(&self) -> u32 {
+        max_duty::<TIM>()
+    }
+
+    /// The current duty cycle value
+    pub fn duty(&self) -> u32 {
+        regs::<TIM>().ccr((C - 1) as usize).read().ccr().bits()
+    }
+
+    /// Set the duty cycle value, from 0 to [`max_duty`](Self::max_duty)
+    pub fn set_duty(&mut self, duty: u32) {
+        regs::<TIM>()
+            .ccr((C - 1) as usize)
+            .write(|w| w.ccr().set(duty));
+    }
+
+    /// Returns the channel to the timer, so that it can be used for
+    /// something else. The output is disabled.
+    pub fn release(mut self) -> Channel<TIM, C> {
+        self.disable();
+        Channel::new()
+    }
+}
+
+impl<TIM: Instance, const C: u8, OUT> ErrorType for PwmChannel<TIM, C, OUT> {
+    type Error = Infallible;
+}
+
+impl<TIM: Instance, const C: u8, OUT: advanced::Outputs> SetDutyCycle
+    for PwmChannel<TIM, C, OUT>
+{
+    fn max_duty_cycle(&self) -> u16 {
+        self.max_duty().min(u16::MAX as u32) as u16
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        let duty = scale_duty(duty, self.max_duty());
+        self.set_duty(duty);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pwm_psc_arr() {
+        // 10 kHz from 250 MHz
+        assert_eq!(pwm_psc_arr(25_000, 0xFFFF, Alignment::Edge), (0, 24_999));
+        assert_eq!(
+            pwm_psc_arr(25_000, 0xFFFF, Alignment::Center1),
+            (0, 12_500)
+        );
+        // ARR + 1 must fit in a 16-bit compare register
+        assert_eq!(pwm_psc_arr(0x1_0000, 0xFFFF, Alignment::Edge), (1, 0x7FFF));
+        assert_eq!(
+            pwm_psc_arr(0x1_0000, 0xFFFF_FFFF, Alignment::Edge),
+            (0, 0xFFFF)
+        );
+    }
+
+    #[test]
+    fn test_scale_duty() {
+        assert_eq!(scale_duty(1000, 25_000), 1000);
+        assert_eq!(scale_duty(u16::MAX, 250_000), 250_000);
+        assert_eq!(scale_duty(u16::MAX / 2, 250_000), 124_998);
+        assert_eq!(scale_duty(0, 250_000), 0);
+    }
+}
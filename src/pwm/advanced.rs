@@ -0,0 +1,319 @@
+//! Complementary outputs, dead time and break inputs of the timers with a
+//! break and dead-time register (TIM1, TIM8 and TIM15 to TIM17)
+
+use core::marker::PhantomData;
+
+use super::{regs, Config, Polarity, Pwm, PwmChannel};
+use crate::gpio::PinState;
+use crate::stm32::tim1;
+use crate::time::Hertz;
+use crate::timer::{
+    clear_pending, AdvancedInstance, Event, Instance, PinBkin, PinBkin2, PinChN,
+};
+
+/// Marker for a PWM channel that only drives its main output
+#[derive(Debug)]
+pub struct Single;
+
+/// Marker for a PWM channel that drives both its main and complementary
+/// outputs
+#[derive(Debug)]
+pub struct Complementary;
+
+/// The outputs that a PWM channel drives
+pub trait Outputs: crate::Sealed {
+    #[doc(hidden)]
+    const COMPLEMENTARY: bool;
+}
+
+impl crate::Sealed for Single {}
+impl Outputs for Single {
+    const COMPLEMENTARY: bool = false;
+}
+
+impl crate::Sealed for Complementary {}
+impl Outputs for Complementary {
+    const COMPLEMENTARY: bool = true;
+}
+
+/// State of the outputs when they are not driven by the counter
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OffState {
+    /// The outputs are disabled, and so are left floating unless the pins
+    /// have a pull-up or pull-down
+    Disabled,
+    /// The outputs are driven to their idle (or inactive) level
+    Idle,
+}
+
+/// Write protection of the timer configuration, which can only be removed
+/// by a reset
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LockLevel {
+    /// The dead time, break inputs, off states, automatic output enable and
+    /// output idle levels are locked
+    Level1,
+    /// Level 1, and the output polarities and off states are locked
+    Level2,
+    /// Level 2, and the output compare modes and preload bits are locked
+    Level3,
+}
+
+/// Break input configuration
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BreakInput {
+    polarity: Polarity,
+    filter: u8,
+}
+
+impl BreakInput {
+    /// A break input that is active at the level given by `polarity`,
+    /// without filtering
+    pub fn new(polarity: Polarity) -> Self {
+        BreakInput {
+            polarity,
+            filter: 0,
+        }
+    }
+
+    /// Set the digital filter of the break input (BKF), from 0 (no filter)
+    /// to 15. Higher values need the input to be stable for more samples,
+    /// at a lower sampling frequency, before a break is detected.
+    pub fn filter(mut self, filter: u8) -> Self {
+        assert!(filter <= 15, "The break filter is at most 15");
+        self.filter = filter;
+        self
+    }
+}
+
+#[inline(always)]
+pub(super) fn bdtr_regs<TIM: Instance>() -> &'static tim1::RegisterBlock {
+    debug_assert!(TIM::HAS_BDTR);
+    // NOTE(unsafe) The timers with a break and dead-time register have the
+    // same layout as TIM1 up to and including that register
+    unsafe { &*(TIM::ptr() as *const tim1::RegisterBlock) }
+}
+
+/// Convert a dead time of `ticks` cycles of the timer clock to the clock
+/// division (CKD) and dead-time generator (DTG) values, rounding up. The
+/// clock division is only used when the dead time is too long without it.
+fn dead_time_bits(ticks: u32) -> Option<(u8, u8)> {
+    for ckd in 0..3 {
+        let t = ticks.div_ceil(1 << ckd);
+        let dtg = match t {
+            0..=127 => t,
+            128..=254 => 0x80 | (t.div_ceil(2) - 64),
+            255..=504 => 0xC0 | (t.div_ceil(8) - 32),
+            505..=1008 => 0xE0 | (t.div_ceil(16) - 32),
+            _ => continue,
+        };
+        return Some((ckd, dtg as u8));
+    }
+    None
+}
+
+/// Configure the dead time, off states and automatic output enable. The
+/// main output stays disabled.
+pub(super) fn init_bdtr<TIM: Instance>(config: &Config, clock: Hertz) {
+    let tim = bdtr_regs::<TIM>();
+
+    let (ckd, dtg) = match config.dead_time {
+        Some(dead_time) => {
+            let ticks = (dead_time.to_nanos() as u64 * clock.raw() as u64)
+                .div_ceil(1_000_000_000);
+            u32::try_from(ticks)
+                .ok()
+                .and_then(dead_time_bits)
+                .expect("Dead time is too long for the timer clock")
+        }
+        None => (0, 0),
+    };
+
+    tim.cr1().modify(|_, w| match ckd {
+        0 => w.ckd().div1(),
+        1 => w.ckd().div2(),
+        _ => w.ckd().div4(),
+    });
+    tim.bdtr().write(|w| {
+        w.dtg()
+            .set(dtg)
+            .ossi()
+            .bit(config.off_state_idle == OffState::Idle)
+            .ossr()
+            .bit(config.off_state_run == OffState::Idle)
+            .aoe()
+            .bit(config.automatic_output_enable)
+    });
+}
+
+/// Enable or disable the outputs of channel `C`
+pub(super) fn set_outputs<TIM: Instance, const C: u8, OUT: Outputs>(
+    enable: bool,
+) {
+    if OUT::COMPLEMENTARY {
+        // Change both outputs at once so that the dead time is respected
+        bdtr_regs::<TIM>()
+            .ccer()
+            .modify(|_, w| w.cce(C - 1).bit(enable).ccne(C - 1).bit(enable));
+    } else {
+        regs::<TIM>().ccer().modify(|_, w| w.cce(C - 1).bit(enable));
+    }
+}
+
+impl<TIM: AdvancedInstance> Pwm<TIM> {
+    /// Enable break input BKIN on `pin`. While the break input is active,
+    /// the main output is disabled in hardware and the outputs go to the
+    /// state set by [`Config::off_state_idle`].
+    pub fn with_break<PIN>(self, _pin: PIN, input: BreakInput) -> Self
+    where
+        PIN: PinBkin<TIM>,
+    {
+        // NOTE(unsafe) The filter is checked to fit in BKF
+        bdtr_regs::<TIM>().bdtr().modify(|_, w| unsafe {
+            w.bke()
+                .enabled()
+                .bkp()
+                .bit(input.polarity == Polarity::ActiveHigh)
+                .bkf()
+                .bits(input.filter)
+        });
+        self
+    }
+
+    /// Enable the second break input BKIN2 on `pin`, which acts in the same
+    /// way as [`with_break`](Self::with_break)
+    pub fn with_break2<PIN>(self, _pin: PIN, input: BreakInput) -> Self
+    where
+        PIN: PinBkin2<TIM>,
+    {
+        // NOTE(unsafe) The filter is checked to fit in BK2F
+        bdtr_regs::<TIM>().bdtr().modify(|_, w| unsafe {
+            w.bk2e()
+                .set_bit()
+                .bk2p()
+                .bit(input.polarity == Polarity::ActiveHigh)
+                .bk2f()
+                .bits(input.filter)
+        });
+        self
+    }
+
+    /// Write-protect the configuration at `level` until the next reset. This
+    /// should be done last, once the break inputs and the idle levels of the
+    /// channels have been configured.
+    pub fn lock(&mut self, level: LockLevel) {
+        bdtr_regs::<TIM>().bdtr().modify(|_, w| match level {
+            LockLevel::Level1 => w.lock().level1(),
+            LockLevel::Level2 => w.lock().level2(),
+            LockLevel::Level3 => w.lock().level3(),
+        });
+    }
+
+    /// Enable the main output, after it was disabled in software or by a
+    /// break. This has no effect while a break input is still active.
+    pub fn enable_outputs(&mut self) {
+        bdtr_regs::<TIM>().bdtr().modify(|_, w| w.moe().enabled());
+    }
+
+    /// Disable the main output. The outputs go to the state set by
+    /// [`Config::off_state_idle`].
+    pub fn disable_outputs(&mut self) {
+        bdtr_regs::<TIM>()
+            .bdtr()
+            .modify(|_, w| w.moe().disabled_idle());
+    }
+
+    /// Whether the main output is enabled
+    pub fn outputs_enabled(&self) -> bool {
+        bdtr_regs::<TIM>().bdtr().read().moe().is_enabled()
+    }
+
+    /// Whether a break has been detected on either break input since the
+    /// flag was last cleared
+    pub fn is_break_detected(&self) -> bool {
+        let sr = bdtr_regs::<TIM>().sr().read();
+        sr.bif().bit_is_set() || sr.b2if().bit_is_set()
+    }
+
+    /// Clear the break flags. They stay set while a break input is active.
+    pub fn clear_break(&mut self) {
+        clear_pending::<TIM>(Event::Break);
+    }
+}
+
+impl<TIM: AdvancedInstance, const C: u8> PwmChannel<TIM, C, Single> {
+    /// Also drive the complementary output of this channel, on `pin`. The
+    /// complementary output is enabled and disabled together with the main
+    /// output.
+    pub fn with_complementary<PIN>(
+        self,
+        _pin: PIN,
+    ) -> PwmChannel<TIM, C, Complementary>
+    where
+        PIN: PinChN<TIM, C>,
+    {
+        let enabled = regs::<TIM>().ccer().read().cce(C - 1).bit_is_set();
+        let channel = PwmChannel {
+            _tim: PhantomData,
+            _out: PhantomData,
+        };
+        set_outputs::<TIM, C, Complementary>(enabled);
+        channel
+    }
+}
+
+impl<TIM: AdvancedInstance, const C: u8, OUT: Outputs> PwmChannel<TIM, C, OUT> {
+    /// Set the level of the channel output while the main output is
+    /// disabled (OISx), such as after a break. This only applies when
+    /// [`Config::off_state_idle`] is [`OffState::Idle`].
+    pub fn set_idle_state(&mut self, state: PinState) {
+        bdtr_regs::<TIM>()
+            .cr2()
+            .modify(|_, w| w.ois(C - 1).bit(state == PinState::High));
+    }
+}
+
+impl<TIM: AdvancedInstance, const C: u8> PwmChannel<TIM, C, Complementary> {
+    /// Set the polarity of the complementary output
+    pub fn set_complementary_polarity(&mut self, polarity: Polarity) {
+        bdtr_regs::<TIM>()
+            .ccer()
+            .modify(|_, w| w.ccnp(C - 1).bit(polarity == Polarity::ActiveLow));
+    }
+
+    /// Set the level of the complementary output while the main output is
+    /// disabled (OISxN). Together with [`set_idle_state`], this should put
+    /// the gate drivers in a safe state.
+    ///
+    /// [`set_idle_state`]: PwmChannel::set_idle_state
+    pub fn set_complementary_idle_state(&mut self, state: PinState) {
+        bdtr_regs::<TIM>()
+            .cr2()
+            .modify(|_, w| w.oisn(C - 1).bit(state == PinState::High));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dead_time_bits() {
+        assert_eq!(dead_time_bits(0), Some((0, 0)));
+        assert_eq!(dead_time_bits(127), Some((0, 127)));
+        // (64 + DTG[5:0]) * 2
+        assert_eq!(dead_time_bits(128), Some((0, 0x80)));
+        assert_eq!(dead_time_bits(129), Some((0, 0x81)));
+        // (32 + DTG[4:0]) * 8
+        assert_eq!(dead_time_bits(255), Some((0, 0xC0)));
+        // (32 + DTG[4:0]) * 16
+        assert_eq!(dead_time_bits(1008), Some((0, 0xFF)));
+        // Longer dead times need the clock to be divided
+        assert_eq!(dead_time_bits(1009), Some((1, 0xE0)));
+        assert_eq!(dead_time_bits(4032), Some((2, 0xFF)));
+        assert_eq!(dead_time_bits(4033), None);
+    }
+}
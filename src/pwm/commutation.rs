@@ -0,0 +1,198 @@
+//! Six-step commutation of brushless motors on the timers with a break and
+//! dead-time register
+//!
+//! The output enables (CCxE, CCxNE) and output compare modes (OCxM) of
+//! channels 1 to 3 are preloaded, and only take effect on a commutation
+//! (COM) event. This lets the next step be loaded ahead of time, for
+//! example from the commutation interrupt of the previous step, and then
+//! be applied all at once by software or by the trigger output of a
+//! [`HallSensor`].
+
+use super::advanced::bdtr_regs;
+use super::{regs, Complementary, Pwm, PwmChannel};
+use crate::timer::{
+    clear_pending, is_pending, select_internal_trigger, set_interrupt, Event,
+    HallSensor, Instance, InternalTrigger,
+};
+
+/// State of the two outputs of a phase during a commutation step
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PhaseState {
+    /// Both outputs are disabled. They are floating, or driven to their
+    /// inactive level when [`Config::off_state_run`] is [`OffState::Idle`].
+    ///
+    /// [`Config::off_state_run`]: super::Config::off_state_run
+    /// [`OffState::Idle`]: super::OffState::Idle
+    Off,
+    /// Complementary PWM on both outputs, with dead time
+    Pwm,
+    /// PWM on the main output, with the complementary output disabled
+    HighSidePwm,
+    /// The main output is inactive and the complementary output is active,
+    /// so the low side switch is on
+    LowSideOn,
+}
+
+/// The states of phases 1 to 3 (channels 1 to 3) during a commutation step
+pub type Step = [PhaseState; 3];
+
+/// A timer driving a three-phase bridge with six-step commutation
+#[derive(Debug)]
+pub struct SixStep<TIM> {
+    pwm: Pwm<TIM>,
+    phases: (
+        PwmChannel<TIM, 1, Complementary>,
+        PwmChannel<TIM, 2, Complementary>,
+        PwmChannel<TIM, 3, Complementary>,
+    ),
+}
+
+impl<TIM: Instance> Pwm<TIM> {
+    /// Use channels 1 to 3 for six-step commutation. All phases are off
+    /// until the first step is loaded with [`SixStep::preload`] and applied
+    /// by a commutation.
+    ///
+    /// Only for timers with a break and dead-time register and at least
+    /// three channels (TIM1 and TIM8).
+    pub fn six_step(
+        self,
+        ch1: PwmChannel<TIM, 1, Complementary>,
+        ch2: PwmChannel<TIM, 2, Complementary>,
+        ch3: PwmChannel<TIM, 3, Complementary>,
+    ) -> SixStep<TIM> {
+        let tim = bdtr_regs::<TIM>();
+        tim.cr2().modify(|_, w| w.ccpc().preloaded().ccus().sw());
+
+        let mut six_step = SixStep {
+            pwm: self,
+            phases: (ch1, ch2, ch3),
+        };
+        six_step.preload([PhaseState::Off; 3]);
+        six_step.commutate();
+        six_step
+    }
+}
+
+impl<TIM: Instance> SixStep<TIM> {
+    /// Load the next step. It takes effect on the next commutation.
+    pub fn preload(&mut self, step: Step) {
+        let tim = regs::<TIM>();
+        let [s1, s2, s3] = step;
+
+        let pwm = |s| s != PhaseState::LowSideOn;
+        tim.ccmr1_output().modify(|_, w| {
+            let w = if pwm(s1) {
+                w.ocm(0).pwm_mode1()
+            } else {
+                w.ocm(0).force_inactive()
+            };
+            if pwm(s2) {
+                w.ocm(1).pwm_mode1()
+            } else {
+                w.ocm(1).force_inactive()
+            }
+        });
+        tim.ccmr2_output().modify(|_, w| {
+            if pwm(s3) {
+                w.ocm(0).pwm_mode1()
+            } else {
+                w.ocm(0).force_inactive()
+            }
+        });
+
+        bdtr_regs::<TIM>().ccer().modify(|_, w| {
+            for (n, s) in (0..).zip(step) {
+                w.cce(n)
+                    .bit(s != PhaseState::Off)
+                    .ccne(n)
+                    .bit(matches!(s, PhaseState::Pwm | PhaseState::LowSideOn));
+            }
+            w
+        });
+    }
+
+    /// Apply the preloaded step now
+    pub fn commutate(&mut self) {
+        bdtr_regs::<TIM>().egr().write(|w| w.comg().set_bit());
+    }
+
+    /// Also apply the preloaded step on each trigger output pulse of
+    /// `hall`, which happens after the commutation delay that follows each
+    /// Hall edge. The preloaded step should be updated after each
+    /// commutation, on [`Event::Commutation`].
+    pub fn commutate_on_trigger<M>(&mut self, _hall: &HallSensor<M>)
+    where
+        M: InternalTrigger<TIM>,
+    {
+        select_internal_trigger::<TIM>(M::ITR);
+        bdtr_regs::<TIM>()
+            .cr2()
+            .modify(|_, w| w.ccus().sw_or_edge());
+    }
+
+    /// Only apply the preloaded step on [`commutate`](Self::commutate)
+    pub fn commutate_on_software(&mut self) {
+        bdtr_regs::<TIM>().cr2().modify(|_, w| w.ccus().sw());
+    }
+
+    /// The maximum duty cycle value, which corresponds to a 100% duty cycle
+    pub fn max_duty(&self) -> u32 {
+        self.pwm.max_duty()
+    }
+
+    /// Set the duty cycle of all phases. This is applied at the start of the
+    /// next period if the compare registers are preloaded.
+    pub fn set_duty(&mut self, duty: u32) {
+        self.phases.0.set_duty(duty);
+        self.phases.1.set_duty(duty);
+        self.phases.2.set_duty(duty);
+    }
+
+    /// Start listening for `event`
+    pub fn listen(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, true);
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, false);
+    }
+
+    /// Whether `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        is_pending::<TIM>(event)
+    }
+
+    /// Clear the pending flag of `event`
+    pub fn clear_pending(&mut self, event: Event) {
+        clear_pending::<TIM>(event);
+    }
+
+    /// The timer's PWM controls, such as for the frequency, break and main
+    /// output
+    pub fn pwm(&mut self) -> &mut Pwm<TIM> {
+        &mut self.pwm
+    }
+
+    /// Stop commutating, with all phases off, and return the timer and
+    /// channels
+    #[allow(clippy::type_complexity)]
+    pub fn release(
+        mut self,
+    ) -> (
+        Pwm<TIM>,
+        PwmChannel<TIM, 1, Complementary>,
+        PwmChannel<TIM, 2, Complementary>,
+        PwmChannel<TIM, 3, Complementary>,
+    ) {
+        bdtr_regs::<TIM>()
+            .cr2()
+            .modify(|_, w| w.ccpc().not_preloaded().ccus().sw());
+        // Without preloading, this takes effect immediately and leaves the
+        // channels in PWM mode
+        self.preload([PhaseState::Off; 3]);
+        let (ch1, ch2, ch3) = self.phases;
+        (self.pwm, ch1, ch2, ch3)
+    }
+}
@@ -0,0 +1,168 @@
+//! PWM waveforms played from memory by the GPDMA
+//!
+//! On each update event, the DMA loads the next duty cycle of a waveform
+//! into the preloaded compare register of a channel, so that each value is
+//! used for one PWM period. This generates serial protocols that encode
+//! bits as pulse widths, such as those of WS2812 LEDs and DShot ESCs,
+//! without any CPU involvement.
+
+use embedded_dma::ReadBuffer;
+
+use super::{regs, Outputs, Pwm, PwmChannel};
+use crate::gpdma::{
+    config::MemoryToPeripheral,
+    periph::{TxAddr, TxBuffer},
+    DmaChannel, DmaConfig, DmaTransfer, Error, Word,
+};
+use crate::timer::{
+    set_dma_burst, set_dma_request, Ccr, DmaBurst, DmaInstance, Event,
+};
+
+/// Wraps a borrowed slice so that it can be used as the memory side of a DMA
+/// transfer. This is only sound because the transfer is always waited upon
+/// to completion, or aborted, before the borrow ends.
+struct SliceBuffer<W> {
+    ptr: *const W,
+    len: usize,
+}
+
+unsafe impl<W: Word> ReadBuffer for SliceBuffer<W> {
+    type Word = W;
+
+    unsafe fn read_buffer(&self) -> (*const W, usize) {
+        (self.ptr, self.len)
+    }
+}
+
+/// Transfer `values` to the register at `A` on the update DMA requests of
+/// the timer, blocking until the last value has been transferred
+fn play<TIM, CH, A, W>(dma: &mut CH, values: &[W]) -> Result<(), Error>
+where
+    TIM: DmaInstance,
+    CH: DmaChannel,
+    A: TxAddr<W>,
+    W: Word,
+{
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    let config: DmaConfig<MemoryToPeripheral, W, W> =
+        DmaConfig::new().with_request(TIM::UPDATE_DMA_REQUEST);
+    let source = SliceBuffer {
+        ptr: values.as_ptr(),
+        len: values.len(),
+    };
+    let mut transfer =
+        DmaTransfer::memory_to_peripheral(config, dma, source, A::tx_buffer());
+
+    // The first value is transferred on the next update event, rather than
+    // on one that was already pending
+    let result = transfer.start().and_then(|_| {
+        set_dma_request::<TIM>(Event::Update, true);
+        transfer.wait_for_transfer_complete()
+    });
+    drop(transfer);
+    set_dma_request::<TIM>(Event::Update, false);
+
+    result
+}
+
+/// A PWM channel whose duty cycle is updated by the GPDMA on each update
+/// event
+#[derive(Debug)]
+pub struct PwmDma<TIM, const C: u8, OUT, CH> {
+    channel: PwmChannel<TIM, C, OUT>,
+    dma: CH,
+}
+
+/// A timer configured for PWM, whose channels have their duty cycles
+/// updated together by the GPDMA on each update event
+#[derive(Debug)]
+pub struct PwmBurst<TIM, CH> {
+    pwm: Pwm<TIM>,
+    dma: CH,
+}
+
+impl<TIM: DmaInstance, const C: u8, OUT: Outputs> PwmChannel<TIM, C, OUT> {
+    /// Use a GPDMA channel to play waveforms on this channel
+    pub fn with_dma<CH: DmaChannel>(self, dma: CH) -> PwmDma<TIM, C, OUT, CH> {
+        PwmDma { channel: self, dma }
+    }
+}
+
+impl<TIM: DmaInstance, const C: u8, OUT: Outputs, CH: DmaChannel>
+    PwmDma<TIM, C, OUT, CH>
+{
+    /// Play a waveform, using each of `duties` as the duty cycle of one PWM
+    /// period, and block until the last one has been loaded. Words are
+    /// half-words (`u16`) or words (`u32`), the latter being needed for duty
+    /// cycles above `u16::MAX` on TIM2 and TIM5. At most `u16::MAX` bytes
+    /// can be played at once.
+    ///
+    /// The last duty cycle stays in use after the waveform, so it is
+    /// usually 0 to leave the output inactive.
+    pub fn play<W>(&mut self, duties: &[W]) -> Result<(), Error>
+    where
+        W: Word,
+        Ccr<TIM, C>: TxAddr<W>,
+    {
+        play::<TIM, CH, Ccr<TIM, C>, W>(&mut self.dma, duties)
+    }
+
+    /// The PWM channel, to enable it or change its polarity
+    pub fn channel(&mut self) -> &mut PwmChannel<TIM, C, OUT> {
+        &mut self.channel
+    }
+
+    /// Release the PWM channel and the DMA channel
+    pub fn free(self) -> (PwmChannel<TIM, C, OUT>, CH) {
+        (self.channel, self.dma)
+    }
+}
+
+impl<TIM: DmaInstance> Pwm<TIM> {
+    /// Use a GPDMA channel to play waveforms on several channels at once,
+    /// through the DMA burst register
+    pub fn with_dma<CH: DmaChannel>(self, dma: CH) -> PwmBurst<TIM, CH> {
+        PwmBurst { pwm: self, dma }
+    }
+}
+
+impl<TIM: DmaInstance, CH: DmaChannel> PwmBurst<TIM, CH> {
+    /// Play a waveform on `channels` consecutive channels, starting from
+    /// channel `first`, and block until the last duty cycles have been
+    /// loaded. `duties` holds the duty cycles of each PWM period in turn,
+    /// one for each channel, from channel `first` upwards.
+    ///
+    /// As for [`PwmDma::play`], the last duty cycles stay in use after the
+    /// waveform. The channels must be enabled as PWM outputs beforehand.
+    pub fn play<W>(
+        &mut self,
+        first: u8,
+        channels: u8,
+        duties: &[W],
+    ) -> Result<(), Error>
+    where
+        W: Word,
+        DmaBurst<TIM>: TxAddr<W>,
+    {
+        set_dma_burst::<TIM>(first, channels);
+        assert!(
+            duties.len().is_multiple_of(channels as usize),
+            "The waveform must have a duty cycle for each channel"
+        );
+        play::<TIM, CH, DmaBurst<TIM>, W>(&mut self.dma, duties)
+    }
+
+    /// The PWM timer, to change its frequency
+    pub fn pwm(&mut self) -> &mut Pwm<TIM> {
+        &mut self.pwm
+    }
+
+    /// Release the PWM timer and the DMA channel
+    pub fn free(self) -> (Pwm<TIM>, CH) {
+        regs::<TIM>().dcr().reset();
+        (self.pwm, self.dma)
+    }
+}
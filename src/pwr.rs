@@ -27,8 +27,24 @@
 //!
 //!     assert_eq!(pwrcfg.vos(), VoltageScale::Scale0);
 //! ```
+//! # Stop mode
 //!
+//! [`stop`] enters Stop mode, in which all of the clocks in the core domain
+//! are stopped while the contents of SRAM and registers are retained. The
+//! system is woken by an interrupt from a peripheral that can run without
+//! them, such as the LPUART or LPTIM clocked from the LSE, or by an EXTI
+//! line.
 //!
+//! ```rust
+//!     let mut cp = cortex_m::Peripherals::take().unwrap();
+//!
+//!     // Configure a wakeup source and unmask its interrupt, then
+//!     pwr::stop(&mut cp.SCB);
+//! ```
+//!
+use cortex_m::peripheral::SCB;
+
+use crate::rcc::clock_state::ClockState;
 use crate::stm32::pwr::voscr::VOS;
 use crate::stm32::pwr::vossr::ACTVOSR;
 use crate::stm32::PWR;
@@ -178,3 +194,28 @@ impl Pwr {
         PowerConfiguration { vos }
     }
 }
+
+/// Enter Stop mode, returning once the system has been woken by an interrupt.
+///
+/// On wakeup the hardware switches the system clock to the HSI and disables
+/// the HSE and PLLs. These are restarted and the system clock source that was
+/// in use beforehand is selected again before returning, so the frequencies
+/// recorded in [`CoreClocks`](crate::rcc::CoreClocks) remain valid.
+///
+/// Peripherals that are to wake the system must be clocked by an oscillator
+/// that keeps running in Stop mode (LSE, LSI, or HSI/CSI on request), and
+/// their interrupts must be unmasked in the NVIC.
+pub fn stop(scb: &mut SCB) {
+    let clocks = ClockState::save();
+
+    // Safety: the low power mode selection is only written here
+    let pwr = unsafe { &*PWR::ptr() };
+    pwr.pmcr().modify(|_, w| w.lpms().stop_mode());
+
+    scb.set_sleepdeep();
+    cortex_m::asm::dsb();
+    cortex_m::asm::wfi();
+    scb.clear_sleepdeep();
+
+    clocks.restore();
+}
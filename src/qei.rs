@@ -0,0 +1,324 @@
+//! Quadrature Encoder Interface (QEI)
+//!
+//! The timers that can count both up and down (TIM1 to TIM5 and TIM8) have
+//! an encoder mode, in which the counter is driven by the A and B signals
+//! of an incremental encoder on channels 1 and 2.
+//!
+//! ```
+//! use stm32h5xx_hal::qei::{Config, Resolution};
+//!
+//! let mut qei = dp.TIM2.qei(
+//!     (pa0.into_alternate(), pa1.into_alternate()),
+//!     Config::default().resolution(Resolution::X4).filter(4),
+//!     ccdr.peripheral.TIM2,
+//! );
+//!
+//! let position = qei.position();
+//! let direction = qei.direction();
+//! ```
+//!
+//! # Position tracking
+//!
+//! TIM2 and TIM5 have a 32-bit counter, and the other timers a 16-bit
+//! counter. [`Qei::count`] returns the raw counter, which wraps around,
+//! while [`Qei::position`] extends it to 64 bits in software, so that the
+//! position does not wrap. For this, [`Qei::position`] needs to be called
+//! before the encoder has moved by half of the counter range, which is
+//! 32768 counts on the 16-bit timers.
+//!
+//! # Index
+//!
+//! The index signal of the encoder can be connected to the external
+//! trigger input (ETR) of the timer with [`Qei::with_index`]. The index
+//! resets the counter, either on each index pulse or only on the first one
+//! after it was enabled, which is used to find the reference position.
+//! [`Qei::position`] is then relative to the last index.
+
+use crate::gpio::PinState;
+use crate::rcc::ResetEnable;
+use crate::stm32::tim2;
+use crate::timer::{
+    clear_pending, is_pending, set_interrupt, EncoderInstance, Event, Instance,
+    PinCh, PinEtr,
+};
+
+/// Number of counts per encoder cycle
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Resolution {
+    /// Count on both edges of the A signal (channel 1)
+    X2,
+    /// Count on both edges of the A and B signals
+    X4,
+}
+
+/// Counting direction
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// Which index pulses reset the counter
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum IndexMode {
+    /// Every index pulse resets the counter
+    Every,
+    /// Only the first index pulse after the index is enabled resets the
+    /// counter
+    First,
+}
+
+/// Encoder interface configuration
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    resolution: Resolution,
+    filter: u8,
+    invert: bool,
+}
+
+impl Config {
+    /// Set the number of counts per encoder cycle. The default is
+    /// [`Resolution::X4`].
+    pub fn resolution(mut self, resolution: Resolution) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Set the digital filter of the encoder inputs, from 0 (no filter) to
+    /// 15. Higher values need the input to be stable for more samples, at
+    /// a lower sampling frequency, before an edge is counted.
+    pub fn filter(mut self, filter: u8) -> Self {
+        assert!(filter <= 15, "The input filter is at most 15");
+        self.filter = filter;
+        self
+    }
+
+    /// Invert the counting direction
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            resolution: Resolution::X4,
+            filter: 0,
+            invert: false,
+        }
+    }
+}
+
+/// The A and B pins of an encoder, on channels 1 and 2 of the timer
+pub trait QeiPins<TIM> {}
+
+impl<TIM, A, B> QeiPins<TIM> for (A, B)
+where
+    A: PinCh<TIM, 1>,
+    B: PinCh<TIM, 2>,
+{
+}
+
+/// A timer configured as a quadrature encoder interface
+#[derive(Debug)]
+pub struct Qei<TIM> {
+    tim: TIM,
+    last_count: u32,
+    position: i64,
+}
+
+pub trait QeiExt<TIM: EncoderInstance>: Sized {
+    fn qei<PINS>(self, pins: PINS, config: Config, rec: TIM::Rec) -> Qei<TIM>
+    where
+        PINS: QeiPins<TIM>;
+}
+
+impl<TIM: EncoderInstance> QeiExt<TIM> for TIM {
+    fn qei<PINS>(self, _pins: PINS, config: Config, rec: TIM::Rec) -> Qei<TIM>
+    where
+        PINS: QeiPins<TIM>,
+    {
+        Qei::new(self, config, rec)
+    }
+}
+
+#[inline(always)]
+fn regs<TIM: Instance>() -> &'static tim2::RegisterBlock {
+    // NOTE(unsafe) The registers used here are at the same offsets in all
+    // timers
+    unsafe { &*TIM::ptr() }
+}
+
+/// The signed change of a counter that wraps at `max_count`, from `last`
+/// to `now`, assuming it has moved by less than half of its range
+fn count_delta(last: u32, now: u32, max_count: u32) -> i64 {
+    let delta = now.wrapping_sub(last);
+    if max_count == u32::MAX {
+        delta as i32 as i64
+    } else {
+        delta as u16 as i16 as i64
+    }
+}
+
+impl<TIM: EncoderInstance> Qei<TIM> {
+    fn new(tim: TIM, config: Config, rec: TIM::Rec) -> Self {
+        let _ = rec.enable().reset();
+
+        let regs = regs::<TIM>();
+        regs.arr().write(|w| w.arr().set(TIM::MAX_ARR));
+        regs.ccmr1_input().write(|w| {
+            w.cc1s()
+                .ti1()
+                .cc2s()
+                .ti2()
+                .icf(0)
+                .set(config.filter)
+                .icf(1)
+                .set(config.filter)
+        });
+        // Inverting one input reverses the direction
+        regs.ccer().write(|w| {
+            w.cce(0)
+                .set_bit()
+                .cce(1)
+                .set_bit()
+                .ccp(0)
+                .bit(config.invert)
+        });
+        regs.smcr().write(|w| match config.resolution {
+            Resolution::X2 => w.sms().set(0b001),
+            Resolution::X4 => w.sms().set(0b011),
+        });
+        regs.cr1().write(|w| w.cen().enabled());
+
+        Qei {
+            tim,
+            last_count: 0,
+            position: 0,
+        }
+    }
+
+    /// Reset the counter when the encoder index is detected on `pin`. The
+    /// index is active at the level given by `active`, and is filtered in
+    /// the same way as the encoder inputs.
+    pub fn with_index<PIN>(
+        mut self,
+        _pin: PIN,
+        mode: IndexMode,
+        active: PinState,
+    ) -> Self
+    where
+        PIN: PinEtr<TIM>,
+    {
+        let regs = regs::<TIM>();
+        let filter = regs.ccmr1_input().read().icf(0).bits();
+        regs.smcr().modify(|_, w| {
+            w.etf().set(filter).etp().bit(active == PinState::Low)
+        });
+        // The index resets the counter in both directions, when both
+        // encoder inputs are low
+        regs.ecr()
+            .write(|w| w.ie().set_bit().fidx().bit(mode == IndexMode::First));
+        clear_pending::<TIM>(Event::Index);
+        self.update_position();
+        self
+    }
+
+    /// The raw value of the counter
+    pub fn count(&self) -> u32 {
+        regs::<TIM>().cnt().read().cnt().bits()
+    }
+
+    /// The maximum value of the counter, after which it wraps around to 0
+    pub fn max_count(&self) -> u32 {
+        TIM::MAX_ARR
+    }
+
+    /// The direction that the counter last moved in
+    pub fn direction(&self) -> Direction {
+        if regs::<TIM>().cr1().read().dir().bit_is_set() {
+            Direction::Down
+        } else {
+            Direction::Up
+        }
+    }
+
+    fn update_position(&mut self) {
+        let count = self.count();
+        if is_pending::<TIM>(Event::Index) {
+            // The counter was reset to 0 at the index
+            clear_pending::<TIM>(Event::Index);
+            self.position = count_delta(0, count, TIM::MAX_ARR);
+        } else {
+            self.position += count_delta(self.last_count, count, TIM::MAX_ARR);
+        }
+        self.last_count = count;
+    }
+
+    /// The position, which is the counter extended to 64 bits so that it
+    /// does not wrap. This needs to be called before the encoder has moved
+    /// by half of the counter range.
+    pub fn position(&mut self) -> i64 {
+        self.update_position();
+        self.position
+    }
+
+    /// Set the current position to `position`
+    pub fn set_position(&mut self, position: i64) {
+        self.update_position();
+        self.position = position;
+    }
+
+    /// Start listening for `event`. [`Event::Index`] is generated on each
+    /// index pulse and [`Event::DirectionChange`] when the encoder changes
+    /// direction.
+    ///
+    /// As [`position`](Self::position) uses the index flag, the index
+    /// interrupt should not clear it.
+    pub fn listen(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, true);
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, false);
+    }
+
+    /// Whether `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        is_pending::<TIM>(event)
+    }
+
+    /// Clear the pending flag of `event`
+    pub fn clear_pending(&mut self, event: Event) {
+        clear_pending::<TIM>(event);
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> TIM {
+        let _ = TIM::rec().reset().disable();
+        self.tim
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_delta() {
+        assert_eq!(count_delta(100, 150, 0xFFFF), 50);
+        assert_eq!(count_delta(150, 100, 0xFFFF), -50);
+        // Wrapping forwards and backwards on a 16-bit counter
+        assert_eq!(count_delta(0xFFF0, 0x0010, 0xFFFF), 0x20);
+        assert_eq!(count_delta(0x0010, 0xFFF0, 0xFFFF), -0x20);
+        // A 32-bit counter does not wrap at 16 bits
+        assert_eq!(count_delta(0xFFF0, 0x1_0010, 0xFFFF_FFFF), 0x20);
+        assert_eq!(count_delta(0x10, 0xFFFF_FFF0, 0xFFFF_FFFF), -0x20);
+    }
+}
@@ -139,13 +139,14 @@ use crate::stm32::rcc::{
     ccipr5::CKPERSEL, cfgr1::SW, cfgr1::TIMPRE, cfgr2::HPRE,
     cfgr2::PPRE1 as PPRE, pll1cfgr::PLL1SRC, pll2cfgr::PLL2SRC,
 };
-use crate::stm32::{RCC, SBS};
+use crate::stm32::{PWR, RCC, SBS};
 use crate::time::Hertz;
 
 use core_clocks::PllClocks;
 #[cfg(feature = "log")]
 use log::debug;
 
+pub(crate) mod clock_state;
 mod core_clocks;
 mod mco;
 mod pll;
@@ -345,7 +346,8 @@ impl Rcc {
         self
     }
 
-    /// Set low speed external clock frequency
+    /// Set low speed external clock frequency. The LSE oscillator is started
+    /// when the RCC is frozen.
     pub fn lse_ck(mut self, freq: Hertz) -> Self {
         self.config.lse = Some(freq.raw());
         self
@@ -718,7 +720,22 @@ impl Rcc {
             None => None,
         };
 
-        let lse_ck = self.config.lse.map(Hertz::from_raw);
+        // LSE
+        let lse_ck = match self.config.lse {
+            Some(lse) => {
+                // The LSE is in the backup domain, which is write protected
+                // after reset
+                let pwr = unsafe { &*PWR::ptr() };
+                pwr.dbpcr().modify(|_, w| w.dbp().set_bit());
+
+                // Ensure LSE is on and stable
+                rcc.bdcr().modify(|_, w| w.lseon().on());
+                while rcc.bdcr().read().lserdy().is_not_ready() {}
+
+                Some(Hertz::from_raw(lse))
+            }
+            None => None,
+        };
 
         let audio_ck = self.config.audio_ck.map(Hertz::from_raw);
 
@@ -0,0 +1,82 @@
+//! Capture of the core clock configuration, so that it can be restored after
+//! leaving Stop mode.
+//!
+//! When the system leaves Stop mode the hardware selects the HSI (or CSI) as
+//! the system clock, and the HSE, CSI, HSI48 and PLLs are left disabled. The
+//! PLL configuration registers are retained, so only the oscillator enables
+//! and the system clock switch need to be restored.
+
+use crate::stm32::rcc::cfgr1::SW;
+use crate::stm32::{PWR, RCC};
+
+/// The state of the oscillators, PLLs and system clock switch
+pub(crate) struct ClockState {
+    hse: bool,
+    csi: bool,
+    hsi48: bool,
+    pll1: bool,
+    pll2: bool,
+    #[cfg(feature = "rm0481")]
+    pll3: bool,
+    sw: SW,
+}
+
+impl ClockState {
+    /// Capture the current clock state
+    pub(crate) fn save() -> Self {
+        let rcc = unsafe { &*RCC::ptr() };
+        let cr = rcc.cr().read();
+
+        ClockState {
+            hse: cr.hseon().is_on(),
+            csi: cr.csion().is_on(),
+            hsi48: cr.hsi48on().is_on(),
+            pll1: cr.pll1on().is_on(),
+            pll2: cr.pll2on().is_on(),
+            #[cfg(feature = "rm0481")]
+            pll3: cr.pll3on().is_on(),
+            sw: rcc.cfgr1().read().sw().variant(),
+        }
+    }
+
+    /// Restart the oscillators and PLLs that were running when the state was
+    /// captured, then switch the system clock back to its previous source.
+    pub(crate) fn restore(&self) {
+        let rcc = unsafe { &*RCC::ptr() };
+        let pwr = unsafe { &*PWR::ptr() };
+
+        // The system must be back at the voltage scale used in Run mode
+        // before the clock frequency is increased
+        while pwr.vossr().read().actvosrdy().is_not_ready() {}
+
+        if self.hse {
+            rcc.cr().modify(|_, w| w.hseon().on());
+            while rcc.cr().read().hserdy().is_not_ready() {}
+        }
+        if self.csi {
+            rcc.cr().modify(|_, w| w.csion().on());
+            while rcc.cr().read().csirdy().is_not_ready() {}
+        }
+        if self.hsi48 {
+            rcc.cr().modify(|_, w| w.hsi48on().on());
+            while rcc.cr().read().hsi48rdy().is_not_ready() {}
+        }
+
+        if self.pll1 {
+            rcc.cr().modify(|_, w| w.pll1on().on());
+            while rcc.cr().read().pll1rdy().is_not_ready() {}
+        }
+        if self.pll2 {
+            rcc.cr().modify(|_, w| w.pll2on().on());
+            while rcc.cr().read().pll2rdy().is_not_ready() {}
+        }
+        #[cfg(feature = "rm0481")]
+        if self.pll3 {
+            rcc.cr().modify(|_, w| w.pll3on().on());
+            while rcc.cr().read().pll3rdy().is_not_ready() {}
+        }
+
+        rcc.cfgr1().modify(|_, w| w.sw().variant(self.sw));
+        while rcc.cfgr1().read().sws().bits() != self.sw.into() {}
+    }
+}
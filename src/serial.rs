@@ -0,0 +1,1336 @@
+//! Serial (USART/UART)
+//!
+//! This module provides a blocking driver for the Universal (Synchronous)
+//! Asynchronous Receiver/Transmitter peripherals (U(S)ART) in asynchronous
+//! mode. All of the USART and UART instances share the same implementation.
+//! The USARTs can also be used as SPI masters in synchronous mode.
+//!
+//! [`Serial`] implements the [embedded-io][embedded_io] `Read` and `Write`
+//! traits as well as the [embedded-hal-nb][embedded_hal_nb] serial traits.
+//! It can also be split into separate [`Tx`] and [`Rx`] halves, each of
+//! which implements the corresponding traits.
+//!
+//! # Usage
+//!
+//! A serial port is initialised from the device peripheral, a tuple of TX
+//! and RX pins, a configuration (or just a baud rate) and the peripheral's
+//! reset and enable control:
+//!
+//! ```
+//! use stm32h5xx_hal::serial;
+//!
+//! let dp = ...;           // Device peripherals
+//! let (tx, rx) = ...;     // GPIO pins
+//!
+//! let mut serial = dp.USART1.serial(
+//!     (tx, rx),
+//!     115_200.Hz(),
+//!     ccdr.peripheral.USART1,
+//!     &ccdr.clocks,
+//! );
+//! ```
+//!
+//! If one direction is not required, explicitly pass one of the filler
+//! types instead:
+//!
+//! ```
+//! let mut serial = dp.USART1.serial((tx, serial::NoRx), 115_200.Hz(), ccdr.peripheral.USART1, &ccdr.clocks);
+//! ```
+//!
+//! The frame format can be changed through [`Config`]:
+//!
+//! ```
+//! use stm32h5xx_hal::serial::{Config, Parity, StopBits, WordLength};
+//!
+//! let config = Config::new(9600.Hz())
+//!     .word_length(WordLength::DataBits7)
+//!     .parity(Parity::ParityEven)
+//!     .stop_bits(StopBits::Stop2);
+//! let mut serial = dp.USART2.serial((tx, rx), config, ccdr.peripheral.USART2, &ccdr.clocks);
+//! ```
+//!
+//! ## Transmitting and receiving
+//!
+//! ```
+//! use embedded_io::{Read, Write};
+//!
+//! serial.write_all(b"Hello, world!\r\n")?;
+//!
+//! let mut buf = [0u8; 8];
+//! let n = serial.read(&mut buf)?;
+//! ```
+//!
+//! The port can be split so that the transmitter and receiver can be owned by
+//! different parts of an application:
+//!
+//! ```
+//! let (mut tx, mut rx) = serial.split();
+//! ```
+//!
+//! ## Flow control and RS-485
+//!
+//! Hardware flow control is enabled by passing the RTS and/or CTS pins to
+//! the serial port after it has been created. Alternatively, the RTS pin can
+//! be used as the driver enable output for an RS-485 transceiver:
+//!
+//! ```
+//! use stm32h5xx_hal::serial::{DePolarity, DriverEnable};
+//!
+//! let serial = dp.USART1
+//!     .serial((tx, rx), 115_200.Hz(), ccdr.peripheral.USART1, &ccdr.clocks)
+//!     .with_driver_enable(de, DriverEnable::new().assertion_time(16).polarity(DePolarity::ActiveHigh));
+//! ```
+//!
+//! On a multidrop bus, mute mode lets a node ignore traffic addressed to
+//! other nodes. With address mark wakeup, the receiver stays muted until an
+//! address character matching its own address is received:
+//!
+//! ```
+//! use stm32h5xx_hal::serial::{AddressLength, Config, Wakeup};
+//!
+//! let config = Config::new(115_200.Hz()).mute_mode(Wakeup::AddressMark {
+//!     address: 0x12,
+//!     length: AddressLength::Bits7,
+//! });
+//! let mut serial = dp.USART1.serial((tx, rx), config, ccdr.peripheral.USART1, &ccdr.clocks);
+//! serial.enter_mute_mode();
+//! ```
+//!
+//! ## DMA
+//!
+//! The transmitter and receiver halves can each be paired with a GPDMA
+//! channel. Transmission blocks until the whole buffer has been sent, while
+//! reception ends when the buffer is full or the end of a packet is detected
+//! by an idle line or the receiver timeout, returning the number of bytes
+//! received:
+//!
+//! ```
+//! let channels = dp.GPDMA1.channels(ccdr.peripheral.GPDMA1);
+//! let (tx, rx) = serial.split();
+//! let mut tx = tx.with_dma(channels.0);
+//! let mut rx = rx.with_dma(channels.1);
+//!
+//! tx.write_dma(b"Hello, world!\r\n")?;
+//!
+//! let mut buf = [0u8; 64];
+//! let n = rx.read_dma(&mut buf)?;
+//! ```
+//!
+//! ## Async
+//!
+//! With the `async` feature enabled, [`TxDma`] implements the
+//! [embedded-io-async][embedded_io_async] `Write` trait and [`RxDma`]
+//! implements `Read`. As above, a read completes when the buffer is full or
+//! the end of a packet is detected. Since the halves are independent they
+//! can be moved to separate tasks. Both the GPDMA channel interrupts and the
+//! USART interrupt must be unmasked in the NVIC:
+//!
+//! ```
+//! use embedded_io_async::{Read, Write};
+//!
+//! unsafe {
+//!     NVIC::unmask(interrupt::GPDMA1_CH0);
+//!     NVIC::unmask(interrupt::GPDMA1_CH1);
+//!     NVIC::unmask(interrupt::USART1);
+//! }
+//!
+//! let n = rx.read(&mut buf).await?;
+//! tx.write_all(&buf[..n]).await?;
+//! ```
+//!
+//! The `async` feature defines the interrupt handlers of all USART/UART
+//! instances, so it cannot be used together with interrupt handlers of the
+//! application for any of them, such as one for [`Serial::listen`].
+//!
+//! ## Synchronous mode
+//!
+//! The USART instances (but not the UARTs) can output a clock on their CK
+//! pin, which allows them to be used as an additional SPI master bus.
+//! [`UsartSpi`] implements the embedded-hal [`SpiBus`][spi_bus] trait for
+//! 8-bit words. The pins are given in the same order as for the SPI
+//! peripherals: CK (SCK), RX (MISO), TX (MOSI). Chip select must be driven
+//! by a GPIO.
+//!
+//! ```
+//! use embedded_hal::spi::SpiBus;
+//! use stm32h5xx_hal::{serial::SpiConfig, spi::MODE_0};
+//!
+//! let mut spi = dp.USART1.usart_spi(
+//!     (ck, rx, tx),
+//!     SpiConfig::new(MODE_0),
+//!     1.MHz(),
+//!     ccdr.peripheral.USART1,
+//!     &ccdr.clocks,
+//! );
+//!
+//! let mut read = [0u8; 3];
+//! spi.transfer(&mut read, &[0x11, 0x22, 0x33])?;
+//! ```
+//!
+//! ## Half-duplex
+//!
+//! In single-wire half-duplex mode, only the TX pin is used. It must be
+//! configured as open drain, with a pull-up on the shared line.
+//! [`HalfDuplex::transaction`] sends a request and waits for the response
+//! with a timeout, which is measured using the DWT cycle counter:
+//!
+//! ```
+//! let _mono = MonoTimer::new(cp.DWT, cp.DCB, &ccdr.clocks);
+//!
+//! let tx = gpioa.pa9.into_alternate_open_drain();
+//! let mut bus = dp.USART1.half_duplex(tx, 1.MHz(), ccdr.peripheral.USART1, &ccdr.clocks);
+//!
+//! let mut status = [0u8; 11];
+//! bus.transaction(&ping, &mut status, 10.millis())?;
+//! ```
+//!
+//! ## LIN
+//!
+//! In LIN mode the USART generates and detects the break at the start of
+//! each frame header. [`Lin`] provides a frame-level API for both the master
+//! and slave sides, computing the protected identifier and checksum of each
+//! frame and enforcing the response timeout. A LIN transceiver is connected
+//! to the TX and RX pins.
+//!
+//! ```
+//! use stm32h5xx_hal::serial::{lin::Checksum, LinConfig};
+//!
+//! let mut lin = dp.USART1.lin((tx, rx), LinConfig::new(19_200.Hz()), ccdr.peripheral.USART1, &ccdr.clocks);
+//!
+//! // Master: publish frame 0x10, then request frame 0x11 from a slave
+//! lin.write_frame(0x10, &[0x01, 0x02], Checksum::Enhanced)?;
+//! let mut data = [0u8; 4];
+//! lin.read_frame(0x11, &mut data, Checksum::Enhanced)?;
+//!
+//! // Slave: respond to frame 0x11
+//! if lin.read_header()? == 0x11 {
+//!     lin.write_response(0x11, &[0xAA, 0xBB, 0xCC, 0xDD], Checksum::Enhanced)?;
+//! }
+//! ```
+//!
+//! ## Modbus RTU
+//!
+//! [`Modbus`] implements the framing of Modbus RTU on top of the USART,
+//! detecting the end of each frame with the receiver timeout and checking
+//! the CRC. It can be used as a master polling slaves, or as a slave
+//! answering requests. Modbus requires 11-bit characters, normally with even
+//! parity, and the DWT cycle counter to be enabled.
+//!
+//! ```
+//! use stm32h5xx_hal::serial::{modbus::ExceptionCode, Config, DriverEnable, Parity};
+//!
+//! let _mono = MonoTimer::new(cp.DWT, cp.DCB, &ccdr.clocks);
+//!
+//! let config = Config::new(19_200.Hz()).parity(Parity::ParityEven);
+//! let mut modbus = dp.USART1
+//!     .modbus((tx, rx), config, ccdr.peripheral.USART1, &ccdr.clocks)
+//!     .with_driver_enable(de, DriverEnable::new());
+//!
+//! // Master: read two holding registers from device 17
+//! let response = modbus.request(17, 0x03, &[0x00, 0x6B, 0x00, 0x02], 100.millis())?;
+//! if let Some(code) = response.exception() { /* ... */ }
+//!
+//! // Slave: answer requests for address 17
+//! modbus.serve(17, |request, response| match request.function() {
+//!     0x03 => { /* fill in response */ Ok(5) }
+//!     _ => Err(ExceptionCode::IllegalFunction),
+//! })?;
+//! ```
+//!
+//! ## Smartcard
+//!
+//! The USART instances can communicate with ISO 7816 smartcards. The card's
+//! I/O line is connected to the TX pin, which must be open drain, and its
+//! clock to the CK pin. The card clock frequency is derived from the USART
+//! kernel clock. Resetting and powering the card is left to GPIOs.
+//!
+//! ```
+//! use stm32h5xx_hal::serial::SmartcardConfig;
+//!
+//! let io = gpioa.pa9.into_alternate_open_drain();
+//! let mut card = dp.USART1.smartcard((io, ck), SmartcardConfig::new(4.MHz()), ccdr.peripheral.USART1, &ccdr.clocks);
+//!
+//! rst.set_high();
+//! let atr = card.read_atr()?;
+//!
+//! // SELECT by name
+//! let (_, sw) = card.command_t0([0x00, 0xA4, 0x04, 0x00, 0x07], &aid, &mut [])?;
+//! ```
+//!
+//! ## Clocks
+//!
+//! The baud rate is derived from the kernel clock currently assigned in the
+//! RCC CCIPR registers, which must have been enabled when the RCC was
+//! frozen. By default the peripheral bus clock is used: PCLK2 for USART1
+//! and PCLK1 for the other instances. The prescaler and oversampling mode
+//! are chosen automatically, and this will panic if the requested baud rate
+//! cannot be generated from the kernel clock. The same applies to the clock
+//! frequency in synchronous mode, which can be at most 1/8 of the kernel
+//! clock.
+//!
+//! # Examples
+//!
+//! - [Serial echo](https://github.com/stm32-rs/stm32h5xx-hal/blob/master/examples/serial.rs)
+//! - [Serial echo using DMA](https://github.com/stm32-rs/stm32h5xx-hal/blob/master/examples/serial_dma.rs)
+//! - [USART as an SPI master](https://github.com/stm32-rs/stm32h5xx-hal/blob/master/examples/usart_spi.rs)
+//!
+//! [embedded_io]: https://docs.rs/embedded-io/0.6.1/embedded_io/
+//! [embedded_io_async]: https://docs.rs/embedded-io-async/0.6.1/embedded_io_async/
+//! [embedded_hal_nb]: https://docs.rs/embedded-hal-nb/1.0.0/embedded_hal_nb/serial/index.html
+//! [spi_bus]: https://docs.rs/embedded-hal/1.0.0/embedded_hal/spi/trait.SpiBus.html
+
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use embedded_hal_nb::nb;
+
+use crate::gpdma::{
+    self,
+    periph::{RxAddr, TxAddr},
+};
+use crate::gpio::{self, Alternate, OpenDrain};
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::usart1;
+use crate::time::Hertz;
+use usart1::presc::PRESCALER;
+
+mod config;
+mod dma;
+#[cfg(feature = "async")]
+mod future;
+mod hal;
+mod half_duplex;
+pub mod lin;
+pub mod modbus;
+mod serial_def;
+pub mod smartcard;
+mod spi;
+
+pub use config::{
+    AddressLength, BitOrder, BreakLength, Config, DePolarity, DriverEnable,
+    LinConfig, Parity, Protocol, SmartcardConfig, SpiConfig, StopBits, Wakeup,
+    WordLength,
+};
+pub use dma::{RxDma, TxDma};
+pub use half_duplex::HalfDuplex;
+pub use lin::Lin;
+pub use modbus::Modbus;
+pub use smartcard::Smartcard;
+pub use spi::UsartSpi;
+
+/// Serial error
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Framing error: a stop bit was not detected where expected
+    Framing,
+    /// Noise was detected on the received frame
+    Noise,
+    /// A character was received before the previous one was read
+    Overrun,
+    /// The parity check failed
+    Parity,
+    /// An error occurred during a DMA transfer
+    Dma(gpdma::Error),
+    /// No data was received before the timeout elapsed
+    Timeout,
+    /// The checksum of a received frame is incorrect
+    Checksum,
+    /// The parity bits of a received LIN protected identifier are incorrect
+    IdentifierParity,
+    /// A LIN sync field was not received correctly, or the baud rate could
+    /// not be measured from it
+    Sync,
+    /// A received frame or response does not follow the protocol, such as
+    /// a smartcard response or a malformed Modbus frame
+    Protocol,
+}
+
+/// Interrupt events
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A character was received (RXNE/RXFNE)
+    RxNotEmpty,
+    /// The transmit register can accept a new character (TXE/TXFNF)
+    TxNotFull,
+    /// Transmission of the last character is complete (TC)
+    TransmissionComplete,
+    /// An idle line was detected (IDLE)
+    Idle,
+    /// The receiver timeout elapsed (RTOF)
+    ReceiverTimeout,
+}
+
+pub trait Pins<USART> {}
+
+pub trait PinTx<USART> {}
+
+pub trait PinRx<USART> {}
+
+/// A pin that can be used as the RTS output for hardware flow control, or
+/// as the driver enable (DE) output for an RS-485 transceiver
+pub trait PinRts<USART> {}
+
+/// A pin that can be used as the CTS input for hardware flow control
+pub trait PinCts<USART> {}
+
+/// A pin that can be used as the clock output in synchronous mode
+pub trait PinCk<USART> {}
+
+impl<USART, TX, RX> Pins<USART> for (TX, RX)
+where
+    TX: PinTx<USART>,
+    RX: PinRx<USART>,
+{
+}
+
+/// Pins for smartcard mode: the TX pin, configured as open drain, is the
+/// card's I/O line and CK supplies its clock
+pub trait SmartcardPins<USART> {}
+
+impl<USART, TX, CK> SmartcardPins<USART> for (TX, CK)
+where
+    TX: PinTx<USART>,
+    CK: PinCk<USART>,
+{
+}
+
+/// Pins for synchronous master mode, given in the same order as for an SPI
+/// bus: CK (SCK), RX (MISO) and TX (MOSI)
+pub trait SpiPins<USART> {}
+
+impl<USART, CK, RX, TX> SpiPins<USART> for (CK, RX, TX)
+where
+    CK: PinCk<USART>,
+    RX: PinRx<USART>,
+    TX: PinTx<USART>,
+{
+}
+
+/// A filler type for when the TX pin is unnecessary
+pub struct NoTx;
+
+/// A filler type for when the RX pin is unnecessary
+pub struct NoRx;
+
+// Implemented by all USART/UART instances
+pub trait Instance:
+    crate::Sealed
+    + Deref<Target = usart1::RegisterBlock>
+    + TxAddr<u8>
+    + RxAddr<u8>
+    + Sized
+{
+    type Rec: ResetEnable;
+
+    /// GPDMA hardware request line for the transmitter
+    const TX_DMA_REQUEST: u8;
+
+    /// GPDMA hardware request line for the receiver
+    const RX_DMA_REQUEST: u8;
+
+    #[doc(hidden)]
+    fn ptr() -> *const usart1::RegisterBlock;
+
+    #[doc(hidden)]
+    fn clock(clocks: &CoreClocks) -> Hertz;
+
+    #[doc(hidden)]
+    fn rec() -> Self::Rec;
+}
+
+// Implemented by the USART instances, which have a clock output (CK) for the
+// synchronous and smartcard modes. The UARTs do not.
+pub trait SynchronousInstance: Instance {}
+
+/// Serial port in asynchronous mode
+#[derive(Debug)]
+pub struct Serial<USART> {
+    usart: USART,
+    tx: Tx<USART>,
+    rx: Rx<USART>,
+}
+
+/// Transmitter half of a serial port
+#[derive(Debug)]
+pub struct Tx<USART> {
+    _usart: PhantomData<USART>,
+}
+
+/// Receiver half of a serial port
+#[derive(Debug)]
+pub struct Rx<USART> {
+    _usart: PhantomData<USART>,
+    mask: u16,
+}
+
+pub trait SerialExt<USART: Instance>: Sized {
+    fn serial<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Serial<USART>
+    where
+        PINS: Pins<USART>,
+        CONFIG: Into<Config>;
+
+    fn serial_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Serial<USART>
+    where
+        CONFIG: Into<Config>;
+
+    fn usart_spi<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        freq: Hertz,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> UsartSpi<USART>
+    where
+        USART: SynchronousInstance,
+        PINS: SpiPins<USART>,
+        CONFIG: Into<SpiConfig>;
+
+    fn usart_spi_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        freq: Hertz,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> UsartSpi<USART>
+    where
+        USART: SynchronousInstance,
+        CONFIG: Into<SpiConfig>;
+
+    fn lin<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Lin<USART>
+    where
+        PINS: Pins<USART>,
+        CONFIG: Into<LinConfig>;
+
+    fn lin_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Lin<USART>
+    where
+        CONFIG: Into<LinConfig>;
+
+    fn modbus<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Modbus<USART>
+    where
+        PINS: Pins<USART>,
+        CONFIG: Into<Config>;
+
+    fn modbus_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Modbus<USART>
+    where
+        CONFIG: Into<Config>;
+
+    fn smartcard<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Smartcard<USART>
+    where
+        USART: SynchronousInstance,
+        PINS: SmartcardPins<USART>,
+        CONFIG: Into<SmartcardConfig>;
+
+    fn smartcard_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Smartcard<USART>
+    where
+        USART: SynchronousInstance,
+        CONFIG: Into<SmartcardConfig>;
+
+    fn half_duplex<const P: char, const N: u8, const A: u8, CONFIG>(
+        self,
+        _tx: gpio::Pin<P, N, Alternate<A, OpenDrain>>,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> HalfDuplex<USART>
+    where
+        gpio::Pin<P, N, Alternate<A, OpenDrain>>: PinTx<USART>,
+        CONFIG: Into<Config>;
+}
+
+impl<USART: Instance> SerialExt<USART> for USART {
+    fn serial<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Serial<USART>
+    where
+        PINS: Pins<USART>,
+        CONFIG: Into<Config>,
+    {
+        Serial::new(self, config, rec, clocks)
+    }
+
+    fn serial_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Serial<USART>
+    where
+        CONFIG: Into<Config>,
+    {
+        Serial::new(self, config, rec, clocks)
+    }
+
+    fn usart_spi<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        freq: Hertz,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> UsartSpi<USART>
+    where
+        USART: SynchronousInstance,
+        PINS: SpiPins<USART>,
+        CONFIG: Into<SpiConfig>,
+    {
+        UsartSpi::new(self, config, freq, rec, clocks)
+    }
+
+    fn usart_spi_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        freq: Hertz,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> UsartSpi<USART>
+    where
+        USART: SynchronousInstance,
+        CONFIG: Into<SpiConfig>,
+    {
+        UsartSpi::new(self, config, freq, rec, clocks)
+    }
+
+    fn lin<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Lin<USART>
+    where
+        PINS: Pins<USART>,
+        CONFIG: Into<LinConfig>,
+    {
+        Lin::new(self, config, rec, clocks)
+    }
+
+    fn lin_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Lin<USART>
+    where
+        CONFIG: Into<LinConfig>,
+    {
+        Lin::new(self, config, rec, clocks)
+    }
+
+    fn modbus<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Modbus<USART>
+    where
+        PINS: Pins<USART>,
+        CONFIG: Into<Config>,
+    {
+        Modbus::new(self, config, rec, clocks)
+    }
+
+    fn modbus_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Modbus<USART>
+    where
+        CONFIG: Into<Config>,
+    {
+        Modbus::new(self, config, rec, clocks)
+    }
+
+    fn smartcard<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Smartcard<USART>
+    where
+        USART: SynchronousInstance,
+        PINS: SmartcardPins<USART>,
+        CONFIG: Into<SmartcardConfig>,
+    {
+        Smartcard::new(self, config, rec, clocks)
+    }
+
+    fn smartcard_unchecked<CONFIG>(
+        self,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Smartcard<USART>
+    where
+        USART: SynchronousInstance,
+        CONFIG: Into<SmartcardConfig>,
+    {
+        Smartcard::new(self, config, rec, clocks)
+    }
+
+    fn half_duplex<const P: char, const N: u8, const A: u8, CONFIG>(
+        self,
+        _tx: gpio::Pin<P, N, Alternate<A, OpenDrain>>,
+        config: CONFIG,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> HalfDuplex<USART>
+    where
+        gpio::Pin<P, N, Alternate<A, OpenDrain>>: PinTx<USART>,
+        CONFIG: Into<Config>,
+    {
+        HalfDuplex::new(self, config, rec, clocks)
+    }
+}
+
+/// Baud rate generator settings
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct BaudRate {
+    presc: PRESCALER,
+    over8: bool,
+    brr: u16,
+}
+
+const PRESCALERS: [(PRESCALER, u32); 12] = [
+    (PRESCALER::Div1, 1),
+    (PRESCALER::Div2, 2),
+    (PRESCALER::Div4, 4),
+    (PRESCALER::Div6, 6),
+    (PRESCALER::Div8, 8),
+    (PRESCALER::Div10, 10),
+    (PRESCALER::Div12, 12),
+    (PRESCALER::Div16, 16),
+    (PRESCALER::Div32, 32),
+    (PRESCALER::Div64, 64),
+    (PRESCALER::Div128, 128),
+    (PRESCALER::Div256, 256),
+];
+
+/// Calculate the prescaler, oversampling mode and BRR value to generate
+/// `baud` from `usart_ker_ck`. Oversampling by 16 is preferred as it is more
+/// tolerant to clock deviation; oversampling by 8 is only used when the
+/// kernel clock is too slow for it.
+fn calc_baud_rate(usart_ker_ck: u32, baud: u32) -> Option<BaudRate> {
+    if baud == 0 {
+        return None;
+    }
+    for (presc, div) in PRESCALERS {
+        let clk = usart_ker_ck / div;
+        let usartdiv = (clk + baud / 2) / baud;
+        if usartdiv > 0xFFFF {
+            continue;
+        }
+        if usartdiv >= 16 {
+            return Some(BaudRate {
+                presc,
+                over8: false,
+                brr: usartdiv as u16,
+            });
+        }
+
+        // Oversampling by 8: BRR[3] must be kept cleared and BRR[2:0] holds
+        // USARTDIV[3:0] shifted right by one bit.
+        let usartdiv = (2 * clk + baud / 2) / baud;
+        if usartdiv < 16 {
+            return None;
+        }
+        let brr = (usartdiv & !0xF) | ((usartdiv & 0xF) >> 1);
+        return Some(BaudRate {
+            presc,
+            over8: true,
+            brr: brr as u16,
+        });
+    }
+    None
+}
+
+impl<USART: Instance> Serial<USART> {
+    fn new(
+        usart: USART,
+        config: impl Into<Config>,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let config: Config = config.into();
+        let _ = rec.enable().reset();
+
+        let serial = Serial {
+            usart,
+            tx: Tx {
+                _usart: PhantomData,
+            },
+            rx: Rx {
+                _usart: PhantomData,
+                mask: (1 << config.word_length.bits()) - 1,
+            },
+        };
+        serial.init(config, USART::clock(clocks))
+    }
+
+    fn init(self, config: Config, clock: Hertz) -> Self {
+        assert!(
+            !(config.word_length == WordLength::DataBits9
+                && config.parity != Parity::ParityNone),
+            "9 data bits cannot be used together with a parity bit"
+        );
+
+        let baud = calc_baud_rate(clock.raw(), config.baudrate.raw())
+            .expect("Baud rate cannot be generated from the kernel clock");
+
+        // The configuration registers can only be written while the USART
+        // is disabled
+        self.usart.cr1().reset();
+        self.usart.cr2().reset();
+        self.usart.cr3().reset();
+
+        self.usart
+            .presc()
+            .write(|w| w.prescaler().variant(baud.presc));
+        self.usart
+            .brr()
+            .write(|w| unsafe { w.brr().bits(baud.brr) });
+
+        if let Some(timeout) = config.receiver_timeout {
+            self.usart
+                .rtor()
+                .write(|w| unsafe { w.rto().bits(timeout) });
+        }
+
+        let (wake, address, addm7) = match config.mute_mode {
+            Some(Wakeup::AddressMark { address, length }) => {
+                let addm7 = length == AddressLength::Bits7;
+                let bits = if addm7 {
+                    config.word_length.bits() - 1
+                } else {
+                    4
+                };
+                assert!(
+                    (address as u16) < (1 << bits),
+                    "Node address must fit in {bits} bits"
+                );
+                (true, address, addm7)
+            }
+            _ => (false, 0, false),
+        };
+
+        self.usart.cr2().write(|w| {
+            unsafe { w.add().bits(address) }
+                .addm7()
+                .bit(addm7)
+                .stop()
+                .variant(config.stop_bits.into())
+                .msbfirst()
+                .variant(config.bit_order.into())
+                .swap()
+                .bit(config.swap_tx_rx)
+                .rtoen()
+                .bit(config.receiver_timeout.is_some())
+        });
+
+        let (m1, m0) = match config.frame_bits() {
+            7 => (true, false),
+            8 => (false, false),
+            _ => (false, true),
+        };
+
+        self.usart.cr1().write(|w| {
+            w.m1()
+                .bit(m1)
+                .m0()
+                .bit(m0)
+                .pce()
+                .bit(config.parity != Parity::ParityNone)
+                .ps()
+                .bit(config.parity == Parity::ParityOdd)
+                .over8()
+                .bit(baud.over8)
+                .fifoen()
+                .bit(config.fifo)
+                .mme()
+                .bit(config.mute_mode.is_some())
+                .wake()
+                .bit(wake)
+                .te()
+                .enabled()
+                .re()
+                .enabled()
+                .ue()
+                .enabled()
+        });
+
+        self
+    }
+
+    /// Transmit `bytes` with the receiver disabled, blocking until
+    /// transmission is complete, so that the transmitted data is not
+    /// received back from a shared line
+    pub(crate) fn write_without_echo(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        self.usart.cr1().modify(|_, w| w.re().disabled());
+
+        let result = self.tx.bwrite_all(bytes);
+        while !self.tx.is_tx_complete() {}
+
+        self.usart.cr1().modify(|_, w| w.re().enabled());
+        result
+    }
+
+    /// Apply configuration that can only be changed while the USART is
+    /// disabled
+    fn reconfigure(&mut self, f: impl FnOnce(&usart1::RegisterBlock)) {
+        self.usart.cr1().modify(|_, w| w.ue().disabled());
+        f(&self.usart);
+        self.usart.cr1().modify(|_, w| w.ue().enabled());
+    }
+
+    /// Enable hardware flow control of reception using the RTS output. RTS
+    /// is asserted (low) while the receiver is able to accept data.
+    ///
+    /// This must be called before any data is transferred.
+    pub fn with_rts<RTS: PinRts<USART>>(mut self, _rts: RTS) -> Self {
+        self.reconfigure(|usart| {
+            usart.cr3().modify(|_, w| w.rtse().enabled());
+        });
+        self
+    }
+
+    /// Enable hardware flow control of transmission using the CTS input.
+    /// Characters are only transmitted while CTS is asserted (low).
+    ///
+    /// This must be called before any data is transferred.
+    pub fn with_cts<CTS: PinCts<USART>>(mut self, _cts: CTS) -> Self {
+        self.reconfigure(|usart| {
+            usart.cr3().modify(|_, w| w.ctse().enabled());
+        });
+        self
+    }
+
+    /// Drive the driver enable input of an RS-485 transceiver from the RTS
+    /// pin. DE is activated before each transmission and deactivated once
+    /// the last character has been sent, with the timing and polarity given
+    /// by `config`.
+    ///
+    /// This must be called before any data is transferred.
+    pub fn with_driver_enable<DE: PinRts<USART>>(
+        mut self,
+        _de: DE,
+        config: DriverEnable,
+    ) -> Self {
+        self.reconfigure(|usart| {
+            usart.cr1().modify(|_, w| unsafe {
+                w.deat()
+                    .bits(config.assertion_time)
+                    .dedt()
+                    .bits(config.deassertion_time)
+            });
+            usart.cr3().modify(|_, w| {
+                w.dem()
+                    .enabled()
+                    .dep()
+                    .bit(config.polarity == DePolarity::ActiveLow)
+            });
+        });
+        self
+    }
+
+    /// Start listening for an interrupt event
+    pub fn listen(&mut self, event: Event) {
+        self.usart.cr1().modify(|_, w| match event {
+            Event::RxNotEmpty => w.rxneie().enabled(),
+            Event::TxNotFull => w.txeie().enabled(),
+            Event::TransmissionComplete => w.tcie().enabled(),
+            Event::Idle => w.idleie().enabled(),
+            Event::ReceiverTimeout => w.rtoie().enabled(),
+        });
+    }
+
+    /// Stop listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        self.usart.cr1().modify(|_, w| match event {
+            Event::RxNotEmpty => w.rxneie().disabled(),
+            Event::TxNotFull => w.txeie().disabled(),
+            Event::TransmissionComplete => w.tcie().disabled(),
+            Event::Idle => w.idleie().disabled(),
+            Event::ReceiverTimeout => w.rtoie().disabled(),
+        });
+        interrupt_clear_clock_sync_delay!(self.usart.cr1());
+    }
+
+    /// Returns true if the line idle status is set
+    pub fn is_idle(&self) -> bool {
+        self.usart.isr().read().idle().bit_is_set()
+    }
+
+    /// Clear the line idle status flag
+    pub fn clear_idle(&mut self) {
+        self.usart.icr().write(|w| w.idlecf().clear());
+        interrupt_clear_clock_sync_delay!(self.usart.isr());
+    }
+
+    /// Returns true if the receiver timeout has elapsed
+    pub fn is_receiver_timeout(&self) -> bool {
+        self.usart.isr().read().rtof().bit_is_set()
+    }
+
+    /// Clear the receiver timeout flag
+    pub fn clear_receiver_timeout(&mut self) {
+        self.usart.icr().write(|w| w.rtocf().clear());
+        interrupt_clear_clock_sync_delay!(self.usart.isr());
+    }
+
+    /// Returns true if there is a received character waiting to be read
+    pub fn is_rx_not_empty(&self) -> bool {
+        self.rx.is_rx_not_empty()
+    }
+
+    /// Put the receiver into mute mode. See [`Rx::enter_mute_mode`].
+    pub fn enter_mute_mode(&mut self) {
+        self.rx.enter_mute_mode()
+    }
+
+    /// Returns true if the receiver is in mute mode
+    pub fn is_muted(&self) -> bool {
+        self.rx.is_muted()
+    }
+
+    /// Returns true if the transmitter can accept another character
+    pub fn is_tx_not_full(&self) -> bool {
+        self.tx.is_tx_not_full()
+    }
+
+    /// Returns true if transmission of all queued characters is complete
+    pub fn is_tx_complete(&self) -> bool {
+        self.tx.is_tx_complete()
+    }
+
+    /// Split the serial port into transmitter and receiver halves
+    pub fn split(self) -> (Tx<USART>, Rx<USART>) {
+        (self.tx, self.rx)
+    }
+
+    /// Disable the peripheral and return it
+    pub fn free(self) -> USART {
+        self.usart.cr1().reset();
+        self.usart
+    }
+
+    pub fn inner(&self) -> &USART {
+        &self.usart
+    }
+
+    pub fn inner_mut(&mut self) -> &mut USART {
+        &mut self.usart
+    }
+}
+
+// Methods of the transmitter halves that only use the status and data
+// registers, shared with the LPUART. `$regs` is the method returning the
+// register block, whose type differs between the USARTs and the LPUART.
+macro_rules! tx_methods {
+    ($regs:ident) => {
+        /// Returns true if the transmitter can accept another character
+        pub fn is_tx_not_full(&self) -> bool {
+            self.$regs().isr().read().txfnf().bit_is_set()
+        }
+
+        /// Returns true if transmission of all queued characters is complete
+        pub fn is_tx_complete(&self) -> bool {
+            self.$regs().isr().read().tc().bit_is_set()
+        }
+
+        /// Write a single character, returning `WouldBlock` if the
+        /// transmitter is busy
+        pub fn write_word(
+            &mut self,
+            word: u16,
+        ) -> nb::Result<(), $crate::serial::Error> {
+            if self.is_tx_not_full() {
+                self.$regs().tdr().write(|w| unsafe { w.tdr().bits(word) });
+                Ok(())
+            } else {
+                Err(nb::Error::WouldBlock)
+            }
+        }
+
+        /// Write all of `bytes`, blocking until they have been queued
+        pub fn bwrite_all(
+            &mut self,
+            bytes: &[u8],
+        ) -> Result<(), $crate::serial::Error> {
+            for &byte in bytes {
+                nb::block!(self.write_word(byte as u16))?;
+            }
+            Ok(())
+        }
+
+        /// Block until all queued characters have been transmitted
+        pub fn bflush(&mut self) -> Result<(), $crate::serial::Error> {
+            while !self.is_tx_complete() {}
+            Ok(())
+        }
+    };
+}
+
+// Checks the status flags captured in `$isr` for a reception error, clearing
+// the flag of any error that is found. Shared with the LPUART.
+macro_rules! rx_errors {
+    ($regs:expr, $isr:expr) => {
+        if $isr.pe().bit_is_set() {
+            $regs.icr().write(|w| w.pecf().clear());
+            Err($crate::serial::Error::Parity)
+        } else if $isr.fe().bit_is_set() {
+            $regs.icr().write(|w| w.fecf().clear());
+            Err($crate::serial::Error::Framing)
+        } else if $isr.ne().bit_is_set() {
+            $regs.icr().write(|w| w.necf().clear());
+            Err($crate::serial::Error::Noise)
+        } else if $isr.ore().bit_is_set() {
+            $regs.icr().write(|w| w.orecf().clear());
+            Err($crate::serial::Error::Overrun)
+        } else {
+            Ok(())
+        }
+    };
+}
+
+// Methods of the receiver halves that only use the status and data
+// registers, shared with the LPUART. `$regs` is the method returning the
+// register block, and the receiver has a `mask` for the data bits.
+macro_rules! rx_methods {
+    ($regs:ident) => {
+        /// Returns true if there is a received character waiting to be read
+        pub fn is_rx_not_empty(&self) -> bool {
+            self.$regs().isr().read().rxfne().bit_is_set()
+        }
+
+        /// Read a single character, returning `WouldBlock` if none has been
+        /// received. Any parity bit is masked off.
+        pub fn read_word(&mut self) -> nb::Result<u16, $crate::serial::Error> {
+            let regs = self.$regs();
+            let isr = regs.isr().read();
+
+            if let Err(err) = $crate::serial::rx_errors!(regs, isr) {
+                // Discard the character associated with the error
+                if isr.rxfne().bit_is_set() {
+                    let _ = regs.rdr().read();
+                }
+                Err(nb::Error::Other(err))
+            } else if isr.rxfne().bit_is_set() {
+                Ok(regs.rdr().read().rdr().bits() & self.mask)
+            } else {
+                Err(nb::Error::WouldBlock)
+            }
+        }
+
+        /// Read into `buffer`, blocking until at least one byte has been
+        /// received. Returns the number of bytes read.
+        pub fn bread(
+            &mut self,
+            buffer: &mut [u8],
+        ) -> Result<usize, $crate::serial::Error> {
+            if buffer.is_empty() {
+                return Ok(0);
+            }
+            buffer[0] = nb::block!(self.read_word())? as u8;
+            let mut count = 1;
+            for byte in buffer[1..].iter_mut() {
+                match self.read_word() {
+                    Ok(word) => *byte = word as u8,
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(err)) => return Err(err),
+                }
+                count += 1;
+            }
+            Ok(count)
+        }
+    };
+}
+
+pub(crate) use {rx_errors, rx_methods, tx_methods};
+
+impl<USART: Instance> Tx<USART> {
+    fn usart(&self) -> &usart1::RegisterBlock {
+        // NOTE(unsafe) Tx only ever accesses the transmit related bits
+        unsafe { &*USART::ptr() }
+    }
+
+    tx_methods!(usart);
+
+    /// Write an address character for mute mode with address mark wakeup,
+    /// setting the most significant data bit to mark it as an address.
+    /// Returns `WouldBlock` if the transmitter is busy.
+    pub fn write_address(&mut self, address: u8) -> nb::Result<(), Error> {
+        let cr1 = self.usart().cr1().read();
+        let frame_bits = match (cr1.m1().bit(), cr1.m0().bit()) {
+            (true, _) => 7,
+            (false, true) => 9,
+            (false, false) => 8,
+        };
+        let data_bits = frame_bits - cr1.pce().bit() as u16;
+        self.write_word((1 << (data_bits - 1)) | address as u16)
+    }
+}
+
+/// Checks the status flags captured in `isr` for a reception error, clearing
+/// the flag of any error that is found.
+fn check_rx_errors(
+    usart: &usart1::RegisterBlock,
+    isr: &usart1::isr::R,
+) -> Result<(), Error> {
+    rx_errors!(usart, isr)
+}
+
+/// Clear the idle line, receiver timeout, LIN break and reception error
+/// flags before starting a new reception
+pub(crate) fn clear_rx_flags(usart: &usart1::RegisterBlock) {
+    usart.icr().write(|w| {
+        w.idlecf()
+            .clear()
+            .rtocf()
+            .clear()
+            .lbdcf()
+            .clear()
+            .pecf()
+            .clear()
+            .fecf()
+            .clear()
+            .necf()
+            .clear()
+            .orecf()
+            .clear()
+    });
+}
+
+/// Discard any received data and clear the reception status flags
+pub(crate) fn flush_rx(usart: &usart1::RegisterBlock) {
+    usart.rqr().write(|w| w.rxfrq().set_bit());
+    clear_rx_flags(usart);
+}
+
+impl<USART: Instance> Rx<USART> {
+    fn usart(&self) -> &usart1::RegisterBlock {
+        // NOTE(unsafe) Rx only ever accesses the receive related bits
+        unsafe { &*USART::ptr() }
+    }
+
+    rx_methods!(usart);
+
+    /// Put the receiver into mute mode, which must have been enabled in the
+    /// [`Config`]. Characters are discarded until the configured wakeup
+    /// condition occurs.
+    pub fn enter_mute_mode(&mut self) {
+        self.usart().rqr().write(|w| w.mmrq().set_bit());
+    }
+
+    /// Returns true if the receiver is in mute mode
+    pub fn is_muted(&self) -> bool {
+        self.usart().isr().read().rwu().bit_is_set()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_baud_rate() {
+        // 250 MHz PCLK, 115200 baud: oversampling by 16 without prescaler
+        assert_eq!(
+            calc_baud_rate(250_000_000, 115_200),
+            Some(BaudRate {
+                presc: PRESCALER::Div1,
+                over8: false,
+                brr: 2170
+            })
+        );
+
+        // 32 MHz HSI, 9600 baud
+        assert_eq!(
+            calc_baud_rate(32_000_000, 9600),
+            Some(BaudRate {
+                presc: PRESCALER::Div1,
+                over8: false,
+                brr: 3333
+            })
+        );
+
+        // 250 MHz PCLK, 1200 baud needs a prescaler to fit BRR
+        assert_eq!(
+            calc_baud_rate(250_000_000, 1200),
+            Some(BaudRate {
+                presc: PRESCALER::Div4,
+                over8: false,
+                brr: 52083
+            })
+        );
+
+        // 32 MHz, 3 Mbaud needs oversampling by 8: USARTDIV = 0x15
+        assert_eq!(
+            calc_baud_rate(32_000_000, 3_000_000),
+            Some(BaudRate {
+                presc: PRESCALER::Div1,
+                over8: true,
+                brr: 0x12
+            })
+        );
+
+        // Faster than the kernel clock / 8
+        assert_eq!(calc_baud_rate(32_000_000, 5_000_000), None);
+    }
+}
@@ -0,0 +1,579 @@
+use embedded_hal::spi::Mode;
+
+use crate::stm32::usart1::cr2::{LBDL, MSBFIRST, STOP};
+use crate::time::Hertz;
+
+/// Number of data bits in each character, excluding any parity bit
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WordLength {
+    /// 7 data bits
+    DataBits7,
+    /// 8 data bits. This is the default.
+    DataBits8,
+    /// 9 data bits. Not available when parity is enabled.
+    DataBits9,
+}
+
+impl WordLength {
+    pub(crate) fn bits(&self) -> u8 {
+        match self {
+            WordLength::DataBits7 => 7,
+            WordLength::DataBits8 => 8,
+            WordLength::DataBits9 => 9,
+        }
+    }
+}
+
+/// Parity generation and checking
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Parity {
+    /// No parity bit. This is the default.
+    ParityNone,
+    /// Even parity
+    ParityEven,
+    /// Odd parity
+    ParityOdd,
+}
+
+/// Number of stop bits appended to each character
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StopBits {
+    /// 1 stop bit. This is the default.
+    Stop1,
+    /// 0.5 stop bits
+    Stop0p5,
+    /// 2 stop bits
+    Stop2,
+    /// 1.5 stop bits
+    Stop1p5,
+}
+
+impl From<StopBits> for STOP {
+    fn from(value: StopBits) -> Self {
+        match value {
+            StopBits::Stop1 => STOP::Stop1,
+            StopBits::Stop0p5 => STOP::Stop0p5,
+            StopBits::Stop2 => STOP::Stop2,
+            StopBits::Stop1p5 => STOP::Stop1p5,
+        }
+    }
+}
+
+/// The order in which the data bits of each character are transmitted
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BitOrder {
+    /// Least significant bit first. This is the default.
+    LsbFirst,
+    /// Most significant bit first
+    MsbFirst,
+}
+
+impl From<BitOrder> for MSBFIRST {
+    fn from(value: BitOrder) -> Self {
+        match value {
+            BitOrder::LsbFirst => MSBFIRST::Lsb,
+            BitOrder::MsbFirst => MSBFIRST::Msb,
+        }
+    }
+}
+
+/// Length of the node address compared in mute mode with address mark
+/// wakeup
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressLength {
+    /// Only the 4 least significant bits of the address are compared
+    Bits4,
+    /// All of the character, excluding the address mark bit, is compared:
+    /// 7 bits when using 8 data bits.
+    Bits7,
+}
+
+/// The event that takes the receiver out of mute mode
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Wakeup {
+    /// Wake up when an idle line is detected
+    IdleLine,
+    /// Wake up when an address character matching the node address is
+    /// received. Address characters are those with the most significant data
+    /// bit (the address mark) set. Receiving a non-matching address character
+    /// automatically re-enters mute mode.
+    AddressMark {
+        /// The address of this node
+        address: u8,
+        /// Number of address bits to compare
+        length: AddressLength,
+    },
+}
+
+/// Polarity of the RS-485 driver enable signal
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DePolarity {
+    /// The driver is enabled while DE is high. This is the default.
+    ActiveHigh,
+    /// The driver is enabled while DE is low
+    ActiveLow,
+}
+
+/// Configuration of the driver enable (DE) signal used to control an RS-485
+/// transceiver.
+///
+/// The assertion and deassertion times are given in sample time units: 1/16
+/// of a bit when oversampling by 16 and 1/8 of a bit when oversampling by 8
+/// (which is only used at baud rates close to the kernel clock frequency).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DriverEnable {
+    pub(super) assertion_time: u8,
+    pub(super) deassertion_time: u8,
+    pub(super) polarity: DePolarity,
+}
+
+impl DriverEnable {
+    /// Create a default driver enable configuration: active high, with no
+    /// additional assertion or deassertion time.
+    pub fn new() -> Self {
+        DriverEnable {
+            assertion_time: 0,
+            deassertion_time: 0,
+            polarity: DePolarity::ActiveHigh,
+        }
+    }
+
+    /// Set the time between the activation of DE and the start bit of the
+    /// first character. Must be less than 32 sample time units.
+    #[must_use]
+    pub fn assertion_time(mut self, time: u8) -> Self {
+        assert!(time < 32, "DE assertion time must be less than 32");
+        self.assertion_time = time;
+        self
+    }
+
+    /// Set the time between the end of the last stop bit and the
+    /// deactivation of DE. Must be less than 32 sample time units.
+    #[must_use]
+    pub fn deassertion_time(mut self, time: u8) -> Self {
+        assert!(time < 32, "DE deassertion time must be less than 32");
+        self.deassertion_time = time;
+        self
+    }
+
+    /// Set the polarity of the DE signal
+    #[must_use]
+    pub fn polarity(mut self, polarity: DePolarity) -> Self {
+        self.polarity = polarity;
+        self
+    }
+}
+
+impl Default for DriverEnable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A structure for specifying the USART configuration.
+///
+/// This structure uses builder semantics to generate the configuration.
+///
+/// `Example`
+/// ```
+/// use stm32h5xx_hal::serial::{Config, Parity, StopBits};
+///
+/// let config = Config::new(115_200.Hz())
+///     .parity(Parity::ParityEven)
+///     .stop_bits(StopBits::Stop2);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub(super) baudrate: Hertz,
+    pub(super) word_length: WordLength,
+    pub(super) parity: Parity,
+    pub(super) stop_bits: StopBits,
+    pub(super) bit_order: BitOrder,
+    pub(super) swap_tx_rx: bool,
+    pub(super) fifo: bool,
+    pub(super) receiver_timeout: Option<u32>,
+    pub(super) mute_mode: Option<Wakeup>,
+}
+
+impl Config {
+    /// Create a default configuration for the USART interface: 8 data bits,
+    /// no parity and 1 stop bit.
+    ///
+    /// Arguments:
+    /// * `baudrate` - The baud rate to configure.
+    pub fn new(baudrate: Hertz) -> Self {
+        Config {
+            baudrate,
+            word_length: WordLength::DataBits8,
+            parity: Parity::ParityNone,
+            stop_bits: StopBits::Stop1,
+            bit_order: BitOrder::LsbFirst,
+            swap_tx_rx: false,
+            fifo: false,
+            receiver_timeout: None,
+            mute_mode: None,
+        }
+    }
+
+    /// Set the baud rate
+    #[must_use]
+    pub fn baudrate(mut self, baudrate: Hertz) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+
+    /// Set the number of data bits in each character
+    #[must_use]
+    pub fn word_length(mut self, word_length: WordLength) -> Self {
+        self.word_length = word_length;
+        self
+    }
+
+    /// Set the parity mode
+    #[must_use]
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Set the number of stop bits
+    #[must_use]
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Set the order in which data bits are transmitted
+    #[must_use]
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    /// Swap the functions of the TX and RX pins. This allows a crossed
+    /// connection to be fixed without rewiring.
+    #[must_use]
+    pub fn swap_tx_rx(mut self) -> Self {
+        self.swap_tx_rx = true;
+        self
+    }
+
+    /// Enable or disable the 8-character transmit and receive FIFOs
+    #[must_use]
+    pub fn fifo(mut self, enable: bool) -> Self {
+        self.fifo = enable;
+        self
+    }
+
+    /// Enable the receiver timeout. The receiver timeout flag is set when
+    /// the line has been idle for `bits` bit durations after the last
+    /// received character, which can be used to detect the end of a packet.
+    /// `bits` must fit in 24 bits.
+    #[must_use]
+    pub fn receiver_timeout(mut self, bits: u32) -> Self {
+        assert!(bits < (1 << 24), "Receiver timeout must fit in 24 bits");
+        self.receiver_timeout = Some(bits);
+        self
+    }
+
+    /// Enable mute mode. While muted, the receiver discards all characters
+    /// without setting any flags until the `wakeup` condition occurs. Mute
+    /// mode is entered with [`Rx::enter_mute_mode`](super::Rx::enter_mute_mode).
+    #[must_use]
+    pub fn mute_mode(mut self, wakeup: Wakeup) -> Self {
+        self.mute_mode = Some(wakeup);
+        self
+    }
+
+    /// Number of bits in a character frame as configured in the M bits: the
+    /// data bits plus the parity bit, if any.
+    pub(super) fn frame_bits(&self) -> u8 {
+        let parity_bit = match self.parity {
+            Parity::ParityNone => 0,
+            _ => 1,
+        };
+        self.word_length.bits() + parity_bit
+    }
+}
+
+impl From<Hertz> for Config {
+    fn from(baudrate: Hertz) -> Self {
+        Config::new(baudrate)
+    }
+}
+
+/// A structure for specifying the configuration of a USART in synchronous
+/// master (SPI) mode.
+///
+/// This structure uses builder semantics to generate the configuration.
+///
+/// `Example`
+/// ```
+/// use stm32h5xx_hal::serial::{BitOrder, SpiConfig};
+/// use stm32h5xx_hal::spi::MODE_3;
+///
+/// let config = SpiConfig::new(MODE_3).bit_order(BitOrder::LsbFirst);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SpiConfig {
+    pub(super) mode: Mode,
+    pub(super) bit_order: BitOrder,
+    pub(super) last_bit_clock: bool,
+}
+
+impl SpiConfig {
+    /// Create a default configuration for the given SPI mode: data is
+    /// transmitted most significant bit first and a clock pulse is output
+    /// for every data bit, as SPI devices expect.
+    pub fn new(mode: Mode) -> Self {
+        SpiConfig {
+            mode,
+            bit_order: BitOrder::MsbFirst,
+            last_bit_clock: true,
+        }
+    }
+
+    /// Set the clock polarity and phase
+    #[must_use]
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the order in which data bits are transmitted
+    #[must_use]
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    /// Set whether a clock pulse is output for the last data bit of each
+    /// character. When disabled, as in the USART's reset state, only 7 clock
+    /// pulses are output for each 8-bit character.
+    #[must_use]
+    pub fn last_bit_clock(mut self, enable: bool) -> Self {
+        self.last_bit_clock = enable;
+        self
+    }
+}
+
+impl From<Mode> for SpiConfig {
+    fn from(mode: Mode) -> Self {
+        SpiConfig::new(mode)
+    }
+}
+
+/// Minimum length of a low level on the line that is detected as a break in
+/// LIN mode
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BreakLength {
+    /// 10 bit times
+    Bits10,
+    /// 11 bit times, the threshold required of a LIN slave. This is the
+    /// default.
+    Bits11,
+}
+
+impl From<BreakLength> for LBDL {
+    fn from(value: BreakLength) -> Self {
+        match value {
+            BreakLength::Bits10 => LBDL::Bit10,
+            BreakLength::Bits11 => LBDL::Bit11,
+        }
+    }
+}
+
+/// A structure for specifying the configuration of a USART in LIN mode.
+///
+/// This structure uses builder semantics to generate the configuration.
+///
+/// `Example`
+/// ```
+/// use stm32h5xx_hal::serial::{BreakLength, LinConfig};
+///
+/// let config = LinConfig::new(19_200.Hz())
+///     .break_length(BreakLength::Bits11)
+///     .auto_baud(true);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinConfig {
+    pub(super) baudrate: Hertz,
+    pub(super) break_length: BreakLength,
+    pub(super) auto_baud: bool,
+}
+
+impl LinConfig {
+    /// Create a default LIN configuration: 11-bit break detection and no
+    /// automatic baud rate detection.
+    ///
+    /// Arguments:
+    /// * `baudrate` - The nominal baud rate of the bus.
+    pub fn new(baudrate: Hertz) -> Self {
+        LinConfig {
+            baudrate,
+            break_length: BreakLength::Bits11,
+            auto_baud: false,
+        }
+    }
+
+    /// Set the baud rate
+    #[must_use]
+    pub fn baudrate(mut self, baudrate: Hertz) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+
+    /// Set the length of a low level that is detected as a break
+    #[must_use]
+    pub fn break_length(mut self, break_length: BreakLength) -> Self {
+        self.break_length = break_length;
+        self
+    }
+
+    /// Measure the baud rate from the sync field of each frame header. This
+    /// lets a slave follow a master whose clock is not accurate, such as an
+    /// RC oscillator. The baud rate passed to [`LinConfig::new`] is used
+    /// until the first sync field is received.
+    #[must_use]
+    pub fn auto_baud(mut self, enable: bool) -> Self {
+        self.auto_baud = enable;
+        self
+    }
+}
+
+impl From<Hertz> for LinConfig {
+    fn from(baudrate: Hertz) -> Self {
+        LinConfig::new(baudrate)
+    }
+}
+
+/// The transmission protocol used to communicate with a smartcard
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Protocol {
+    /// Character oriented protocol. Parity errors are signalled with a NACK
+    /// and the character is repeated. This is the default.
+    T0,
+    /// Block oriented protocol. Errors are detected with the block checksum
+    /// instead of NACKs.
+    T1,
+}
+
+/// A structure for specifying the configuration of a USART in smartcard
+/// mode.
+///
+/// This structure uses builder semantics to generate the configuration.
+///
+/// `Example`
+/// ```
+/// use stm32h5xx_hal::serial::{Protocol, SmartcardConfig};
+///
+/// let config = SmartcardConfig::new(4.MHz())
+///     .protocol(Protocol::T0)
+///     .retries(3);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SmartcardConfig {
+    pub(super) card_clock: Hertz,
+    pub(super) clock_rate_factor: u16,
+    pub(super) baud_rate_factor: u8,
+    pub(super) guard_time: u8,
+    pub(super) protocol: Protocol,
+    pub(super) retries: u8,
+    pub(super) waiting_time: u32,
+    pub(super) character_waiting_time: u32,
+}
+
+impl SmartcardConfig {
+    /// Create a default configuration with the ISO 7816-3 default
+    /// communication parameters, which must be used to receive the ATR: an
+    /// elementary time unit (etu) of 372 card clock cycles, protocol T=0 and
+    /// an initial waiting time of 9600 etu.
+    ///
+    /// Arguments:
+    /// * `card_clock` - The maximum frequency of the clock supplied to the
+    ///   card on the CK pin. The closest frequency that does not exceed it is
+    ///   used.
+    pub fn new(card_clock: Hertz) -> Self {
+        SmartcardConfig {
+            card_clock,
+            clock_rate_factor: 372,
+            baud_rate_factor: 1,
+            guard_time: 2,
+            protocol: Protocol::T0,
+            retries: 3,
+            waiting_time: 9600,
+            character_waiting_time: 8203,
+        }
+    }
+
+    /// Set the clock rate conversion factor F and the baud rate adjustment
+    /// factor D, as negotiated with the card. One etu is F/D card clock
+    /// cycles.
+    #[must_use]
+    pub fn etu(mut self, f: u16, d: u8) -> Self {
+        assert!(d > 0, "D must not be zero");
+        self.clock_rate_factor = f;
+        self.baud_rate_factor = d;
+        self
+    }
+
+    /// Set the guard time inserted after each transmitted character, in
+    /// etu. The default of 2 gives the 12 etu character duration required
+    /// by ISO 7816-3; add the extra guard time N given in the card's TC1
+    /// byte if it is not zero.
+    #[must_use]
+    pub fn guard_time(mut self, guard_time: u8) -> Self {
+        self.guard_time = guard_time;
+        self
+    }
+
+    /// Set the transmission protocol
+    #[must_use]
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set the number of times a character is retransmitted after being
+    /// NACKed by the card (and the number of NACKs sent for a received
+    /// character) before a parity or framing error is reported. Only used by
+    /// protocol T=0; at most 7.
+    #[must_use]
+    pub fn retries(mut self, retries: u8) -> Self {
+        assert!(retries < 8, "At most 7 retries are supported");
+        self.retries = retries;
+        self
+    }
+
+    /// Set the maximum time to wait for the card to send a character, in
+    /// etu. This is the waiting time (WT) for protocol T=0, or the block
+    /// waiting time (BWT) before the start of a block for T=1.
+    #[must_use]
+    pub fn waiting_time(mut self, etu: u32) -> Self {
+        assert!(etu < (1 << 24), "Waiting time is limited to 24 bits");
+        self.waiting_time = etu;
+        self
+    }
+
+    /// Set the character waiting time (CWT) between the characters of a
+    /// block for protocol T=1, in etu
+    #[must_use]
+    pub fn character_waiting_time(mut self, etu: u32) -> Self {
+        assert!(etu < (1 << 24), "Waiting time is limited to 24 bits");
+        self.character_waiting_time = etu;
+        self
+    }
+}
@@ -0,0 +1,162 @@
+use embedded_dma::{ReadBuffer, WriteBuffer};
+
+use crate::gpdma::{
+    config::{MemoryToPeripheral, PeripheralToMemory},
+    periph::{DmaRx, DmaTx},
+    DmaChannel, DmaConfig,
+};
+
+use super::{check_rx_errors, clear_rx_flags, Error, Instance, Rx, Tx};
+
+/// Wraps a borrowed slice so that it can be used as the memory side of a DMA
+/// transfer. This is only sound because the transfer never outlives the
+/// borrow: it is either waited upon to completion or aborted (when dropped)
+/// before the operation that created it returns or is cancelled.
+pub(super) struct SliceBuffer {
+    pub(super) ptr: *const u8,
+    pub(super) len: usize,
+}
+
+unsafe impl ReadBuffer for SliceBuffer {
+    type Word = u8;
+
+    unsafe fn read_buffer(&self) -> (*const u8, usize) {
+        (self.ptr, self.len)
+    }
+}
+
+pub(super) struct SliceBufferMut {
+    pub(super) ptr: *mut u8,
+    pub(super) len: usize,
+}
+
+unsafe impl WriteBuffer for SliceBufferMut {
+    type Word = u8;
+
+    unsafe fn write_buffer(&mut self) -> (*mut u8, usize) {
+        (self.ptr, self.len)
+    }
+}
+
+/// Serial transmitter using a GPDMA channel to transfer data to the USART
+pub struct TxDma<USART, CH> {
+    pub(super) tx: Tx<USART>,
+    pub(super) dma: DmaTx<USART, u8, CH>,
+}
+
+/// Serial receiver using a GPDMA channel to transfer data from the USART
+pub struct RxDma<USART, CH> {
+    pub(super) rx: Rx<USART>,
+    pub(super) dma: DmaRx<USART, u8, CH>,
+}
+
+impl<USART: Instance> Tx<USART> {
+    /// Use a GPDMA channel for transmission
+    pub fn with_dma<CH: DmaChannel>(self, channel: CH) -> TxDma<USART, CH> {
+        TxDma {
+            tx: self,
+            dma: DmaTx::from(channel),
+        }
+    }
+}
+
+impl<USART: Instance> Rx<USART> {
+    /// Use a GPDMA channel for reception
+    pub fn with_dma<CH: DmaChannel>(self, channel: CH) -> RxDma<USART, CH> {
+        RxDma {
+            rx: self,
+            dma: DmaRx::from(channel),
+        }
+    }
+}
+
+impl<USART: Instance, CH: DmaChannel> TxDma<USART, CH> {
+    /// Transmit all of `data`, blocking until the last character has left
+    /// the transmit shift register. At most `u16::MAX` bytes can be sent at
+    /// once.
+    pub fn write_dma(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let usart = self.tx.usart();
+        usart.icr().write(|w| w.tccf().clear());
+
+        let config: DmaConfig<MemoryToPeripheral, u8, u8> =
+            DmaConfig::new().with_request(USART::TX_DMA_REQUEST);
+        let source = SliceBuffer {
+            ptr: data.as_ptr(),
+            len: data.len(),
+        };
+        let mut transfer = self.dma.init_tx_transfer(config, source);
+
+        usart.cr3().modify(|_, w| w.dmat().enabled());
+        let result = transfer
+            .start()
+            .and_then(|_| transfer.wait_for_transfer_complete());
+        drop(transfer);
+        usart.cr3().modify(|_, w| w.dmat().disabled());
+
+        result.map_err(Error::Dma)?;
+        self.tx.bflush()
+    }
+
+    /// Release the transmitter and the DMA channel
+    pub fn free(self) -> (Tx<USART>, CH) {
+        (self.tx, self.dma.free())
+    }
+}
+
+impl<USART: Instance, CH: DmaChannel> RxDma<USART, CH> {
+    /// Receive a packet into `buffer`, returning the number of bytes
+    /// received.
+    ///
+    /// Reception ends when the buffer is full, when an idle line is detected
+    /// after at least one character has been received, or when the receiver
+    /// timeout elapses (if enabled in [`Config`](super::Config)). At most
+    /// `u16::MAX` bytes can be received at once.
+    pub fn read_dma(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let usart = self.rx.usart();
+        clear_rx_flags(usart);
+
+        let len = buffer.len();
+        let config: DmaConfig<PeripheralToMemory, u8, u8> =
+            DmaConfig::new().with_request(USART::RX_DMA_REQUEST);
+        let destination = SliceBufferMut {
+            ptr: buffer.as_mut_ptr(),
+            len,
+        };
+        let mut transfer = self.dma.init_rx_transfer(config, destination);
+
+        usart.cr3().modify(|_, w| w.dmar().enabled());
+        let result = transfer.start().map_err(Error::Dma).and_then(|_| loop {
+            let isr = usart.isr().read();
+            check_rx_errors(usart, &isr)?;
+            if isr.idle().bit_is_set() || isr.rtof().bit_is_set() {
+                transfer.suspend_after_fifo_drained();
+                break Ok(());
+            }
+            if !transfer.is_running() {
+                break transfer
+                    .wait_for_transfer_complete()
+                    .map_err(Error::Dma);
+            }
+        });
+        let remaining = transfer.remaining_transfer_size_bytes();
+        drop(transfer);
+
+        usart.cr3().modify(|_, w| w.dmar().disabled());
+        usart.icr().write(|w| w.idlecf().clear().rtocf().clear());
+
+        result.map(|_| len - remaining)
+    }
+
+    /// Release the receiver and the DMA channel
+    pub fn free(self) -> (Rx<USART>, CH) {
+        (self.rx, self.dma.free())
+    }
+}
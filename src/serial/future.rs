@@ -0,0 +1,246 @@
+//! This module implements the [embedded-io-async] `Read` and `Write` traits for the DMA serial
+//! transmitter and receiver using the GPDMA futures. It is enabled with the `async` feature.
+//!
+//! In addition to the GPDMA channel interrupts, reception (which completes when an idle line is
+//! detected or the receiver timeout elapses) and flushing rely on the USART interrupt to wake
+//! the waiting task. The interrupt handlers for all USART/UART instances are defined by this
+//! module, but as with the GPDMA channel interrupts they must be unmasked in the NVIC by the
+//! user:
+//!```
+//!    use stm32h5xx_hal::pac::{NVIC, interrupt};
+//!
+//!    unsafe {
+//!        NVIC::unmask(interrupt::USART1);
+//!    };
+//! ```
+//!
+//! As the handlers are defined for every instance, whether or not it is used asynchronously, the
+//! application cannot define its own interrupt handler for any USART/UART with this module
+//! enabled: doing so fails to link with a duplicate symbol. Interrupt-driven use of
+//! [`Serial::listen`](super::Serial::listen) is therefore not possible with the `async` feature.
+//!
+//! [embedded-io-async]: https://docs.rs/embedded-io-async/0.6.1/embedded_io_async/
+use core::{
+    future::{poll_fn, Future, IntoFuture},
+    pin::Pin,
+    task::Poll,
+};
+
+use futures_util::task::AtomicWaker;
+
+use crate::gpdma::{
+    config::{MemoryToPeripheral, PeripheralToMemory},
+    DmaChannel, DmaConfig,
+};
+use crate::interrupt;
+use crate::stm32::usart1;
+#[cfg(feature = "h56x_h573")]
+use crate::stm32::{UART12, UART7, UART8, UART9, USART10, USART11};
+#[cfg(feature = "rm0481")]
+use crate::stm32::{UART4, UART5, USART6};
+use crate::stm32::{USART1, USART2, USART3};
+
+use super::dma::{SliceBuffer, SliceBufferMut};
+use super::{check_rx_errors, clear_rx_flags, Error, Instance, RxDma, TxDma};
+
+/// Wakers for the tasks waiting on the transmitter and receiver of a USART.
+/// They are kept separate so that the two halves can be awaited from
+/// different tasks.
+struct Wakers {
+    tx: AtomicWaker,
+    rx: AtomicWaker,
+}
+
+impl Wakers {
+    const fn new() -> Self {
+        Wakers {
+            tx: AtomicWaker::new(),
+            rx: AtomicWaker::new(),
+        }
+    }
+}
+
+/// Private trait that provides access to the [`AtomicWaker`]s for a USART instance
+trait InstanceWaker {
+    fn wakers() -> &'static Wakers;
+}
+
+/// Runs the contained closure when dropped, so that the USART is returned to
+/// its idle state even if a future is cancelled part way through.
+struct OnDrop<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        (self.0)()
+    }
+}
+
+fn disable_rx_interrupts(usart: &usart1::RegisterBlock) {
+    usart.cr1().modify(|_, w| {
+        w.idleie().disabled().rtoie().disabled().peie().disabled()
+    });
+    usart.cr3().modify(|_, w| w.eie().disabled());
+}
+
+fn on_interrupt<USART: Instance + InstanceWaker>() {
+    // Safety:
+    // The interrupt handler only disables the interrupt sources used by the futures below, so
+    // that the interrupt doesn't trigger again before the woken tasks are polled. The status
+    // flags are left for the tasks to inspect, and they re-enable the interrupts they require
+    // if they need to wait again.
+    let usart = unsafe { &*USART::ptr() };
+    usart.cr1().modify(|_, w| w.tcie().disabled());
+    disable_rx_interrupts(usart);
+
+    let wakers = USART::wakers();
+    wakers.tx.wake();
+    wakers.rx.wake();
+}
+
+macro_rules! usart_irq {
+    ($($USARTX:ident),+) => {
+        $(
+            impl InstanceWaker for $USARTX {
+                #[inline(always)]
+                fn wakers() -> &'static Wakers {
+                    static WAKERS: Wakers = Wakers::new();
+                    &WAKERS
+                }
+            }
+
+            #[interrupt]
+            fn $USARTX() {
+                on_interrupt::<$USARTX>();
+            }
+        )+
+    };
+}
+
+usart_irq!(USART1, USART2, USART3);
+#[cfg(feature = "rm0481")]
+usart_irq!(UART4, UART5, USART6);
+#[cfg(feature = "h56x_h573")]
+usart_irq!(UART7, UART8, UART9, USART10, USART11, UART12);
+
+impl<USART, CH> embedded_io::ErrorType for TxDma<USART, CH> {
+    type Error = Error;
+}
+
+impl<USART, CH> embedded_io::ErrorType for RxDma<USART, CH> {
+    type Error = Error;
+}
+
+#[allow(private_bounds)]
+impl<USART, CH> embedded_io_async::Write for TxDma<USART, CH>
+where
+    USART: Instance + InstanceWaker,
+    CH: DmaChannel,
+{
+    /// Transmit up to `u16::MAX` bytes from `buf`, completing once they have
+    /// all been written to the USART. Use `flush` to wait for the
+    /// transmission of the last character to finish.
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(u16::MAX as usize);
+        let usart = self.tx.usart();
+        usart.icr().write(|w| w.tccf().clear());
+
+        let config: DmaConfig<MemoryToPeripheral, u8, u8> =
+            DmaConfig::new().with_request(USART::TX_DMA_REQUEST);
+        let source = SliceBuffer {
+            ptr: buf.as_ptr(),
+            len,
+        };
+
+        let _dmat = OnDrop(|| {
+            usart.cr3().modify(|_, w| w.dmat().disabled());
+        });
+        let mut transfer =
+            self.dma.init_tx_transfer(config, source).into_future();
+
+        usart.cr3().modify(|_, w| w.dmat().enabled());
+        transfer.start().map_err(Error::Dma)?;
+        (&mut transfer).await.map_err(Error::Dma)?;
+
+        Ok(len)
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        let usart = self.tx.usart();
+        poll_fn(|cx| {
+            USART::wakers().tx.register(cx.waker());
+            if usart.isr().read().tc().bit_is_set() {
+                Poll::Ready(Ok(()))
+            } else {
+                usart.cr1().modify(|_, w| w.tcie().enabled());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[allow(private_bounds)]
+impl<USART, CH> embedded_io_async::Read for RxDma<USART, CH>
+where
+    USART: Instance + InstanceWaker,
+    CH: DmaChannel,
+{
+    /// Receive into `buf`, completing when it is full, or when an idle line
+    /// is detected (or the receiver timeout elapses, if enabled) after at
+    /// least one character has been received. At most `u16::MAX` bytes are
+    /// received at once.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let usart = self.rx.usart();
+        clear_rx_flags(usart);
+
+        let len = buf.len().min(u16::MAX as usize);
+        let config: DmaConfig<PeripheralToMemory, u8, u8> =
+            DmaConfig::new().with_request(USART::RX_DMA_REQUEST);
+        let destination = SliceBufferMut {
+            ptr: buf.as_mut_ptr(),
+            len,
+        };
+
+        let _dmar = OnDrop(|| {
+            usart.cr3().modify(|_, w| w.dmar().disabled());
+            disable_rx_interrupts(usart);
+            usart.icr().write(|w| w.idlecf().clear().rtocf().clear());
+        });
+        let mut transfer =
+            self.dma.init_rx_transfer(config, destination).into_future();
+
+        usart.cr3().modify(|_, w| w.dmar().enabled());
+        transfer.start().map_err(Error::Dma)?;
+
+        poll_fn(|cx| {
+            USART::wakers().rx.register(cx.waker());
+
+            let isr = usart.isr().read();
+            check_rx_errors(usart, &isr)?;
+            if isr.idle().bit_is_set() || isr.rtof().bit_is_set() {
+                transfer.suspend_after_fifo_drained();
+                return Poll::Ready(Ok(()));
+            }
+            if let Poll::Ready(result) = Pin::new(&mut transfer).poll(cx) {
+                return Poll::Ready(result.map_err(Error::Dma));
+            }
+
+            usart.cr1().modify(|_, w| {
+                w.idleie().enabled().rtoie().enabled().peie().enabled()
+            });
+            usart.cr3().modify(|_, w| w.eie().enabled());
+            Poll::Pending
+        })
+        .await?;
+
+        Ok(len - transfer.remaining_transfer_size_bytes())
+    }
+}
@@ -0,0 +1,202 @@
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial;
+
+use super::{Error, Instance, Rx, Serial, Tx};
+
+impl serial::Error for Error {
+    fn kind(&self) -> serial::ErrorKind {
+        match self {
+            Error::Framing => serial::ErrorKind::FrameFormat,
+            Error::Noise => serial::ErrorKind::Noise,
+            Error::Overrun => serial::ErrorKind::Overrun,
+            Error::Parity => serial::ErrorKind::Parity,
+            Error::Dma(_)
+            | Error::Timeout
+            | Error::Checksum
+            | Error::IdentifierParity
+            | Error::Sync
+            | Error::Protocol => serial::ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Error::Framing
+            | Error::Noise
+            | Error::Parity
+            | Error::Checksum
+            | Error::IdentifierParity
+            | Error::Sync
+            | Error::Protocol => embedded_io::ErrorKind::InvalidData,
+            Error::Timeout => embedded_io::ErrorKind::TimedOut,
+            Error::Overrun | Error::Dma(_) => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_hal::spi::Error for Error {
+    fn kind(&self) -> embedded_hal::spi::ErrorKind {
+        match self {
+            Error::Overrun => embedded_hal::spi::ErrorKind::Overrun,
+            _ => embedded_hal::spi::ErrorKind::Other,
+        }
+    }
+}
+
+macro_rules! error_type {
+    ($($Type:ident),+) => {
+        $(
+            impl<USART> serial::ErrorType for $Type<USART> {
+                type Error = Error;
+            }
+
+            impl<USART> embedded_io::ErrorType for $Type<USART> {
+                type Error = Error;
+            }
+        )+
+    };
+}
+
+error_type!(Serial, Tx, Rx);
+
+impl<USART: Instance> serial::Write<u8> for Tx<USART> {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.write_word(word as u16)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.is_tx_complete() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<USART: Instance> serial::Write<u16> for Tx<USART> {
+    fn write(&mut self, word: u16) -> nb::Result<(), Self::Error> {
+        self.write_word(word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.is_tx_complete() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<USART: Instance> serial::Read<u8> for Rx<USART> {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.read_word().map(|word| word as u8)
+    }
+}
+
+impl<USART: Instance> serial::Read<u16> for Rx<USART> {
+    fn read(&mut self) -> nb::Result<u16, Self::Error> {
+        self.read_word()
+    }
+}
+
+impl<USART: Instance> embedded_io::Write for Tx<USART> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        nb::block!(self.write_word(buf[0] as u16))?;
+        let mut count = 1;
+        for &byte in &buf[1..] {
+            match self.write_word(byte as u16) {
+                Ok(()) => count += 1,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(err)) => return Err(err),
+            }
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.bflush()
+    }
+}
+
+impl<USART: Instance> embedded_io::WriteReady for Tx<USART> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_tx_not_full())
+    }
+}
+
+impl<USART: Instance> embedded_io::Read for Rx<USART> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.bread(buf)
+    }
+}
+
+impl<USART: Instance> embedded_io::ReadReady for Rx<USART> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_rx_not_empty())
+    }
+}
+
+impl<USART: Instance> serial::Write<u8> for Serial<USART> {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        serial::Write::write(&mut self.tx, word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        serial::Write::<u8>::flush(&mut self.tx)
+    }
+}
+
+impl<USART: Instance> serial::Write<u16> for Serial<USART> {
+    fn write(&mut self, word: u16) -> nb::Result<(), Self::Error> {
+        self.tx.write_word(word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        serial::Write::<u8>::flush(&mut self.tx)
+    }
+}
+
+impl<USART: Instance> serial::Read<u8> for Serial<USART> {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        serial::Read::read(&mut self.rx)
+    }
+}
+
+impl<USART: Instance> serial::Read<u16> for Serial<USART> {
+    fn read(&mut self) -> nb::Result<u16, Self::Error> {
+        self.rx.read_word()
+    }
+}
+
+impl<USART: Instance> embedded_io::Write for Serial<USART> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(&mut self.tx, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.tx.bflush()
+    }
+}
+
+impl<USART: Instance> embedded_io::WriteReady for Serial<USART> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_tx_not_full())
+    }
+}
+
+impl<USART: Instance> embedded_io::Read for Serial<USART> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.rx.bread(buf)
+    }
+}
+
+impl<USART: Instance> embedded_io::ReadReady for Serial<USART> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_rx_not_empty())
+    }
+}
@@ -0,0 +1,106 @@
+//! Single-wire half-duplex mode.
+//!
+//! In half-duplex mode the TX pin is used to both transmit and receive, so
+//! that several devices can share one open-drain line with a pull-up, as on
+//! Dynamixel-style servo buses. The receiver is disabled while transmitting,
+//! so that the transmitted data is not received back.
+//!
+//! Timeouts are measured with the DWT cycle counter, which must have been
+//! enabled, for example by creating a [`MonoTimer`](crate::dwt::MonoTimer).
+use cortex_m::peripheral::DWT;
+use embedded_hal_nb::nb;
+
+use crate::rcc::CoreClocks;
+use crate::time::{Hertz, MicroSeconds};
+
+use super::{flush_rx, Config, Error, Instance, Serial};
+
+/// A USART in single-wire half-duplex mode
+#[derive(Debug)]
+pub struct HalfDuplex<USART> {
+    serial: Serial<USART>,
+    hclk: Hertz,
+}
+
+impl<USART: Instance> HalfDuplex<USART> {
+    pub(super) fn new(
+        usart: USART,
+        config: impl Into<Config>,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let mut serial = Serial::new(usart, config, rec, clocks);
+        serial.reconfigure(|usart| {
+            usart.cr3().modify(|_, w| w.hdsel().selected());
+        });
+
+        HalfDuplex {
+            serial,
+            hclk: clocks.hclk(),
+        }
+    }
+
+    /// Transmit `bytes`, blocking until transmission is complete. The
+    /// receiver is disabled until the last character has been sent.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.serial.write_without_echo(bytes)
+    }
+
+    /// Receive `bytes.len()` bytes, failing with [`Error::Timeout`] if they
+    /// have not all been received within `timeout`
+    pub fn read(
+        &mut self,
+        bytes: &mut [u8],
+        timeout: MicroSeconds,
+    ) -> Result<(), Error> {
+        assert!(
+            DWT::cycle_counter_enabled(),
+            "The DWT cycle counter must be enabled for timeouts"
+        );
+        let ticks = self.hclk.raw() as u64 * timeout.ticks() as u64 / 1_000_000;
+        let ticks = ticks.min(u32::MAX as u64 / 2) as u32;
+        let start = DWT::cycle_count();
+
+        for byte in bytes {
+            *byte = loop {
+                match self.serial.rx.read_word() {
+                    Ok(word) => break word as u8,
+                    Err(nb::Error::Other(err)) => return Err(err),
+                    Err(nb::Error::WouldBlock) => {}
+                }
+                if DWT::cycle_count().wrapping_sub(start) > ticks {
+                    return Err(Error::Timeout);
+                }
+            };
+        }
+        Ok(())
+    }
+
+    /// Send a request and receive the response over the shared line. Any
+    /// data received before the request is discarded, then `write` is
+    /// transmitted and `read` filled with the response, which must be
+    /// complete within `timeout` of the end of the request.
+    pub fn transaction(
+        &mut self,
+        write: &[u8],
+        read: &mut [u8],
+        timeout: MicroSeconds,
+    ) -> Result<(), Error> {
+        flush_rx(&self.serial.usart);
+        self.write(write)?;
+        self.read(read, timeout)
+    }
+
+    /// Disable the peripheral and return it
+    pub fn free(self) -> USART {
+        self.serial.free()
+    }
+
+    pub fn inner(&self) -> &USART {
+        self.serial.inner()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut USART {
+        self.serial.inner_mut()
+    }
+}
@@ -0,0 +1,408 @@
+//! LIN (Local Interconnect Network) mode.
+//!
+//! A LIN frame consists of a header sent by the master, made up of a break,
+//! the sync field (0x55) and the protected identifier (PID), followed by a
+//! response of 1 to 8 data bytes and a checksum. The response is sent either
+//! by the master itself or by one of the slaves, depending on the frame.
+//!
+//! The USART generates and detects breaks in hardware, and can measure the
+//! baud rate from the sync field. The PID parity bits and the checksums are
+//! computed in software.
+use embedded_hal_nb::nb;
+
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::time::Hertz;
+
+use super::{
+    calc_baud_rate, check_rx_errors, flush_rx, Error, Instance, LinConfig,
+};
+
+/// Value of the sync field
+const SYNC: u8 = 0x55;
+
+/// The checksum model of a frame
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Checksum {
+    /// The checksum covers the data bytes only. Used by LIN 1.x nodes and
+    /// for the diagnostic frames (identifiers 0x3C and 0x3D).
+    Classic,
+    /// The checksum covers the protected identifier and the data bytes.
+    /// Used by LIN 2.x nodes.
+    Enhanced,
+}
+
+/// Calculate the protected identifier of frame identifier `id`, adding the
+/// two parity bits to the 6-bit identifier
+pub fn protected_id(id: u8) -> u8 {
+    assert!(id < 64, "LIN frame identifiers are 6 bits");
+    let bit = |n: u8| (id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    id | (p0 << 6) | (p1 << 7)
+}
+
+/// Calculate the checksum of a frame with protected identifier `pid`. The
+/// data is summed with end-around carry and the result inverted.
+pub fn checksum(model: Checksum, pid: u8, data: &[u8]) -> u8 {
+    let init = match model {
+        Checksum::Classic => 0,
+        Checksum::Enhanced => pid as u16,
+    };
+    let sum = data.iter().fold(init, |sum, &byte| {
+        let sum = sum + byte as u16;
+        if sum > 0xFF {
+            sum - 0xFF
+        } else {
+            sum
+        }
+    });
+    !(sum as u8)
+}
+
+/// Check the parity bits of a received protected identifier, returning the
+/// frame identifier
+fn frame_id(pid: u8) -> Result<u8, Error> {
+    let id = pid & 0x3F;
+    if protected_id(id) == pid {
+        Ok(id)
+    } else {
+        Err(Error::IdentifierParity)
+    }
+}
+
+/// A USART in LIN mode
+#[derive(Debug)]
+pub struct Lin<USART> {
+    usart: USART,
+}
+
+impl<USART: Instance> Lin<USART> {
+    pub(super) fn new(
+        usart: USART,
+        config: impl Into<LinConfig>,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let config: LinConfig = config.into();
+        let _ = rec.enable().reset();
+
+        let lin = Lin { usart };
+        lin.init(config, USART::clock(clocks))
+    }
+
+    fn init(self, config: LinConfig, clock: Hertz) -> Self {
+        let baud = calc_baud_rate(clock.raw(), config.baudrate.raw())
+            .expect("Baud rate cannot be generated from the kernel clock");
+
+        self.usart.cr1().reset();
+        self.usart.cr2().reset();
+        self.usart.cr3().reset();
+
+        self.usart
+            .presc()
+            .write(|w| w.prescaler().variant(baud.presc));
+        self.usart
+            .brr()
+            .write(|w| unsafe { w.brr().bits(baud.brr) });
+
+        // LIN mode requires 1 stop bit and the clock, smartcard, half duplex
+        // and IrDA modes to be disabled, which is the reset state. The
+        // receiver timeout is used for the response timeout.
+        self.usart.cr2().write(|w| {
+            w.linen()
+                .enabled()
+                .lbdl()
+                .variant(config.break_length.into())
+                .abren()
+                .bit(config.auto_baud)
+                .abrmod()
+                .frame55()
+                .rtoen()
+                .enabled()
+        });
+
+        // 8 data bits, no parity
+        self.usart.cr1().write(|w| {
+            w.over8()
+                .bit(baud.over8)
+                .te()
+                .enabled()
+                .re()
+                .enabled()
+                .ue()
+                .enabled()
+        });
+
+        self
+    }
+
+    /// Transmit a break. It is sent once the character currently being
+    /// transmitted is complete.
+    pub fn send_break(&mut self) {
+        self.usart.rqr().write(|w| w.sbkrq().set_bit());
+    }
+
+    /// Returns true if a break has been detected on the bus
+    pub fn is_break_detected(&self) -> bool {
+        self.usart.isr().read().lbdf().bit_is_set()
+    }
+
+    /// Clear the break detection flag
+    pub fn clear_break_detected(&mut self) {
+        self.usart.icr().write(|w| w.lbdcf().clear());
+    }
+
+    /// Start listening for the break detection interrupt
+    pub fn listen_break(&mut self) {
+        self.usart.cr2().modify(|_, w| w.lbdie().enabled());
+    }
+
+    /// Stop listening for the break detection interrupt
+    pub fn unlisten_break(&mut self) {
+        self.usart.cr2().modify(|_, w| w.lbdie().disabled());
+        interrupt_clear_clock_sync_delay!(self.usart.cr2());
+    }
+
+    /// Send a frame header for frame identifier `id`. This is used by the
+    /// master to start each frame of its schedule table.
+    pub fn send_header(&mut self, id: u8) -> Result<(), Error> {
+        let pid = protected_id(id);
+
+        self.send_break();
+        self.write_byte(SYNC);
+        self.write_byte(pid);
+        self.wait_tx_complete();
+
+        // Discard the header, which was received as it was sent. The
+        // response timeout counts from the end of the PID.
+        self.flush_rx();
+        Ok(())
+    }
+
+    /// Send the header for frame identifier `id` followed by `data` as the
+    /// response. This is used by the master to publish a frame.
+    pub fn write_frame(
+        &mut self,
+        id: u8,
+        data: &[u8],
+        model: Checksum,
+    ) -> Result<(), Error> {
+        self.send_header(id)?;
+        self.write_response(id, data, model)
+    }
+
+    /// Send the header for frame identifier `id` and receive the response
+    /// from a slave into `data`. This is used by the master to request a
+    /// frame, and fails with [`Error::Timeout`] if the slave does not
+    /// respond in time.
+    pub fn read_frame(
+        &mut self,
+        id: u8,
+        data: &mut [u8],
+        model: Checksum,
+    ) -> Result<(), Error> {
+        self.send_header(id)?;
+        self.read_response(id, data, model)
+    }
+
+    /// Block until a frame header is received, returning its frame
+    /// identifier. This is used by a slave, which must then either send or
+    /// receive the response depending on the identifier.
+    ///
+    /// If automatic baud rate detection is enabled, the baud rate is
+    /// measured from the sync field.
+    pub fn read_header(&mut self) -> Result<u8, Error> {
+        let auto_baud = self.usart.cr2().read().abren().bit_is_set();
+
+        // Anything received before the break is not part of the frame
+        while !self.is_break_detected() {
+            if self.usart.isr().read().rxfne().bit_is_set() {
+                let _ = self.usart.rdr().read();
+            }
+        }
+        self.flush_rx();
+        if auto_baud {
+            self.usart.rqr().write(|w| w.abrrq().set_bit());
+        }
+
+        let sync = nb::block!(self.read_byte())?;
+        if auto_baud {
+            let isr = self.usart.isr().read();
+            if isr.abre().bit_is_set() || isr.abrf().bit_is_clear() {
+                return Err(Error::Sync);
+            }
+        } else if sync != SYNC {
+            return Err(Error::Sync);
+        }
+
+        let pid = nb::block!(self.read_byte())?;
+        // The response timeout counts from the end of the PID
+        self.usart.icr().write(|w| w.rtocf().clear());
+        frame_id(pid)
+    }
+
+    /// Send `data` followed by its checksum as the response to the header
+    /// for frame identifier `id`
+    pub fn write_response(
+        &mut self,
+        id: u8,
+        data: &[u8],
+        model: Checksum,
+    ) -> Result<(), Error> {
+        assert!(
+            (1..=8).contains(&data.len()),
+            "LIN responses carry 1 to 8 data bytes"
+        );
+        let checksum = checksum(model, protected_id(id), data);
+
+        for &byte in data {
+            self.write_byte(byte);
+        }
+        self.write_byte(checksum);
+        self.wait_tx_complete();
+
+        // Discard the response, which was received as it was sent
+        self.flush_rx();
+        Ok(())
+    }
+
+    /// Receive the response to the header for frame identifier `id` into
+    /// `data` and verify its checksum.
+    ///
+    /// The whole response must be received within the maximum response
+    /// time of 1.4 times its nominal length, or this fails with
+    /// [`Error::Timeout`]. This is measured with the receiver timeout from
+    /// the end of the header, including any spaces between the response
+    /// bytes.
+    pub fn read_response(
+        &mut self,
+        id: u8,
+        data: &mut [u8],
+        model: Checksum,
+    ) -> Result<(), Error> {
+        assert!(
+            (1..=8).contains(&data.len()),
+            "LIN responses carry 1 to 8 data bytes"
+        );
+        // 10 bits for each data byte and the checksum, plus 40%
+        let window = 14 * (data.len() as u32 + 1);
+        let mut elapsed = 0;
+
+        for byte in data.iter_mut() {
+            *byte = self.read_byte_within(window, &mut elapsed)?;
+        }
+        let received = self.read_byte_within(window, &mut elapsed)?;
+
+        if received == checksum(model, protected_id(id), data) {
+            Ok(())
+        } else {
+            Err(Error::Checksum)
+        }
+    }
+
+    /// Disable the peripheral and return it
+    pub fn free(self) -> USART {
+        self.usart.cr1().reset();
+        self.usart
+    }
+
+    pub fn inner(&self) -> &USART {
+        &self.usart
+    }
+
+    pub fn inner_mut(&mut self) -> &mut USART {
+        &mut self.usart
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        while self.usart.isr().read().txfnf().bit_is_clear() {}
+        self.usart
+            .tdr()
+            .write(|w| unsafe { w.tdr().bits(byte as u16) });
+    }
+
+    fn wait_tx_complete(&self) {
+        while self.usart.isr().read().tc().bit_is_clear() {}
+    }
+
+    fn read_byte(&mut self) -> nb::Result<u8, Error> {
+        let isr = self.usart.isr().read();
+        check_rx_errors(&self.usart, &isr)?;
+        if isr.rxfne().bit_is_set() {
+            Ok(self.usart.rdr().read().rdr().bits() as u8)
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Read a byte of a response, failing once `elapsed` reaches `window`.
+    /// `elapsed` counts the bit times since the end of the header, and is
+    /// updated with the idle time before the character and its length.
+    fn read_byte_within(
+        &mut self,
+        window: u32,
+        elapsed: &mut u32,
+    ) -> Result<u8, Error> {
+        // The receiver timeout counts from the end of the last character
+        // received. It is moved one bit time further each time it elapses,
+        // to count the idle bit times before this character.
+        let mut idle = 0;
+        self.usart.rtor().write(|w| unsafe { w.rto().bits(1) });
+        loop {
+            match self.read_byte() {
+                Err(nb::Error::WouldBlock) => {}
+                result => {
+                    *elapsed += idle + 10;
+                    return result.map_err(nb_error);
+                }
+            }
+            if self.usart.isr().read().rtof().bit_is_set() {
+                self.usart.icr().write(|w| w.rtocf().clear());
+                idle += 1;
+                if *elapsed + idle + 10 > window {
+                    return Err(Error::Timeout);
+                }
+                self.usart
+                    .rtor()
+                    .write(|w| unsafe { w.rto().bits(idle + 1) });
+            }
+        }
+    }
+
+    /// Discard any received data and clear the reception status flags
+    fn flush_rx(&mut self) {
+        flush_rx(&self.usart);
+    }
+}
+
+fn nb_error(error: nb::Error<Error>) -> Error {
+    match error {
+        nb::Error::Other(error) => error,
+        nb::Error::WouldBlock => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_id() {
+        assert_eq!(protected_id(0x00), 0x80);
+        assert_eq!(protected_id(0x01), 0xC1);
+        assert_eq!(protected_id(0x10), 0x50);
+        assert_eq!(protected_id(0x3C), 0x3C);
+        assert_eq!(protected_id(0x3D), 0x7D);
+        assert_eq!(frame_id(0x7D), Ok(0x3D));
+        assert_eq!(frame_id(0x3D), Err(Error::IdentifierParity));
+    }
+
+    #[test]
+    fn test_checksum() {
+        // Example from the LIN 2.2A specification, section 2.8.3
+        let data = [0x4A, 0x55, 0x93, 0xE5];
+        assert_eq!(checksum(Checksum::Classic, 0x80, &data), 0xE6);
+        assert_eq!(checksum(Checksum::Enhanced, 0x4A, &data[1..]), 0xE6);
+    }
+}
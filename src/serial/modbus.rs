@@ -0,0 +1,516 @@
+//! Modbus RTU framing.
+//!
+//! Modbus RTU frames (ADUs) consist of the device address, the function
+//! code, up to 252 data bytes and a CRC-16. Frames are delimited by silent
+//! intervals on the line: a frame ends when the line has been idle for 3.5
+//! character times, and a frame in which two characters are separated by
+//! more than 1.5 character times is incomplete and must be discarded. Above
+//! 19200 baud, the gaps are fixed at 1.75 ms and 0.75 ms respectively.
+//!
+//! The end of each frame is detected with the USART receiver timeout. The
+//! gap between characters, and the response timeout of the master, are
+//! measured with the DWT cycle counter, which must have been enabled, for
+//! example by creating a [`MonoTimer`](crate::dwt::MonoTimer).
+//!
+//! The receiver is disabled while transmitting, so that the transmitted
+//! frame is not received back when the RS-485 transceiver echoes it.
+use cortex_m::peripheral::DWT;
+
+use crate::rcc::CoreClocks;
+use crate::time::MicroSeconds;
+
+use super::{
+    check_rx_errors, flush_rx, Config, DriverEnable, Error, Instance, Parity,
+    PinRts, Serial, StopBits,
+};
+
+/// The address used to send a request to all devices. Broadcast requests
+/// are never answered.
+pub const BROADCAST: u8 = 0;
+
+/// The maximum length of an ADU, including the address and CRC
+pub const MAX_ADU_LEN: usize = 256;
+
+/// The maximum number of data bytes in an ADU, excluding the function code
+pub const MAX_DATA_LEN: usize = MAX_ADU_LEN - 4;
+
+/// Set in the function code of a response to indicate an exception
+const EXCEPTION_FLAG: u8 = 0x80;
+
+/// Calculate the CRC-16 of a frame. The CRC is appended to the frame with
+/// the least significant byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u16, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Calculate the 1.5 and 3.5 character gaps in bit times, for characters of
+/// `char_bits` bits including the start and stop bits
+fn gap_bits(baudrate: u32, char_bits: u32) -> (u32, u32) {
+    if baudrate > 19_200 {
+        let bits = |us: u64| (us * baudrate as u64).div_ceil(1_000_000) as u32;
+        (bits(750), bits(1750))
+    } else {
+        ((3 * char_bits).div_ceil(2), (7 * char_bits).div_ceil(2))
+    }
+}
+
+/// An exception code, sent by a device in response to a request that it
+/// cannot process
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExceptionCode {
+    /// The function code is not supported
+    IllegalFunction,
+    /// The data address is not valid for this device
+    IllegalDataAddress,
+    /// A value in the request is not valid
+    IllegalDataValue,
+    /// An unrecoverable error occurred while processing the request
+    ServerDeviceFailure,
+    /// The request was accepted, but will take a long time to process
+    Acknowledge,
+    /// The device is busy processing a long-duration request
+    ServerDeviceBusy,
+    /// A parity error was detected in extended memory
+    MemoryParityError,
+    /// A gateway could not allocate a path to the target device
+    GatewayPathUnavailable,
+    /// The target device behind a gateway did not respond
+    GatewayTargetFailedToRespond,
+    /// Any other exception code
+    Other(u8),
+}
+
+impl From<u8> for ExceptionCode {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => ExceptionCode::IllegalFunction,
+            0x02 => ExceptionCode::IllegalDataAddress,
+            0x03 => ExceptionCode::IllegalDataValue,
+            0x04 => ExceptionCode::ServerDeviceFailure,
+            0x05 => ExceptionCode::Acknowledge,
+            0x06 => ExceptionCode::ServerDeviceBusy,
+            0x08 => ExceptionCode::MemoryParityError,
+            0x0A => ExceptionCode::GatewayPathUnavailable,
+            0x0B => ExceptionCode::GatewayTargetFailedToRespond,
+            code => ExceptionCode::Other(code),
+        }
+    }
+}
+
+impl From<ExceptionCode> for u8 {
+    fn from(code: ExceptionCode) -> Self {
+        match code {
+            ExceptionCode::IllegalFunction => 0x01,
+            ExceptionCode::IllegalDataAddress => 0x02,
+            ExceptionCode::IllegalDataValue => 0x03,
+            ExceptionCode::ServerDeviceFailure => 0x04,
+            ExceptionCode::Acknowledge => 0x05,
+            ExceptionCode::ServerDeviceBusy => 0x06,
+            ExceptionCode::MemoryParityError => 0x08,
+            ExceptionCode::GatewayPathUnavailable => 0x0A,
+            ExceptionCode::GatewayTargetFailedToRespond => 0x0B,
+            ExceptionCode::Other(code) => code,
+        }
+    }
+}
+
+/// A complete Modbus RTU frame, including its CRC
+#[derive(Clone)]
+pub struct Adu {
+    bytes: [u8; MAX_ADU_LEN],
+    len: usize,
+}
+
+impl Adu {
+    /// Build a frame for device `address` from a function code and data,
+    /// appending the CRC
+    pub fn new(address: u8, function: u8, data: &[u8]) -> Self {
+        assert!(
+            data.len() <= MAX_DATA_LEN,
+            "Modbus frames carry at most 252 data bytes"
+        );
+        let mut bytes = [0; MAX_ADU_LEN];
+        let len = data.len() + 4;
+        bytes[0] = address;
+        bytes[1] = function;
+        bytes[2..len - 2].copy_from_slice(data);
+        let crc = crc16(&bytes[..len - 2]);
+        bytes[len - 2..len].copy_from_slice(&crc.to_le_bytes());
+
+        Adu { bytes, len }
+    }
+
+    /// Build an exception response from device `address` to a request with
+    /// function code `function`
+    pub fn exception_response(
+        address: u8,
+        function: u8,
+        code: ExceptionCode,
+    ) -> Self {
+        Adu::new(address, function | EXCEPTION_FLAG, &[code.into()])
+    }
+
+    /// Parse a received frame, checking its length and CRC
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if !(4..=MAX_ADU_LEN).contains(&bytes.len()) {
+            return Err(Error::Protocol);
+        }
+        // The CRC of a frame including its own CRC is zero
+        if crc16(bytes) != 0 {
+            return Err(Error::Checksum);
+        }
+
+        let mut adu = Adu {
+            bytes: [0; MAX_ADU_LEN],
+            len: bytes.len(),
+        };
+        adu.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(adu)
+    }
+
+    /// The address of the device that the frame was sent to or by
+    pub fn address(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Returns true if the frame is a request to all devices
+    pub fn is_broadcast(&self) -> bool {
+        self.address() == BROADCAST
+    }
+
+    /// The function code, without the exception flag
+    pub fn function(&self) -> u8 {
+        self.bytes[1] & !EXCEPTION_FLAG
+    }
+
+    /// The data following the function code, excluding the CRC
+    pub fn data(&self) -> &[u8] {
+        &self.bytes[2..self.len - 2]
+    }
+
+    /// The exception code, if the frame is an exception response
+    pub fn exception(&self) -> Option<ExceptionCode> {
+        if self.bytes[1] & EXCEPTION_FLAG != 0 {
+            self.data().first().map(|&code| code.into())
+        } else {
+            None
+        }
+    }
+
+    /// The whole frame, as transmitted on the line
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl core::fmt::Debug for Adu {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Adu")
+            .field("bytes", &self.as_bytes())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Adu {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Adu {{ bytes: {=[u8]:02x} }}", self.as_bytes())
+    }
+}
+
+/// A USART used as a Modbus RTU master or slave
+#[derive(Debug)]
+pub struct Modbus<USART> {
+    serial: Serial<USART>,
+    /// Cycles between the ends of two consecutive characters of a frame,
+    /// above which the 1.5 character gap was exceeded
+    char_gap: u32,
+    /// Cycles per microsecond
+    cycles_per_us: u32,
+}
+
+impl<USART: Instance> Modbus<USART> {
+    pub(super) fn new(
+        usart: USART,
+        config: impl Into<Config>,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        assert!(
+            DWT::cycle_counter_enabled(),
+            "The DWT cycle counter must be enabled for Modbus timing"
+        );
+        let config: Config = config.into();
+        let baudrate = config.baudrate.raw();
+
+        let parity_bits = match config.parity {
+            Parity::ParityNone => 0,
+            _ => 1,
+        };
+        let stop_bits = match config.stop_bits {
+            StopBits::Stop2 | StopBits::Stop1p5 => 2,
+            _ => 1,
+        };
+        let char_bits =
+            1 + config.word_length.bits() as u32 + parity_bits + stop_bits;
+        let (t15, t35) = gap_bits(baudrate, char_bits);
+
+        let hclk = clocks.hclk().raw() as u64;
+        let char_gap = hclk * (char_bits + t15) as u64 / baudrate as u64;
+
+        // The receiver timeout marks the end of each frame
+        let serial =
+            Serial::new(usart, config.receiver_timeout(t35), rec, clocks);
+
+        Modbus {
+            serial,
+            char_gap: char_gap as u32,
+            cycles_per_us: (hclk / 1_000_000) as u32,
+        }
+    }
+
+    /// Drive the driver enable input of an RS-485 transceiver from the RTS
+    /// pin. See [`Serial::with_driver_enable`].
+    pub fn with_driver_enable<DE: PinRts<USART>>(
+        mut self,
+        de: DE,
+        config: DriverEnable,
+    ) -> Self {
+        self.serial = self.serial.with_driver_enable(de, config);
+        self
+    }
+
+    /// Send a request to device `address` and receive its response. This
+    /// is used by a master to poll a slave.
+    ///
+    /// The response must start within `timeout` of the end of the request,
+    /// or this fails with [`Error::Timeout`]. The timeout is limited to half
+    /// the range of the DWT cycle counter, about 8.5 s at 250 MHz. A
+    /// response from the wrong device or for the wrong function fails with
+    /// [`Error::Protocol`].
+    /// Exception responses are returned like any other response, see
+    /// [`Adu::exception`].
+    pub fn request(
+        &mut self,
+        address: u8,
+        function: u8,
+        data: &[u8],
+        timeout: MicroSeconds,
+    ) -> Result<Adu, Error> {
+        assert!(
+            (1..=247).contains(&address),
+            "Modbus device addresses are 1 to 247"
+        );
+
+        flush_rx(&self.serial.usart);
+        self.write(&Adu::new(address, function, data))?;
+
+        // The elapsed cycles are compared with wrapping arithmetic, so the
+        // timeout must be less than half the range of the cycle counter
+        let timeout = self
+            .cycles_per_us
+            .saturating_mul(timeout.ticks())
+            .min(u32::MAX / 2);
+        let response = self.receive(Some(timeout))?;
+        if response.address() != address || response.function() != function {
+            return Err(Error::Protocol);
+        }
+        Ok(response)
+    }
+
+    /// Send a request to all devices. There is no response, and the master
+    /// should wait for the devices to process the request before sending
+    /// the next one.
+    pub fn broadcast(
+        &mut self,
+        function: u8,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.write(&Adu::new(BROADCAST, function, data))
+    }
+
+    /// Block until a valid request for device `address`, or a broadcast
+    /// request, is received. This is used by a slave. Frames for other
+    /// devices are ignored, while frames that were received with errors or
+    /// an incorrect CRC fail, so that they can be counted.
+    pub fn read_request(&mut self, address: u8) -> Result<Adu, Error> {
+        loop {
+            let request = self.receive(None)?;
+            if request.address() == address || request.is_broadcast() {
+                return Ok(request);
+            }
+        }
+    }
+
+    /// Send a response with `data` to `request`. Nothing is sent in
+    /// response to a broadcast request.
+    pub fn respond(&mut self, request: &Adu, data: &[u8]) -> Result<(), Error> {
+        if request.is_broadcast() {
+            return Ok(());
+        }
+        self.write(&Adu::new(request.address(), request.function(), data))
+    }
+
+    /// Send an exception response to `request`. Nothing is sent in response
+    /// to a broadcast request.
+    pub fn respond_exception(
+        &mut self,
+        request: &Adu,
+        code: ExceptionCode,
+    ) -> Result<(), Error> {
+        if request.is_broadcast() {
+            return Ok(());
+        }
+        self.write(&Adu::exception_response(
+            request.address(),
+            request.function(),
+            code,
+        ))
+    }
+
+    /// Receive a request for device `address` and pass it to `handler`,
+    /// which writes the response data into the buffer it is given and
+    /// returns its length, or returns an exception code. The response is
+    /// then sent, unless the request was broadcast.
+    pub fn serve<F>(&mut self, address: u8, handler: F) -> Result<(), Error>
+    where
+        F: FnOnce(&Adu, &mut [u8]) -> Result<usize, ExceptionCode>,
+    {
+        let request = self.read_request(address)?;
+
+        let mut data = [0; MAX_DATA_LEN];
+        match handler(&request, &mut data) {
+            Ok(len) => self.respond(&request, &data[..len]),
+            Err(code) => self.respond_exception(&request, code),
+        }
+    }
+
+    /// Disable the peripheral and return it
+    pub fn free(self) -> USART {
+        self.serial.free()
+    }
+
+    pub fn inner(&self) -> &USART {
+        self.serial.inner()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut USART {
+        self.serial.inner_mut()
+    }
+
+    /// Transmit a frame, blocking until transmission is complete
+    fn write(&mut self, adu: &Adu) -> Result<(), Error> {
+        self.serial.write_without_echo(adu.as_bytes())
+    }
+
+    /// Receive a frame, which ends when the receiver timeout elapses. If
+    /// `timeout` is given, fail if no frame starts within that many cycles.
+    ///
+    /// After an error, the rest of the frame is still received so that
+    /// reception restarts at the beginning of the next frame.
+    fn receive(&mut self, timeout: Option<u32>) -> Result<Adu, Error> {
+        let usart = &self.serial.usart;
+        let mut bytes = [0; MAX_ADU_LEN];
+        let mut len = 0;
+        let mut error = None;
+        let mut last = None;
+
+        usart.icr().write(|w| w.rtocf().clear());
+        let start = DWT::cycle_count();
+
+        loop {
+            let isr = usart.isr().read();
+            if let Err(e) = check_rx_errors(usart, &isr) {
+                error.get_or_insert(e);
+            }
+
+            if isr.rxfne().bit_is_set() {
+                let byte = usart.rdr().read().rdr().bits() as u8;
+                let now = DWT::cycle_count();
+
+                if matches!(last, Some(t) if now.wrapping_sub(t) > self.char_gap)
+                {
+                    // More than 1.5 characters of silence within the frame
+                    error.get_or_insert(Error::Protocol);
+                }
+                last = Some(now);
+
+                if len < MAX_ADU_LEN {
+                    bytes[len] = byte;
+                    len += 1;
+                } else {
+                    error.get_or_insert(Error::Protocol);
+                }
+            } else if isr.rtof().bit_is_set() {
+                usart.icr().write(|w| w.rtocf().clear());
+                if last.is_some() {
+                    break;
+                }
+            } else if let (None, Some(timeout)) = (last, timeout) {
+                if DWT::cycle_count().wrapping_sub(start) > timeout {
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+
+        match error {
+            Some(error) => Err(error),
+            None => Adu::from_bytes(&bytes[..len]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        // Read holding registers 0 to 9 from device 1
+        let adu = Adu::new(0x01, 0x03, &[0x00, 0x00, 0x00, 0x0A]);
+        assert_eq!(
+            adu.as_bytes(),
+            &[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD]
+        );
+        assert!(Adu::from_bytes(adu.as_bytes()).is_ok());
+        assert_eq!(
+            Adu::from_bytes(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCC])
+                .err(),
+            Some(Error::Checksum)
+        );
+        assert_eq!(
+            Adu::from_bytes(&[0x01, 0x03, 0x00]).err(),
+            Some(Error::Protocol)
+        );
+    }
+
+    #[test]
+    fn test_exception_response() {
+        let adu = Adu::exception_response(
+            0x0A,
+            0x01,
+            ExceptionCode::IllegalDataAddress,
+        );
+        assert_eq!(adu.as_bytes(), &[0x0A, 0x81, 0x02, 0xB0, 0x53]);
+        assert_eq!(adu.function(), 0x01);
+        assert_eq!(adu.exception(), Some(ExceptionCode::IllegalDataAddress));
+        assert_eq!(Adu::new(0x0A, 0x01, &[0x02]).exception(), None);
+    }
+
+    #[test]
+    fn test_gap_bits() {
+        // 8 data bits, even parity, 1 stop bit
+        assert_eq!(gap_bits(9600, 11), (17, 39));
+        assert_eq!(gap_bits(19_200, 11), (17, 39));
+        assert_eq!(gap_bits(115_200, 11), (87, 202));
+    }
+}
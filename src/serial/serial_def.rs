@@ -0,0 +1,608 @@
+use core::marker::PhantomData;
+
+use super::{
+    Instance, NoRx, NoTx, PinCk, PinCts, PinRts, PinRx, PinTx,
+    SynchronousInstance,
+};
+use crate::gpdma::periph::{RxAddr, TxAddr};
+use crate::gpio::{self, Alternate};
+use crate::rcc::{rec, CoreClocks};
+use crate::stm32::{self, rcc::ccipr1::USARTSEL, usart1};
+use crate::stm32::{LPUART, USART1, USART2, USART3};
+use crate::time::Hertz;
+
+macro_rules! pins {
+    ($($USARTX:ty:
+       TX: [$($( #[ $pmeta1:meta ] )* gpio::$TX:ident<Alternate<$TXAF:literal>>),*]
+       RX: [$($( #[ $pmeta2:meta ] )* $RX:ty),*]
+       RTS: [$($( #[ $pmeta3:meta ] )* $RTS:ty),*]
+       CTS: [$($( #[ $pmeta4:meta ] )* $CTS:ty),*]
+       CK: [$($( #[ $pmeta5:meta ] )* $CK:ty),*]
+    )+) => {
+        $(
+            impl PinTx<$USARTX> for NoTx {}
+            // The TX pin may be open drain, as required in smartcard and
+            // single-wire half-duplex modes
+            $(
+                $( #[ $pmeta1 ] )*
+                impl<OTYPE> PinTx<$USARTX> for gpio::$TX<Alternate<$TXAF, OTYPE>> {}
+            )*
+            $(
+                $( #[ $pmeta2 ] )*
+                impl PinRx<$USARTX> for $RX {}
+            )*
+            $(
+                $( #[ $pmeta3 ] )*
+                impl PinRts<$USARTX> for $RTS {}
+            )*
+            $(
+                $( #[ $pmeta4 ] )*
+                impl PinCts<$USARTX> for $CTS {}
+            )*
+            $(
+                $( #[ $pmeta5 ] )*
+                impl PinCk<$USARTX> for $CK {}
+            )*
+        )+
+    }
+}
+
+// Implemented by all USART/UART instances
+macro_rules! instance {
+    ($USARTX:ident: $Usart:ident, $ccipr:ident, $pclk:ident, $tx_req:literal, $rx_req:literal) => { paste::item! {
+        impl Instance for $USARTX {
+            type Rec = rec::$Usart;
+
+            const TX_DMA_REQUEST: u8 = $tx_req;
+            const RX_DMA_REQUEST: u8 = $rx_req;
+
+            fn ptr() -> *const usart1::RegisterBlock {
+                <$USARTX>::ptr() as *const _
+            }
+
+            fn clock(clocks: &CoreClocks) -> Hertz {
+                let ccipr = unsafe { (*stm32::RCC::ptr()).$ccipr().read() };
+                let ck_sel = ccipr.[<$USARTX:lower sel>]().variant().expect("No source clock selected");
+                match ck_sel {
+                    USARTSEL::Pclk => Some(clocks.$pclk()),
+                    USARTSEL::Pll2Q => clocks.pll2().q_ck(),
+                    #[cfg(feature = "rm0481")]
+                    USARTSEL::Pll3Q => clocks.pll3().q_ck(),
+                    USARTSEL::HsiKer => clocks.hsi_ck(),
+                    USARTSEL::CsiKer => clocks.csi_ck(),
+                    USARTSEL::Lse => clocks.lse_ck(),
+                }.expect("Source clock not enabled")
+            }
+
+            fn rec() -> Self::Rec {
+                rec::$Usart { _marker: PhantomData }
+            }
+        }
+
+        impl crate::Sealed for $USARTX {}
+
+        impl TxAddr<u8> for $USARTX {
+            unsafe fn tx_addr() -> *mut u8 {
+                (*<$USARTX>::ptr()).tdr().as_ptr() as *mut u8
+            }
+        }
+
+        impl RxAddr<u8> for $USARTX {
+            unsafe fn rx_addr() -> *const u8 {
+                (*<$USARTX>::ptr()).rdr().as_ptr() as *const u8
+            }
+        }
+    }};
+}
+
+// Implemented by the USART instances, which have a clock output
+macro_rules! synchronous {
+    ($($USARTX:ident),+) => {
+        $(
+            impl SynchronousInstance for $USARTX {}
+        )+
+    };
+}
+
+instance! { USART1: Usart1, ccipr1, pclk2, 22, 21 }
+instance! { USART2: Usart2, ccipr1, pclk1, 24, 23 }
+instance! { USART3: Usart3, ccipr1, pclk1, 26, 25 }
+
+synchronous! { USART1, USART2, USART3 }
+
+#[cfg(feature = "rm0492")]
+mod rm492 {
+    use super::*;
+
+    pins! {
+        USART1:
+            TX: [
+                gpio::PA2<Alternate<8>>,
+                gpio::PA9<Alternate<7>>,
+                gpio::PA12<Alternate<8>>,
+                gpio::PA14<Alternate<7>>,
+                gpio::PB6<Alternate<7>>,
+                gpio::PB14<Alternate<4>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA1<Alternate<8>>,
+                gpio::PA10<Alternate<7>>,
+                gpio::PA11<Alternate<8>>,
+                gpio::PA13<Alternate<7>>,
+                gpio::PB7<Alternate<7>>,
+                gpio::PB15<Alternate<4>>
+            ]
+            RTS: [
+                gpio::PA4<Alternate<8>>,
+                gpio::PA7<Alternate<8>>,
+                gpio::PA12<Alternate<7>>,
+                gpio::PC2<Alternate<7>>
+            ]
+            CTS: [
+                gpio::PA0<Alternate<8>>,
+                gpio::PA11<Alternate<7>>,
+                gpio::PA15<Alternate<8>>,
+                gpio::PC1<Alternate<7>>
+            ]
+            CK: [
+                gpio::PA3<Alternate<8>>,
+                gpio::PA8<Alternate<7>>,
+                gpio::PB8<Alternate<7>>,
+                gpio::PB12<Alternate<8>>
+            ]
+        USART2:
+            TX: [
+                gpio::PA2<Alternate<7>>,
+                gpio::PA5<Alternate<9>>,
+                gpio::PA8<Alternate<4>>,
+                gpio::PA12<Alternate<4>>,
+                gpio::PA14<Alternate<9>>,
+                gpio::PB0<Alternate<9>>,
+                gpio::PB4<Alternate<13>>,
+                gpio::PC6<Alternate<13>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA3<Alternate<7>>,
+                gpio::PA11<Alternate<4>>,
+                gpio::PA13<Alternate<9>>,
+                gpio::PA15<Alternate<9>>,
+                gpio::PB1<Alternate<9>>,
+                gpio::PB5<Alternate<13>>,
+                gpio::PB15<Alternate<13>>,
+                gpio::PC7<Alternate<13>>
+            ]
+            RTS: [
+                gpio::PA1<Alternate<7>>,
+                gpio::PA15<Alternate<11>>,
+                gpio::PB15<Alternate<11>>,
+                gpio::PC10<Alternate<13>>,
+                gpio::PD2<Alternate<9>>
+            ]
+            CTS: [
+                gpio::PA0<Alternate<7>>,
+                gpio::PA5<Alternate<11>>,
+                gpio::PB6<Alternate<11>>,
+                gpio::PC3<Alternate<7>>,
+                gpio::PC9<Alternate<13>>
+            ]
+            CK: [
+                gpio::PA1<Alternate<9>>,
+                gpio::PA4<Alternate<7>>,
+                gpio::PA15<Alternate<4>>,
+                gpio::PB2<Alternate<9>>,
+                gpio::PB6<Alternate<13>>,
+                gpio::PC8<Alternate<13>>
+            ]
+        USART3:
+            TX: [
+                gpio::PA4<Alternate<13>>,
+                gpio::PA8<Alternate<13>>,
+                gpio::PB3<Alternate<13>>,
+                gpio::PB7<Alternate<13>>,
+                gpio::PB10<Alternate<7>>,
+                gpio::PC10<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA3<Alternate<13>>,
+                gpio::PA5<Alternate<13>>,
+                gpio::PA12<Alternate<13>>,
+                gpio::PA15<Alternate<13>>,
+                gpio::PB8<Alternate<13>>,
+                gpio::PC4<Alternate<7>>,
+                gpio::PC11<Alternate<7>>
+            ]
+            RTS: [
+                gpio::PA7<Alternate<9>>,
+                gpio::PA11<Alternate<13>>,
+                gpio::PB14<Alternate<7>>,
+                gpio::PC9<Alternate<7>>
+            ]
+            CTS: [
+                gpio::PA0<Alternate<9>>,
+                gpio::PB13<Alternate<7>>,
+                gpio::PB15<Alternate<7>>,
+                gpio::PD2<Alternate<7>>
+            ]
+            CK: [
+                gpio::PA0<Alternate<13>>,
+                gpio::PA7<Alternate<13>>,
+                gpio::PA9<Alternate<13>>,
+                gpio::PB10<Alternate<13>>,
+                gpio::PB12<Alternate<7>>,
+                gpio::PC12<Alternate<7>>
+            ]
+        LPUART:
+            TX: [
+                gpio::PA3<Alternate<3>>,
+                gpio::PA9<Alternate<3>>,
+                gpio::PB6<Alternate<8>>,
+                gpio::PC3<Alternate<3>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA2<Alternate<3>>,
+                gpio::PA10<Alternate<3>>,
+                gpio::PB7<Alternate<8>>,
+                gpio::PB15<Alternate<8>>
+            ]
+            RTS: [
+                gpio::PA5<Alternate<8>>,
+                gpio::PA12<Alternate<3>>,
+                gpio::PA14<Alternate<8>>,
+                gpio::PB14<Alternate<8>>,
+                gpio::PC2<Alternate<8>>
+            ]
+            CTS: [
+                gpio::PA8<Alternate<8>>,
+                gpio::PA11<Alternate<3>>,
+                gpio::PA13<Alternate<8>>,
+                gpio::PB13<Alternate<8>>,
+                gpio::PC1<Alternate<8>>
+            ]
+            CK: []
+    }
+}
+
+// Note: pin data is taken from stm32h56x, stm32h573, stm32h523 and stm32h533 datasheets
+#[cfg(feature = "rm0481")]
+mod rm0481_common {
+    use super::*;
+    use crate::stm32::{UART4, UART5, USART6};
+
+    instance! { UART4: Uart4, ccipr1, pclk1, 28, 27 }
+    instance! { UART5: Uart5, ccipr1, pclk1, 30, 29 }
+    instance! { USART6: Usart6, ccipr1, pclk1, 32, 31 }
+
+    synchronous! { USART6 }
+
+    pins! {
+        USART1:
+            TX: [
+                gpio::PA9<Alternate<7>>,
+                #[cfg(feature = "h523_h533")]
+                gpio::PA15<Alternate<7>>,
+                gpio::PB6<Alternate<7>>,
+                gpio::PB14<Alternate<4>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA10<Alternate<7>>,
+                gpio::PB7<Alternate<7>>,
+                gpio::PB15<Alternate<4>>
+            ]
+            RTS: [
+                gpio::PA12<Alternate<7>>
+            ]
+            CTS: [
+                gpio::PA11<Alternate<7>>
+            ]
+            CK: [
+                gpio::PA8<Alternate<7>>
+            ]
+        USART2:
+            TX: [
+                gpio::PA2<Alternate<7>>,
+                #[cfg(feature = "h523_h533")]
+                gpio::PB0<Alternate<7>>,
+                gpio::PD5<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA3<Alternate<7>>,
+                gpio::PD6<Alternate<7>>
+            ]
+            RTS: [
+                gpio::PA1<Alternate<7>>,
+                gpio::PD4<Alternate<7>>
+            ]
+            CTS: [
+                gpio::PA0<Alternate<7>>,
+                gpio::PD3<Alternate<7>>
+            ]
+            CK: [
+                gpio::PA4<Alternate<7>>,
+                gpio::PD7<Alternate<7>>
+            ]
+        USART3:
+            TX: [
+                gpio::PB10<Alternate<7>>,
+                gpio::PC10<Alternate<7>>,
+                gpio::PD8<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                #[cfg(feature = "h523_h533")]
+                gpio::PB1<Alternate<7>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PB11<Alternate<7>>,
+                gpio::PC4<Alternate<7>>,
+                gpio::PC11<Alternate<7>>,
+                gpio::PD9<Alternate<7>>
+            ]
+            RTS: [
+                gpio::PB14<Alternate<7>>,
+                gpio::PD12<Alternate<7>>
+            ]
+            CTS: [
+                gpio::PB13<Alternate<7>>,
+                gpio::PD11<Alternate<7>>
+            ]
+            CK: [
+                gpio::PB12<Alternate<7>>,
+                gpio::PC12<Alternate<7>>,
+                gpio::PD10<Alternate<7>>
+            ]
+        UART4:
+            TX: [
+                gpio::PA0<Alternate<8>>,
+                gpio::PA12<Alternate<6>>,
+                gpio::PB9<Alternate<8>>,
+                gpio::PC10<Alternate<8>>,
+                gpio::PD1<Alternate<8>>,
+                gpio::PD12<Alternate<8>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH13<Alternate<8>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA1<Alternate<8>>,
+                gpio::PA11<Alternate<6>>,
+                gpio::PB8<Alternate<8>>,
+                gpio::PC11<Alternate<8>>,
+                gpio::PD0<Alternate<8>>,
+                gpio::PD11<Alternate<8>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH14<Alternate<8>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PI9<Alternate<8>>
+            ]
+            RTS: [
+                gpio::PA15<Alternate<8>>,
+                gpio::PB14<Alternate<8>>
+            ]
+            CTS: [
+                gpio::PB0<Alternate<8>>,
+                gpio::PB15<Alternate<8>>
+            ]
+            CK: []
+        UART5:
+            TX: [
+                #[cfg(feature = "h523_h533")]
+                gpio::PB3<Alternate<14>>,
+                gpio::PB6<Alternate<14>>,
+                gpio::PB13<Alternate<14>>,
+                gpio::PC12<Alternate<8>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PB5<Alternate<14>>,
+                gpio::PB12<Alternate<14>>,
+                gpio::PB15<Alternate<14>>,
+                gpio::PD2<Alternate<8>>
+            ]
+            RTS: [
+                gpio::PC8<Alternate<8>>
+            ]
+            CTS: [
+                gpio::PC9<Alternate<8>>
+            ]
+            CK: []
+        USART6:
+            TX: [
+                #[cfg(feature = "h523_h533")]
+                gpio::PB5<Alternate<6>>,
+                gpio::PC6<Alternate<7>>,
+                gpio::PG14<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                #[cfg(feature = "h523_h533")]
+                gpio::PB6<Alternate<6>>,
+                gpio::PC7<Alternate<7>>,
+                gpio::PG9<Alternate<7>>
+            ]
+            RTS: [
+                gpio::PG8<Alternate<7>>,
+                gpio::PG12<Alternate<7>>
+            ]
+            CTS: [
+                #[cfg(feature = "h523_h533")]
+                gpio::PB7<Alternate<6>>,
+                gpio::PG13<Alternate<7>>,
+                gpio::PG15<Alternate<7>>
+            ]
+            CK: [
+                #[cfg(feature = "h523_h533")]
+                gpio::PA1<Alternate<14>>,
+                gpio::PC8<Alternate<7>>,
+                gpio::PG7<Alternate<7>>
+            ]
+        LPUART:
+            TX: [
+                #[cfg(feature = "h523_h533")]
+                gpio::PA2<Alternate<3>>,
+                gpio::PA9<Alternate<3>>,
+                #[cfg(feature = "h523_h533")]
+                gpio::PB3<Alternate<8>>,
+                gpio::PB6<Alternate<8>>,
+                #[cfg(feature = "h523_h533")]
+                gpio::PC3<Alternate<3>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA10<Alternate<3>>,
+                gpio::PB7<Alternate<8>>,
+                #[cfg(feature = "h523_h533")]
+                gpio::PB13<Alternate<8>>
+            ]
+            RTS: [
+                gpio::PA12<Alternate<3>>
+            ]
+            CTS: [
+                gpio::PA11<Alternate<3>>
+            ]
+            CK: []
+    }
+}
+
+// Note: pin data is taken from stm32h56x and stm32h573 datasheets
+#[cfg(feature = "h56x_h573")]
+mod h56x_h573 {
+    use super::*;
+    use crate::stm32::{UART12, UART7, UART8, UART9, USART10, USART11};
+
+    instance! { UART7: Uart7, ccipr1, pclk1, 34, 33 }
+    instance! { UART8: Uart8, ccipr1, pclk1, 36, 35 }
+    instance! { UART9: Uart9, ccipr1, pclk1, 38, 37 }
+    instance! { USART10: Usart10, ccipr1, pclk1, 40, 39 }
+    instance! { USART11: Usart11, ccipr2, pclk1, 42, 41 }
+    instance! { UART12: Uart12, ccipr2, pclk1, 44, 43 }
+
+    synchronous! { USART10, USART11 }
+
+    pins! {
+        UART7:
+            TX: [
+                gpio::PA15<Alternate<11>>,
+                gpio::PB4<Alternate<11>>,
+                gpio::PE8<Alternate<7>>,
+                gpio::PF7<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA8<Alternate<11>>,
+                gpio::PB3<Alternate<11>>,
+                gpio::PE7<Alternate<7>>,
+                gpio::PF6<Alternate<7>>
+            ]
+            RTS: [
+                gpio::PE9<Alternate<7>>,
+                gpio::PF8<Alternate<7>>
+            ]
+            CTS: [
+                gpio::PE10<Alternate<7>>,
+                gpio::PF9<Alternate<7>>
+            ]
+            CK: []
+        UART8:
+            TX: [
+                gpio::PE1<Alternate<8>>,
+                gpio::PE2<Alternate<8>>,
+                gpio::PH13<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PE0<Alternate<8>>
+            ]
+            RTS: [
+                gpio::PD15<Alternate<8>>
+            ]
+            CTS: [
+                gpio::PD14<Alternate<8>>
+            ]
+            CK: []
+        UART9:
+            TX: [
+                gpio::PD15<Alternate<11>>,
+                gpio::PG1<Alternate<11>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PD14<Alternate<11>>,
+                gpio::PG0<Alternate<11>>
+            ]
+            RTS: [
+                gpio::PD13<Alternate<11>>
+            ]
+            CTS: [
+                gpio::PD0<Alternate<11>>
+            ]
+            CK: []
+        USART10:
+            TX: [
+                gpio::PE3<Alternate<7>>,
+                gpio::PG12<Alternate<6>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PE2<Alternate<7>>,
+                gpio::PG11<Alternate<6>>
+            ]
+            RTS: [
+                gpio::PG14<Alternate<6>>
+            ]
+            CTS: [
+                gpio::PG13<Alternate<6>>
+            ]
+            CK: [
+                gpio::PE15<Alternate<7>>,
+                gpio::PG15<Alternate<6>>
+            ]
+        USART11:
+            TX: [
+                gpio::PA6<Alternate<7>>,
+                gpio::PF3<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PA7<Alternate<7>>,
+                gpio::PF4<Alternate<7>>
+            ]
+            RTS: [
+                gpio::PC1<Alternate<7>>,
+                gpio::PG11<Alternate<7>>
+            ]
+            CTS: [
+                gpio::PB15<Alternate<7>>,
+                gpio::PF5<Alternate<7>>
+            ]
+            CK: [
+                gpio::PB0<Alternate<7>>,
+                gpio::PF2<Alternate<7>>
+            ]
+        UART12:
+            TX: [
+                gpio::PE10<Alternate<6>>,
+                gpio::PF2<Alternate<6>>,
+                gpio::PG3<Alternate<7>>
+            ]
+            RX: [
+                NoRx,
+                gpio::PE9<Alternate<6>>,
+                gpio::PF5<Alternate<6>>,
+                gpio::PG2<Alternate<7>>
+            ]
+            RTS: [
+                gpio::PC5<Alternate<7>>,
+                gpio::PE7<Alternate<6>>
+            ]
+            CTS: [
+                gpio::PB3<Alternate<7>>,
+                gpio::PE8<Alternate<6>>
+            ]
+            CK: []
+    }
+}
@@ -0,0 +1,656 @@
+//! ISO 7816-3 smartcard mode.
+//!
+//! In smartcard mode the USART communicates with the card over a single
+//! bidirectional I/O line on the TX pin, which must be configured as open
+//! drain, and supplies the card clock on the CK pin. The card's reset and
+//! power supply are driven by GPIOs.
+//!
+//! With protocol T=0, the USART sends a NACK when a character is received
+//! with a parity error and automatically retransmits characters that are
+//! NACKed by the card.
+use embedded_hal_nb::nb;
+
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::time::Hertz;
+
+use super::{
+    check_rx_errors, flush_rx, Error, Instance, Protocol, SmartcardConfig,
+    SynchronousInstance, PRESCALERS,
+};
+use crate::stm32::usart1::presc::PRESCALER;
+
+/// Maximum length of an answer to reset
+pub const ATR_MAX_LEN: usize = 33;
+
+/// Initial character of an ATR with the direct convention
+const TS_DIRECT: u8 = 0x3B;
+
+/// Initial character of an ATR with the inverse convention
+const TS_INVERSE: u8 = 0x3F;
+
+/// The inverse convention initial character, as received using the direct
+/// convention
+const TS_INVERSE_AS_DIRECT: u8 = 0x03;
+
+/// Procedure byte sent by a T=0 card to request more time
+const NULL: u8 = 0x60;
+
+/// An answer to reset received from a card
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Atr {
+    bytes: [u8; ATR_MAX_LEN],
+    len: usize,
+    historical: usize,
+    tck: bool,
+}
+
+impl Atr {
+    /// The complete ATR, starting with TS
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// The historical bytes
+    pub fn historical_bytes(&self) -> &[u8] {
+        let end = self.len - self.tck as usize;
+        &self.bytes[end - self.historical..end]
+    }
+
+    /// Returns true if the card uses the inverse convention
+    pub fn is_inverse_convention(&self) -> bool {
+        self.bytes[0] == TS_INVERSE
+    }
+}
+
+/// The structure of an ATR, as given by its format and interface bytes
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct AtrLayout {
+    /// Total length including TCK
+    len: usize,
+    /// Number of historical bytes
+    historical: usize,
+    /// Whether the check byte TCK is present
+    tck: bool,
+}
+
+/// Determine the structure of an ATR from the bytes received so far, or
+/// `None` if more bytes are needed to do so
+fn atr_layout(atr: &[u8]) -> Option<AtrLayout> {
+    let t0 = *atr.get(1)?;
+    let historical = (t0 & 0x0F) as usize;
+    let mut indicator = t0 >> 4;
+    let mut i = 2;
+    let mut tck = false;
+
+    loop {
+        let count = indicator.count_ones() as usize;
+        i += count;
+        if indicator & 0x8 == 0 {
+            break;
+        }
+        // TDi is the last of the interface bytes in this group. It gives the
+        // protocol and the bytes present in the next group.
+        let td = *atr.get(i - 1)?;
+        if td & 0x0F != 0 {
+            tck = true;
+        }
+        indicator = td >> 4;
+    }
+
+    Some(AtrLayout {
+        len: i + historical + tck as usize,
+        historical,
+        tck,
+    })
+}
+
+/// Header of a T=1 block
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Block {
+    /// Node address byte
+    pub nad: u8,
+    /// Protocol control byte, identifying the block type
+    pub pcb: u8,
+    /// Length of the information field
+    pub len: usize,
+}
+
+/// Longitudinal redundancy check of a T=1 block: the XOR of all its bytes
+fn lrc(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |lrc, byte| lrc ^ byte)
+}
+
+/// Smartcard clock settings
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct CardClock {
+    /// Kernel clock prescaler
+    presc: PRESCALER,
+    /// Divider of the prescaled kernel clock to the card clock is 2 * psc
+    psc: u8,
+    /// Baud rate divider for an etu of F/D card clock cycles
+    brr: u16,
+}
+
+/// Calculate the prescalers to generate a card clock of at most `card_clk`
+/// from `usart_ker_ck`, and the baud rate divider for an etu of `f`/`d`
+/// card clock cycles
+fn calc_card_clock(
+    usart_ker_ck: u32,
+    card_clk: u32,
+    f: u16,
+    d: u8,
+) -> Option<CardClock> {
+    if card_clk == 0 {
+        return None;
+    }
+    for (presc, div) in PRESCALERS {
+        let clk = usart_ker_ck / div;
+        let psc = clk.div_ceil(2 * card_clk).max(1);
+        if psc > 31 {
+            continue;
+        }
+        let brr = (2 * psc * f as u32 + d as u32 / 2) / d as u32;
+        if !(16..=0xFFFF).contains(&brr) {
+            return None;
+        }
+        return Some(CardClock {
+            presc,
+            psc: psc as u8,
+            brr: brr as u16,
+        });
+    }
+    None
+}
+
+/// A USART in smartcard mode
+#[derive(Debug)]
+pub struct Smartcard<USART> {
+    usart: USART,
+    clock: CardClock,
+    card_clock: Hertz,
+    protocol: Protocol,
+    waiting_time: u32,
+    character_waiting_time: u32,
+}
+
+impl<USART: Instance> Smartcard<USART> {
+    pub(super) fn new(
+        usart: USART,
+        config: impl Into<SmartcardConfig>,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Self
+    where
+        USART: SynchronousInstance,
+    {
+        let config: SmartcardConfig = config.into();
+        let _ = rec.enable().reset();
+
+        let ker_ck = USART::clock(clocks);
+        let clock = calc_card_clock(
+            ker_ck.raw(),
+            config.card_clock.raw(),
+            config.clock_rate_factor,
+            config.baud_rate_factor,
+        )
+        .expect("Card clock cannot be generated from the kernel clock");
+        let div = PRESCALERS
+            .iter()
+            .find(|(presc, _)| *presc == clock.presc)
+            .map(|(_, div)| div)
+            .unwrap();
+        let card_clock =
+            Hertz::from_raw(ker_ck.raw() / div / (2 * clock.psc as u32));
+
+        let smartcard = Smartcard {
+            usart,
+            clock,
+            card_clock,
+            protocol: config.protocol,
+            waiting_time: config.waiting_time,
+            character_waiting_time: config.character_waiting_time,
+        };
+        smartcard.init(config)
+    }
+
+    fn init(self, config: SmartcardConfig) -> Self {
+        self.usart.cr1().reset();
+        self.usart.cr2().reset();
+        self.usart.cr3().reset();
+
+        self.usart
+            .presc()
+            .write(|w| w.prescaler().variant(self.clock.presc));
+        self.usart
+            .brr()
+            .write(|w| unsafe { w.brr().bits(self.clock.brr) });
+        self.usart.gtpr().write(|w| unsafe {
+            w.psc().bits(self.clock.psc).gt().bits(config.guard_time)
+        });
+
+        // The smartcard standard requires 1.5 stop bits
+        self.usart
+            .cr2()
+            .write(|w| w.clken().enabled().stop().stop1p5().rtoen().enabled());
+
+        // NACKs and retransmission are only used by T=0
+        let t0 = self.protocol == Protocol::T0;
+        let retries = if t0 { config.retries } else { 0 };
+        self.usart.cr3().write(|w| unsafe {
+            w.scen().enabled().nack().bit(t0).scarcnt().bits(retries)
+        });
+
+        // 8 data bits with even parity
+        self.usart.cr1().write(|w| {
+            w.m0()
+                .bit9()
+                .pce()
+                .enabled()
+                .te()
+                .enabled()
+                .re()
+                .enabled()
+                .ue()
+                .enabled()
+        });
+
+        self
+    }
+
+    /// The frequency of the clock supplied to the card
+    pub fn card_clock(&self) -> Hertz {
+        self.card_clock
+    }
+
+    /// Change the etu to `f`/`d` card clock cycles, for example after a
+    /// successful protocol and parameters selection (PPS) exchange
+    pub fn set_etu(&mut self, f: u16, d: u8) {
+        assert!(d > 0, "D must not be zero");
+        let brr =
+            (2 * self.clock.psc as u32 * f as u32 + d as u32 / 2) / d as u32;
+        assert!(
+            (16..=0xFFFF).contains(&brr),
+            "etu cannot be generated from the card clock"
+        );
+        self.clock.brr = brr as u16;
+
+        // BRR can only be written while the USART is disabled
+        self.usart.cr1().modify(|_, w| w.ue().disabled());
+        self.usart
+            .brr()
+            .write(|w| unsafe { w.brr().bits(self.clock.brr) });
+        self.usart.cr1().modify(|_, w| w.ue().enabled());
+    }
+
+    /// Receive the answer to reset (ATR) from the card. This should be
+    /// called as soon as the card's reset line is released.
+    ///
+    /// Card clock is output as soon as the USART is configured, so the
+    /// reset line must be held low for at least 400 card clock cycles
+    /// first. Cards using the inverse convention are detected from the
+    /// initial character TS, which is received without parity checking,
+    /// and the USART is switched to it for the rest of the ATR.
+    pub fn read_atr(&mut self) -> Result<Atr, Error> {
+        self.flush_rx();
+
+        let mut atr = Atr {
+            bytes: [0; ATR_MAX_LEN],
+            len: 0,
+            historical: 0,
+            tck: false,
+        };
+
+        // The parity bit of TS in the inverse convention is wrong in the
+        // direct convention, so TS is received without parity checking or
+        // NACKs, with the parity bit as a ninth data bit
+        self.reconfigure(|usart| {
+            usart.cr1().modify(|_, w| w.pce().disabled());
+            usart.cr3().modify(|_, w| w.nack().disabled());
+        });
+        let ts = self.read_byte(self.waiting_time);
+        let inverse = matches!(ts, Ok(TS_INVERSE_AS_DIRECT));
+        let nack = self.protocol == Protocol::T0;
+        self.reconfigure(|usart| {
+            usart.cr1().modify(|_, w| w.pce().enabled());
+            usart.cr3().modify(|_, w| w.nack().bit(nack));
+            if inverse {
+                usart
+                    .cr2()
+                    .modify(|_, w| w.datainv().negative().msbfirst().msb());
+            }
+        });
+
+        atr.bytes[0] = match ts? {
+            TS_DIRECT => TS_DIRECT,
+            TS_INVERSE_AS_DIRECT => TS_INVERSE,
+            _ => return Err(Error::Protocol),
+        };
+        atr.len = 1;
+
+        let layout = loop {
+            if let Some(layout) = atr_layout(&atr.bytes[..atr.len]) {
+                break layout;
+            }
+            atr.bytes[atr.len] = self.read_byte(self.waiting_time)?;
+            atr.len += 1;
+        };
+        if layout.len > ATR_MAX_LEN {
+            return Err(Error::Protocol);
+        }
+        while atr.len < layout.len {
+            atr.bytes[atr.len] = self.read_byte(self.waiting_time)?;
+            atr.len += 1;
+        }
+        atr.historical = layout.historical;
+        atr.tck = layout.tck;
+
+        // The XOR of all bytes from T0 to TCK is zero
+        if layout.tck && lrc(&atr.bytes[1..atr.len]) != 0 {
+            return Err(Error::Checksum);
+        }
+        Ok(atr)
+    }
+
+    /// Send a T=0 command. `header` contains the class, instruction, P1 and
+    /// P2 bytes, followed by P3: the length of either `data` or the
+    /// expected response. `data` is sent to the card if it is not empty;
+    /// otherwise up to P3 bytes (256 if P3 is 0) of response data are
+    /// received into `response`.
+    ///
+    /// Returns the number of response bytes stored in `response` and the
+    /// status word SW1-SW2.
+    pub fn command_t0(
+        &mut self,
+        header: [u8; 5],
+        data: &[u8],
+        response: &mut [u8],
+    ) -> Result<(usize, u16), Error> {
+        let ins = header[1];
+        let p3 = header[4] as usize;
+        let (to_send, expected) = if data.is_empty() {
+            (0, if p3 == 0 { 256 } else { p3 })
+        } else {
+            assert_eq!(data.len(), p3, "P3 must be the length of the data");
+            (p3, 0)
+        };
+        let mut sent = 0;
+        let mut received = 0;
+
+        self.write(&header)?;
+
+        loop {
+            let procedure = self.read_byte(self.waiting_time)?;
+            match procedure {
+                NULL => {}
+                // ACK: transfer all remaining data
+                _ if procedure == ins => {
+                    if sent < to_send {
+                        self.write(&data[sent..])?;
+                        sent = to_send;
+                    }
+                    while received < expected {
+                        self.receive_into(response, received)?;
+                        received += 1;
+                    }
+                }
+                // ACK: transfer the next byte
+                _ if procedure == !ins => {
+                    if sent < to_send {
+                        self.write(&data[sent..=sent])?;
+                        sent += 1;
+                    } else if received < expected {
+                        self.receive_into(response, received)?;
+                        received += 1;
+                    } else {
+                        return Err(Error::Protocol);
+                    }
+                }
+                _ if matches!(procedure & 0xF0, 0x60 | 0x90) => {
+                    let sw2 = self.read_byte(self.waiting_time)?;
+                    let len = received.min(response.len());
+                    return Ok((len, u16::from_be_bytes([procedure, sw2])));
+                }
+                _ => return Err(Error::Protocol),
+            }
+        }
+    }
+
+    /// Send a T=1 block with the given node address, protocol control byte
+    /// and information field
+    pub fn write_block(
+        &mut self,
+        nad: u8,
+        pcb: u8,
+        inf: &[u8],
+    ) -> Result<(), Error> {
+        assert!(
+            inf.len() < 255,
+            "The information field is at most 254 bytes"
+        );
+        let prologue = [nad, pcb, inf.len() as u8];
+        let edc = lrc(&prologue) ^ lrc(inf);
+
+        self.write_with(|smartcard| {
+            smartcard.write_bytes(&prologue);
+            smartcard.write_bytes(inf);
+            smartcard.write_bytes(&[edc]);
+        })
+    }
+
+    /// Receive a T=1 block, storing its information field in `inf`. The
+    /// first character must be received within the block waiting time, and
+    /// each following character within the character waiting time.
+    pub fn read_block(&mut self, inf: &mut [u8]) -> Result<Block, Error> {
+        let nad = self.read_byte(self.waiting_time)?;
+        let pcb = self.read_byte(self.character_waiting_time)?;
+        let len = self.read_byte(self.character_waiting_time)?;
+        let mut edc = nad ^ pcb ^ len;
+
+        for n in 0..len as usize {
+            let byte = self.read_byte(self.character_waiting_time)?;
+            if let Some(b) = inf.get_mut(n) {
+                *b = byte;
+            }
+            edc ^= byte;
+        }
+        edc ^= self.read_byte(self.character_waiting_time)?;
+
+        if edc != 0 {
+            Err(Error::Checksum)
+        } else if len as usize > inf.len() {
+            Err(Error::Protocol)
+        } else {
+            Ok(Block {
+                nad,
+                pcb,
+                len: len as usize,
+            })
+        }
+    }
+
+    /// Send `bytes` to the card. With protocol T=0, a character NACKed by
+    /// the card is retransmitted up to the configured number of times
+    /// before this fails with [`Error::Framing`].
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.write_with(|smartcard| smartcard.write_bytes(bytes))
+    }
+
+    /// Receive `bytes.len()` bytes from the card, each within the waiting
+    /// time of the previous character
+    pub fn read(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        for byte in bytes {
+            *byte = self.read_byte(self.waiting_time)?;
+        }
+        Ok(())
+    }
+
+    /// Disable the peripheral and return it. This also stops the card
+    /// clock.
+    pub fn free(self) -> USART {
+        self.usart.cr1().reset();
+        self.usart
+    }
+
+    pub fn inner(&self) -> &USART {
+        &self.usart
+    }
+
+    pub fn inner_mut(&mut self) -> &mut USART {
+        &mut self.usart
+    }
+
+    /// Change the configuration in `f`, with the USART disabled as the
+    /// frame format and smartcard settings can only be written then
+    fn reconfigure(&mut self, f: impl FnOnce(&USART)) {
+        self.usart.cr1().modify(|_, w| w.ue().disabled());
+        f(&self.usart);
+        self.usart.cr1().modify(|_, w| w.ue().enabled());
+    }
+
+    /// Transmit with the receiver enabled, as it detects the NACK sent by
+    /// the card for a character received with a parity error. The
+    /// transmitted characters are also received from the shared I/O line,
+    /// and are dropped.
+    fn write_with(&mut self, f: impl FnOnce(&mut Self)) -> Result<(), Error> {
+        f(self);
+        while self.usart.isr().read().tc().bit_is_clear() {
+            self.drop_echo();
+        }
+
+        // A character that is still NACKed after the last retransmission
+        // sets the framing error flag
+        let nacked = self.usart.isr().read().fe().bit_is_set();
+        // This also restarts the waiting time from the end of the last
+        // character sent
+        self.flush_rx();
+        if nacked {
+            Err(Error::Framing)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            while self.usart.isr().read().txfnf().bit_is_clear() {
+                self.drop_echo();
+            }
+            self.usart
+                .tdr()
+                .write(|w| unsafe { w.tdr().bits(byte as u16) });
+        }
+    }
+
+    /// Drop a transmitted character received from the I/O line, so that
+    /// the receive FIFO doesn't overrun during a long transmission
+    fn drop_echo(&mut self) {
+        if self.usart.isr().read().rxfne().bit_is_set() {
+            let _ = self.usart.rdr().read();
+        }
+    }
+
+    /// Read a byte, failing if it isn't received within `etu` of the last
+    /// character
+    fn read_byte(&mut self, etu: u32) -> Result<u8, Error> {
+        self.usart.rtor().write(|w| unsafe { w.rto().bits(etu) });
+        nb::block!({
+            let isr = self.usart.isr().read();
+            check_rx_errors(&self.usart, &isr)?;
+            if isr.rxfne().bit_is_set() {
+                Ok(self.usart.rdr().read().rdr().bits() as u8)
+            } else if isr.rtof().bit_is_set() {
+                self.usart.icr().write(|w| w.rtocf().clear());
+                Err(nb::Error::Other(Error::Timeout))
+            } else {
+                Err(nb::Error::WouldBlock)
+            }
+        })
+    }
+
+    /// Receive the byte at position `n` of a T=0 response, discarding it if
+    /// it doesn't fit in `response`
+    fn receive_into(
+        &mut self,
+        response: &mut [u8],
+        n: usize,
+    ) -> Result<(), Error> {
+        let byte = self.read_byte(self.waiting_time)?;
+        if let Some(b) = response.get_mut(n) {
+            *b = byte;
+        }
+        Ok(())
+    }
+
+    /// Discard any received data and clear the reception status flags
+    fn flush_rx(&mut self) {
+        flush_rx(&self.usart);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atr_layout() {
+        // T=0 only: no TCK
+        let atr = [0x3B, 0x65, 0x00, 0x00, 0x20, 0x63, 0xCB, 0x68, 0x00];
+        assert_eq!(atr_layout(&atr[..1]), None);
+        assert_eq!(
+            atr_layout(&atr[..2]),
+            Some(AtrLayout {
+                len: 9,
+                historical: 5,
+                tck: false
+            })
+        );
+
+        // TD1 indicates T=1, so TCK is present
+        let atr = [0x3B, 0x80, 0x81, 0x31, 0xFE, 0x45, 0xF0];
+        assert_eq!(atr_layout(&atr[..2]), None);
+        assert_eq!(atr_layout(&atr[..3]), None);
+        assert_eq!(
+            atr_layout(&atr[..4]),
+            Some(AtrLayout {
+                len: 7,
+                historical: 0,
+                tck: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_calc_card_clock() {
+        // 32 MHz HSI, 4 MHz card clock
+        assert_eq!(
+            calc_card_clock(32_000_000, 4_000_000, 372, 1),
+            Some(CardClock {
+                presc: PRESCALER::Div1,
+                psc: 4,
+                brr: 2976
+            })
+        );
+
+        // 250 MHz PCLK needs the kernel clock prescaler: 3.9 MHz
+        assert_eq!(
+            calc_card_clock(250_000_000, 4_000_000, 372, 1),
+            Some(CardClock {
+                presc: PRESCALER::Div2,
+                psc: 16,
+                brr: 11904
+            })
+        );
+
+        // F = 512, D = 8 after PPS
+        assert_eq!(
+            calc_card_clock(32_000_000, 4_000_000, 512, 8),
+            Some(CardClock {
+                presc: PRESCALER::Div1,
+                psc: 4,
+                brr: 512
+            })
+        );
+    }
+}
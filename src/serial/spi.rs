@@ -0,0 +1,194 @@
+//! USART synchronous master mode, used as an SPI bus.
+//!
+//! In synchronous mode the USART outputs a clock on its CK pin while
+//! transmitting, with TX acting as MOSI and RX as MISO. There is no hardware
+//! chip select, so the CS of each device must be driven from a GPIO, for
+//! example with `embedded-hal-bus`.
+use embedded_hal::spi::{ErrorType, Phase, Polarity, SpiBus};
+
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::time::Hertz;
+
+use super::{
+    calc_baud_rate, check_rx_errors, BitOrder, Error, Instance, SpiConfig,
+    SynchronousInstance,
+};
+
+/// A USART in synchronous master mode, implementing [`SpiBus`]
+#[derive(Debug)]
+pub struct UsartSpi<USART> {
+    usart: USART,
+}
+
+impl<USART: Instance> UsartSpi<USART> {
+    pub(super) fn new(
+        usart: USART,
+        config: impl Into<SpiConfig>,
+        freq: Hertz,
+        rec: USART::Rec,
+        clocks: &CoreClocks,
+    ) -> Self
+    where
+        USART: SynchronousInstance,
+    {
+        let config: SpiConfig = config.into();
+        let _ = rec.enable().reset();
+
+        let spi = UsartSpi { usart };
+        spi.init(config, freq, USART::clock(clocks))
+    }
+
+    fn init(self, config: SpiConfig, freq: Hertz, clock: Hertz) -> Self {
+        let baud = calc_baud_rate(clock.raw(), freq.raw())
+            .expect("SPI clock cannot be generated from the kernel clock");
+
+        self.usart.cr1().reset();
+        self.usart.cr2().reset();
+        self.usart.cr3().reset();
+
+        self.usart
+            .presc()
+            .write(|w| w.prescaler().variant(baud.presc));
+        self.usart
+            .brr()
+            .write(|w| unsafe { w.brr().bits(baud.brr) });
+
+        self.usart.cr2().write(|w| {
+            w.clken()
+                .enabled()
+                .cpol()
+                .bit(config.mode.polarity == Polarity::IdleHigh)
+                .cpha()
+                .bit(config.mode.phase == Phase::CaptureOnSecondTransition)
+                .lbcl()
+                .bit(config.last_bit_clock)
+                .msbfirst()
+                .bit(config.bit_order == BitOrder::MsbFirst)
+        });
+
+        // 8 data bits, no parity
+        self.usart.cr1().write(|w| {
+            w.over8()
+                .bit(baud.over8)
+                .te()
+                .enabled()
+                .re()
+                .enabled()
+                .ue()
+                .enabled()
+        });
+
+        self
+    }
+
+    /// Transmit a word and return the word received at the same time
+    fn exchange(&mut self, word: u8) -> Result<u8, Error> {
+        while self.usart.isr().read().txfnf().bit_is_clear() {}
+        self.usart
+            .tdr()
+            .write(|w| unsafe { w.tdr().bits(word as u16) });
+
+        loop {
+            let isr = self.usart.isr().read();
+            check_rx_errors(&self.usart, &isr)?;
+            if isr.rxfne().bit_is_set() {
+                return Ok(self.usart.rdr().read().rdr().bits() as u8);
+            }
+        }
+    }
+
+    /// Read `words.len()` words while transmitting zeros
+    pub fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        for word in words {
+            *word = self.exchange(0)?;
+        }
+        Ok(())
+    }
+
+    /// Write all of `words`, discarding the received words
+    pub fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        for &word in words {
+            self.exchange(word)?;
+        }
+        Ok(())
+    }
+
+    /// Write `write` while reading into `read`. If the buffers have
+    /// different lengths, zeros are written after the end of `write` and
+    /// words received after the end of `read` are discarded.
+    pub fn transfer(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+    ) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        for i in 0..len {
+            let word = self.exchange(write.get(i).copied().unwrap_or(0))?;
+            if let Some(r) = read.get_mut(i) {
+                *r = word;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write `words` while replacing them with the received words
+    pub fn transfer_inplace(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        for word in words {
+            *word = self.exchange(*word)?;
+        }
+        Ok(())
+    }
+
+    /// Block until the last word has been transmitted
+    pub fn flush(&mut self) -> Result<(), Error> {
+        while self.usart.isr().read().tc().bit_is_clear() {}
+        Ok(())
+    }
+
+    /// Disable the peripheral and return it
+    pub fn free(self) -> USART {
+        self.usart.cr1().reset();
+        self.usart
+    }
+
+    pub fn inner(&self) -> &USART {
+        &self.usart
+    }
+
+    pub fn inner_mut(&mut self) -> &mut USART {
+        &mut self.usart
+    }
+}
+
+impl<USART> ErrorType for UsartSpi<USART> {
+    type Error = Error;
+}
+
+impl<USART: Instance> SpiBus<u8> for UsartSpi<USART> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        UsartSpi::read(self, words)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        UsartSpi::write(self, words)
+    }
+
+    fn transfer(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+    ) -> Result<(), Self::Error> {
+        UsartSpi::transfer(self, read, write)
+    }
+
+    fn transfer_in_place(
+        &mut self,
+        words: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        UsartSpi::transfer_inplace(self, words)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        UsartSpi::flush(self)
+    }
+}
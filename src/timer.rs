@@ -0,0 +1,411 @@
+//! Timers
+//!
+//! The general-purpose and advanced-control timers implement the
+//! [`Instance`] trait, which gives the timer drivers such as
+//! [`pwm`](crate::pwm), [`capture`](crate::capture) and [`qei`](crate::qei)
+//! access to their registers and kernel clock. The pins that can be used
+//! for each capture/compare channel implement [`PinCh`].
+//! The complementary outputs and break inputs of the timers with a break and
+//! dead-time register implement [`PinChN`], [`PinBkin`] and [`PinBkin2`],
+//! and the external trigger inputs implement [`PinEtr`].
+//!
+//! Any timer, including the basic timers TIM6 and TIM7, can be used as a
+//! periodic [`Counter`] or [`CounterHz`] with update interrupts, or as a
+//! [`Delay`] provider, through [`TimerExt`]. The [`hall`] module configures
+//! a timer as the Hall sensor interface of a brushless motor.
+//!
+//! Behind cargo features, the `monotonic` module makes TIM2 or TIM5 an
+//! RTIC monotonic timer or the time driver of `embassy-time`.
+//!
+//! The timers with GPDMA requests implement [`DmaInstance`]. Their
+//! capture/compare registers ([`Ccr`]) and DMA burst register
+//! ([`DmaBurst`]) can be used as the peripheral side of a
+//! [`DmaTransfer`](crate::gpdma::DmaTransfer), paced by the DMA requests
+//! that the PWM and capture drivers enable with `listen_dma`.
+//!
+//! The timers with a trigger output implement [`MasterInstance`]. The timer
+//! drivers select the source of the trigger output with a [`MasterMode`],
+//! and a [`SlaveMode`] in which the trigger output of another timer
+//! controls their counter, to chain timers together.
+//!
+//! The registers that all timers have are at the same offsets in each of
+//! them, so they are accessed through the TIM2 register block, which has
+//! the most complete set of general-purpose features.
+use core::marker::PhantomData;
+
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::{tim1, tim2, tim6};
+use crate::time::Hertz;
+
+mod counter;
+mod delay;
+mod dma;
+#[cfg(any(
+    feature = "rtic-tim2",
+    feature = "rtic-tim5",
+    feature = "time-driver-tim2",
+    feature = "time-driver-tim5",
+    feature = "time-driver-lptim1"
+))]
+pub(crate) mod half_period;
+pub mod hall;
+#[cfg(any(
+    feature = "rtic-tim2",
+    feature = "rtic-tim5",
+    feature = "time-driver-tim2",
+    feature = "time-driver-tim5"
+))]
+pub mod monotonic;
+pub(crate) mod sync;
+mod timer_def;
+
+pub use counter::{Counter, CounterHz, CounterMs, CounterUs};
+pub use delay::{Delay, DelayUs};
+pub(crate) use dma::{dma_methods, set_dma_burst, set_dma_request};
+pub use dma::{Ccr, DmaBurst};
+pub use hall::{HallConfig, HallExt, HallSensor};
+#[cfg(any(feature = "time-driver-tim2", feature = "time-driver-tim5"))]
+pub use monotonic::init_time_driver;
+#[cfg(feature = "rtic-tim2")]
+pub use monotonic::{Tim2Backend, Tim2Monotonic};
+#[cfg(feature = "rtic-tim5")]
+pub use monotonic::{Tim5Backend, Tim5Monotonic};
+pub use sync::{MasterInstance, MasterMode, SlaveInstance, SlaveMode};
+
+/// Interrupt events
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The counter overflowed or underflowed, or was reinitialised (UIF)
+    Update,
+    /// Capture or compare on channel 1 (CC1IF)
+    CaptureCompare1,
+    /// Capture or compare on channel 2 (CC2IF)
+    CaptureCompare2,
+    /// Capture or compare on channel 3 (CC3IF)
+    CaptureCompare3,
+    /// Capture or compare on channel 4 (CC4IF)
+    CaptureCompare4,
+    /// The capture/compare control bits were updated by a commutation
+    /// (COMIF). Only for timers with a break and dead-time register.
+    Commutation,
+    /// An edge was detected on the trigger input (TIF)
+    Trigger,
+    /// A break input became active (BIF/B2IF). Only for timers with a break
+    /// and dead-time register.
+    Break,
+    /// An encoder index was detected (IDXF)
+    Index,
+    /// The counting direction changed in encoder mode (DIRF)
+    DirectionChange,
+}
+
+impl Event {
+    /// The bits of this event in DIER and SR, which are the same in both
+    fn mask(self) -> u32 {
+        match self {
+            Event::Update => 1 << 0,
+            Event::CaptureCompare1 => 1 << 1,
+            Event::CaptureCompare2 => 1 << 2,
+            Event::CaptureCompare3 => 1 << 3,
+            Event::CaptureCompare4 => 1 << 4,
+            Event::Commutation => 1 << 5,
+            Event::Trigger => 1 << 6,
+            // BIE enables both break interrupts, and B2IF is bit 8 of SR
+            Event::Break => 1 << 7,
+            Event::Index => 1 << 20,
+            Event::DirectionChange => 1 << 21,
+        }
+    }
+}
+
+// Implemented by all timer instances, including the basic timers
+pub trait BasicInstance: crate::Sealed + Sized {
+    type Rec: ResetEnable;
+
+    /// The maximum value of the counter and auto-reload register
+    const MAX_ARR: u32;
+
+    #[doc(hidden)]
+    fn basic_ptr() -> *const tim6::RegisterBlock;
+
+    #[doc(hidden)]
+    fn clock(clocks: &CoreClocks) -> Hertz;
+
+    #[doc(hidden)]
+    fn rec() -> Self::Rec;
+}
+
+// Implemented by all timer instances with capture/compare channels
+pub trait Instance: BasicInstance {
+    /// The number of capture/compare channels
+    const CHANNELS: u8;
+
+    /// True for timers with a break and dead-time register, whose outputs
+    /// are only enabled while the main output enable (MOE) bit is set
+    const HAS_BDTR: bool;
+
+    /// True for timers that can count down, and so support center-aligned
+    /// counting
+    const UP_DOWN: bool;
+
+    #[doc(hidden)]
+    fn ptr() -> *const tim2::RegisterBlock;
+}
+
+// Implemented by the timers with a break and dead-time register (TIM1, TIM8
+// and TIM15 to TIM17)
+pub trait AdvancedInstance: Instance {}
+
+// Implemented by the timers that can count both up and down, which have an
+// encoder mode (TIM1 to TIM5 and TIM8)
+pub trait EncoderInstance: Instance {}
+
+// Implemented by all timer instances with GPDMA requests
+pub trait DmaInstance: Instance {
+    /// GPDMA hardware request line of the update event
+    const UPDATE_DMA_REQUEST: u8;
+
+    /// GPDMA hardware request lines of the capture/compare events, for the
+    /// channels that have one, starting with channel 1
+    const CC_DMA_REQUESTS: &'static [u8];
+}
+
+pub trait TimerExt<TIM: BasicInstance>: Sized {
+    /// Use the timer as a counter of periods given as a frequency
+    fn counter_hz(self, rec: TIM::Rec, clocks: &CoreClocks) -> CounterHz<TIM>;
+
+    /// Use the timer as a counter running at `FREQ` Hz
+    fn counter<const FREQ: u32>(
+        self,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Counter<TIM, FREQ>;
+
+    /// Use the timer as a counter running at 1 MHz
+    fn counter_us(self, rec: TIM::Rec, clocks: &CoreClocks) -> CounterUs<TIM> {
+        self.counter(rec, clocks)
+    }
+
+    /// Use the timer as a delay provider running at `FREQ` Hz
+    fn delay<const FREQ: u32>(
+        self,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Delay<TIM, FREQ>;
+
+    /// Use the timer as a delay provider running at 1 MHz
+    fn delay_us(self, rec: TIM::Rec, clocks: &CoreClocks) -> DelayUs<TIM> {
+        self.delay(rec, clocks)
+    }
+}
+
+impl<TIM: BasicInstance> TimerExt<TIM> for TIM {
+    fn counter_hz(self, rec: TIM::Rec, clocks: &CoreClocks) -> CounterHz<TIM> {
+        CounterHz::new(self, rec, clocks)
+    }
+
+    fn counter<const FREQ: u32>(
+        self,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Counter<TIM, FREQ> {
+        Counter::new(self, rec, clocks)
+    }
+
+    fn delay<const FREQ: u32>(
+        self,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Delay<TIM, FREQ> {
+        Delay::new(self, rec, clocks)
+    }
+}
+
+/// Implemented by the timers whose trigger output (TRGO) is connected to an
+/// internal trigger input (ITR) of timer `TIM`
+pub trait InternalTrigger<TIM> {
+    /// The index of the internal trigger input of `TIM`
+    const ITR: u8;
+}
+
+/// A pin that can be used for capture/compare channel `C` (1 to 4) of the
+/// timer
+pub trait PinCh<TIM, const C: u8> {}
+
+/// A pin that can be used for the complementary output of capture/compare
+/// channel `C` (1 to 4) of a timer with a break and dead-time register
+pub trait PinChN<TIM, const C: u8> {}
+
+/// A pin that can be used as the external trigger input (ETR) of the timer
+pub trait PinEtr<TIM> {}
+
+/// A pin that can be used as the break input (BKIN) of the timer
+pub trait PinBkin<TIM> {}
+
+/// A pin that can be used as the second break input (BKIN2) of the timer
+pub trait PinBkin2<TIM> {}
+
+/// Capture/compare channel `C` (1 to 4) of a timer, which has not yet been
+/// assigned a function
+///
+/// Channels are handed out by the timer drivers when the timer is
+/// configured, and are turned into a channel driver by binding a pin to
+/// them, for example with [`Channel::pwm`].
+#[derive(Debug)]
+pub struct Channel<TIM, const C: u8> {
+    _tim: PhantomData<TIM>,
+}
+
+impl<TIM, const C: u8> Channel<TIM, C> {
+    pub(crate) fn new() -> Self {
+        Channel { _tim: PhantomData }
+    }
+}
+
+/// The capture/compare channels of a timer. Channels beyond the number
+/// that a timer has have no pins, and so cannot be used.
+#[derive(Debug)]
+pub struct Channels<TIM> {
+    pub ch1: Channel<TIM, 1>,
+    pub ch2: Channel<TIM, 2>,
+    pub ch3: Channel<TIM, 3>,
+    pub ch4: Channel<TIM, 4>,
+}
+
+impl<TIM> Channels<TIM> {
+    pub(crate) fn new() -> Self {
+        Channels {
+            ch1: Channel::new(),
+            ch2: Channel::new(),
+            ch3: Channel::new(),
+            ch4: Channel::new(),
+        }
+    }
+}
+
+/// Calculate the prescaler and auto-reload values for a counter period of
+/// `ticks` cycles of the timer clock. The smallest prescaler that keeps the
+/// auto-reload value within `max_arr` is used, for the finest resolution.
+pub(crate) fn calculate_psc_arr(ticks: u64, max_arr: u32) -> (u16, u32) {
+    assert!(ticks >= 2, "Timer period is too short for the timer clock");
+    let psc = (ticks - 1) / (max_arr as u64 + 1);
+    assert!(
+        psc <= u16::MAX as u64,
+        "Timer period is too long for the timer clock"
+    );
+    let arr = ticks / (psc + 1) - 1;
+    (psc as u16, arr as u32)
+}
+
+/// Set or clear the main output enable bit of a timer with a break and
+/// dead-time register
+pub(crate) fn set_main_output<TIM: Instance>(enable: bool) {
+    debug_assert!(TIM::HAS_BDTR);
+    // NOTE(unsafe) BDTR is at the same offset in all timers that have it
+    let tim = unsafe { &*(TIM::ptr() as *const tim1::RegisterBlock) };
+    tim.bdtr().modify(|_, w| w.moe().bit(enable));
+}
+
+/// Enable or disable the interrupt for `event`
+pub(crate) fn set_interrupt<TIM: Instance>(event: Event, enable: bool) {
+    if matches!(event, Event::Commutation | Event::Break) {
+        assert!(TIM::HAS_BDTR, "The timer does not have this event");
+    }
+    // NOTE(unsafe) The event is one that the timer has
+    let tim = unsafe { &*TIM::ptr() };
+    tim.dier().modify(|r, w| unsafe {
+        if enable {
+            w.bits(r.bits() | event.mask())
+        } else {
+            w.bits(r.bits() & !event.mask())
+        }
+    });
+}
+
+/// Whether the flag of `event` is set
+pub(crate) fn is_pending<TIM: Instance>(event: Event) -> bool {
+    let mask = match event {
+        Event::Break => event.mask() | 1 << 8,
+        _ => event.mask(),
+    };
+    // NOTE(unsafe) Atomic read with no side effects
+    let tim = unsafe { &*TIM::ptr() };
+    tim.sr().read().bits() & mask != 0
+}
+
+/// Clear the flag of `event`
+pub(crate) fn clear_pending<TIM: BasicInstance>(event: Event) {
+    let mask = match event {
+        Event::Break => event.mask() | 1 << 8,
+        _ => event.mask(),
+    };
+    // NOTE(unsafe) The flags are cleared by writing zero, so writing ones to
+    // the other bits leaves them unchanged. SR is at the same offset in all
+    // timers.
+    let tim = unsafe { &*(TIM::basic_ptr() as *const tim2::RegisterBlock) };
+    tim.sr().write(|w| unsafe { w.bits(!mask) });
+}
+
+/// The prescaler division for a counter frequency of `frequency` from the
+/// timer clock `clock`. This will panic if the frequency cannot be
+/// generated.
+pub(crate) fn counter_prescaler(clock: Hertz, frequency: Hertz) -> u32 {
+    let psc = clock.raw() / frequency.raw();
+    assert!(
+        (1..=u16::MAX as u32 + 1).contains(&psc),
+        "The counter frequency cannot be generated from the timer clock"
+    );
+    psc
+}
+
+/// The trigger selection (TS) value of internal trigger input `itr`. ITR0
+/// to ITR3 are encoded as 0 to 3 and the others from 8 onwards, as the
+/// values in between select the timer's own inputs.
+pub(crate) fn internal_trigger_selection(itr: u8) -> u8 {
+    if itr < 4 {
+        itr
+    } else {
+        itr + 4
+    }
+}
+
+/// Select the trigger input (TRGI) of the timer from its trigger selection
+/// (TS) value
+pub(crate) fn select_trigger<TIM: Instance>(ts: u8) {
+    // NOTE(unsafe) SMCR is at the same offset in all timers with a trigger
+    // input
+    let tim = unsafe { &*TIM::ptr() };
+    tim.smcr()
+        .modify(|_, w| w.ts().set(ts & 0b111).ts2().set(ts >> 3));
+}
+
+/// Select internal trigger input `itr` as the trigger input (TRGI) of the
+/// timer
+pub(crate) fn select_internal_trigger<TIM: Instance>(itr: u8) {
+    select_trigger::<TIM>(internal_trigger_selection(itr));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_psc_arr() {
+        // 1 kHz from 250 MHz on a 16-bit timer
+        assert_eq!(calculate_psc_arr(250_000, 0xFFFF), (3, 62_499));
+        // The same period needs no prescaler on a 32-bit timer
+        assert_eq!(calculate_psc_arr(250_000, 0xFFFF_FFFF), (0, 249_999));
+        assert_eq!(calculate_psc_arr(0x1_0000, 0xFFFF), (0, 0xFFFF));
+        assert_eq!(calculate_psc_arr(0x1_0001, 0xFFFF), (1, 0x8000 - 1));
+    }
+
+    #[test]
+    fn test_internal_trigger_selection() {
+        assert_eq!(internal_trigger_selection(0), 0b00000);
+        assert_eq!(internal_trigger_selection(3), 0b00011);
+        // TI1F_ED to ETRF are in between
+        assert_eq!(internal_trigger_selection(4), 0b01000);
+        assert_eq!(internal_trigger_selection(5), 0b01001);
+    }
+}
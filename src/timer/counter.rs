@@ -0,0 +1,240 @@
+//! Periodic counters
+//!
+//! Any timer, including the basic timers TIM6 and TIM7, can be used as a
+//! periodic counter that raises its update flag, and optionally an
+//! interrupt, at the end of each period.
+//!
+//! [`CounterHz`] counts periods given as a frequency, and picks the
+//! prescaler for each of them. [`Counter`] counts at a fixed frequency
+//! `FREQ`, so its periods are [`fugit`] durations and [`Counter::now`]
+//! returns an instant:
+//!
+//! ```
+//! use stm32h5xx_hal::timer::{CounterUs, TimerExt};
+//!
+//! let mut counter: CounterUs<_> = dp.TIM6.counter_us(ccdr.peripheral.TIM6, &ccdr.clocks);
+//!
+//! counter.start(250.millis());
+//! counter.listen();
+//!
+//! // In the TIM6 interrupt
+//! counter.clear_irq();
+//! ```
+
+use core::convert::Infallible;
+
+use embedded_hal_nb::nb;
+use fugit::{TimerDurationU32, TimerInstantU32};
+
+use super::sync::{master_methods, slave_methods};
+use super::{
+    calculate_psc_arr, clear_pending, BasicInstance, Event, Instance,
+    MasterInstance,
+};
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::stm32::tim6;
+use crate::time::Hertz;
+
+/// A timer counting periods given as a frequency
+#[derive(Debug)]
+pub struct CounterHz<TIM> {
+    tim: TIM,
+    clock: Hertz,
+}
+
+/// A timer counting at `FREQ` Hz
+#[derive(Debug)]
+pub struct Counter<TIM, const FREQ: u32> {
+    tim: TIM,
+}
+
+/// A timer counting in microseconds
+pub type CounterUs<TIM> = Counter<TIM, 1_000_000>;
+
+/// A timer counting in milliseconds
+pub type CounterMs<TIM> = Counter<TIM, 1_000>;
+
+#[inline(always)]
+pub(super) fn basic_regs<TIM: BasicInstance>() -> &'static tim6::RegisterBlock {
+    // NOTE(unsafe) The registers of the basic timers are at the same offsets
+    // in all timers
+    unsafe { &*TIM::basic_ptr() }
+}
+
+/// Reset and enable the timer, with the counter stopped. Only counter
+/// overflows generate an update event, rather than also setting the
+/// prescaler and auto-reload values.
+pub(super) fn init<TIM: BasicInstance>(rec: TIM::Rec) {
+    let _ = rec.enable().reset();
+    basic_regs::<TIM>().cr1().write(|w| w.urs().set_bit());
+}
+
+/// Set the prescaler and auto-reload values, reset the counter and clear the
+/// update flag
+pub(super) fn load<TIM: BasicInstance>(psc: u16, arr: u32) {
+    let tim = basic_regs::<TIM>();
+    tim.psc().write(|w| w.psc().set(psc));
+    // NOTE(unsafe) The auto-reload register is 32 bits on some timers, and
+    // the value is checked to fit
+    tim.arr().write(|w| unsafe { w.bits(arr) });
+    tim.egr().write(|w| w.ug().set_bit());
+    clear_pending::<TIM>(Event::Update);
+}
+
+/// The prescaler value for a counter running at `freq` Hz from the clock of
+/// the timer. This will panic if the timer clock is not a multiple of the
+/// counter frequency.
+pub(super) fn fixed_prescaler<TIM: BasicInstance>(
+    clocks: &CoreClocks,
+    freq: u32,
+) -> u16 {
+    let clock = TIM::clock(clocks).raw();
+    assert!(
+        clock % freq == 0
+            && (1..=u16::MAX as u32 + 1).contains(&(clock / freq)),
+        "The counter frequency cannot be generated from the timer clock"
+    );
+    (clock / freq - 1) as u16
+}
+
+/// The auto-reload value for a period of `ticks` counter ticks. This will
+/// panic if the period cannot be generated by a timer with a maximum
+/// auto-reload value of `max_arr`.
+fn period_ticks(ticks: u32, max_arr: u32) -> u32 {
+    // The counter is blocked while the auto-reload value is 0
+    assert!(
+        (2..=max_arr as u64 + 1).contains(&(ticks as u64)),
+        "The period is out of the range of the timer"
+    );
+    ticks - 1
+}
+
+macro_rules! counter_common {
+    () => {
+        /// Whether the period has elapsed, and clear the update flag if it
+        /// has
+        pub fn wait(&mut self) -> nb::Result<(), Infallible> {
+            let tim = basic_regs::<TIM>();
+            if tim.sr().read().uif().bit_is_set() {
+                clear_pending::<TIM>(Event::Update);
+                Ok(())
+            } else {
+                Err(nb::Error::WouldBlock)
+            }
+        }
+
+        /// Stop the counter
+        pub fn cancel(&mut self) {
+            basic_regs::<TIM>().cr1().modify(|_, w| w.cen().clear_bit());
+        }
+
+        /// Whether the counter is running
+        pub fn is_running(&self) -> bool {
+            basic_regs::<TIM>().cr1().read().cen().bit_is_set()
+        }
+
+        /// Start listening for the update interrupt at the end of each
+        /// period
+        pub fn listen(&mut self) {
+            basic_regs::<TIM>().dier().modify(|_, w| w.uie().set_bit());
+        }
+
+        /// Stop listening for the update interrupt
+        pub fn unlisten(&mut self) {
+            basic_regs::<TIM>()
+                .dier()
+                .modify(|_, w| w.uie().clear_bit());
+        }
+
+        /// Clear the update interrupt flag
+        pub fn clear_irq(&mut self) {
+            clear_pending::<TIM>(Event::Update);
+        }
+
+        /// Releases the timer peripheral
+        pub fn free(self) -> TIM {
+            let _ = TIM::rec().reset().disable();
+            self.tim
+        }
+    };
+}
+
+impl<TIM: BasicInstance> CounterHz<TIM> {
+    pub(super) fn new(tim: TIM, rec: TIM::Rec, clocks: &CoreClocks) -> Self {
+        init::<TIM>(rec);
+        CounterHz {
+            tim,
+            clock: TIM::clock(clocks),
+        }
+    }
+
+    /// Start counting periods at `frequency`. This will panic if the
+    /// frequency cannot be generated from the timer clock.
+    pub fn start(&mut self, frequency: Hertz) {
+        self.cancel();
+        let ticks = self.clock.raw() as u64 / frequency.raw() as u64;
+        assert!(ticks >= 2, "The frequency is too high for the timer clock");
+        let (psc, arr) = calculate_psc_arr(ticks, TIM::MAX_ARR);
+        load::<TIM>(psc, arr);
+        basic_regs::<TIM>().cr1().modify(|_, w| w.cen().set_bit());
+    }
+
+    counter_common!();
+}
+
+impl<TIM: MasterInstance> CounterHz<TIM> {
+    master_methods!(counter);
+}
+
+impl<TIM: Instance> CounterHz<TIM> {
+    slave_methods!();
+}
+
+impl<TIM: BasicInstance, const FREQ: u32> Counter<TIM, FREQ> {
+    pub(super) fn new(tim: TIM, rec: TIM::Rec, clocks: &CoreClocks) -> Self {
+        let psc = fixed_prescaler::<TIM>(clocks, FREQ);
+        init::<TIM>(rec);
+        load::<TIM>(psc, TIM::MAX_ARR);
+        Counter { tim }
+    }
+
+    /// Start counting periods of `timeout`. This will panic if the timeout
+    /// is shorter than two ticks, or longer than the range of the timer.
+    pub fn start(&mut self, timeout: TimerDurationU32<FREQ>) {
+        self.cancel();
+        let arr = period_ticks(timeout.ticks(), TIM::MAX_ARR);
+        let psc = basic_regs::<TIM>().psc().read().psc().bits();
+        load::<TIM>(psc, arr);
+        basic_regs::<TIM>().cr1().modify(|_, w| w.cen().set_bit());
+    }
+
+    /// The current value of the counter, since the start of the current
+    /// period
+    pub fn now(&self) -> TimerInstantU32<FREQ> {
+        TimerInstantU32::from_ticks(
+            basic_regs::<TIM>().cnt().read().bits() & TIM::MAX_ARR,
+        )
+    }
+
+    counter_common!();
+}
+
+impl<TIM: MasterInstance, const FREQ: u32> Counter<TIM, FREQ> {
+    master_methods!(counter);
+}
+
+impl<TIM: Instance, const FREQ: u32> Counter<TIM, FREQ> {
+    slave_methods!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_ticks() {
+        assert_eq!(period_ticks(2, 0xFFFF), 1);
+        assert_eq!(period_ticks(0x1_0000, 0xFFFF), 0xFFFF);
+        assert_eq!(period_ticks(u32::MAX, u32::MAX), u32::MAX - 1);
+    }
+}
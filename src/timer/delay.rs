@@ -0,0 +1,93 @@
+//! Timer-backed delays
+//!
+//! [`Delay`] implements the embedded-hal [`DelayNs`] trait on any timer,
+//! including the basic timers TIM6 and TIM7, which leaves SysTick free for
+//! an RTOS. The counter runs at `FREQ` Hz, which sets the resolution of the
+//! delays. Delays are rounded up to a whole number of ticks.
+//!
+//! ```
+//! use embedded_hal::delay::DelayNs;
+//! use stm32h5xx_hal::timer::TimerExt;
+//!
+//! let mut delay = dp.TIM7.delay_us(ccdr.peripheral.TIM7, &ccdr.clocks);
+//!
+//! delay.delay_us(50);
+//! delay.delay(3.millis());
+//! ```
+
+use embedded_hal::delay::DelayNs;
+use fugit::TimerDurationU32;
+
+use super::counter::{basic_regs, fixed_prescaler, init, load};
+use super::BasicInstance;
+use crate::rcc::{CoreClocks, ResetEnable};
+
+/// A timer used as a delay provider, counting at `FREQ` Hz
+#[derive(Debug)]
+pub struct Delay<TIM, const FREQ: u32> {
+    tim: TIM,
+    psc: u16,
+}
+
+/// A timer used as a delay provider, counting in microseconds
+pub type DelayUs<TIM> = Delay<TIM, 1_000_000>;
+
+/// The number of ticks at `freq` Hz in `ns` nanoseconds, rounded up
+fn nanos_to_ticks(ns: u32, freq: u32) -> u64 {
+    (ns as u64 * freq as u64).div_ceil(1_000_000_000)
+}
+
+impl<TIM: BasicInstance, const FREQ: u32> Delay<TIM, FREQ> {
+    pub(super) fn new(tim: TIM, rec: TIM::Rec, clocks: &CoreClocks) -> Self {
+        let psc = fixed_prescaler::<TIM>(clocks, FREQ);
+        init::<TIM>(rec);
+        // Each delay is a single period
+        basic_regs::<TIM>().cr1().modify(|_, w| w.opm().set_bit());
+        Delay { tim, psc }
+    }
+
+    /// Wait for `ticks` counter ticks
+    fn delay_ticks(&mut self, mut ticks: u64) {
+        let tim = basic_regs::<TIM>();
+        while ticks > 0 {
+            // The counter is blocked while the auto-reload value is 0, so
+            // each period is at least two ticks
+            let period = ticks.clamp(2, TIM::MAX_ARR as u64 + 1);
+            load::<TIM>(self.psc, (period - 1) as u32);
+            tim.cr1().modify(|_, w| w.cen().set_bit());
+            while tim.sr().read().uif().bit_is_clear() {}
+            ticks = ticks.saturating_sub(period);
+        }
+    }
+
+    /// Wait for `duration`
+    pub fn delay(&mut self, duration: TimerDurationU32<FREQ>) {
+        self.delay_ticks(duration.ticks() as u64);
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> TIM {
+        let _ = TIM::rec().reset().disable();
+        self.tim
+    }
+}
+
+impl<TIM: BasicInstance, const FREQ: u32> DelayNs for Delay<TIM, FREQ> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay_ticks(nanos_to_ticks(ns, FREQ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nanos_to_ticks() {
+        assert_eq!(nanos_to_ticks(0, 1_000_000), 0);
+        assert_eq!(nanos_to_ticks(1, 1_000_000), 1);
+        assert_eq!(nanos_to_ticks(1000, 1_000_000), 1);
+        assert_eq!(nanos_to_ticks(1001, 1_000_000), 2);
+        assert_eq!(nanos_to_ticks(u32::MAX, 250_000_000), 1_073_741_824);
+    }
+}
@@ -0,0 +1,163 @@
+//! DMA requests and registers of the timers
+//!
+//! A timer raises a GPDMA request on an event when the DMA request of that
+//! event is enabled, with `listen_dma` of the PWM or capture driver. The
+//! DMA can then update a capture/compare register ([`Ccr`]) on each
+//! request, for example to change the duty cycle of a PWM output every
+//! period, or read the captured value of an input.
+//!
+//! The DMA burst register ([`DmaBurst`]) gives access to a block of
+//! consecutive timer registers. Each request then transfers one value to
+//! each register of the block, so that several channels can be updated at
+//! once from one request.
+
+use core::marker::PhantomData;
+
+use super::{Event, Instance};
+use crate::gpdma::periph::{RxAddr, TxAddr};
+
+/// The capture/compare register of channel `C` (1 to 4) of a timer, as the
+/// peripheral side of a DMA transfer
+#[derive(Debug)]
+pub struct Ccr<TIM, const C: u8> {
+    _tim: PhantomData<TIM>,
+}
+
+/// The DMA burst register (DMAR) of a timer, as the peripheral side of a
+/// DMA transfer. The block of registers that it accesses is selected by the
+/// timer driver.
+#[derive(Debug)]
+pub struct DmaBurst<TIM> {
+    _tim: PhantomData<TIM>,
+}
+
+/// Burst source selection (DBSS) of the update event. The capture/compare
+/// events follow from 0b0010.
+const DBSS_UPDATE: u8 = 0b0001;
+
+/// The offset of CCR1 from CR1, in 32-bit words
+const CCR1_OFFSET: u8 = 0x34 / 4;
+
+// The timer registers can be accessed as half-words or words. Half-words
+// are used on the 16-bit timers to save memory.
+macro_rules! dma_addr {
+    ($($W:ty),+) => {
+        $(
+            impl<TIM: Instance, const C: u8> TxAddr<$W> for Ccr<TIM, C> {
+                unsafe fn tx_addr() -> *mut $W {
+                    (*TIM::ptr()).ccr(C as usize - 1).as_ptr() as *mut $W
+                }
+            }
+
+            impl<TIM: Instance, const C: u8> RxAddr<$W> for Ccr<TIM, C> {
+                unsafe fn rx_addr() -> *const $W {
+                    (*TIM::ptr()).ccr(C as usize - 1).as_ptr() as *const $W
+                }
+            }
+
+            impl<TIM: Instance> TxAddr<$W> for DmaBurst<TIM> {
+                unsafe fn tx_addr() -> *mut $W {
+                    (*TIM::ptr()).dmar().as_ptr() as *mut $W
+                }
+            }
+        )+
+    };
+}
+
+dma_addr!(u16, u32);
+
+/// The bit of the DMA request of `event` in DIER
+fn dma_request_mask(event: Event) -> u32 {
+    match event {
+        Event::Update
+        | Event::CaptureCompare1
+        | Event::CaptureCompare2
+        | Event::CaptureCompare3
+        | Event::CaptureCompare4
+        | Event::Commutation
+        | Event::Trigger => event.mask() << 8,
+        _ => panic!("The event does not have a DMA request"),
+    }
+}
+
+/// Enable or disable the DMA request of `event`
+pub(crate) fn set_dma_request<TIM: Instance>(event: Event, enable: bool) {
+    let mask = dma_request_mask(event);
+    // NOTE(unsafe) Only the bit of the DMA request is changed
+    let tim = unsafe { &*TIM::ptr() };
+    tim.dier().modify(|r, w| unsafe {
+        if enable {
+            w.bits(r.bits() | mask)
+        } else {
+            w.bits(r.bits() & !mask)
+        }
+    });
+}
+
+/// Select the compare registers of `channels` consecutive channels, starting
+/// from channel `first`, as the block accessed through the DMA burst
+/// register on each update event
+pub(crate) fn set_dma_burst<TIM: Instance>(first: u8, channels: u8) {
+    assert!(
+        first >= 1 && channels >= 1 && first + channels - 1 <= TIM::CHANNELS,
+        "The timer does not have these channels"
+    );
+    // NOTE(unsafe) DCR is at the same offset in all timers
+    let tim = unsafe { &*TIM::ptr() };
+    tim.dcr().write(|w| unsafe {
+        w.dba()
+            .set(CCR1_OFFSET + first - 1)
+            .dbl()
+            .bits(channels - 1)
+            .dbss()
+            .bits(DBSS_UPDATE)
+    });
+}
+
+// DMA request methods of the timer drivers, with which a
+// [`DmaTransfer`](crate::gpdma::DmaTransfer) to or from a [`Ccr`] or the
+// [`DmaBurst`] register is paced by the timer. Captures only have the DMA
+// requests, as the burst register is only written.
+macro_rules! dma_methods {
+    (capture) => {
+        /// Enable the DMA request of `event`, on the hardware request line
+        /// given by [`DmaInstance`](crate::timer::DmaInstance). Only the
+        /// update, capture/compare, commutation and trigger events have a
+        /// DMA request, and this will panic for the others.
+        pub fn listen_dma(&mut self, event: $crate::timer::Event) {
+            $crate::timer::set_dma_request::<TIM>(event, true);
+        }
+
+        /// Disable the DMA request of `event`
+        pub fn unlisten_dma(&mut self, event: $crate::timer::Event) {
+            $crate::timer::set_dma_request::<TIM>(event, false);
+        }
+    };
+    () => {
+        $crate::timer::dma_methods!(capture);
+
+        /// Select the compare registers of `channels` consecutive channels,
+        /// starting from channel `first`, as the block of registers that
+        /// [`DmaBurst`](crate::timer::DmaBurst) accesses on each update DMA
+        /// request
+        pub fn set_dma_burst(&mut self, first: u8, channels: u8) {
+            $crate::timer::set_dma_burst::<TIM>(first, channels);
+        }
+    };
+}
+
+pub(crate) use dma_methods;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dma_request_mask() {
+        // UDE, CC1DE to CC4DE, COMDE and TDE are bits 8 to 14
+        assert_eq!(dma_request_mask(Event::Update), 1 << 8);
+        assert_eq!(dma_request_mask(Event::CaptureCompare1), 1 << 9);
+        assert_eq!(dma_request_mask(Event::CaptureCompare4), 1 << 12);
+        assert_eq!(dma_request_mask(Event::Trigger), 1 << 14);
+    }
+}
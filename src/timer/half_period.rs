@@ -0,0 +1,202 @@
+//! Counters extended to 64 bits by counting half periods
+//!
+//! The update interrupt at each overflow and the channel 2 compare interrupt
+//! halfway through each period both increment a half-period counter, from
+//! which and the value of the counter the 64-bit time is read without a
+//! critical section. Channel 1 compares against the next wake-up time.
+//!
+//! This is shared by the monotonic timers on TIM2 and TIM5, with 32-bit
+//! counters, and the time driver on LPTIM1, with a 16-bit counter.
+
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+
+/// A timer whose counter is extended by counting half periods
+pub(crate) trait HalfPeriodTimer {
+    /// The width of the counter in bits
+    const BITS: u32;
+
+    /// The value of the counter
+    fn counter() -> u32;
+
+    /// Set the compare value of channel 1
+    fn set_compare_value(ccr: u32);
+
+    /// Clear the overflow and halfway compare flags, returning the number of
+    /// half periods that they mark
+    fn take_half_periods() -> u32;
+
+    /// Clear the channel 1 compare flag, returning whether it was set
+    fn take_compare() -> bool;
+
+    /// Enable or disable the channel 1 compare interrupt
+    fn listen_compare(enable: bool);
+}
+
+/// The 64-bit time from the number of half periods counted and the value of
+/// a counter of `bits` bits. The half periods are counted by interrupts, so
+/// the count can be one behind the counter, which the parity of the count
+/// detects: an even count is expected with the counter in the first half of
+/// a period, and an odd count in the second half.
+fn calculate_now(bits: u32, half_periods: u32, counter: u32) -> u64 {
+    let shift = bits - 1;
+    ((half_periods as u64) << shift)
+        + (counter ^ ((half_periods & 1) << shift)) as u64
+}
+
+/// The value of CCR1 of a counter of `bits` bits to compare against time
+/// `at`, at time `now`. A time more than one period ahead cannot be compared
+/// against yet, so the compare then happens one period from now, which is
+/// as late as possible, and the next wake-up time is compared against
+/// again.
+fn compare_value(bits: u32, now: u64, at: u64) -> u32 {
+    let max = u64::MAX >> (64 - bits);
+    if at.saturating_sub(now) > max {
+        (now & max) as u32
+    } else {
+        (at & max) as u32
+    }
+}
+
+/// The 64-bit time of the counter
+pub(crate) fn now<T: HalfPeriodTimer>(half_periods: &AtomicU32) -> u64 {
+    let half_periods = half_periods.load(Ordering::Relaxed);
+    // The counter must be read after the half periods
+    compiler_fence(Ordering::Acquire);
+    calculate_now(T::BITS, half_periods, T::counter())
+}
+
+/// Count the half periods that have elapsed, from the interrupt handler
+pub(crate) fn count_half_periods<T: HalfPeriodTimer>(half_periods: &AtomicU32) {
+    half_periods.fetch_add(T::take_half_periods(), Ordering::Relaxed);
+}
+
+/// Compare channel 1 against time `at`
+pub(crate) fn set_compare<T: HalfPeriodTimer>(
+    half_periods: &AtomicU32,
+    at: u64,
+) {
+    let ccr = compare_value(T::BITS, now::<T>(half_periods), at);
+    T::set_compare_value(ccr);
+}
+
+#[cfg(any(
+    feature = "time-driver-tim2",
+    feature = "time-driver-tim5",
+    feature = "time-driver-lptim1"
+))]
+mod time_driver {
+    use core::cell::RefCell;
+    use core::marker::PhantomData;
+    use core::sync::atomic::AtomicU32;
+    use core::task::Waker;
+
+    use cortex_m::interrupt::{free, CriticalSection, Mutex};
+    use embassy_time_driver::Driver;
+    use embassy_time_queue_utils::Queue;
+
+    use super::{count_half_periods, now, set_compare, HalfPeriodTimer};
+
+    /// The time driver of `embassy-time` on timer `T`
+    pub(crate) struct TimeDriver<T> {
+        half_periods: AtomicU32,
+        queue: Mutex<RefCell<Queue>>,
+        _timer: PhantomData<fn() -> T>,
+    }
+
+    impl<T: HalfPeriodTimer> TimeDriver<T> {
+        pub(crate) const fn new() -> Self {
+            TimeDriver {
+                half_periods: AtomicU32::new(0),
+                queue: Mutex::new(RefCell::new(Queue::new())),
+                _timer: PhantomData,
+            }
+        }
+
+        /// Wake the tasks whose time has passed, and compare against the
+        /// next wake-up time
+        fn wake(&self, cs: &CriticalSection) {
+            let mut queue = self.queue.borrow(cs).borrow_mut();
+            while !self
+                .set_alarm(queue.next_expiration(now::<T>(&self.half_periods)))
+            {
+            }
+        }
+
+        /// Compare against time `at`, and return false if it has already
+        /// passed
+        fn set_alarm(&self, at: u64) -> bool {
+            if at == u64::MAX {
+                T::listen_compare(false);
+                return true;
+            }
+            set_compare::<T>(&self.half_periods, at);
+            T::take_compare();
+            T::listen_compare(true);
+            now::<T>(&self.half_periods) < at
+        }
+
+        /// Handle the interrupt of the timer, which must be called from its
+        /// interrupt handler
+        pub(crate) fn on_interrupt(&self) {
+            count_half_periods::<T>(&self.half_periods);
+            if T::take_compare() {
+                free(|cs| self.wake(cs));
+            }
+        }
+    }
+
+    impl<T: HalfPeriodTimer + 'static> Driver for TimeDriver<T> {
+        fn now(&self) -> u64 {
+            now::<T>(&self.half_periods)
+        }
+
+        fn schedule_wake(&self, at: u64, waker: &Waker) {
+            free(|cs| {
+                if self.queue.borrow(cs).borrow_mut().schedule_wake(at, waker) {
+                    self.wake(cs);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "time-driver-tim2",
+    feature = "time-driver-tim5",
+    feature = "time-driver-lptim1"
+))]
+pub(crate) use time_driver::TimeDriver;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_now() {
+        let half = 1 << 31;
+        assert_eq!(calculate_now(32, 0, 5), 5);
+        assert_eq!(calculate_now(32, 1, half + 5), half as u64 + 5);
+        assert_eq!(calculate_now(32, 2, 5), (1 << 32) + 5);
+        // The overflow or halfway compare interrupt is still pending
+        assert_eq!(calculate_now(32, 0, half + 5), half as u64 + 5);
+        assert_eq!(calculate_now(32, 1, 5), (1 << 32) + 5);
+
+        // 16-bit counter
+        assert_eq!(calculate_now(16, 1, (1 << 15) + 5), (1 << 15) + 5);
+        assert_eq!(calculate_now(16, 2, 5), (1 << 16) + 5);
+        assert_eq!(calculate_now(16, 1, 5), (1 << 16) + 5);
+    }
+
+    #[test]
+    fn test_compare_value() {
+        assert_eq!(compare_value(32, 10, 100), 100);
+        assert_eq!(compare_value(32, (1 << 32) + 10, (1 << 32) + 100), 100);
+        assert_eq!(compare_value(32, 10, u32::MAX as u64 + 10), 9);
+        // More than one period ahead
+        assert_eq!(compare_value(32, 10, (1 << 32) + 100), 10);
+
+        // 16-bit counter
+        assert_eq!(compare_value(16, (1 << 16) + 10, (1 << 16) + 100), 100);
+        assert_eq!(compare_value(16, (1 << 16) + 10, (1 << 17) + 100), 10);
+    }
+}
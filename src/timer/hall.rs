@@ -0,0 +1,241 @@
+//! Hall sensor interface
+//!
+//! The three Hall sensor signals of a brushless motor are connected to
+//! channels 1 to 3 of a timer, which combines them with an XOR gate into
+//! its channel 1 input. Every edge of any of the sensors then captures the
+//! counter into CCR1 and restarts the counter, so that CCR1 holds the time
+//! between the last two Hall edges, from which the rotor speed is derived.
+//!
+//! Channel 2 generates a pulse a configurable delay after each Hall edge,
+//! which is output on the timer's trigger output (TRGO). When this is
+//! connected to an advanced-control timer driving the motor, it triggers
+//! the commutation to the next step without any CPU involvement (see
+//! [`SixStep`](crate::pwm::SixStep)).
+//!
+//! If the motor stops, the counter overflows and an [`Event::Update`] is
+//! generated.
+//!
+//! ```
+//! let mut hall = dp.TIM3.hall_sensor(
+//!     (pa6.into_alternate(), pa7.into_alternate(), pb0.into_alternate()),
+//!     HallConfig::new(1.MHz()).filter(8),
+//!     ccdr.peripheral.TIM3,
+//!     &ccdr.clocks,
+//! );
+//!
+//! if let Some(ticks) = hall.period() {
+//!     // One sixth of an electrical revolution took `ticks` microseconds
+//! }
+//! ```
+
+use crate::rcc::{CoreClocks, ResetEnable};
+use crate::time::Hertz;
+
+use super::{
+    clear_pending, counter_prescaler, is_pending, set_interrupt, Event,
+    Instance, PinCh,
+};
+
+/// Hall sensor interface configuration
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HallConfig {
+    frequency: Hertz,
+    filter: u8,
+    commutation_delay: u32,
+}
+
+impl HallConfig {
+    /// Count at `frequency`, which sets the resolution of the measured
+    /// period and commutation delay. There is no input filter, and the
+    /// trigger output pulses on the counter cycle after each Hall edge.
+    pub fn new(frequency: Hertz) -> Self {
+        HallConfig {
+            frequency,
+            filter: 0,
+            commutation_delay: 1,
+        }
+    }
+
+    /// Set the digital filter of the Hall sensor inputs (IC1F), from 0 (no
+    /// filter) to 15
+    pub fn filter(mut self, filter: u8) -> Self {
+        assert!(filter <= 15, "The input filter is at most 15");
+        self.filter = filter;
+        self
+    }
+
+    /// Set the delay from a Hall edge to the trigger output pulse, in
+    /// counter ticks. This is at least 1.
+    pub fn commutation_delay(mut self, ticks: u32) -> Self {
+        assert!(ticks >= 1, "The commutation delay is at least one tick");
+        self.commutation_delay = ticks;
+        self
+    }
+}
+
+impl From<Hertz> for HallConfig {
+    fn from(frequency: Hertz) -> Self {
+        HallConfig::new(frequency)
+    }
+}
+
+/// The three pins of a Hall sensor interface, on channels 1 to 3 of the
+/// timer
+pub trait HallPins<TIM> {}
+
+impl<TIM, H1, H2, H3> HallPins<TIM> for (H1, H2, H3)
+where
+    H1: PinCh<TIM, 1>,
+    H2: PinCh<TIM, 2>,
+    H3: PinCh<TIM, 3>,
+{
+}
+
+/// A timer configured as a Hall sensor interface
+#[derive(Debug)]
+pub struct HallSensor<TIM> {
+    tim: TIM,
+    frequency: Hertz,
+}
+
+pub trait HallExt<TIM: Instance>: Sized {
+    /// Configure the timer as a Hall sensor interface on `pins`
+    fn hall_sensor<PINS, CONFIG>(
+        self,
+        pins: PINS,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> HallSensor<TIM>
+    where
+        PINS: HallPins<TIM>,
+        CONFIG: Into<HallConfig>;
+}
+
+impl<TIM: Instance> HallExt<TIM> for TIM {
+    fn hall_sensor<PINS, CONFIG>(
+        self,
+        _pins: PINS,
+        config: CONFIG,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> HallSensor<TIM>
+    where
+        PINS: HallPins<TIM>,
+        CONFIG: Into<HallConfig>,
+    {
+        HallSensor::new(self, config, rec, clocks)
+    }
+}
+
+impl<TIM: Instance> HallSensor<TIM> {
+    fn new(
+        tim: TIM,
+        config: impl Into<HallConfig>,
+        rec: TIM::Rec,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let config: HallConfig = config.into();
+        let _ = rec.enable().reset();
+
+        let clock = TIM::clock(clocks);
+        let psc = counter_prescaler(clock, config.frequency);
+
+        // NOTE(unsafe) The registers used here are at the same offsets in
+        // all timers
+        let regs = unsafe { &*TIM::ptr() };
+        regs.psc().write(|w| w.psc().set((psc - 1) as u16));
+        regs.arr().write(|w| w.arr().set(TIM::MAX_ARR));
+
+        // Only counter overflows generate an update event, rather than each
+        // reset by a Hall edge
+        regs.cr1().write(|w| w.urs().set_bit());
+
+        // The XOR of the three inputs goes to TI1, whose edges (TI1F_ED)
+        // reset the counter and are captured in CCR1 through TRC
+        regs.cr2().write(|w| {
+            w.ti1s()
+                .set_bit()
+                // OC2REF is output on TRGO
+                .mms()
+                .set(0b101)
+        });
+        regs.smcr().write(|w| w.ts().set(0b100).sms().set(0b100));
+        regs.ccmr1_input()
+            .write(|w| w.cc1s().trc().ic1f().set(config.filter));
+
+        // Channel 2 goes active once the commutation delay has elapsed after
+        // each Hall edge
+        regs.ccmr1_output()
+            .modify(|_, w| w.ccs(1).output().ocm(1).pwm_mode2());
+        regs.ccr(1).write(|w| w.ccr().set(config.commutation_delay));
+
+        regs.ccer().write(|w| w.cce(0).set_bit());
+        regs.egr().write(|w| w.ug().set_bit());
+        clear_pending::<TIM>(Event::Update);
+        regs.cr1().modify(|_, w| w.cen().enabled());
+
+        HallSensor {
+            tim,
+            frequency: Hertz::from_raw(clock.raw() / psc),
+        }
+    }
+
+    /// The counter frequency, in which the period and commutation delay are
+    /// measured
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// The number of counter ticks between the last two Hall edges, if
+    /// there has been a new edge since this was last called
+    pub fn period(&mut self) -> Option<u32> {
+        // NOTE(unsafe) The registers used here are at the same offsets in
+        // all timers
+        let regs = unsafe { &*TIM::ptr() };
+        if regs.sr().read().ccif(0).bit_is_set() {
+            // Reading the capture clears the flag
+            Some(regs.ccr(0).read().ccr().bits())
+        } else {
+            None
+        }
+    }
+
+    /// Set the delay from a Hall edge to the trigger output pulse, in
+    /// counter ticks. This is at least 1.
+    pub fn set_commutation_delay(&mut self, ticks: u32) {
+        assert!(ticks >= 1, "The commutation delay is at least one tick");
+        // NOTE(unsafe) The registers used here are at the same offsets in
+        // all timers
+        let regs = unsafe { &*TIM::ptr() };
+        regs.ccr(1).write(|w| w.ccr().set(ticks));
+    }
+
+    /// Start listening for `event`. [`Event::CaptureCompare1`] is generated
+    /// on each Hall edge and [`Event::Update`] when the motor has stopped.
+    pub fn listen(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, true);
+    }
+
+    /// Stop listening for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        set_interrupt::<TIM>(event, false);
+    }
+
+    /// Whether `event` is pending
+    pub fn is_pending(&self, event: Event) -> bool {
+        is_pending::<TIM>(event)
+    }
+
+    /// Clear the pending flag of `event`
+    pub fn clear_pending(&mut self, event: Event) {
+        clear_pending::<TIM>(event);
+    }
+
+    /// Releases the timer peripheral
+    pub fn free(self) -> TIM {
+        let _ = TIM::rec().reset().disable();
+        self.tim
+    }
+}
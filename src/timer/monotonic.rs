@@ -0,0 +1,300 @@
+//! Monotonic timers on TIM2 and TIM5
+//!
+//! The 32-bit counters of TIM2 and TIM5 are extended to 64 bits by counting
+//! half periods: the update interrupt at each overflow and the channel 2
+//! compare interrupt halfway through each period both increment a
+//! half-period counter, from which and the value of the counter the 64-bit
+//! time is read without a critical section. Channel 1 compares against the
+//! next wake-up time.
+//!
+//! The counter runs at a fixed frequency, which the kernel clock of the
+//! timer (`timx_ker_ck`) must be a multiple of.
+//!
+//! The `rtic-tim2` and `rtic-tim5` features provide `Tim2Monotonic` and
+//! `Tim5Monotonic`, which implement the `rtic_time::Monotonic` trait for
+//! RTIC 2:
+//!
+//! ```
+//! use stm32h5xx_hal::timer::Tim2Monotonic;
+//! use rtic_time::Monotonic;
+//!
+//! type Mono = Tim2Monotonic<1_000_000>;
+//!
+//! Mono::start(dp.TIM2, ccdr.peripheral.TIM2, &ccdr.clocks);
+//!
+//! // In a task
+//! Mono::delay(100.millis()).await;
+//! ```
+//!
+//! The `time-driver-tim2` and `time-driver-tim5` features make the timer the
+//! time driver of `embassy-time`, counting at the tick rate selected by the
+//! `tick-hz-*` features of `embassy-time`. The driver must be started with
+//! `init_time_driver` before any time is read:
+//!
+//! ```
+//! stm32h5xx_hal::timer::init_time_driver(dp.TIM2, ccdr.peripheral.TIM2, &ccdr.clocks);
+//! ```
+//!
+//! Each of these features defines the interrupt handler of its timer, so a
+//! timer can only be used by one of them.
+
+#[cfg(all(feature = "rtic-tim2", feature = "time-driver-tim2"))]
+compile_error!("TIM2 cannot be both an RTIC monotonic and the time driver");
+
+#[cfg(all(feature = "rtic-tim5", feature = "time-driver-tim5"))]
+compile_error!("TIM5 cannot be both an RTIC monotonic and the time driver");
+
+#[cfg(all(feature = "time-driver-tim2", feature = "time-driver-tim5"))]
+compile_error!("Only one timer can be the time driver");
+
+#[cfg(all(
+    any(feature = "rtic-tim5", feature = "time-driver-tim5"),
+    not(feature = "rm0481")
+))]
+compile_error!("TIM5 is only present on STM32H52x/6x/7x");
+
+use super::counter::{fixed_prescaler, init, load};
+use super::half_period::HalfPeriodTimer;
+use super::{clear_pending, is_pending, set_interrupt, Event, Instance};
+use crate::interrupt;
+use crate::rcc::CoreClocks;
+use crate::stm32::Interrupt;
+
+/// The value of CCR2 halfway through the period of the counter
+const HALF_PERIOD: u32 = 1 << 31;
+
+/// Start the counter at `freq` Hz, with the update and channel 2 compare
+/// interrupts enabled to count half periods. This will panic if the timer
+/// clock is not a multiple of the counter frequency.
+fn start<TIM: Instance>(rec: TIM::Rec, clocks: &CoreClocks, freq: u32) {
+    let psc = fixed_prescaler::<TIM>(clocks, freq);
+    init::<TIM>(rec);
+    load::<TIM>(psc, TIM::MAX_ARR);
+
+    // NOTE(unsafe) CCR2 is only used by the monotonic timer
+    let tim = unsafe { &*TIM::ptr() };
+    tim.ccr(1).write(|w| w.ccr().set(HALF_PERIOD));
+    clear_pending::<TIM>(Event::CaptureCompare2);
+    set_interrupt::<TIM>(Event::Update, true);
+    set_interrupt::<TIM>(Event::CaptureCompare2, true);
+    tim.cr1().modify(|_, w| w.cen().set_bit());
+}
+
+impl<TIM: Instance> HalfPeriodTimer for TIM {
+    const BITS: u32 = if TIM::MAX_ARR == u32::MAX { 32 } else { 16 };
+
+    fn counter() -> u32 {
+        // NOTE(unsafe) Atomic read with no side effects
+        unsafe { &*TIM::ptr() }.cnt().read().bits()
+    }
+
+    fn set_compare_value(ccr: u32) {
+        // NOTE(unsafe) CCR1 is only used by the monotonic timer
+        let tim = unsafe { &*TIM::ptr() };
+        tim.ccr(0).write(|w| w.ccr().set(ccr));
+    }
+
+    fn take_half_periods() -> u32 {
+        let mut count = 0;
+        for event in [Event::Update, Event::CaptureCompare2] {
+            if is_pending::<TIM>(event) {
+                clear_pending::<TIM>(event);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    fn take_compare() -> bool {
+        let pending = is_pending::<TIM>(Event::CaptureCompare1);
+        clear_pending::<TIM>(Event::CaptureCompare1);
+        pending
+    }
+
+    fn listen_compare(enable: bool) {
+        set_interrupt::<TIM>(Event::CaptureCompare1, enable);
+    }
+}
+
+#[cfg(any(feature = "rtic-tim2", feature = "rtic-tim5"))]
+mod rtic {
+    use core::sync::atomic::AtomicU32;
+
+    use cortex_m::peripheral::NVIC;
+    use rtic_time::{
+        monotonic::TimerQueueBasedMonotonic,
+        timer_queue::{TimerQueue, TimerQueueBackend},
+    };
+
+    use super::{interrupt, start, Interrupt};
+    use crate::rcc::{rec, CoreClocks};
+    use crate::timer::half_period::{
+        count_half_periods, now, set_compare, HalfPeriodTimer,
+    };
+
+    macro_rules! rtic_monotonic {
+        ($TIMX:ident: $Tim:ident, $Mono:ident, $Backend:ident) => {
+            use crate::stm32::$TIMX;
+
+            /// The timer queue backend of the monotonic timer on
+            #[doc = stringify!($TIMX)]
+            pub struct $Backend {
+                _private: (),
+            }
+
+            impl $Backend {
+                #[inline(always)]
+                fn half_periods() -> &'static AtomicU32 {
+                    static HALF_PERIODS: AtomicU32 = AtomicU32::new(0);
+                    &HALF_PERIODS
+                }
+            }
+
+            impl TimerQueueBackend for $Backend {
+                type Ticks = u64;
+
+                fn now() -> u64 {
+                    now::<$TIMX>(Self::half_periods())
+                }
+
+                fn set_compare(instant: u64) {
+                    set_compare::<$TIMX>(Self::half_periods(), instant);
+                }
+
+                fn clear_compare_flag() {
+                    $TIMX::take_compare();
+                }
+
+                fn pend_interrupt() {
+                    NVIC::pend(Interrupt::$TIMX);
+                }
+
+                fn on_interrupt() {
+                    count_half_periods::<$TIMX>(Self::half_periods());
+                }
+
+                fn enable_timer() {
+                    $TIMX::listen_compare(true);
+                }
+
+                fn disable_timer() {
+                    $TIMX::listen_compare(false);
+                }
+
+                fn timer_queue() -> &'static TimerQueue<Self> {
+                    static QUEUE: TimerQueue<$Backend> = TimerQueue::new();
+                    &QUEUE
+                }
+            }
+
+            /// A monotonic timer for RTIC counting at `FREQ` Hz on
+            #[doc = stringify!($TIMX)]
+            pub struct $Mono<const FREQ: u32>;
+
+            impl<const FREQ: u32> $Mono<FREQ> {
+                /// Start the monotonic timer and unmask its interrupt. This
+                /// will panic if the timer clock is not a multiple of
+                /// `FREQ`.
+                pub fn start(_tim: $TIMX, rec: rec::$Tim, clocks: &CoreClocks) {
+                    start::<$TIMX>(rec, clocks, FREQ);
+                    $Backend::timer_queue()
+                        .initialize($Backend { _private: () });
+                    // NOTE(unsafe) The interrupt handler is defined here, and
+                    // only uses the timer, which is owned by the monotonic
+                    unsafe { NVIC::unmask(Interrupt::$TIMX) };
+                }
+            }
+
+            impl<const FREQ: u32> TimerQueueBasedMonotonic for $Mono<FREQ> {
+                type Backend = $Backend;
+                type Instant = fugit::Instant<u64, 1, FREQ>;
+                type Duration = fugit::Duration<u64, 1, FREQ>;
+            }
+
+            #[interrupt]
+            fn $TIMX() {
+                // NOTE(unsafe) Called from the interrupt of the timer
+                unsafe { $Backend::timer_queue().on_monotonic_interrupt() };
+            }
+        };
+    }
+
+    #[cfg(feature = "rtic-tim2")]
+    mod tim2 {
+        use super::*;
+
+        rtic_monotonic! { TIM2: Tim2, Tim2Monotonic, Tim2Backend }
+    }
+
+    #[cfg(feature = "rtic-tim2")]
+    pub use tim2::{Tim2Backend, Tim2Monotonic};
+
+    #[cfg(feature = "rtic-tim5")]
+    mod tim5 {
+        use super::*;
+
+        rtic_monotonic! { TIM5: Tim5, Tim5Monotonic, Tim5Backend }
+    }
+
+    #[cfg(feature = "rtic-tim5")]
+    pub use tim5::{Tim5Backend, Tim5Monotonic};
+}
+
+#[cfg(any(feature = "rtic-tim2", feature = "rtic-tim5"))]
+pub use rtic::*;
+
+#[cfg(any(feature = "time-driver-tim2", feature = "time-driver-tim5"))]
+mod time_driver {
+    use cortex_m::peripheral::NVIC;
+    use embassy_time_driver::TICK_HZ;
+
+    use super::{interrupt, start, Interrupt};
+    use crate::rcc::CoreClocks;
+    use crate::timer::half_period::TimeDriver;
+    use crate::timer::BasicInstance;
+
+    #[cfg(feature = "time-driver-tim2")]
+    use crate::stm32::TIM2 as TIM;
+    #[cfg(feature = "time-driver-tim5")]
+    use crate::stm32::TIM5 as TIM;
+
+    #[cfg(feature = "time-driver-tim2")]
+    const IRQ: Interrupt = Interrupt::TIM2;
+    #[cfg(feature = "time-driver-tim5")]
+    const IRQ: Interrupt = Interrupt::TIM5;
+
+    embassy_time_driver::time_driver_impl!(static DRIVER: TimeDriver<TIM> = TimeDriver::new());
+
+    /// Start the time driver and unmask the interrupt of its timer. This
+    /// will panic if the timer clock is not a multiple of the tick rate of
+    /// `embassy-time`.
+    pub fn init_time_driver(
+        _tim: TIM,
+        rec: <TIM as BasicInstance>::Rec,
+        clocks: &CoreClocks,
+    ) {
+        assert!(
+            TICK_HZ <= u32::MAX as u64,
+            "The tick rate is too high for the timer"
+        );
+        start::<TIM>(rec, clocks, TICK_HZ as u32);
+        // NOTE(unsafe) The interrupt handler is defined here, and only uses
+        // the timer, which is owned by the time driver
+        unsafe { NVIC::unmask(IRQ) };
+    }
+
+    #[cfg(feature = "time-driver-tim2")]
+    #[interrupt]
+    fn TIM2() {
+        DRIVER.on_interrupt();
+    }
+
+    #[cfg(feature = "time-driver-tim5")]
+    #[interrupt]
+    fn TIM5() {
+        DRIVER.on_interrupt();
+    }
+}
+
+#[cfg(any(feature = "time-driver-tim2", feature = "time-driver-tim5"))]
+pub use time_driver::init_time_driver;
@@ -0,0 +1,251 @@
+//! Master/slave synchronization of timers
+//!
+//! A master timer drives its trigger output (TRGO) from one of its events,
+//! selected by a [`MasterMode`]. TRGO is connected to the internal trigger
+//! inputs (ITR) of the other timers, and to the trigger inputs of the ADCs
+//! and DACs. TIM1 and TIM8 have a second trigger output (TRGO2) that is
+//! only connected to the ADCs.
+//!
+//! A slave timer uses the trigger output of its master as its trigger
+//! input, in a [`SlaveMode`] that resets, gates, starts or clocks its
+//! counter. The master is given as a type parameter, and can only be a
+//! timer whose trigger output is connected to the slave, as given by
+//! [`InternalTrigger`]:
+//!
+//! ```
+//! use stm32h5xx_hal::timer::{MasterMode, SlaveMode};
+//! use stm32h5xx_hal::pac::TIM2;
+//!
+//! // TIM3 outputs PWM while channel 1 of TIM2 is active
+//! let (mut gate, channels2) = dp.TIM2.pwm(1.Hz(), ccdr.peripheral.TIM2, &ccdr.clocks);
+//! gate.set_master_mode(MasterMode::Oc1Ref);
+//! let mut gate_ch = channels2.ch1.pwm_unchecked();
+//! gate_ch.set_duty(gate_ch.max_duty() / 10);
+//!
+//! let (mut pwm, channels3) = dp.TIM3.pwm(10.kHz(), ccdr.peripheral.TIM3, &ccdr.clocks);
+//! pwm.set_slave_mode::<TIM2>(SlaveMode::Gated);
+//! ```
+//!
+//! To start timers together, the slaves are paused and set to
+//! [`SlaveMode::Trigger`], and the master is set to [`MasterMode::Enable`]
+//! before it is started.
+
+use super::{internal_trigger_selection, select_trigger};
+use super::{BasicInstance, Instance, InternalTrigger};
+use crate::stm32::tim1;
+
+/// The source of the trigger output of a master timer
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MasterMode {
+    /// A pulse when the counter is reinitialised by software (UG bit) or by
+    /// the slave mode controller in reset mode
+    Reset,
+    /// The counter enable signal, which is active while the counter runs
+    Enable,
+    /// A pulse on each update event, which is each counter overflow
+    Update,
+    /// A pulse when channel 1 captures or matches its compare value
+    ComparePulse,
+    /// The output compare reference signal of channel 1 (OC1REF)
+    Oc1Ref,
+    /// The output compare reference signal of channel 2 (OC2REF)
+    Oc2Ref,
+    /// The output compare reference signal of channel 3 (OC3REF)
+    Oc3Ref,
+    /// The output compare reference signal of channel 4 (OC4REF)
+    Oc4Ref,
+}
+
+impl MasterMode {
+    /// The master mode selection (MMS or MMS2) value, which is the same for
+    /// both trigger outputs
+    fn bits(self) -> u8 {
+        match self {
+            MasterMode::Reset => 0b000,
+            MasterMode::Enable => 0b001,
+            MasterMode::Update => 0b010,
+            MasterMode::ComparePulse => 0b011,
+            MasterMode::Oc1Ref => 0b100,
+            MasterMode::Oc2Ref => 0b101,
+            MasterMode::Oc3Ref => 0b110,
+            MasterMode::Oc4Ref => 0b111,
+        }
+    }
+
+    /// Whether the mode uses only the counter, and not the channels
+    pub(crate) fn is_counter_mode(self) -> bool {
+        matches!(
+            self,
+            MasterMode::Reset | MasterMode::Enable | MasterMode::Update
+        )
+    }
+}
+
+/// The effect of the trigger input on the counter of a slave timer
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SlaveMode {
+    /// A rising edge of the trigger reinitialises the counter
+    Reset,
+    /// The counter runs while the trigger is high
+    Gated,
+    /// A rising edge of the trigger starts the counter
+    Trigger,
+    /// The counter counts the rising edges of the trigger (external clock
+    /// mode 1), to chain timers into a longer counter
+    ExternalClock,
+    /// A rising edge of the trigger reinitialises the counter and starts
+    /// it
+    ResetTrigger,
+    /// The counter runs while the trigger is high, and is reinitialised
+    /// when it goes high
+    GatedReset,
+}
+
+impl SlaveMode {
+    /// The slave mode selection (SMS) value
+    fn bits(self) -> u8 {
+        match self {
+            SlaveMode::Reset => 0b0100,
+            SlaveMode::Gated => 0b0101,
+            SlaveMode::Trigger => 0b0110,
+            SlaveMode::ExternalClock => 0b0111,
+            SlaveMode::ResetTrigger => 0b1000,
+            SlaveMode::GatedReset => 0b1001,
+        }
+    }
+}
+
+// Implemented by the timers with a trigger output
+pub trait MasterInstance: BasicInstance {
+    /// True for the timers with a second trigger output (TRGO2)
+    const HAS_TRGO2: bool;
+}
+
+// Implemented by the timers with a slave mode controller, which selects
+// their trigger input. TIM13, TIM14, TIM16 and TIM17 do not have one.
+pub trait SlaveInstance: Instance {}
+
+#[inline(always)]
+fn cr2_regs<TIM: BasicInstance>() -> &'static tim1::RegisterBlock {
+    // NOTE(unsafe) CR2 is at the same offset in all timers, and the master
+    // mode fields are only written on timers that have them
+    unsafe { &*(TIM::basic_ptr() as *const tim1::RegisterBlock) }
+}
+
+/// Select the source of the trigger output (TRGO)
+pub(crate) fn set_master_mode<TIM: MasterInstance>(mode: MasterMode) {
+    cr2_regs::<TIM>()
+        .cr2()
+        .modify(|_, w| w.mms().set(mode.bits()).mms_3().clear_bit());
+}
+
+/// Select the source of the second trigger output (TRGO2)
+pub(crate) fn set_master_mode2<TIM: MasterInstance>(mode: MasterMode) {
+    assert!(TIM::HAS_TRGO2, "The timer does not have a TRGO2 output");
+    // NOTE(unsafe) The value is a valid MMS2 value
+    cr2_regs::<TIM>()
+        .cr2()
+        .modify(|_, w| unsafe { w.mms2().bits(mode.bits()) });
+}
+
+/// Use the trigger output of timer `M` as the trigger input, in `mode`
+pub(crate) fn set_slave_mode<TIM, M>(mode: SlaveMode)
+where
+    TIM: Instance,
+    M: InternalTrigger<TIM>,
+{
+    disable_slave_mode::<TIM>();
+    select_trigger::<TIM>(internal_trigger_selection(M::ITR));
+    let sms = mode.bits();
+    // NOTE(unsafe) SMCR is at the same offset in all timers with a trigger
+    // input
+    let tim = unsafe { &*TIM::ptr() };
+    tim.smcr()
+        .modify(|_, w| w.sms().set(sms & 0b111).sms_3().bit(sms & 0b1000 != 0));
+}
+
+/// Disable the slave mode, so that the counter is clocked by the timer
+/// clock and controlled by software only
+pub(crate) fn disable_slave_mode<TIM: Instance>() {
+    // NOTE(unsafe) SMCR is at the same offset in all timers with a trigger
+    // input
+    let tim = unsafe { &*TIM::ptr() };
+    tim.smcr().modify(|_, w| w.sms().set(0).sms_3().clear_bit());
+}
+
+// Master mode methods of the timer drivers. Counters only have the master
+// modes that use the counter, as the channels are not configured.
+macro_rules! master_methods {
+    (counter) => {
+        /// Select the source of the trigger output (TRGO), which is used by
+        /// slave timers and to trigger the ADCs and DACs. Only the reset,
+        /// enable and update master modes are supported.
+        pub fn set_master_mode(&mut self, mode: $crate::timer::MasterMode) {
+            assert!(
+                mode.is_counter_mode(),
+                "The master mode uses the channels of the timer"
+            );
+            $crate::timer::sync::set_master_mode::<TIM>(mode);
+        }
+
+        /// Select the source of the second trigger output (TRGO2), which is
+        /// used to trigger the ADCs. Only for TIM1 and TIM8.
+        pub fn set_master_mode2(&mut self, mode: $crate::timer::MasterMode) {
+            assert!(
+                mode.is_counter_mode(),
+                "The master mode uses the channels of the timer"
+            );
+            $crate::timer::sync::set_master_mode2::<TIM>(mode);
+        }
+    };
+    () => {
+        /// Select the source of the trigger output (TRGO), which is used by
+        /// slave timers and to trigger the ADCs and DACs
+        pub fn set_master_mode(&mut self, mode: $crate::timer::MasterMode) {
+            $crate::timer::sync::set_master_mode::<TIM>(mode);
+        }
+
+        /// Select the source of the second trigger output (TRGO2), which is
+        /// used to trigger the ADCs. Only for TIM1 and TIM8.
+        pub fn set_master_mode2(&mut self, mode: $crate::timer::MasterMode) {
+            $crate::timer::sync::set_master_mode2::<TIM>(mode);
+        }
+    };
+}
+
+// Slave mode methods of the timer drivers
+macro_rules! slave_methods {
+    () => {
+        /// Use the trigger output of timer `M` as the trigger input, with
+        /// the effect on the counter given by `mode`
+        pub fn set_slave_mode<M>(&mut self, mode: $crate::timer::SlaveMode)
+        where
+            M: $crate::timer::InternalTrigger<TIM>,
+        {
+            $crate::timer::sync::set_slave_mode::<TIM, M>(mode);
+        }
+
+        /// Stop using the trigger input, so that the counter is controlled
+        /// by software only
+        pub fn disable_slave_mode(&mut self) {
+            $crate::timer::sync::disable_slave_mode::<TIM>();
+        }
+    };
+}
+
+pub(crate) use {master_methods, slave_methods};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slave_mode_bits() {
+        // The modes from combined reset + trigger onwards need SMS[3]
+        assert_eq!(SlaveMode::ExternalClock.bits(), 0b0111);
+        assert_eq!(SlaveMode::ResetTrigger.bits(), 0b1000);
+        assert_eq!(SlaveMode::GatedReset.bits(), 0b1001);
+    }
+}
@@ -0,0 +1,685 @@
+use core::marker::PhantomData;
+
+use super::{
+    AdvancedInstance, BasicInstance, DmaInstance, EncoderInstance, Instance,
+    InternalTrigger, MasterInstance, PinCh, SlaveInstance,
+};
+use crate::gpio::{self, Alternate};
+use crate::rcc::{rec, CoreClocks};
+use crate::stm32::{tim2, tim6, TIM1, TIM2, TIM3, TIM6, TIM7};
+use crate::time::Hertz;
+
+macro_rules! pins {
+    ($($TIMX:ty:
+       CH1: [$($( #[ $pmeta1:meta ] )* $CH1:ty),*]
+       CH2: [$($( #[ $pmeta2:meta ] )* $CH2:ty),*]
+       CH3: [$($( #[ $pmeta3:meta ] )* $CH3:ty),*]
+       CH4: [$($( #[ $pmeta4:meta ] )* $CH4:ty),*]
+    )+) => {
+        $(
+            $(
+                $( #[ $pmeta1 ] )*
+                impl PinCh<$TIMX, 1> for $CH1 {}
+            )*
+            $(
+                $( #[ $pmeta2 ] )*
+                impl PinCh<$TIMX, 2> for $CH2 {}
+            )*
+            $(
+                $( #[ $pmeta3 ] )*
+                impl PinCh<$TIMX, 3> for $CH3 {}
+            )*
+            $(
+                $( #[ $pmeta4 ] )*
+                impl PinCh<$TIMX, 4> for $CH4 {}
+            )*
+        )+
+    }
+}
+
+// Complementary outputs and break inputs of the timers with a break and
+// dead-time register
+macro_rules! complementary_pins {
+    ($($TIMX:ty:
+       CH1N: [$($( #[ $pmeta1:meta ] )* $CH1N:ty),*]
+       CH2N: [$($( #[ $pmeta2:meta ] )* $CH2N:ty),*]
+       CH3N: [$($( #[ $pmeta3:meta ] )* $CH3N:ty),*]
+       CH4N: [$($( #[ $pmeta4:meta ] )* $CH4N:ty),*]
+       BKIN: [$($( #[ $pmeta5:meta ] )* $BKIN:ty),*]
+       BKIN2: [$($( #[ $pmeta6:meta ] )* $BKIN2:ty),*]
+    )+) => {
+        $(
+            $(
+                $( #[ $pmeta1 ] )*
+                impl crate::timer::PinChN<$TIMX, 1> for $CH1N {}
+            )*
+            $(
+                $( #[ $pmeta2 ] )*
+                impl crate::timer::PinChN<$TIMX, 2> for $CH2N {}
+            )*
+            $(
+                $( #[ $pmeta3 ] )*
+                impl crate::timer::PinChN<$TIMX, 3> for $CH3N {}
+            )*
+            $(
+                $( #[ $pmeta4 ] )*
+                impl crate::timer::PinChN<$TIMX, 4> for $CH4N {}
+            )*
+            $(
+                $( #[ $pmeta5 ] )*
+                impl crate::timer::PinBkin<$TIMX> for $BKIN {}
+            )*
+            $(
+                $( #[ $pmeta6 ] )*
+                impl crate::timer::PinBkin2<$TIMX> for $BKIN2 {}
+            )*
+        )+
+    }
+}
+
+// External trigger inputs
+macro_rules! etr_pins {
+    ($($TIMX:ty: [$($( #[ $pmeta:meta ] )* $ETR:ty),*])+) => {
+        $(
+            $(
+                $( #[ $pmeta ] )*
+                impl crate::timer::PinEtr<$TIMX> for $ETR {}
+            )*
+        )+
+    }
+}
+
+// Implemented by all timer instances. Timers on APB1 are clocked from
+// timx_ker_ck, and those on APB2 from timy_ker_ck.
+macro_rules! basic_instance {
+    ($TIMX:ident: $Tim:ident, $max_arr:literal, $ker_ck:ident) => {
+        impl BasicInstance for $TIMX {
+            type Rec = rec::$Tim;
+
+            const MAX_ARR: u32 = $max_arr;
+
+            fn basic_ptr() -> *const tim6::RegisterBlock {
+                <$TIMX>::ptr() as *const _
+            }
+
+            fn clock(clocks: &CoreClocks) -> Hertz {
+                clocks.$ker_ck()
+            }
+
+            fn rec() -> Self::Rec {
+                rec::$Tim {
+                    _marker: PhantomData,
+                }
+            }
+        }
+
+        impl crate::Sealed for $TIMX {}
+    };
+}
+
+// Implemented by all timer instances with capture/compare channels
+macro_rules! instance {
+    ($TIMX:ident: $Tim:ident, $max_arr:literal, $channels:literal, $bdtr:tt, $up_down:tt, $ker_ck:ident) => {
+        basic_instance! { $TIMX: $Tim, $max_arr, $ker_ck }
+
+        impl Instance for $TIMX {
+            const CHANNELS: u8 = $channels;
+            const HAS_BDTR: bool = $bdtr;
+            const UP_DOWN: bool = $up_down;
+
+            fn ptr() -> *const tim2::RegisterBlock {
+                <$TIMX>::ptr() as *const _
+            }
+        }
+
+        instance! { @bdtr $TIMX, $bdtr }
+        instance! { @up_down $TIMX, $up_down }
+    };
+    (@bdtr $TIMX:ident, true) => {
+        impl AdvancedInstance for $TIMX {}
+    };
+    (@bdtr $TIMX:ident, false) => {};
+    (@up_down $TIMX:ident, true) => {
+        impl EncoderInstance for $TIMX {}
+    };
+    (@up_down $TIMX:ident, false) => {};
+}
+
+// The trigger outputs of TIM1 to TIM5 and TIM8 are connected to internal
+// trigger inputs ITR0 to ITR5 of the other timers
+macro_rules! internal_triggers {
+    ($($TIMX:ty: [$($MASTER:ty: $itr:literal),*])+) => {
+        $(
+            $(
+                impl InternalTrigger<$TIMX> for $MASTER {
+                    const ITR: u8 = $itr;
+                }
+            )*
+        )+
+    };
+}
+
+// GPDMA request lines of the update event and of the capture/compare events
+// of each channel that has one. TIM12 to TIM14 do not have DMA requests.
+macro_rules! dma_requests {
+    ($($TIMX:ty: $update:literal, [$($cc:literal),*])+) => {
+        $(
+            impl DmaInstance for $TIMX {
+                const UPDATE_DMA_REQUEST: u8 = $update;
+                const CC_DMA_REQUESTS: &'static [u8] = &[$($cc),*];
+            }
+        )+
+    };
+}
+
+// Implemented by the timers with a trigger output (TRGO), and whether they
+// have a second one (TRGO2). TIM13, TIM14, TIM16 and TIM17 do not have one.
+macro_rules! master_instances {
+    ($($TIMX:ty: $trgo2:literal),+) => {
+        $(
+            impl MasterInstance for $TIMX {
+                const HAS_TRGO2: bool = $trgo2;
+            }
+        )+
+    };
+}
+
+// Implemented by the timers with a slave mode controller
+macro_rules! slave_instances {
+    ($($TIMX:ty),+) => {
+        $(
+            impl SlaveInstance for $TIMX {}
+        )+
+    };
+}
+
+instance! { TIM1: Tim1, 0xFFFF, 4, true, true, timy_ker_ck }
+instance! { TIM2: Tim2, 0xFFFF_FFFF, 4, false, true, timx_ker_ck }
+instance! { TIM3: Tim3, 0xFFFF, 4, false, true, timx_ker_ck }
+basic_instance! { TIM6: Tim6, 0xFFFF, timx_ker_ck }
+basic_instance! { TIM7: Tim7, 0xFFFF, timx_ker_ck }
+
+master_instances! { TIM1: true, TIM2: false, TIM3: false, TIM6: false, TIM7: false }
+slave_instances! { TIM1, TIM2, TIM3 }
+
+#[cfg(feature = "rm0492")]
+mod rm0492 {
+    use super::*;
+
+    internal_triggers! {
+        TIM1: [TIM2: 1, TIM3: 2]
+        TIM2: [TIM1: 0, TIM3: 2]
+        TIM3: [TIM1: 0, TIM2: 1]
+    }
+
+    dma_requests! {
+        TIM1: 33, [29, 30, 31, 32]
+        TIM2: 40, [36, 37, 38, 39]
+        TIM3: 45, [41, 42, 43, 44]
+    }
+
+    pins! {
+        TIM1:
+            CH1: [
+                gpio::PA8<Alternate<1>>
+            ]
+            CH2: [
+                gpio::PA9<Alternate<1>>
+            ]
+            CH3: [
+                gpio::PA10<Alternate<1>>
+            ]
+            CH4: [
+                gpio::PA11<Alternate<1>>
+            ]
+        TIM2:
+            CH1: [
+                gpio::PA0<Alternate<1>>,
+                gpio::PA5<Alternate<1>>,
+                gpio::PA15<Alternate<1>>,
+                gpio::PB2<Alternate<14>>
+            ]
+            CH2: [
+                gpio::PA1<Alternate<1>>,
+                gpio::PB3<Alternate<1>>,
+                gpio::PC11<Alternate<1>>
+            ]
+            CH3: [
+                gpio::PA2<Alternate<1>>,
+                gpio::PA7<Alternate<14>>,
+                gpio::PB10<Alternate<1>>,
+                gpio::PD2<Alternate<1>>
+            ]
+            CH4: [
+                gpio::PA3<Alternate<1>>,
+                gpio::PA12<Alternate<14>>,
+                gpio::PC4<Alternate<1>>,
+                gpio::PC12<Alternate<1>>
+            ]
+        TIM3:
+            CH1: [
+                gpio::PA0<Alternate<2>>,
+                gpio::PA6<Alternate<2>>,
+                gpio::PA14<Alternate<2>>,
+                gpio::PB4<Alternate<2>>,
+                gpio::PC6<Alternate<2>>
+            ]
+            CH2: [
+                gpio::PA7<Alternate<2>>,
+                gpio::PA11<Alternate<2>>,
+                gpio::PB5<Alternate<2>>,
+                gpio::PC7<Alternate<2>>
+            ]
+            CH3: [
+                gpio::PA8<Alternate<2>>,
+                gpio::PB0<Alternate<2>>,
+                gpio::PB6<Alternate<2>>,
+                gpio::PC8<Alternate<2>>
+            ]
+            CH4: [
+                gpio::PA12<Alternate<2>>,
+                gpio::PB1<Alternate<2>>,
+                gpio::PB15<Alternate<14>>,
+                gpio::PC9<Alternate<2>>
+            ]
+    }
+
+    complementary_pins! {
+        TIM1:
+            CH1N: [
+                gpio::PA7<Alternate<1>>,
+                gpio::PB13<Alternate<1>>
+            ]
+            CH2N: [
+                gpio::PB0<Alternate<1>>,
+                gpio::PB14<Alternate<1>>
+            ]
+            CH3N: [
+                gpio::PB1<Alternate<1>>,
+                gpio::PB15<Alternate<1>>
+            ]
+            CH4N: [
+                gpio::PC5<Alternate<1>>
+            ]
+            BKIN: [
+                gpio::PA6<Alternate<1>>,
+                gpio::PB12<Alternate<1>>
+            ]
+            BKIN2: []
+    }
+
+    etr_pins! {
+        TIM1: [gpio::PA12<Alternate<1>>]
+        TIM2: [
+            gpio::PA0<Alternate<1>>,
+            gpio::PA5<Alternate<1>>,
+            gpio::PA15<Alternate<1>>
+        ]
+        TIM3: [gpio::PD2<Alternate<2>>]
+    }
+}
+
+// Note: pin data is taken from stm32h56x, stm32h573, stm32h523 and stm32h533 datasheets
+#[cfg(feature = "rm0481")]
+mod rm0481_common {
+    use super::*;
+    use crate::stm32::{TIM15, TIM4, TIM5, TIM8};
+
+    instance! { TIM4: Tim4, 0xFFFF, 4, false, true, timx_ker_ck }
+    instance! { TIM5: Tim5, 0xFFFF_FFFF, 4, false, true, timx_ker_ck }
+    instance! { TIM8: Tim8, 0xFFFF, 4, true, true, timy_ker_ck }
+    instance! { TIM15: Tim15, 0xFFFF, 2, true, false, timy_ker_ck }
+
+    internal_triggers! {
+        TIM1: [TIM2: 1, TIM3: 2, TIM4: 3, TIM5: 4, TIM8: 5]
+        TIM2: [TIM1: 0, TIM3: 2, TIM4: 3, TIM5: 4, TIM8: 5]
+        TIM3: [TIM1: 0, TIM2: 1, TIM4: 3, TIM5: 4, TIM8: 5]
+        TIM4: [TIM1: 0, TIM2: 1, TIM3: 2, TIM5: 4, TIM8: 5]
+        TIM5: [TIM1: 0, TIM2: 1, TIM3: 2, TIM4: 3, TIM8: 5]
+        TIM8: [TIM1: 0, TIM2: 1, TIM3: 2, TIM4: 3, TIM5: 4]
+    }
+
+    master_instances! { TIM4: false, TIM5: false, TIM8: true, TIM15: false }
+    slave_instances! { TIM4, TIM5, TIM8, TIM15 }
+
+    dma_requests! {
+        TIM1: 62, [58, 59, 60, 61]
+        TIM8: 69, [65, 66, 67, 68]
+        TIM2: 76, [72, 73, 74, 75]
+        TIM3: 81, [77, 78, 79, 80]
+        TIM4: 87, [83, 84, 85, 86]
+        TIM5: 92, [88, 89, 90, 91]
+        TIM15: 95, [94]
+    }
+
+    pins! {
+        TIM1:
+            CH1: [
+                gpio::PA8<Alternate<1>>,
+                gpio::PE9<Alternate<1>>
+            ]
+            CH2: [
+                gpio::PA9<Alternate<1>>,
+                gpio::PE11<Alternate<1>>
+            ]
+            CH3: [
+                gpio::PA10<Alternate<1>>,
+                gpio::PE13<Alternate<1>>
+            ]
+            CH4: [
+                gpio::PA11<Alternate<1>>,
+                gpio::PE14<Alternate<1>>
+            ]
+        TIM2:
+            CH1: [
+                gpio::PA0<Alternate<1>>,
+                gpio::PA5<Alternate<1>>,
+                gpio::PA15<Alternate<1>>
+            ]
+            CH2: [
+                gpio::PA1<Alternate<1>>,
+                gpio::PB3<Alternate<1>>
+            ]
+            CH3: [
+                gpio::PA2<Alternate<1>>,
+                gpio::PB10<Alternate<1>>
+            ]
+            CH4: [
+                gpio::PA3<Alternate<1>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PB11<Alternate<1>>,
+                gpio::PC4<Alternate<1>>
+            ]
+        TIM3:
+            CH1: [
+                gpio::PA6<Alternate<2>>,
+                gpio::PB4<Alternate<2>>,
+                gpio::PC6<Alternate<2>>
+            ]
+            CH2: [
+                gpio::PA7<Alternate<2>>,
+                gpio::PB5<Alternate<2>>,
+                gpio::PC7<Alternate<2>>
+            ]
+            CH3: [
+                gpio::PB0<Alternate<2>>,
+                gpio::PC8<Alternate<2>>
+            ]
+            CH4: [
+                gpio::PB1<Alternate<2>>,
+                gpio::PC9<Alternate<2>>
+            ]
+        TIM4:
+            CH1: [
+                gpio::PB6<Alternate<2>>,
+                gpio::PD12<Alternate<2>>
+            ]
+            CH2: [
+                gpio::PB7<Alternate<2>>,
+                gpio::PD13<Alternate<2>>
+            ]
+            CH3: [
+                gpio::PB8<Alternate<2>>,
+                gpio::PD14<Alternate<2>>
+            ]
+            CH4: [
+                gpio::PB9<Alternate<2>>,
+                gpio::PC2<Alternate<2>>,
+                gpio::PD15<Alternate<2>>
+            ]
+        TIM5:
+            CH1: [
+                gpio::PA0<Alternate<2>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH10<Alternate<2>>
+            ]
+            CH2: [
+                gpio::PA1<Alternate<2>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH11<Alternate<2>>
+            ]
+            CH3: [
+                gpio::PA2<Alternate<2>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH12<Alternate<2>>
+            ]
+            CH4: [
+                gpio::PA3<Alternate<2>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PI0<Alternate<2>>
+            ]
+        TIM8:
+            CH1: [
+                gpio::PC6<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PI5<Alternate<3>>
+            ]
+            CH2: [
+                gpio::PC7<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PI6<Alternate<3>>
+            ]
+            CH3: [
+                gpio::PC8<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PI7<Alternate<3>>
+            ]
+            CH4: [
+                gpio::PC9<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PI2<Alternate<3>>
+            ]
+        TIM15:
+            CH1: [
+                gpio::PA2<Alternate<4>>,
+                gpio::PC12<Alternate<2>>,
+                gpio::PE5<Alternate<4>>
+            ]
+            CH2: [
+                gpio::PA3<Alternate<4>>,
+                gpio::PE6<Alternate<4>>
+            ]
+            CH3: []
+            CH4: []
+    }
+
+    complementary_pins! {
+        TIM1:
+            CH1N: [
+                gpio::PA7<Alternate<1>>,
+                gpio::PB13<Alternate<1>>,
+                gpio::PE8<Alternate<1>>
+            ]
+            CH2N: [
+                gpio::PB0<Alternate<1>>,
+                gpio::PB14<Alternate<1>>,
+                gpio::PE10<Alternate<1>>
+            ]
+            CH3N: [
+                gpio::PB1<Alternate<1>>,
+                gpio::PB15<Alternate<1>>,
+                gpio::PE12<Alternate<1>>
+            ]
+            CH4N: [
+                gpio::PC5<Alternate<1>>,
+                gpio::PE15<Alternate<3>>
+            ]
+            BKIN: [
+                gpio::PA6<Alternate<1>>,
+                gpio::PB12<Alternate<1>>,
+                gpio::PE15<Alternate<1>>
+            ]
+            BKIN2: [
+                gpio::PE6<Alternate<1>>,
+                gpio::PG4<Alternate<1>>
+            ]
+        TIM8:
+            CH1N: [
+                gpio::PA5<Alternate<3>>,
+                gpio::PA7<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH13<Alternate<3>>
+            ]
+            CH2N: [
+                gpio::PB0<Alternate<3>>,
+                gpio::PB14<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH14<Alternate<3>>
+            ]
+            CH3N: [
+                gpio::PB1<Alternate<3>>,
+                gpio::PB15<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH15<Alternate<3>>
+            ]
+            CH4N: []
+            BKIN: [
+                gpio::PA6<Alternate<3>>,
+                gpio::PG2<Alternate<3>>
+            ]
+            BKIN2: [
+                gpio::PA8<Alternate<3>>,
+                gpio::PG3<Alternate<3>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PI4<Alternate<3>>
+            ]
+        TIM15:
+            CH1N: [
+                gpio::PA1<Alternate<4>>,
+                gpio::PE4<Alternate<4>>
+            ]
+            CH2N: []
+            CH3N: []
+            CH4N: []
+            BKIN: [
+                gpio::PA0<Alternate<4>>,
+                gpio::PE3<Alternate<4>>
+            ]
+            BKIN2: []
+    }
+
+    etr_pins! {
+        TIM1: [
+            gpio::PA12<Alternate<1>>,
+            gpio::PE7<Alternate<1>>
+        ]
+        TIM2: [
+            gpio::PA0<Alternate<1>>,
+            gpio::PA5<Alternate<1>>,
+            gpio::PA15<Alternate<1>>
+        ]
+        TIM3: [gpio::PD2<Alternate<2>>]
+        TIM4: [gpio::PE0<Alternate<2>>]
+        TIM8: [gpio::PA0<Alternate<3>>]
+    }
+}
+
+// TIM12 is not present on the STM32H523
+#[cfg(any(feature = "stm32h533", feature = "h56x_h573"))]
+mod tim12 {
+    use super::*;
+    use crate::stm32::TIM12;
+
+    instance! { TIM12: Tim12, 0xFFFF, 2, false, false, timx_ker_ck }
+
+    master_instances! { TIM12: false }
+    slave_instances! { TIM12 }
+
+    pins! {
+        TIM12:
+            CH1: [
+                gpio::PB14<Alternate<2>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH6<Alternate<2>>
+            ]
+            CH2: [
+                gpio::PB15<Alternate<2>>,
+                #[cfg(feature = "h56x_h573")]
+                gpio::PH9<Alternate<2>>
+            ]
+            CH3: []
+            CH4: []
+    }
+}
+
+// Note: pin data is taken from stm32h56x and stm32h573 datasheets
+#[cfg(feature = "h56x_h573")]
+mod h56x_h573 {
+    use super::*;
+    use crate::stm32::{TIM13, TIM14, TIM16, TIM17};
+
+    instance! { TIM13: Tim13, 0xFFFF, 1, false, false, timx_ker_ck }
+    instance! { TIM14: Tim14, 0xFFFF, 1, false, false, timx_ker_ck }
+    instance! { TIM16: Tim16, 0xFFFF, 1, true, false, timy_ker_ck }
+    instance! { TIM17: Tim17, 0xFFFF, 1, true, false, timy_ker_ck }
+
+    dma_requests! {
+        TIM16: 99, [98]
+        TIM17: 101, [100]
+    }
+
+    pins! {
+        TIM13:
+            CH1: [
+                gpio::PA6<Alternate<9>>,
+                gpio::PF8<Alternate<9>>
+            ]
+            CH2: []
+            CH3: []
+            CH4: []
+        TIM14:
+            CH1: [
+                gpio::PA7<Alternate<9>>,
+                gpio::PF9<Alternate<9>>
+            ]
+            CH2: []
+            CH3: []
+            CH4: []
+        TIM16:
+            CH1: [
+                gpio::PB8<Alternate<1>>,
+                gpio::PF6<Alternate<1>>
+            ]
+            CH2: []
+            CH3: []
+            CH4: []
+        TIM17:
+            CH1: [
+                gpio::PB9<Alternate<1>>,
+                gpio::PC2<Alternate<1>>,
+                gpio::PF7<Alternate<1>>
+            ]
+            CH2: []
+            CH3: []
+            CH4: []
+    }
+
+    complementary_pins! {
+        TIM16:
+            CH1N: [
+                gpio::PB6<Alternate<1>>,
+                gpio::PF8<Alternate<1>>
+            ]
+            CH2N: []
+            CH3N: []
+            CH4N: []
+            BKIN: [
+                gpio::PB5<Alternate<1>>,
+                gpio::PF10<Alternate<1>>
+            ]
+            BKIN2: []
+        TIM17:
+            CH1N: [
+                gpio::PB7<Alternate<1>>,
+                gpio::PF9<Alternate<1>>
+            ]
+            CH2N: []
+            CH3N: []
+            CH4N: []
+            BKIN: [
+                gpio::PB4<Alternate<1>>,
+                gpio::PG6<Alternate<1>>
+            ]
+            BKIN2: []
+    }
+}